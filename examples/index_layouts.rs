@@ -0,0 +1,111 @@
+// mlodato, 2020
+
+//! Compare the cost of `Layer` operations for each `Index64_3D` bit layout
+//!
+//! Run with `cargo run --release --example index_layouts [object_count]`
+
+extern crate zvxryb_broadphase as broadphase;
+extern crate cgmath;
+extern crate rand;
+extern crate rand_chacha;
+
+use broadphase::{Bounds, Index64_3D, Index64_3DDepthHigh, Layer, LayerBuilder, SpatialIndex};
+use cgmath::{Point3, Vector3};
+use rand::prelude::*;
+
+use std::time::{Duration, Instant};
+
+const ROUNDS: u32 = 10;
+const TEST_COUNT: usize = 1000;
+
+struct Timings {
+    extend: Duration,
+    sort: Duration,
+    scan: Duration,
+    test: Duration,
+    collisions: usize,
+    hits: usize,
+}
+
+fn time<F: FnOnce()>(f: F) -> Duration {
+    let start = Instant::now();
+    f();
+    start.elapsed()
+}
+
+fn run<Index>(
+    system_bounds: Bounds<Point3<f32>>,
+    objects: &[(Bounds<Point3<f32>>, u32)],
+    tests: &[Bounds<Point3<f32>>]) -> Timings
+where
+    Index: SpatialIndex<Point = Point3<u32>>
+{
+    let mut timings = Timings{
+        extend: Duration::default(),
+        sort: Duration::default(),
+        scan: Duration::default(),
+        test: Duration::default(),
+        collisions: 0,
+        hits: 0};
+    for _ in 0..ROUNDS {
+        let mut layer: Layer<Index, u32> = LayerBuilder::new().build();
+        timings.extend += time(|| layer.extend(system_bounds, objects.iter().cloned()));
+        timings.sort += time(|| layer.sort());
+        let mut collisions = 0;
+        timings.scan += time(|| collisions = layer.scan().len());
+        let mut hits = 0;
+        timings.test += time(|| {
+            hits = tests.iter()
+                .map(|&test_bounds| layer.test_box(system_bounds, test_bounds, None).len())
+                .sum();
+        });
+        timings.collisions = collisions;
+        timings.hits = hits;
+    }
+    timings
+}
+
+fn report(name: &str, timings: &Timings) {
+    let ms = |duration: Duration| duration.as_secs_f64() * 1e3 / f64::from(ROUNDS);
+    println!("{:<20} {:>10.3} {:>10.3} {:>10.3} {:>10.3} {:>12} {:>10}",
+        name,
+        ms(timings.extend),
+        ms(timings.sort),
+        ms(timings.scan),
+        ms(timings.test),
+        timings.collisions,
+        timings.hits);
+}
+
+fn main() {
+    let object_count: u32 = std::env::args().nth(1)
+        .map(|arg| arg.parse().expect("object_count must be an integer"))
+        .unwrap_or(100_000);
+
+    let system_bounds = Bounds::new(Point3::new(0f32, 0f32, 0f32), Point3::new(1000f32, 1000f32, 1000f32));
+    let mut prng = rand_chacha::ChaChaRng::seed_from_u64(0);
+    let mut random_bounds = |max_size: f32| {
+        let min = Point3::new(
+            prng.gen_range(0f32, 1000f32 - max_size),
+            prng.gen_range(0f32, 1000f32 - max_size),
+            prng.gen_range(0f32, 1000f32 - max_size));
+        let size = Vector3::new(
+            prng.gen_range(0f32, max_size),
+            prng.gen_range(0f32, max_size),
+            prng.gen_range(0f32, max_size));
+        Bounds::new(min, min + size)
+    };
+    let objects: Vec<_> = (0..object_count).map(|id| (random_bounds(10f32), id)).collect();
+    let tests: Vec<_> = (0..TEST_COUNT).map(|_| random_bounds(50f32)).collect();
+
+    println!("{} objects, {} box tests, mean of {} rounds (ms)", object_count, TEST_COUNT, ROUNDS);
+    println!("{:<20} {:>10} {:>10} {:>10} {:>10} {:>12} {:>10}",
+        "layout", "extend", "sort", "scan", "test_box", "collisions", "hits");
+    let depth_low = run::<Index64_3D>(system_bounds, &objects, &tests);
+    let depth_high = run::<Index64_3DDepthHigh>(system_bounds, &objects, &tests);
+    report("Index64_3D", &depth_low);
+    report("Index64_3DDepthHigh", &depth_high);
+
+    assert_eq!(depth_low.collisions, depth_high.collisions);
+    assert_eq!(depth_low.hits, depth_high.hits);
+}
@@ -0,0 +1,79 @@
+// mlodato, 2020
+
+use crate::index::SpatialIndex;
+
+#[cfg(feature="bevy_reflect")]
+use bevy_reflect::Reflect;
+
+/// [`Layer::analysis`]: struct.Layer.html#method.analysis
+/// A summary of how entries are distributed within a `Layer`, see [`Layer::analysis`]
+/// 
+/// Pathological clustering (e.g. most entries in a few cells, or most entries at `min_depth`) can
+/// silently destroy `scan` performance; this is intended to help detect it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature="bevy_reflect", derive(Reflect))]
+pub struct LayerAnalysis<Index>
+where
+    Index: SpatialIndex
+{
+    /// The total number of entries (index-ID pairs)
+    pub entries: usize,
+
+    /// The number of entries at each depth, indexed by depth
+    pub depth_histogram: Vec<usize>,
+
+    /// The depth at which cells were counted
+    pub cell_depth: u32,
+
+    /// The total number of occupied cells at `cell_depth`
+    pub occupied_cells: usize,
+
+    /// The most heavily-occupied cells, with their entry counts, in descending order of count
+    /// 
+    /// Entries shallower than `cell_depth` are counted in their own (larger) cell
+    pub hot_cells: Vec<(Index, usize)>,
+
+    /// The most entries belonging to any single object
+    pub max_object_entries: usize,
+
+    /// The number of objects with more entries than `LayerBuilder::with_entry_warning_threshold`, or
+    /// zero if no threshold is set
+    pub heavy_objects: usize,
+}
+
+/// [`Layer::memory_usage`]: struct.Layer.html#method.memory_usage
+/// Heap memory held by a `Layer`, in bytes, see [`Layer::memory_usage`]
+/// 
+/// Sizes are computed from allocated capacity rather than length, since capacity is what a `Layer`
+/// retains between frames.  Hash map sizes are estimates.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature="bevy_reflect", derive(Reflect))]
+pub struct MemoryReport {
+    /// Committed entries
+    pub tree: usize,
+
+    /// Staged (uncommitted) entries, IDs, and bounds
+    pub staging: usize,
+
+    /// Committed unbounded and clipped object lists, and stored bounds
+    pub objects: usize,
+
+    /// Persistent per-cell scan state (see `LayerBuilder::with_hot_cell_sap`)
+    pub hot_cells: usize,
+
+    /// Buffers used within a single query
+    pub scratch: usize,
+
+    /// Per-thread pair buffers used by `par_scan`
+    pub thread_local: usize,
+
+    /// Pairs retained from the last scan
+    pub pair_cache: usize,
+}
+
+impl MemoryReport {
+    /// The sum of all categories
+    pub fn total(&self) -> usize {
+        self.tree + self.staging + self.objects + self.hot_cells + self.scratch + self.thread_local + self.pair_cache
+    }
+}
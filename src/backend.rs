@@ -0,0 +1,119 @@
+// mlodato, 2020
+
+use crate::geom::{Bounds, IndexGenerator, RayTestGeometry, SystemBounds, TestGeometry, VecDim};
+use crate::index::SpatialIndex;
+use crate::layer::Layer;
+use crate::traits::ObjectID;
+
+use cgmath::prelude::*;
+
+use std::fmt::Debug;
+
+/// [`Layer`]: struct.Layer.html
+/// A common interface for broadphase collision detection backends
+/// 
+/// [`Layer`] is the primary implementation.  Downstream code may be written against this trait, rather
+/// than a concrete type, so that alternative backends can be swapped in
+pub trait Broadphase<Point_>
+where
+    Point_: EuclideanSpace<Scalar = f32>
+{
+    type ID: ObjectID;
+
+    /// Remove all objects
+    fn clear(&mut self);
+
+    /// Append multiple objects
+    fn extend<Iter>(&mut self, system_bounds: Bounds<Point_>, objects: Iter)
+    where
+        Iter: Iterator<Item = (Bounds<Point_>, Self::ID)>;
+
+    /// Replace all objects
+    /// 
+    /// By default, this is equivalent to `clear` followed by `extend`; backends which retain state between
+    /// frames may implement this more efficiently
+    fn update<Iter>(&mut self, system_bounds: Bounds<Point_>, objects: Iter)
+    where
+        Iter: Iterator<Item = (Bounds<Point_>, Self::ID)>
+    {
+        self.clear();
+        self.extend(system_bounds, objects);
+    }
+
+    /// Detect potential collisions between all objects
+    fn scan(&mut self) -> &Vec<(Self::ID, Self::ID)>;
+
+    /// Find all objects potentially intersecting a ray
+    fn test_ray(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        origin   : Point_,
+        direction: Point_::Diff,
+        range_min: f32,
+        range_max: f32) -> &Vec<Self::ID>;
+
+    /// Find the nearest object along a ray, as determined by `get_dist`
+    /// 
+    /// If multiple objects are at exactly the nearest distance, the one with the lowest ID is returned
+    fn pick_ray<GetDist>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        origin   : Point_,
+        direction: Point_::Diff,
+        max_dist: f32,
+        get_dist: GetDist) -> Option<(f32, Self::ID, Point_)>
+    where
+        GetDist: FnMut(&Point_, &Point_::Diff, f32, Self::ID) -> f32;
+}
+
+impl<Index, ID, Point_> Broadphase<Point_> for Layer<Index, ID>
+where
+    Index: SpatialIndex,
+    ID: ObjectID,
+    Bounds<Index::Point>: IndexGenerator<Index>,
+    Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+    Point_::Diff: VectorSpace<Scalar = f32> + ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+    Bounds<Point_>: SystemBounds<Point_, Index::Point>,
+    RayTestGeometry<Point_>: TestGeometry
+{
+    type ID = ID;
+
+    fn clear(&mut self) {
+        Layer::clear(self);
+    }
+
+    fn extend<Iter>(&mut self, system_bounds: Bounds<Point_>, objects: Iter)
+    where
+        Iter: Iterator<Item = (Bounds<Point_>, ID)>
+    {
+        Layer::extend(self, system_bounds, objects);
+    }
+
+    fn scan(&mut self) -> &Vec<(ID, ID)> {
+        Layer::scan(self)
+    }
+
+    fn test_ray(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        origin   : Point_,
+        direction: Point_::Diff,
+        range_min: f32,
+        range_max: f32) -> &Vec<ID>
+    {
+        Layer::test_ray(self, system_bounds, origin, direction, range_min, range_max, None)
+    }
+
+    fn pick_ray<GetDist>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        origin   : Point_,
+        direction: Point_::Diff,
+        max_dist: f32,
+        get_dist: GetDist) -> Option<(f32, ID, Point_)>
+    where
+        GetDist: FnMut(&Point_, &Point_::Diff, f32, ID) -> f32
+    {
+        Layer::pick_ray(self, system_bounds, origin, direction, max_dist, None, get_dist)
+    }
+}
@@ -0,0 +1,156 @@
+// mlodato, 2020
+
+//! Offline baking of static layers
+//! 
+//! [`bake_static_layer`]: fn.bake_static_layer.html
+//! [`load_static_layer`]: fn.load_static_layer.html
+//! 
+//! [`bake_static_layer`] does all of the work of building a `FrozenLayer` for static geometry up front
+//! (computing indices, sorting, and precomputing cell ranges), and serializes the result compactly.  It is
+//! intended to run in an asset pipeline, so that at startup the result only needs to be loaded with
+//! [`load_static_layer`].
+
+use crate::frozen::FrozenLayer;
+use crate::geom::{Bounds, IndexGenerator, SystemBounds};
+use crate::index::SpatialIndex;
+use crate::layer::{LayerBuilder, OutOfBoundsPolicy};
+use crate::traits::ObjectID;
+
+use bincode::Options;
+use cgmath::prelude::*;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+const MAGIC: [u8; 4] = *b"BPBK";
+const VERSION: u32 = 1;
+
+/// The header at the start of each baked layer
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+struct Header {
+    magic: [u8; 4],
+    version: u32,
+}
+
+/// Integers are variable-length encoded, which substantially reduces the size of small IDs and depths
+fn options() -> impl Options {
+    bincode::options()
+        .with_varint_encoding()
+        .with_little_endian()
+}
+
+/// [`bake_static_layer`]: fn.bake_static_layer.html
+/// Settings for [`bake_static_layer`]
+#[derive(Clone, Copy, Debug)]
+pub struct BakeConfig<Point_> {
+    /// The system bounds, which should match those used to query the layer at runtime
+    pub system_bounds: Bounds<Point_>,
+
+    /// See `LayerBuilder::with_min_depth`
+    pub min_depth: u32,
+
+    /// The depth up to which cell ranges are precomputed, see `Layer::freeze_with_ranges`
+    /// 
+    /// Deeper ranges make queries faster, at the cost of a larger baked layer
+    pub range_depth: Option<u32>,
+
+    /// See `LayerBuilder::with_out_of_bounds_policy`
+    pub out_of_bounds: OutOfBoundsPolicy,
+}
+
+impl<Point_> BakeConfig<Point_> {
+    /// Default settings: no minimum depth, ranges precomputed to depth 8, and out-of-bounds objects
+    /// rejected
+    pub fn new(system_bounds: Bounds<Point_>) -> Self {
+        Self{
+            system_bounds,
+            min_depth: 0,
+            range_depth: Some(8),
+            out_of_bounds: OutOfBoundsPolicy::Reject}
+    }
+}
+
+/// [`load_static_layer`]: fn.load_static_layer.html
+/// Build, sort, and freeze a layer containing `objects`, and serialize it for [`load_static_layer`]
+pub fn bake_static_layer<Index, ID, Point_, Iter>(objects: Iter, config: &BakeConfig<Point_>) -> Vec<u8>
+where
+    Index: SpatialIndex + Serialize,
+    ID: ObjectID + Serialize,
+    Bounds<Index::Point>: IndexGenerator<Index>,
+    Iter: IntoIterator<Item = (Bounds<Point_>, ID)>,
+    Point_: EuclideanSpace<Scalar = f32>,
+    Point_::Diff: ElementWise,
+    Bounds<Point_>: SystemBounds<Point_, Index::Point>
+{
+    let mut layer = LayerBuilder::new()
+        .with_min_depth(config.min_depth)
+        .with_out_of_bounds_policy(config.out_of_bounds)
+        .build();
+    layer.extend(config.system_bounds, objects.into_iter());
+    let frozen: FrozenLayer<Index, ID> = match config.range_depth {
+        Some(range_depth) => layer.freeze_with_ranges(range_depth),
+        None => layer.freeze()
+    };
+
+    let header = Header{
+        magic: MAGIC,
+        version: VERSION};
+    let mut bytes = options().serialize(&header).expect("failed to serialize header");
+    options().serialize_into(&mut bytes, &frozen).expect("failed to serialize layer");
+    bytes
+}
+
+/// [`bake_static_layer`]: fn.bake_static_layer.html
+/// Load a layer baked by [`bake_static_layer`]
+pub fn load_static_layer<Index, ID>(mut bytes: &[u8]) -> bincode::Result<FrozenLayer<Index, ID>>
+where
+    Index: SpatialIndex + DeserializeOwned,
+    ID: ObjectID + Default + DeserializeOwned,
+    Bounds<Index::Point>: IndexGenerator<Index>
+{
+    let header: Header = options().deserialize_from(&mut bytes)?;
+    if header.magic != MAGIC || header.version != VERSION {
+        return Err(Box::new(bincode::ErrorKind::Custom("unrecognized baked layer format".to_string())));
+    }
+    options().deserialize(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index64_3D;
+
+    use cgmath::Point3;
+
+    #[test]
+    fn bake_and_load() {
+        let system_bounds = Bounds{
+            min: Point3::new(0f32, 0f32, 0f32),
+            max: Point3::new(64f32, 64f32, 64f32)};
+        let objects: Vec<_> = (0..64u32)
+            .map(|id| {
+                let x = (id % 16) as f32 * 3.5f32 + 0.5f32;
+                let y = (id / 16) as f32 * 7.5f32 + 0.5f32;
+                (Bounds{min: Point3::new(x, y, x), max: Point3::new(x + 5f32, y + 1f32, x + 1f32)}, id)
+            })
+            .collect();
+
+        let config = BakeConfig::new(system_bounds);
+        let bytes = bake_static_layer::<Index64_3D, u32, _, _>(objects.iter().cloned(), &config);
+        let mut baked: FrozenLayer<Index64_3D, u32> = load_static_layer(&bytes).unwrap();
+
+        let mut layer = LayerBuilder::new().build();
+        layer.extend(system_bounds, objects.into_iter());
+        let mut frozen: FrozenLayer<Index64_3D, u32> = layer.freeze_with_ranges(8);
+        assert!(baked == frozen);
+        assert!(bytes.len() < bincode::serialize(&frozen).unwrap().len());
+
+        let test_bounds = Bounds{
+            min: Point3::new(0f32, 0f32, 0f32),
+            max: Point3::new(16f32, 16f32, 16f32)};
+        assert_eq!(
+            baked.test_box(system_bounds, test_bounds, None),
+            frozen.test_box(system_bounds, test_bounds, None));
+
+        assert!(load_static_layer::<Index64_3D, u32>(&bytes[1..]).is_err());
+    }
+}
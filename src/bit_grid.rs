@@ -0,0 +1,211 @@
+// mlodato, 2020
+
+use crate::geom::{Bounds, IndexGenerator};
+use crate::index::SpatialIndex;
+use crate::layer::Layer;
+use crate::traits::ObjectID;
+
+use cgmath::prelude::*;
+
+/// [`Layer::occupied_cells`]: struct.Layer.html#method.occupied_cells
+/// A dense grid of bits, one per cell at a fixed depth, e.g. as produced by [`Layer::occupied_cells`]
+///
+/// The grid has `side()` cells along each axis, in local (system bounds) space, so that cell `c` along
+/// an axis covers `[c, c + 1) / side()` of the system bounds.  Bits are packed into words with the
+/// first axis varying fastest.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BitGrid {
+    dims: usize,
+    depth: u32,
+    words: Vec<u64>,
+}
+
+impl BitGrid {
+    /// Create an empty grid with `dims` axes and `2^depth` cells along each
+    ///
+    /// Panics if the grid would have more than `2^32` cells.
+    pub fn new(dims: usize, depth: u32) -> Self {
+        assert!(dims as u32 * depth <= 32, "bit grid would exceed 2^32 cells");
+        let cells = 1u64 << (dims as u32 * depth);
+        Self{
+            dims,
+            depth,
+            words: vec![0; ((cells + 63) / 64) as usize]}
+    }
+
+    /// The number of axes
+    pub fn dims(&self) -> usize {
+        self.dims
+    }
+
+    /// The depth of each cell
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// The number of cells along each axis
+    pub fn side(&self) -> u32 {
+        1 << self.depth
+    }
+
+    /// The packed bits, with the first axis varying fastest
+    pub fn words(&self) -> &[u64] {
+        &self.words
+    }
+
+    fn offset(&self, cell: &[u32]) -> usize {
+        assert_eq!(cell.len(), self.dims, "cell has the wrong number of axes");
+        cell.iter().rev().fold(0usize, |offset, &coord| {
+            assert!(coord < self.side(), "cell is outside of the grid");
+            (offset << self.depth) | coord as usize
+        })
+    }
+
+    /// Whether the bit for `cell` is set
+    pub fn get(&self, cell: &[u32]) -> bool {
+        let offset = self.offset(cell);
+        self.words[offset / 64] & (1 << (offset % 64)) != 0
+    }
+
+    /// Set or clear the bit for `cell`
+    pub fn set(&mut self, cell: &[u32], value: bool) {
+        let offset = self.offset(cell);
+        if value {
+            self.words[offset / 64] |= 1 << (offset % 64);
+        } else {
+            self.words[offset / 64] &= !(1 << (offset % 64));
+        }
+    }
+
+    /// The number of set bits
+    pub fn count_ones(&self) -> usize {
+        self.words.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    /// Clear all bits
+    pub fn clear(&mut self) {
+        for word in &mut self.words {
+            *word = 0;
+        }
+    }
+
+    /// Set the bits for all cells in `[min, max]`, inclusive
+    fn fill(&mut self, min: &[u32], max: &[u32]) {
+        let mut cell = min.to_vec();
+        loop {
+            self.set(&cell, true);
+            let mut axis = 0;
+            loop {
+                if axis == self.dims {
+                    return;
+                }
+                if cell[axis] < max[axis] {
+                    cell[axis] += 1;
+                    break;
+                }
+                cell[axis] = min[axis];
+                axis += 1;
+            }
+        }
+    }
+}
+
+impl<Index, ID> Layer<Index, ID>
+where
+    Index: SpatialIndex,
+    ID: ObjectID,
+    Bounds<Index::Point>: IndexGenerator<Index>
+{
+    /// Rasterize the occupied regions of this `Layer` into a `BitGrid` with `2^depth` cells per axis
+    ///
+    /// A cell is occupied if it overlaps any entry, so this is conservative, like other queries: it is
+    /// intended as a cheap occupancy view for navigation or spawning, not for exact tests.  Where
+    /// bounds are stored, entries are clipped to the bounds of their objects.  Inactive objects are
+    /// skipped if excluded from queries, and unbounded objects are never included.
+    ///
+    /// Panics if the grid would have more than `2^32` cells.
+    pub fn occupied_cells(&mut self, depth: u32) -> BitGrid {
+        self.commit();
+
+        let dims = Index::Point::len();
+        let mut grid = BitGrid::new(dims, depth);
+        let to_cell = |coord: u32| (u64::from(coord) >> (32 - depth)) as u32;
+        let mut min = vec![0u32; dims];
+        let mut max = vec![0u32; dims];
+        for &(index, id) in self.sorted_tree() {
+            if !self.is_queryable(id) {
+                continue;
+            }
+            let origin = index.origin();
+            let extent = (1u64 << (32 - index.depth())) - 1;
+            let clip = self.stored_bounds(id);
+            for axis in 0..dims {
+                let mut lower = origin[axis];
+                let mut upper = (u64::from(origin[axis]) + extent) as u32;
+                if let Some(clip) = clip {
+                    lower = lower.max(clip.min[axis]);
+                    upper = upper.min(clip.max[axis]);
+                }
+                min[axis] = to_cell(lower);
+                max[axis] = to_cell(upper.max(lower));
+            }
+            grid.fill(&min, &max);
+        }
+        grid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BitGrid;
+    use crate::geom::Bounds;
+    use crate::index::Index64_2D;
+    use crate::layer::{Layer, LayerBuilder};
+
+    use cgmath::Point2;
+
+    #[test]
+    fn occupied_cells() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let objects = vec![
+            (Bounds{min: Point2::new(1f32, 1f32), max: Point2::new(2.5f32, 2.5f32)}, 0),
+            (Bounds{min: Point2::new(9f32, 5f32), max: Point2::new(11f32, 5.5f32)}, 1),
+            (Bounds{min: Point2::new(12.5f32, 14f32), max: Point2::new(12.75f32, 14.25f32)}, 2)];
+
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_stored_bounds()
+            .build();
+        layer.extend(system_bounds, objects.iter().cloned());
+        layer.extend_unbounded(std::iter::once(100));
+
+        // 4x4 cells, each 4 units across
+        let grid = layer.occupied_cells(2);
+        assert_eq!((grid.dims(), grid.depth(), grid.side()), (2, 2, 4));
+        let mut expected = BitGrid::new(2, 2);
+        expected.set(&[0, 0], true);
+        expected.set(&[2, 1], true);
+        expected.set(&[3, 3], true);
+        assert_eq!(grid, expected);
+        assert_eq!(grid.count_ones(), 3);
+
+        // without stored bounds, entries may cover more cells, but never fewer
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, objects.iter().cloned());
+        let coarse = layer.occupied_cells(3);
+        for (bounds, _) in &objects {
+            for x in (bounds.min.x / 2f32) as u32..=(bounds.max.x / 2f32) as u32 {
+                for y in (bounds.min.y / 2f32) as u32..=(bounds.max.y / 2f32) as u32 {
+                    assert!(coarse.get(&[x, y]));
+                }
+            }
+        }
+        assert!(!coarse.get(&[7, 0]));
+
+        let mut cleared = coarse.clone();
+        cleared.clear();
+        assert_eq!(cleared.count_ones(), 0);
+        assert_eq!(cleared.words().len(), 1);
+    }
+}
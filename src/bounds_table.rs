@@ -0,0 +1,180 @@
+// mlodato, 2020
+
+use crate::geom::Bounds;
+use crate::traits::ObjectID;
+
+use cgmath::prelude::*;
+use rustc_hash::FxHashMap;
+
+use std::mem::size_of;
+
+/// Local bounds with the upper 16 bits of each coordinate, for up to three dimensions
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct QuantizedBounds {
+    min: [u16; 3],
+    max: [u16; 3],
+}
+
+impl QuantizedBounds {
+    /// Quantize local bounds, rounding outward
+    fn new<Point>(bounds: Bounds<Point>) -> Self
+    where
+        Point: EuclideanSpace<Scalar = u32>
+    {
+        assert!(Point::len() <= 3, "quantized bounds support at most three dimensions");
+        let mut quantized = Self::default();
+        for i in 0..Point::len() {
+            quantized.min[i] = (bounds.min[i] >> 16) as u16;
+            quantized.max[i] = (bounds.max[i] >> 16) as u16;
+        }
+        quantized
+    }
+
+    /// Local bounds containing all bounds which quantize to these
+    fn bounds<Point>(self) -> Bounds<Point>
+    where
+        Point: EuclideanSpace<Scalar = u32>
+    {
+        let mut bounds = Bounds::new(Point::origin(), Point::origin());
+        for i in 0..Point::len() {
+            bounds.min[i] = u32::from(self.min[i]) << 16;
+            bounds.max[i] = u32::from(self.max[i]) << 16 | 0xffff;
+        }
+        bounds
+    }
+}
+
+/// Per-object local bounds, stored in full or quantized to 16 bits per axis
+/// 
+/// Quantized bounds are expanded to the nearest 1/65536th of the system bounds when retrieved, so any
+/// overlap of the original bounds is also an overlap of the retrieved bounds.
+#[derive(Clone, Debug)]
+pub(crate) enum BoundsTable<Point, ID> {
+    Full(FxHashMap<ID, Bounds<Point>>),
+    Quantized(FxHashMap<ID, QuantizedBounds>),
+}
+
+impl<Point, ID> BoundsTable<Point, ID>
+where
+    Point: EuclideanSpace<Scalar = u32>,
+    ID: ObjectID
+{
+    pub(crate) fn new(quantized: bool) -> Self {
+        if quantized {
+            BoundsTable::Quantized(FxHashMap::default())
+        } else {
+            BoundsTable::Full(FxHashMap::default())
+        }
+    }
+
+    pub(crate) fn is_quantized(&self) -> bool {
+        matches!(self, BoundsTable::Quantized(_))
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            BoundsTable::Full(table) => table.len(),
+            BoundsTable::Quantized(table) => table.len(),
+        }
+    }
+
+    pub(crate) fn contains(&self, id: ID) -> bool {
+        match self {
+            BoundsTable::Full(table) => table.contains_key(&id),
+            BoundsTable::Quantized(table) => table.contains_key(&id),
+        }
+    }
+
+    pub(crate) fn get(&self, id: ID) -> Option<Bounds<Point>> {
+        match self {
+            BoundsTable::Full(table) => table.get(&id).cloned(),
+            BoundsTable::Quantized(table) => table.get(&id).map(|&bounds| bounds.bounds()),
+        }
+    }
+
+    /// Add bounds for `id`, taking the union with any already present
+    pub(crate) fn insert(&mut self, id: ID, bounds: Bounds<Point>) {
+        match self {
+            BoundsTable::Full(table) => {
+                table.entry(id)
+                    .and_modify(|lhs| *lhs = lhs.union(bounds))
+                    .or_insert(bounds);
+            },
+            BoundsTable::Quantized(table) => {
+                let bounds = QuantizedBounds::new(bounds);
+                table.entry(id)
+                    .and_modify(|lhs| for i in 0..3 {
+                        lhs.min[i] = lhs.min[i].min(bounds.min[i]);
+                        lhs.max[i] = lhs.max[i].max(bounds.max[i]);
+                    })
+                    .or_insert(bounds);
+            }
+        }
+    }
+
+    /// Set the bounds for `id`, discarding any already present
+    pub(crate) fn replace(&mut self, id: ID, bounds: Bounds<Point>) {
+        match self {
+            BoundsTable::Full(table) => { table.insert(id, bounds); },
+            BoundsTable::Quantized(table) => { table.insert(id, QuantizedBounds::new(bounds)); }
+        }
+    }
+
+    pub(crate) fn remove(&mut self, id: ID) {
+        match self {
+            BoundsTable::Full(table) => { table.remove(&id); },
+            BoundsTable::Quantized(table) => { table.remove(&id); }
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        match self {
+            BoundsTable::Full(table) => table.clear(),
+            BoundsTable::Quantized(table) => table.clear(),
+        }
+    }
+
+    pub(crate) fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (ID, Bounds<Point>)> + 'a> {
+        match self {
+            BoundsTable::Full(table) => Box::new(table.iter()
+                .map(|(&id, &bounds)| (id, bounds))),
+            BoundsTable::Quantized(table) => Box::new(table.iter()
+                .map(|(&id, &bounds)| (id, bounds.bounds()))),
+        }
+    }
+
+    /// Estimated allocated size, in bytes (one control byte per bucket)
+    pub(crate) fn memory_usage(&self) -> usize {
+        match self {
+            BoundsTable::Full(table) => table.capacity() * (size_of::<(ID, Bounds<Point>)>() + 1),
+            BoundsTable::Quantized(table) => table.capacity() * (size_of::<(ID, QuantizedBounds)>() + 1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BoundsTable;
+    use crate::geom::Bounds;
+
+    use cgmath::Point3;
+
+    #[test]
+    fn quantized_bounds_are_conservative() {
+        let mut table: BoundsTable<Point3<u32>, u32> = BoundsTable::new(true);
+        let a = Bounds::new(Point3::new(0x1234_5678, 0x0000_ffff, 0), Point3::new(0x1234_5679, 0x0001_0000, 0xffff_ffff));
+        let b = Bounds::new(Point3::new(0x1234_567a, 0x0001_0000, 7), Point3::new(0x2000_0000, 0x0001_0001, 8));
+        table.insert(0, a);
+        table.insert(0, b);
+
+        let stored = table.get(0).unwrap();
+        let union = a.union(b);
+        for i in 0..3 {
+            assert!(stored.min[i] <= union.min[i]);
+            assert!(stored.max[i] >= union.max[i]);
+            assert!(union.min[i] - stored.min[i] <= 0xffff);
+            assert!(stored.max[i] - union.max[i] <= 0xffff);
+        }
+        assert_eq!(table.get(1), None);
+    }
+}
@@ -0,0 +1,301 @@
+// mlodato, 2020
+
+use crate::geom::{Bounds, IndexGenerator};
+use crate::index::SpatialIndex;
+use crate::layer::Layer;
+use crate::traits::ObjectID;
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::marker::PhantomData;
+
+/// [`CompressedPairs`]: struct.CompressedPairs.html
+/// Object IDs which may be losslessly converted to and from `u64`, as required by [`CompressedPairs`]
+pub trait PackedID: ObjectID {
+    fn to_u64(self) -> u64;
+    fn from_u64(value: u64) -> Self;
+}
+
+macro_rules! packed_id_impl {
+    ($($t:ty),*) => {
+        $(
+            impl PackedID for $t {
+                fn to_u64(self) -> u64 {
+                    self as u64
+                }
+
+                fn from_u64(value: u64) -> Self {
+                    value as $t
+                }
+            }
+        )*
+    };
+}
+
+packed_id_impl!(u8, u16, u32, u64, usize);
+
+/// [`Layer::scan_compressed`]: struct.Layer.html#method.scan_compressed
+/// A sorted list of collision pairs, delta- and varint-encoded
+/// 
+/// Consecutive sorted pairs usually share their first ID, or differ by a small amount, so most pairs
+/// take only a few bytes.  Pairs must be pushed in strictly increasing order.  See
+/// [`Layer::scan_compressed`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompressedPairs<ID> {
+    bytes: Vec<u8>,
+    len: usize,
+    last: Option<(u64, u64)>,
+    _id: PhantomData<ID>,
+}
+
+impl<ID> Default for CompressedPairs<ID> {
+    fn default() -> Self {
+        Self{
+            bytes: Vec::new(),
+            len: 0,
+            last: None,
+            _id: PhantomData}
+    }
+}
+
+impl<ID> CompressedPairs<ID>
+where
+    ID: PackedID
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of pairs
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The size of the encoded pairs, in bytes
+    pub fn encoded_len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Append a pair, which must be greater than the last pair appended
+    pub fn push(&mut self, pair: (ID, ID)) {
+        let (a, b) = (pair.0.to_u64(), pair.1.to_u64());
+        let (last_a, last_b) = self.last.unwrap_or((0, 0));
+        assert!(!matches!(self.last, Some(last) if last >= (a, b)), "pairs must be pushed in strictly increasing order");
+
+        if a == last_a {
+            write_varint(&mut self.bytes, 0);
+            write_varint(&mut self.bytes, b - last_b);
+        } else {
+            write_varint(&mut self.bytes, a - last_a);
+            write_varint(&mut self.bytes, b);
+        }
+
+        self.last = Some((a, b));
+        self.len += 1;
+    }
+
+    /// Decode pairs, in order
+    pub fn iter(&self) -> CompressedPairsIter<'_, ID> {
+        CompressedPairsIter{
+            bytes: &self.bytes,
+            last: (0, 0),
+            _id: PhantomData}
+    }
+
+    /// Remove all pairs, retaining the allocated buffer
+    pub fn clear(&mut self) {
+        self.bytes.clear();
+        self.len = 0;
+        self.last = None;
+    }
+}
+
+impl<'a, ID> IntoIterator for &'a CompressedPairs<ID>
+where
+    ID: PackedID
+{
+    type Item = (ID, ID);
+    type IntoIter = CompressedPairsIter<'a, ID>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// [`CompressedPairs`]: struct.CompressedPairs.html
+/// An iterator decoding [`CompressedPairs`]
+pub struct CompressedPairsIter<'a, ID> {
+    bytes: &'a [u8],
+    last: (u64, u64),
+    _id: PhantomData<ID>,
+}
+
+impl<'a, ID> Iterator for CompressedPairsIter<'a, ID>
+where
+    ID: PackedID
+{
+    type Item = (ID, ID);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.bytes.is_empty() {
+            return None;
+        }
+
+        let (last_a, last_b) = self.last;
+        let delta_a = read_varint(&mut self.bytes);
+        let b = read_varint(&mut self.bytes);
+        self.last = if delta_a == 0 {
+            (last_a, last_b + b)
+        } else {
+            (last_a + delta_a, b)
+        };
+
+        let (a, b) = self.last;
+        Some((ID::from_u64(a), ID::from_u64(b)))
+    }
+}
+
+/// Sort, deduplicate, and compress `chunk` into a new run, leaving `chunk` empty
+fn flush_chunk<ID>(chunk: &mut Vec<(ID, ID)>, runs: &mut Vec<CompressedPairs<ID>>)
+where
+    ID: PackedID
+{
+    chunk.sort_unstable();
+    chunk.dedup();
+    let mut run = CompressedPairs::new();
+    for &pair in chunk.iter() {
+        run.push(pair);
+    }
+    runs.push(run);
+    chunk.clear();
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn read_varint(bytes: &mut &[u8]) -> u64 {
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let (&byte, tail) = bytes.split_first().expect("truncated pair encoding");
+        *bytes = tail;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+impl<Index, ID> Layer<Index, ID>
+where
+    Index: SpatialIndex,
+    ID: PackedID,
+    Bounds<Index::Point>: IndexGenerator<Index>
+{
+    /// [`scan`]: #method.scan
+    /// Equivalent to [`scan`], returning compressed pairs
+    /// 
+    /// Raw pairs are buffered at most `chunk_size` at a time; each full chunk is sorted, deduplicated,
+    /// and compressed, and the compressed chunks are merged at the end.  This bounds peak memory for
+    /// scans which emit a very large number of pairs.  The `Layer`'s own pair buffer is not used.
+    pub fn scan_compressed(&mut self, chunk_size: usize) -> CompressedPairs<ID> {
+        self.scan_filtered_compressed(chunk_size, |_, _| true)
+    }
+
+    /// [`scan_filtered`]: #method.scan_filtered
+    /// [`scan_compressed`]: #method.scan_compressed
+    /// Equivalent to [`scan_filtered`], returning compressed pairs, see [`scan_compressed`]
+    pub fn scan_filtered_compressed<F>(&mut self, chunk_size: usize, mut filter: F) -> CompressedPairs<ID>
+    where
+        F: FnMut(ID, ID) -> bool
+    {
+        assert!(chunk_size > 0, "chunk_size must be non-zero");
+
+        let mut chunk: Vec<(ID, ID)> = Vec::with_capacity(chunk_size);
+        let mut runs: Vec<CompressedPairs<ID>> = Vec::new();
+
+        // pairs are intercepted by the filter, so the regular pair buffer stays empty
+        self.scan_filtered(|a, b| {
+            if filter(a, b) {
+                chunk.push((a, b));
+                if chunk.len() >= chunk_size {
+                    flush_chunk(&mut chunk, &mut runs);
+                }
+            }
+            false
+        });
+        if !chunk.is_empty() || runs.is_empty() {
+            flush_chunk(&mut chunk, &mut runs);
+        }
+
+        if runs.len() == 1 {
+            return runs.pop().unwrap();
+        }
+
+        // k-way merge of sorted runs, removing duplicates between runs
+        let mut iters: Vec<_> = runs.iter().map(|run| run.iter()).collect();
+        let mut heads: BinaryHeap<Reverse<((ID, ID), usize)>> = iters.iter_mut()
+            .enumerate()
+            .filter_map(|(i, iter)| iter.next().map(|pair| Reverse((pair, i))))
+            .collect();
+        let mut result = CompressedPairs::new();
+        while let Some(Reverse((pair, i))) = heads.pop() {
+            if result.last != Some((pair.0.to_u64(), pair.1.to_u64())) {
+                result.push(pair);
+            }
+            if let Some(next) = iters[i].next() {
+                heads.push(Reverse((next, i)));
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index64_2D;
+    use crate::layer::LayerBuilder;
+
+    use cgmath::Point2;
+
+    #[test]
+    fn varint_round_trip() {
+        let mut pairs: CompressedPairs<u64> = CompressedPairs::new();
+        let expected = vec![(0, 0), (0, 1), (0, 300), (5, 2), (1 << 40, 7), (u64::MAX, u64::MAX)];
+        for &pair in &expected {
+            pairs.push(pair);
+        }
+        assert_eq!(pairs.len(), expected.len());
+        assert_eq!(pairs.iter().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn scan_compressed() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, (0..64u32).map(|id| {
+            let x = (id % 8) as f32 * 1.5f32 + 0.5f32;
+            let y = (id / 8) as f32 * 1.5f32 + 0.5f32;
+            (Bounds{min: Point2::new(x, y), max: Point2::new(x + 2f32, y + 2f32)}, id)
+        }));
+
+        let expected = layer.scan().clone();
+        let compressed = layer.scan_compressed(16);
+        assert!(expected.len() > 16);
+        assert_eq!(compressed.iter().collect::<Vec<_>>(), expected);
+        assert!(compressed.encoded_len() < expected.len() * std::mem::size_of::<(u32, u32)>());
+    }
+}
@@ -0,0 +1,206 @@
+// mlodato, 2020
+
+use crate::geom::{Bounds, IndexGenerator};
+use crate::index::SpatialIndex;
+use crate::layer::Layer;
+use crate::traits::ObjectID;
+
+use rustc_hash::FxHashMap;
+
+/// [`ContactTracker`]: struct.ContactTracker.html
+/// How a pair's contact changed in the last update of a [`ContactTracker`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ContactKind {
+    /// The pair was found for the first time since it was last absent
+    Began,
+    /// The pair was found in both this update and the one before
+    Persisted,
+    /// The pair was found in the update before, but not this one
+    Ended,
+}
+
+/// [`ContactTracker`]: struct.ContactTracker.html
+/// A change in a pair's contact, reported by [`ContactTracker::update`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ContactEvent<ID> {
+    /// The pair of objects, lowest ID first
+    pub pair: (ID, ID),
+    /// The number of consecutive updates in which the pair was found, including this one; for ended
+    /// contacts, the number of updates for which it lasted
+    pub age: u32,
+    /// Whether the contact began, persisted, or ended
+    pub kind: ContactKind,
+}
+
+/// [`Layer::scan_tracked`]: struct.Layer.html#method.scan_tracked
+/// Per-pair contact ages, persisted across frames
+/// 
+/// Each update takes the pairs found in a frame (e.g. by `Layer::scan`, see [`Layer::scan_tracked`])
+/// and reports an event for every pair which began, persisted, or ended, with the number of frames
+/// for which it has overlapped.  This allows e.g. triggers which only fire after ten frames of
+/// overlap, without a separate map keyed by pair.
+/// 
+/// Pairs are unordered; `(a, b)` and `(b, a)` are the same contact.
+#[derive(Clone, Debug)]
+pub struct ContactTracker<ID>
+where
+    ID: ObjectID
+{
+    ages: FxHashMap<(ID, ID), u32>,
+    previous: FxHashMap<(ID, ID), u32>,
+    events: Vec<ContactEvent<ID>>,
+}
+
+impl<ID> Default for ContactTracker<ID>
+where
+    ID: ObjectID
+{
+    fn default() -> Self {
+        Self{
+            ages: FxHashMap::default(),
+            previous: FxHashMap::default(),
+            events: Vec::new()}
+    }
+}
+
+impl<ID> ContactTracker<ID>
+where
+    ID: ObjectID
+{
+    /// Create a tracker with no contacts
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the current contacts with `pairs` (which may contain duplicates), returning events for
+    /// all pairs which began, persisted, or ended, sorted by pair
+    pub fn update<I>(&mut self, pairs: I) -> &[ContactEvent<ID>]
+    where
+        I: IntoIterator<Item = (ID, ID)>
+    {
+        std::mem::swap(&mut self.ages, &mut self.previous);
+        self.ages.clear();
+        self.events.clear();
+
+        for (a, b) in pairs {
+            let pair = (a.min(b), a.max(b));
+            if self.ages.contains_key(&pair) {
+                continue;
+            }
+            let (age, kind) = match self.previous.get(&pair) {
+                Some(age) => (age.saturating_add(1), ContactKind::Persisted),
+                None => (1, ContactKind::Began)
+            };
+            self.ages.insert(pair, age);
+            self.events.push(ContactEvent{pair, age, kind});
+        }
+        for (&pair, &age) in &self.previous {
+            if !self.ages.contains_key(&pair) {
+                self.events.push(ContactEvent{pair, age, kind: ContactKind::Ended});
+            }
+        }
+
+        self.events.sort_unstable_by_key(|event| event.pair);
+        &self.events
+    }
+
+    /// The events of the last update, sorted by pair
+    pub fn events(&self) -> &[ContactEvent<ID>] {
+        &self.events
+    }
+
+    /// The age of a current contact, in updates, or `None` if the pair isn't in contact
+    pub fn age(&self, a: ID, b: ID) -> Option<u32> {
+        self.ages.get(&(a.min(b), a.max(b))).cloned()
+    }
+
+    /// The number of current contacts
+    pub fn len(&self) -> usize {
+        self.ages.len()
+    }
+
+    /// Whether there are no current contacts
+    pub fn is_empty(&self) -> bool {
+        self.ages.is_empty()
+    }
+
+    /// Forget all contacts, without reporting them as ended
+    pub fn clear(&mut self) {
+        self.ages.clear();
+        self.previous.clear();
+        self.events.clear();
+    }
+}
+
+impl<Index, ID> Layer<Index, ID>
+where
+    Index: SpatialIndex,
+    ID: ObjectID,
+    Bounds<Index::Point>: IndexGenerator<Index>
+{
+    /// [`scan`]: #method.scan
+    /// Equivalent to [`scan`], updating `tracker` with the pairs found and returning its events
+    pub fn scan_tracked<'a>(&mut self, tracker: &'a mut ContactTracker<ID>) -> &'a [ContactEvent<ID>] {
+        tracker.update(self.scan().iter().cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ContactEvent, ContactKind, ContactTracker};
+    use crate::geom::Bounds;
+    use crate::index::Index64_2D;
+    use crate::layer::{Layer, LayerBuilder};
+
+    use cgmath::{Point2, Vector2};
+
+    #[test]
+    fn contact_ages() {
+        let mut tracker = ContactTracker::new();
+        let events = tracker.update(vec![(1u32, 0u32), (0, 1), (2, 3)]).to_vec();
+        assert_eq!(events, vec![
+            ContactEvent{pair: (0, 1), age: 1, kind: ContactKind::Began},
+            ContactEvent{pair: (2, 3), age: 1, kind: ContactKind::Began}]);
+
+        tracker.update(vec![(0, 1), (2, 3)]);
+        let events = tracker.update(vec![(0, 1), (4, 2)]).to_vec();
+        assert_eq!(events, vec![
+            ContactEvent{pair: (0, 1), age: 3, kind: ContactKind::Persisted},
+            ContactEvent{pair: (2, 3), age: 2, kind: ContactKind::Ended},
+            ContactEvent{pair: (2, 4), age: 1, kind: ContactKind::Began}]);
+        assert_eq!(tracker.age(1, 0), Some(3));
+        assert_eq!(tracker.age(2, 3), None);
+        assert_eq!(tracker.len(), 2);
+
+        // a contact which ends and begins again starts over
+        tracker.update(vec![(2, 4)]);
+        assert_eq!(tracker.update(vec![(0, 1)]), &[
+            ContactEvent{pair: (0, 1), age: 1, kind: ContactKind::Began},
+            ContactEvent{pair: (2, 4), age: 2, kind: ContactKind::Ended}]);
+    }
+
+    #[test]
+    fn scan_tracked() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let size = Vector2::new(1f32, 1f32);
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        let mut tracker = ContactTracker::new();
+        for (frame, &x) in [4f32, 4.25f32, 4.5f32, 10f32].iter().enumerate() {
+            let moving = Point2::new(x, 4f32);
+            layer.clear();
+            layer.extend(system_bounds, vec![
+                (Bounds{min: Point2::new(4f32, 4f32), max: Point2::new(4f32, 4f32) + size}, 0),
+                (Bounds{min: moving, max: moving + size}, 1)].into_iter());
+            let events = layer.scan_tracked(&mut tracker);
+            let expected = match frame {
+                0 => ContactEvent{pair: (0, 1), age: 1, kind: ContactKind::Began},
+                1 => ContactEvent{pair: (0, 1), age: 2, kind: ContactKind::Persisted},
+                2 => ContactEvent{pair: (0, 1), age: 3, kind: ContactKind::Persisted},
+                _ => ContactEvent{pair: (0, 1), age: 3, kind: ContactKind::Ended},
+            };
+            assert_eq!(events, &[expected]);
+        }
+    }
+}
@@ -0,0 +1,149 @@
+// mlodato, 2020
+
+use crate::geom::Bounds;
+use crate::index::Index64_3D;
+use crate::layer::{Layer, LayerBuilder};
+
+use cgmath::{Point3, Vector3};
+
+/// [`Layer`]: struct.Layer.html
+/// A non-generic 3D [`Layer`], using `Index64_3D` indices and `u64` IDs
+/// 
+/// All methods are concrete, and callbacks are passed as trait objects, so this type is compiled
+/// once within this crate rather than instantiated at every call site.  This is intended for plugin
+/// architectures and scripting bindings; generic code should generally use [`Layer`] directly.
+/// 
+/// The system bounds are fixed when the `DynLayer` is created.
+#[derive(Clone)]
+pub struct DynLayer {
+    system_bounds: Bounds<Point3<f32>>,
+    layer: Layer<Index64_3D, u64>,
+}
+
+impl DynLayer {
+    /// Create an empty `DynLayer` with default settings
+    pub fn new(system_bounds: Bounds<Point3<f32>>) -> Self {
+        Self::with_layer(system_bounds, LayerBuilder::new().build())
+    }
+
+    /// [`LayerBuilder`]: struct.LayerBuilder.html
+    /// Wrap an existing `Layer`, e.g. one configured by a [`LayerBuilder`]
+    pub fn with_layer(system_bounds: Bounds<Point3<f32>>, layer: Layer<Index64_3D, u64>) -> Self {
+        Self{system_bounds, layer}
+    }
+
+    pub fn system_bounds(&self) -> Bounds<Point3<f32>> {
+        self.system_bounds
+    }
+
+    /// The underlying `Layer`
+    pub fn layer(&self) -> &Layer<Index64_3D, u64> {
+        &self.layer
+    }
+
+    /// The underlying `Layer`, for operations not exposed by `DynLayer`
+    pub fn layer_mut(&mut self) -> &mut Layer<Index64_3D, u64> {
+        &mut self.layer
+    }
+
+    /// Return the underlying `Layer`
+    pub fn into_inner(self) -> Layer<Index64_3D, u64> {
+        self.layer
+    }
+
+    /// See `Layer::clear`
+    pub fn clear(&mut self) {
+        self.layer.clear();
+    }
+
+    /// See `Layer::extend`
+    pub fn extend(&mut self, objects: &[(Bounds<Point3<f32>>, u64)]) {
+        self.layer.extend(self.system_bounds, objects.iter().cloned());
+    }
+
+    /// See `Layer::extend_unbounded`
+    pub fn extend_unbounded(&mut self, ids: &[u64]) {
+        self.layer.extend_unbounded(ids.iter().cloned());
+    }
+
+    /// See `Layer::commit`
+    pub fn commit(&mut self) {
+        self.layer.commit();
+    }
+
+    /// See `Layer::scan`
+    pub fn scan(&mut self) -> &[(u64, u64)] {
+        self.layer.scan()
+    }
+
+    /// See `Layer::scan_filtered`
+    pub fn scan_filtered(&mut self, filter: &mut dyn FnMut(u64, u64) -> bool) -> &[(u64, u64)] {
+        self.layer.scan_filtered(filter)
+    }
+
+    /// See `Layer::par_scan`
+    #[cfg(feature="parallel")]
+    pub fn par_scan(&mut self) -> &[(u64, u64)] {
+        self.layer.par_scan()
+    }
+
+    /// See `Layer::test_box`
+    pub fn test_box(&mut self, test_bounds: Bounds<Point3<f32>>, max_depth: Option<u32>) -> &[u64] {
+        self.layer.test_box(self.system_bounds, test_bounds, max_depth)
+    }
+
+    /// See `Layer::test_ray`
+    pub fn test_ray(
+        &mut self,
+        origin   : Point3<f32>,
+        direction: Vector3<f32>,
+        range_min: f32,
+        range_max: f32,
+        max_depth: Option<u32>) -> &[u64]
+    {
+        self.layer.test_ray(self.system_bounds, origin, direction, range_min, range_max, max_depth)
+    }
+
+    /// See `Layer::pick_ray`
+    #[allow(clippy::type_complexity)]
+    pub fn pick_ray(
+        &mut self,
+        origin   : Point3<f32>,
+        direction: Vector3<f32>,
+        max_dist: f32,
+        max_depth: Option<u32>,
+        get_dist: &mut dyn FnMut(&Point3<f32>, &Vector3<f32>, f32, u64) -> f32) -> Option<(f32, u64, Point3<f32>)>
+    {
+        self.layer.pick_ray(self.system_bounds, origin, direction, max_dist, max_depth, get_dist)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dyn_layer() {
+        let mut layer = DynLayer::new(Bounds{
+            min: Point3::new(0f32, 0f32, 0f32),
+            max: Point3::new(64f32, 64f32, 64f32)});
+        let object_bounds = |x: f32| Bounds{
+            min: Point3::new(x, x, x),
+            max: Point3::new(x + 1.5f32, x + 1.5f32, x + 1.5f32)};
+        let objects: Vec<_> = (0..4u64).map(|id| (object_bounds(id as f32 * 8f32 + 0.1f32), id)).collect();
+        layer.extend(&objects);
+        layer.extend(&[(object_bounds(0.5f32), 4)]);
+
+        let mut rejected = 0;
+        assert_eq!(layer.scan_filtered(&mut |_, _| { rejected += 1; false }), &[]);
+        assert_eq!(rejected, 1);
+        assert_eq!(layer.scan().len(), 1);
+
+        let origin = Point3::new(0f32, 0f32, 0f32);
+        let direction = Vector3::new(1f32, 1f32, 1f32);
+        assert_eq!(layer.test_ray(origin, direction, 0f32, 100f32, None), &[0, 1, 2, 3, 4]);
+
+        let picked = layer.pick_ray(origin, direction, 100f32, None, &mut |_, _, _, id| id as f32 * 10f32 + 1f32);
+        assert_eq!(picked.map(|(_, id, _)| id), Some(0));
+    }
+}
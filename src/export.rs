@@ -0,0 +1,108 @@
+// mlodato, 2020
+
+use crate::analysis::LayerAnalysis;
+use crate::geom::{Bounds, IndexGenerator};
+use crate::index::SpatialIndex;
+use crate::layer::Layer;
+use crate::traits::ObjectID;
+
+use cgmath::prelude::*;
+
+use std::io::{self, Write};
+
+const AXES: [&str; 3] = ["x", "y", "z"];
+
+impl<Index, ID> Layer<Index, ID>
+where
+    Index: SpatialIndex,
+    ID: ObjectID,
+    Bounds<Index::Point>: IndexGenerator<Index>
+{
+    /// Write all committed entries as CSV, one row per index-ID pair
+    /// 
+    /// Columns are `depth`, the local (integer) origin of each cell along each axis (`x`, `y`, `z`), and
+    /// `id` (formatted using `Debug`).  Entries are written in their current order.
+    pub fn write_csv<W>(&self, mut writer: W) -> io::Result<()>
+    where
+        W: Write
+    {
+        let dim = Index::Point::len();
+
+        write!(writer, "depth")?;
+        for axis in &AXES[..dim] {
+            write!(writer, ",{}", axis)?;
+        }
+        writeln!(writer, ",id")?;
+
+        for &(index, id) in self.iter() {
+            let origin = index.origin();
+            write!(writer, "{}", index.depth())?;
+            for axis in 0..dim {
+                write!(writer, ",{}", origin[axis])?;
+            }
+            writeln!(writer, ",\"{:?}\"", id)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<Index> LayerAnalysis<Index>
+where
+    Index: SpatialIndex
+{
+    /// Write this analysis as CSV
+    /// 
+    /// Columns are `metric`, `depth`, `key`, and `value`; the `depth` and `key` columns are empty where
+    /// not applicable.  Hot cells are keyed by the local origin of the cell, with axes separated by `;`.
+    pub fn write_csv<W>(&self, mut writer: W) -> io::Result<()>
+    where
+        W: Write
+    {
+        writeln!(writer, "metric,depth,key,value")?;
+        writeln!(writer, "entries,,,{}", self.entries)?;
+        writeln!(writer, "occupied_cells,{},,{}", self.cell_depth, self.occupied_cells)?;
+        for (depth, &count) in self.depth_histogram.iter().enumerate() {
+            writeln!(writer, "depth_histogram,{},,{}", depth, count)?;
+        }
+        for &(cell, count) in &self.hot_cells {
+            let origin = cell.origin();
+            let key: Vec<String> = (0..Index::Point::len())
+                .map(|axis| origin[axis].to_string())
+                .collect();
+            writeln!(writer, "hot_cell,{},{},{}", cell.depth(), key.join(";"), count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index64_2D;
+    use crate::layer::LayerBuilder;
+
+    use cgmath::Point2;
+
+    #[test]
+    fn write_csv() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, std::iter::once((Bounds{
+            min: Point2::new(0.1f32, 0.1f32),
+            max: Point2::new(3.9f32, 3.9f32)}, 7)));
+        layer.commit();
+
+        let mut csv = Vec::new();
+        layer.write_csv(&mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(), "depth,x,y,id\n2,0,0,\"7\"\n");
+
+        let mut csv = Vec::new();
+        layer.analysis(1, 1).write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.starts_with("metric,depth,key,value\nentries,,,1\noccupied_cells,1,,1\n"));
+        assert!(csv.ends_with("hot_cell,1,0;0,1\n"));
+    }
+}
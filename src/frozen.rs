@@ -0,0 +1,493 @@
+// mlodato, 2020
+
+use crate::geom::{
+    Bounds,
+    BoxTestGeometry,
+    CacheKey,
+    IndexGenerator,
+    RayTestGeometry,
+    TestGeometry,
+    VecDim,
+};
+use crate::index::SpatialIndex;
+use crate::layer::{payload_dist, Layer, SplitTree};
+use crate::observer::Progress;
+use crate::traits::ObjectID;
+
+#[cfg(feature="mmap")]
+use crate::mapped::MappedEntries;
+
+use cgmath::prelude::*;
+use rustc_hash::FxHashMap;
+
+use std::collections::hash_map::Entry;
+use std::fmt::Debug;
+
+/// [`Layer`]: struct.Layer.html
+/// [`Layer::freeze`]: struct.Layer.html#method.freeze
+/// A sorted, read-only [`Layer`], intended for static geometry
+/// 
+/// Created by [`Layer::freeze`].  Since the contents of a `FrozenLayer` don't change between frames,
+/// query results may optionally be cached and returned directly when an identical query is repeated
+/// (e.g. render culling from a stationary camera)
+/// 
+/// With the `mmap` feature, entries may instead be backed by a memory-mapped file (see `map_file`),
+/// so that multiple processes may share a single copy.  Memory-mapped entries are not serialized.
+
+#[derive(Clone)]
+#[cfg_attr(any(test, feature="serde"), derive(Deserialize, Serialize))]
+pub struct FrozenLayer<Index, ID>
+where
+    Index: SpatialIndex,
+    ID: ObjectID,
+    Bounds<Index::Point>: IndexGenerator<Index>
+{
+    layer: Layer<Index, ID>,
+    ranges: Option<NodeRanges<Index>>,
+
+    #[cfg_attr(any(test, feature="serde"), serde(skip))]
+    cache: Option<QueryCache<ID>>,
+
+    #[cfg(feature="mmap")]
+    #[cfg_attr(any(test, feature="serde"), serde(skip))]
+    mapped: Option<MappedEntries<Index, ID>>,
+}
+
+/// The range of entries contained by each occupied cell, up to some depth
+#[derive(Clone)]
+#[cfg_attr(any(test, feature="serde"), derive(Deserialize, Serialize))]
+pub(crate) struct NodeRanges<Index>
+where
+    Index: SpatialIndex
+{
+    depth: u32,
+    ranges: FxHashMap<Index, (usize, usize)>,
+}
+
+impl<Index> NodeRanges<Index>
+where
+    Index: SpatialIndex
+{
+    /// `progress` is passed the number of entries as their ranges are completed
+    fn new<ID, P>(tree: &[(Index, ID)], depth: u32, progress: &mut P) -> Self
+    where
+        ID: ObjectID,
+        Bounds<Index::Point>: IndexGenerator<Index>,
+        P: FnMut(usize)
+    {
+        let mut ranges = Self{
+            depth,
+            ranges: FxHashMap::default()};
+        ranges.insert(tree, 0, Index::default(), progress);
+        ranges
+    }
+
+    fn insert<ID, P>(&mut self, tree: &[(Index, ID)], offset: usize, cell: Index, progress: &mut P)
+    where
+        ID: ObjectID,
+        Bounds<Index::Point>: IndexGenerator<Index>,
+        P: FnMut(usize)
+    {
+        if tree.is_empty() {
+            return;
+        }
+
+        self.ranges.insert(cell, (offset, offset + tree.len()));
+
+        if cell.depth() >= self.depth {
+            progress(tree.len());
+            return;
+        }
+
+        if let Some(sub_cells) = cell.subdivide() {
+            let (head, sub_trees) = Layer::<Index, ID>::split_tree(tree, offset, sub_cells.as_ref());
+            progress(head.len());
+            for (&sub_cell, &(sub_tree, sub_offset)) in sub_cells.as_ref().iter().zip(sub_trees.iter()) {
+                self.insert(sub_tree, sub_offset, sub_cell, progress);
+            }
+        } else {
+            progress(tree.len());
+        }
+    }
+
+    pub(crate) fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// Equivalent to `Layer::split_tree`, using precomputed ranges for each sub-cell
+    pub(crate) fn split_tree<'a, ID>(
+        &self,
+        tree: &'a [(Index, ID)],
+        offset: usize,
+        sub_cells: &[Index]) -> SplitTree<'a, Index, ID>
+    {
+        let mut head_end = offset + tree.len();
+        let sub_trees = sub_cells.iter()
+            .map(|cell| match self.ranges.get(cell) {
+                Some(&(start, end)) => {
+                    head_end = head_end.min(start);
+                    (&tree[start - offset..end - offset], start)
+                },
+                None => (&tree[..0], offset)
+            })
+            .collect();
+        (&tree[..head_end - offset], sub_trees)
+    }
+}
+
+#[derive(Clone)]
+struct QueryCache<ID> {
+    capacity: usize,
+    results: FxHashMap<(CacheKey, Option<u32>), Vec<ID>>,
+}
+
+impl<Index, ID> FrozenLayer<Index, ID>
+where
+    Index: SpatialIndex,
+    ID: ObjectID,
+    Bounds<Index::Point>: IndexGenerator<Index>
+{
+    pub(crate) fn new(layer: Layer<Index, ID>, range_depth: Option<u32>) -> Self {
+        Self::with_progress(layer, range_depth, |_| ())
+    }
+
+    /// Equivalent to `new`, reporting the fraction of work completed to `progress`
+    /// 
+    /// Sorting is reported as a single step, covering the first half of the work if ranges are
+    /// computed, and all of it otherwise.
+    pub(crate) fn with_progress<P>(mut layer: Layer<Index, ID>, range_depth: Option<u32>, mut progress: P) -> Self
+    where
+        P: FnMut(f32)
+    {
+        progress(0f32);
+        layer.commit();
+        let ranges = range_depth.map(|depth| {
+            let tree = layer.iter().as_slice();
+            let mut progress = Progress::new(|fraction| progress(0.5f32 + 0.5f32 * fraction), tree.len());
+            let ranges = NodeRanges::new(tree, depth, &mut |entries| progress.advance(entries));
+            progress.finish();
+            ranges
+        });
+        if ranges.is_none() {
+            progress(1f32);
+        }
+        Self{
+            layer,
+            ranges,
+            cache: None,
+            #[cfg(feature="mmap")]
+            mapped: None}
+    }
+
+    /// Create a `FrozenLayer` querying memory-mapped `entries` in place of those of `layer`
+    #[cfg(feature="mmap")]
+    pub(crate) fn with_mapped(layer: Layer<Index, ID>, range_depth: Option<u32>, mapped: MappedEntries<Index, ID>) -> Self {
+        let ranges = range_depth.map(|depth| NodeRanges::new(mapped.entries(), depth, &mut |_| ()));
+        Self{
+            layer,
+            ranges,
+            cache: None,
+            mapped: Some(mapped)}
+    }
+
+    /// Memory-mapped entries, if any, which are queried in place of those of the underlying `Layer`
+    fn mapped_entries(&self) -> Option<&[(Index, ID)]> {
+        #[cfg(feature="mmap")]
+        {
+            self.mapped.as_ref().map(MappedEntries::entries)
+        }
+        #[cfg(not(feature="mmap"))]
+        {
+            None
+        }
+    }
+
+    /// Copy any memory-mapped entries into the underlying `Layer`, releasing the mapping
+    fn unmap(&mut self) {
+        #[cfg(feature="mmap")]
+        {
+            if let Some(mapped) = self.mapped.take() {
+                self.layer.reset_committed(self.layer.min_depth()).extend_from_slice(mapped.entries());
+            }
+        }
+    }
+
+    /// Return the underlying `Layer` for further modification
+    /// 
+    /// Memory-mapped entries are copied into the returned `Layer`
+    pub fn thaw(mut self) -> Layer<Index, ID> {
+        self.unmap();
+        self.layer
+    }
+
+    /// The underlying (sorted) `Layer`
+    /// 
+    /// If entries are memory-mapped, they are not included in this `Layer`
+    pub fn layer(&self) -> &Layer<Index, ID> {
+        &self.layer
+    }
+
+    /// Whether entries are backed by a memory-mapped file
+    pub fn is_mapped(&self) -> bool {
+        self.mapped_entries().is_some()
+    }
+
+    /// Iterate over all indices in the `FrozenLayer`
+    pub fn iter(&self) -> std::slice::Iter<'_, (Index, ID)> {
+        self.entries().iter()
+    }
+
+    /// All (sorted) entries, whether memory-mapped or not
+    pub(crate) fn entries(&self) -> &[(Index, ID)] {
+        self.mapped_entries().unwrap_or_else(|| self.layer.iter().as_slice())
+    }
+
+    /// [`TestGeometry::cache_key`]: trait.TestGeometry.html#method.cache_key
+    /// Enable caching of query results
+    /// 
+    /// Results are keyed on [`TestGeometry::cache_key`] and `max_depth`.  When more than `capacity`
+    /// distinct queries have been cached, the cache is cleared.
+    pub fn enable_query_cache(&mut self, capacity: usize) {
+        self.cache = Some(QueryCache{
+            capacity,
+            results: FxHashMap::default()});
+    }
+
+    /// Disable caching of query results, discarding any cached results
+    pub fn disable_query_cache(&mut self) {
+        self.cache = None;
+    }
+
+    /// Discard any cached query results
+    pub fn clear_query_cache(&mut self) {
+        if let Some(cache) = &mut self.cache {
+            cache.results.clear();
+        }
+    }
+
+    /// Merge a `Layer` into this `FrozenLayer`
+    /// 
+    /// This commits the underlying `Layer`, recomputes any precomputed ranges, and invalidates any
+    /// cached query results.  Memory-mapped entries are first copied into the underlying `Layer`.
+    pub fn merge(&mut self, other: &Layer<Index, ID>) {
+        self.unmap();
+        self.layer.merge(other);
+        self.layer.commit();
+        if let Some(ranges) = &mut self.ranges {
+            *ranges = NodeRanges::new(self.layer.iter().as_slice(), ranges.depth, &mut |_| ());
+        }
+        self.clear_query_cache();
+    }
+
+    /// [`Layer::test`]: struct.Layer.html#method.test
+    /// Equivalent to [`Layer::test`], returning cached results if available
+    pub fn test<'a, TestGeom>(
+        &'a mut self,
+        test_geom: &TestGeom,
+        max_depth: Option<u32>) -> &'a Vec<ID>
+    where
+        TestGeom: TestGeometry
+    {
+        #[cfg(feature="mmap")]
+        let entries = self.mapped.as_ref().map(MappedEntries::entries);
+        #[cfg(not(feature="mmap"))]
+        let entries = None;
+        let Self{layer, ranges, cache, ..} = self;
+        let ranges = ranges.as_ref();
+
+        let (cache, key) = match (cache, test_geom.cache_key()) {
+            (Some(cache), Some(key)) => (cache, (key, max_depth)),
+            _ => return layer.test_with_ranges(test_geom, max_depth, ranges, entries)
+        };
+
+        if cache.results.len() >= cache.capacity && !cache.results.contains_key(&key) {
+            cache.results.clear();
+        }
+
+        match cache.results.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(layer.test_with_ranges(test_geom, max_depth, ranges, entries).clone())
+        }
+    }
+
+    /// [`Layer::test_box`]: struct.Layer.html#method.test_box
+    /// Equivalent to [`Layer::test_box`], returning cached results if available
+    pub fn test_box<Point_>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        test_bounds: Bounds<Point_>,
+        max_depth: Option<u32>) -> &Vec<ID>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + Debug,
+        Point_::Diff: ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        BoxTestGeometry<Point_>: TestGeometry
+    {
+        let test_geom = BoxTestGeometry::with_system_bounds(
+            system_bounds,
+            test_bounds);
+
+        self.test(&test_geom, max_depth)
+    }
+
+    /// [`Layer::test_ray`]: struct.Layer.html#method.test_ray
+    /// Equivalent to [`Layer::test_ray`], returning cached results if available
+    pub fn test_ray<Point_>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        origin   : Point_,
+        direction: Point_::Diff,
+        range_min: f32,
+        range_max: f32,
+        max_depth: Option<u32>) -> &Vec<ID>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+        Point_::Diff: ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        RayTestGeometry<Point_>: TestGeometry
+    {
+        let test_geom = RayTestGeometry::with_system_bounds(
+            system_bounds,
+            origin,
+            direction,
+            range_min,
+            range_max);
+
+        self.test(&test_geom, max_depth)
+    }
+
+    /// [`Layer::pick`]: struct.Layer.html#method.pick
+    /// Equivalent to [`Layer::pick`]; picking results are never cached
+    pub fn pick<TestGeom, GetDist>(
+        &mut self,
+        test_geom: &TestGeom,
+        max_dist: f32,
+        max_depth: Option<u32>,
+        get_dist: GetDist) -> Option<(f32, ID)>
+    where
+        TestGeom: TestGeometry,
+        GetDist: FnMut(&TestGeom, f32, ID) -> f32
+    {
+        self.pick_with_payload(test_geom, max_dist, max_depth, payload_dist(get_dist))
+            .map(|(dist, id, _)| (dist, id))
+    }
+
+    /// [`Layer::pick_with_payload`]: struct.Layer.html#method.pick_with_payload
+    /// Equivalent to [`Layer::pick_with_payload`]; picking results are never cached
+    pub fn pick_with_payload<T, TestGeom, GetDist>(
+        &mut self,
+        test_geom: &TestGeom,
+        max_dist: f32,
+        max_depth: Option<u32>,
+        get_dist: GetDist) -> Option<(f32, ID, T)>
+    where
+        TestGeom: TestGeometry,
+        GetDist: FnMut(&TestGeom, f32, ID) -> Option<(f32, T)>
+    {
+        #[cfg(feature="mmap")]
+        let entries = self.mapped.as_ref().map(MappedEntries::entries);
+        #[cfg(not(feature="mmap"))]
+        let entries = None;
+        self.layer.pick_with_ranges(test_geom, max_dist, max_depth, self.ranges.as_ref(), entries, get_dist, |_| 0)
+    }
+
+    /// [`Layer::scan`]: struct.Layer.html#method.scan
+    /// Equivalent to [`Layer::scan`]
+    pub fn scan(&mut self)
+        -> &Vec<(ID, ID)>
+    {
+        #[cfg(feature="mmap")]
+        {
+            if let Some(mapped) = &self.mapped {
+                return self.layer.scan_entries(mapped.entries());
+            }
+        }
+        self.layer.scan()
+    }
+}
+
+impl<Index, ID> PartialEq<Self> for FrozenLayer<Index, ID>
+where
+    Index: SpatialIndex,
+    ID: ObjectID,
+    Bounds<Index::Point>: IndexGenerator<Index>
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.layer.min_depth() == other.layer.min_depth() &&
+        self.entries()         == other.entries() &&
+        self.layer.unbounded() == other.layer.unbounded()
+    }
+}
+
+impl<Index, ID> Eq for FrozenLayer<Index, ID>
+where
+    Index: SpatialIndex,
+    ID: ObjectID,
+    Bounds<Index::Point>: IndexGenerator<Index>
+{}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index64_3D;
+    use crate::layer::LayerBuilder;
+
+    use cgmath::Point3;
+
+    #[test]
+    fn cached_test_box() {
+        let system_bounds = Bounds{
+            min: Point3::new(0f32, 0f32, 0f32),
+            max: Point3::new(64f32, 64f32, 64f32)};
+        let object_bounds = |x: f32| Bounds{
+            min: Point3::new(x, x, x),
+            max: Point3::new(x + 1f32, x + 1f32, x + 1f32)};
+
+        let mut layer: Layer<Index64_3D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, (0..32).map(|i| (object_bounds(i as f32 * 2f32), i)));
+
+        let mut frozen = layer.freeze();
+        frozen.enable_query_cache(16);
+
+        let test_bounds = Bounds{
+            min: Point3::new(0f32, 0f32, 0f32),
+            max: Point3::new(8f32, 8f32, 8f32)};
+        let expected = frozen.layer.clone().test_box(system_bounds, test_bounds, None).clone();
+        assert_eq!(frozen.test_box(system_bounds, test_bounds, None), &expected);
+        assert_eq!(frozen.test_box(system_bounds, test_bounds, None), &expected);
+
+        let mut other: Layer<Index64_3D, u32> = LayerBuilder::new().build();
+        other.extend(system_bounds, std::iter::once((object_bounds(4.5f32), 100)));
+        frozen.merge(&other);
+
+        let actual = frozen.test_box(system_bounds, test_bounds, None);
+        assert!(actual.contains(&100));
+    }
+
+    #[test]
+    fn ranged_test_ray() {
+        use rand::prelude::*;
+
+        let system_bounds = Bounds{
+            min: Point3::new(0f32, 0f32, 0f32),
+            max: Point3::new(64f32, 64f32, 64f32)};
+
+        let mut prng = rand_chacha::ChaChaRng::seed_from_u64(0);
+        let mut layer: Layer<Index64_3D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, (0..1000).map(|id| {
+            let min = Point3::new(
+                prng.gen_range(0f32, 62f32),
+                prng.gen_range(0f32, 62f32),
+                prng.gen_range(0f32, 62f32));
+            let size = prng.gen_range(0.1f32, 2f32);
+            (Bounds{min, max: min + cgmath::Vector3::new(size, size, size)}, id)
+        }));
+
+        let origin = Point3::new(1f32, 2f32, 3f32);
+        let direction = cgmath::Vector3::new(1f32, 0.8f32, 0.9f32);
+
+        let mut unranged = layer.clone().freeze();
+        let mut ranged = layer.freeze_with_ranges(4);
+        assert_eq!(
+            ranged.test_ray(system_bounds, origin, direction, 0f32, f32::INFINITY, None),
+            unranged.test_ray(system_bounds, origin, direction, 0f32, f32::INFINITY, None));
+    }
+}
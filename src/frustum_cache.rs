@@ -0,0 +1,276 @@
+// mlodato, 2020
+
+use crate::geom::{Bounds, FrustumDeltaGeometry, FrustumTestGeometry, IndexGenerator, VecDim};
+use crate::index::SpatialIndex;
+use crate::layer::Layer;
+use crate::traits::ObjectID;
+
+use cgmath::prelude::*;
+use rustc_hash::FxHashMap;
+
+use std::fmt::Debug;
+
+/// [`Layer::test_frustum_cached`]: struct.Layer.html#method.test_frustum_cached
+/// The results of a previous frustum query, which are updated incrementally by
+/// [`Layer::test_frustum_cached`]
+/// 
+/// A cache must only be used with a single `Layer`.
+pub struct FrustumCache<Point, ID>
+where
+    Point: EuclideanSpace<Scalar = f32>
+{
+    threshold: f32,
+    system_bounds: Option<Bounds<Point>>,
+    planes: Vec<(Point::Diff, f32)>,
+    max_depth: Option<u32>,
+    stamp: Option<u64>,
+    /// the number of entries for each visible object
+    counts: FxHashMap<ID, u32>,
+    visible: Vec<ID>,
+    newly_visible: Vec<ID>,
+    newly_hidden: Vec<ID>,
+}
+
+impl<Point, ID> FrustumCache<Point, ID>
+where
+    Point: EuclideanSpace<Scalar = f32>,
+    Point::Diff: Array<Element = f32>,
+    ID: ObjectID
+{
+    /// Create an empty cache
+    /// 
+    /// Frusta are updated incrementally if no plane coefficient has changed by more than `threshold`.
+    /// Results are exact in either case; this only limits the amount of work done by incremental
+    /// updates, which may exceed that of a full query if the frustum has moved too far.
+    pub fn new(threshold: f32) -> Self {
+        Self{
+            threshold,
+            system_bounds: None,
+            planes: Vec::new(),
+            max_depth: None,
+            stamp: None,
+            counts: FxHashMap::default(),
+            visible: Vec::new(),
+            newly_visible: Vec::new(),
+            newly_hidden: Vec::new()}
+    }
+
+    /// Objects found by the last query, in sorted order
+    pub fn visible(&self) -> &[ID] {
+        &self.visible
+    }
+
+    /// Objects found by the last query but not the one before, in sorted order
+    pub fn newly_visible(&self) -> &[ID] {
+        &self.newly_visible
+    }
+
+    /// Objects found by the query before the last but not the last, in sorted order
+    pub fn newly_hidden(&self) -> &[ID] {
+        &self.newly_hidden
+    }
+
+    /// Discard all results, so that the next query is done in full and reports everything as newly
+    /// visible
+    pub fn clear(&mut self) {
+        self.stamp = None;
+        self.counts.clear();
+        self.visible.clear();
+        self.newly_visible.clear();
+        self.newly_hidden.clear();
+    }
+
+    fn is_reusable(
+        &self,
+        stamp: u64,
+        system_bounds: Bounds<Point>,
+        planes: &[(Point::Diff, f32)],
+        max_depth: Option<u32>) -> bool
+    where
+        Point: VecDim + PartialEq
+    {
+        let near = |lhs: f32, rhs: f32| (lhs - rhs).abs() <= self.threshold;
+        self.stamp == Some(stamp)
+            && self.system_bounds == Some(system_bounds)
+            && self.max_depth == max_depth
+            && self.planes.len() == planes.len()
+            && self.planes.iter().zip(planes).all(|(&(lhs, lhs_offset), &(rhs, rhs_offset))|
+                near(lhs_offset, rhs_offset) && (0..Point::DIM).all(|axis| near(lhs[axis], rhs[axis])))
+    }
+}
+
+/// Elements of sorted `lhs` which aren't in sorted `rhs`
+fn sorted_difference<ID: ObjectID>(lhs: &[ID], rhs: &[ID]) -> Vec<ID> {
+    lhs.iter()
+        .filter(|id| rhs.binary_search(id).is_err())
+        .cloned()
+        .collect()
+}
+
+impl<Index, ID> Layer<Index, ID>
+where
+    Index: SpatialIndex,
+    ID: ObjectID,
+    Bounds<Index::Point>: IndexGenerator<Index>
+{
+    /// [`test_frustum`]: #method.test_frustum
+    /// Equivalent to [`test_frustum`], updating the results of a previous query in `cache`
+    /// 
+    /// If this `Layer`'s contents haven't changed since the previous query, and the frustum hasn't
+    /// moved beyond the cache's threshold, only cells which are inside one frustum but not the other
+    /// are visited.  This makes repeated queries from a slowly-moving camera cheap.  Objects which
+    /// became visible or hidden are listed by `FrustumCache::newly_visible` and
+    /// `FrustumCache::newly_hidden`.  Returns whether the results were updated incrementally.
+    pub fn test_frustum_cached<Point_>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        planes: &[(Point_::Diff, f32)],
+        max_depth: Option<u32>,
+        cache: &mut FrustumCache<Point_, ID>) -> bool
+    where
+        Point_: EuclideanSpace<Scalar = f32> + VecDim + PartialEq + Debug,
+        Point_::Diff: Array<Element = f32> + Debug
+    {
+        self.commit();
+
+        let incremental = cache.is_reusable(self.stamp(), system_bounds, planes, max_depth);
+        if incremental {
+            let test_geom = FrustumDeltaGeometry{
+                old: FrustumTestGeometry::with_system_bounds(system_bounds, &cache.planes),
+                new: FrustumTestGeometry::with_system_bounds(system_bounds, planes)};
+            let counts = &mut cache.counts;
+            let mut changed: Vec<ID> = Vec::new();
+            Self::test_impl(
+                self.sorted_tree(),
+                0,
+                Index::default(),
+                &test_geom,
+                f32::INFINITY,
+                max_depth,
+                None,
+                &mut |test_geom, nearest, _, id| {
+                    if !self.is_queryable(id) {
+                        return nearest;
+                    }
+                    match test_geom.visibility() {
+                        (false, true) => *counts.entry(id).or_insert(0) += 1,
+                        (true, false) => *counts.get_mut(&id).expect("entry was not previously visible") -= 1,
+                        _ => return nearest
+                    }
+                    changed.push(id);
+                    nearest
+                },
+                &mut ());
+            changed.sort_unstable();
+            changed.dedup();
+
+            // objects may gain and lose entries in the same update, so only compare final visibility
+            cache.newly_visible.clear();
+            cache.newly_hidden.clear();
+            for id in changed {
+                let was_visible = cache.visible.binary_search(&id).is_ok();
+                let is_visible = counts[&id] > 0
+                    || (self.unbounded().binary_search(&id).is_ok() && self.is_queryable(id));
+                if counts[&id] == 0 {
+                    counts.remove(&id);
+                }
+                match (was_visible, is_visible) {
+                    (false, true) => cache.newly_visible.push(id),
+                    (true, false) => cache.newly_hidden.push(id),
+                    _ => ()
+                }
+            }
+            let mut visible = sorted_difference(&cache.visible, &cache.newly_hidden);
+            visible.extend(cache.newly_visible.iter().cloned());
+            visible.sort_unstable();
+            cache.visible = visible;
+        } else {
+            let test_geom = FrustumTestGeometry::with_system_bounds(system_bounds, planes);
+            let counts = &mut cache.counts;
+            counts.clear();
+            Self::test_impl(
+                self.sorted_tree(),
+                0,
+                Index::default(),
+                &test_geom,
+                f32::INFINITY,
+                max_depth,
+                None,
+                &mut |_, nearest, _, id| {
+                    if self.is_queryable(id) {
+                        *counts.entry(id).or_insert(0) += 1;
+                    }
+                    nearest
+                },
+                &mut ());
+            let mut visible: Vec<ID> = counts.keys()
+                .chain(self.unbounded().iter().filter(|&&id| self.is_queryable(id)))
+                .cloned()
+                .collect();
+            visible.sort_unstable();
+            visible.dedup();
+
+            cache.newly_visible = sorted_difference(&visible, &cache.visible);
+            cache.newly_hidden = sorted_difference(&cache.visible, &visible);
+            cache.visible = visible;
+        }
+
+        cache.system_bounds = Some(system_bounds);
+        cache.planes.clear();
+        cache.planes.extend_from_slice(planes);
+        cache.max_depth = max_depth;
+        cache.stamp = Some(self.stamp());
+
+        incremental
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FrustumCache;
+    use crate::geom::Bounds;
+    use crate::index::Index64_2D;
+    use crate::layer::{Layer, LayerBuilder};
+
+    use cgmath::{Point2, Vector2};
+    use rand::prelude::*;
+
+    #[test]
+    fn test_frustum_cached() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut prng = rand_chacha::ChaChaRng::seed_from_u64(0);
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, (0..256u32).map(|id| {
+            let min = Point2::new(prng.gen_range(0f32, 15f32), prng.gen_range(0f32, 15f32));
+            let size = Vector2::new(prng.gen_range(0f32, 1f32), prng.gen_range(0f32, 1f32));
+            (Bounds{min, max: min + size}, id)
+        }));
+        layer.extend_unbounded(std::iter::once(1000));
+
+        // a wedge, sweeping across the system
+        let planes = |x: f32| [
+            (Vector2::new(-1f32, 0f32), x),
+            (Vector2::new(1f32, -0.5f32), 2f32 - x)];
+        let mut cache = FrustumCache::new(1f32);
+        let mut previous: Vec<u32> = Vec::new();
+        for (i, x) in [4f32, 4.5f32, 5f32, 5.25f32, 8f32, 7.5f32].iter().cloned().enumerate() {
+            let incremental = layer.test_frustum_cached(system_bounds, &planes(x), None, &mut cache);
+            assert_eq!(incremental, i > 0 && i != 4);
+
+            let expected = layer.test_frustum(system_bounds, &planes(x), None).clone();
+            assert_eq!(cache.visible(), expected.as_slice());
+            assert_eq!(cache.newly_visible(), expected.iter()
+                .filter(|id| !previous.contains(id)).cloned().collect::<Vec<_>>().as_slice());
+            assert_eq!(cache.newly_hidden(), previous.iter()
+                .filter(|id| !expected.contains(id)).cloned().collect::<Vec<_>>().as_slice());
+            previous = expected;
+        }
+
+        layer.extend_unbounded(std::iter::once(1001));
+        assert!(!layer.test_frustum_cached(system_bounds, &planes(7.5f32), None, &mut cache));
+        assert_eq!(cache.newly_visible(), &[1001]);
+        assert!(cache.newly_hidden().is_empty());
+    }
+}
@@ -2,21 +2,47 @@
 
 use crate::index::SpatialIndex;
 
-use cgmath::{Point2, Point3, Vector2, Vector3};
+use cgmath::{Point2, Point3, Rad, Vector2, Vector3};
 use cgmath::prelude::*;
 use num_traits::{Float, One, PrimInt};
 use smallvec::SmallVec;
 
 use std::fmt::{Debug, Formatter};
 
+/// The dimension of a point or vector type
+/// 
+/// This is the only place where specific point types are distinguished; index generation and test
+/// geometry are implemented once, generically over `DIM`
 pub trait VecDim {
     const DIM: usize;
+
+    /// An array with one element per sub-cell, i.e. `2^DIM` elements
+    type Cells<T>: CellArray<T>;
+}
+
+impl<T> VecDim for Point2 <T> { const DIM: usize = 2; type Cells<U> = [U; 4]; }
+impl<T> VecDim for Point3 <T> { const DIM: usize = 3; type Cells<U> = [U; 8]; }
+impl<T> VecDim for Vector2<T> { const DIM: usize = 2; type Cells<U> = [U; 4]; }
+impl<T> VecDim for Vector3<T> { const DIM: usize = 3; type Cells<U> = [U; 8]; }
+
+/// A fixed-size array, see `VecDim::Cells`
+pub trait CellArray<T>: AsRef<[T]> + AsMut<[T]> + smallvec::Array<Item = T> {
+    fn from_fn<F>(f: F) -> Self
+    where
+        F: FnMut(usize) -> T;
 }
 
-impl<T> VecDim for Point2 <T> { const DIM: usize = 2; }
-impl<T> VecDim for Point3 <T> { const DIM: usize = 3; }
-impl<T> VecDim for Vector2<T> { const DIM: usize = 2; }
-impl<T> VecDim for Vector3<T> { const DIM: usize = 3; }
+impl<T, const N: usize> CellArray<T> for [T; N]
+where
+    [T; N]: smallvec::Array<Item = T>
+{
+    fn from_fn<F>(f: F) -> Self
+    where
+        F: FnMut(usize) -> T
+    {
+        std::array::from_fn(f)
+    }
+}
 
 fn fold_arr<Arr, State, F>(arr: Arr, init: State, f: F) -> State
 where
@@ -37,6 +63,13 @@ where
     }
 }
 
+fn push_bits<Arr>(key: &mut CacheKey, arr: Arr)
+where
+    Arr: Array<Element = f32>
+{
+    key.extend((0..Arr::len()).map(|i| arr[i].to_bits()));
+}
+
 fn max_axis<Arr>(arr: Arr) -> Arr::Element
 where
     Arr: Array,
@@ -73,6 +106,8 @@ where
     type Output;
 
     fn indices(self, min_depth: Option<u32>) -> Self::Output;
+    /// As `indices`, but no deeper than `max_depth` (unless this is less than `min_depth`)
+    fn indices_in_range(self, min_depth: Option<u32>, max_depth: Option<u32>) -> Self::Output;
     fn indices_at_depth(self, depth: u32) -> Self::Output;
 }
 
@@ -127,9 +162,197 @@ where
         true
     }
 
+    /// The smallest bounds containing both `self` and `other`
+    pub fn union(self, other: Bounds<Point>) -> Bounds<Point> {
+        let mut result = self;
+        for i in 0..Point::len() {
+            if other.min[i] < result.min[i] {
+                result.min[i] = other.min[i];
+            }
+            if other.max[i] > result.max[i] {
+                result.max[i] = other.max[i];
+            }
+        }
+        result
+    }
+
+    /// The smallest bounds containing `self` both before and after moving by `displacement`
+    pub fn swept(self, displacement: Point::Diff) -> Bounds<Point> {
+        self.union(Bounds::new(self.min + displacement, self.max + displacement))
+    }
+
+    /// The smallest bounds containing all corners of `self` after applying `transform`
+    pub fn transformed<T>(self, transform: &T) -> Bounds<Point>
+    where
+        T: Transform<Point>
+    {
+        let corner = |mask: usize| {
+            let mut corner = self.min;
+            for i in 0..Point::len() {
+                if mask & (1 << i) != 0 {
+                    corner[i] = self.max[i];
+                }
+            }
+            transform.transform_point(corner)
+        };
+        let first = corner(0);
+        (1..1usize << Point::len())
+            .map(corner)
+            .fold(Bounds::new(first, first), |bounds, corner| bounds.union(Bounds::new(corner, corner)))
+    }
+
+    /// The distance from `point` to the nearest point of `self`, zero if `self` contains `point`
+    pub fn distance_to(self, point: Point) -> f32
+    where
+        Point: EuclideanSpace<Scalar = f32>
+    {
+        (0..Point::len())
+            .map(|i| {
+                let delta = point[i] - point[i].max(self.min[i]).min(self.max[i]);
+                delta * delta
+            })
+            .sum::<f32>()
+            .sqrt()
+    }
+
+    /// The distance from `point` to the farthest point of `self`
+    pub fn farthest_distance_to(self, point: Point) -> f32
+    where
+        Point: EuclideanSpace<Scalar = f32>
+    {
+        (0..Point::len())
+            .map(|i| {
+                let delta = (point[i] - self.min[i]).abs().max((self.max[i] - point[i]).abs());
+                delta * delta
+            })
+            .sum::<f32>()
+            .sqrt()
+    }
+
+    /// The distance between the nearest points of `self` and `other`, zero if they overlap
+    pub fn distance_to_bounds(self, other: Bounds<Point>) -> f32
+    where
+        Point: EuclideanSpace<Scalar = f32>
+    {
+        (0..Point::len())
+            .map(|i| {
+                let gap = (other.min[i] - self.max[i]).max(self.min[i] - other.max[i]).max(0f32);
+                gap * gap
+            })
+            .sum::<f32>()
+            .sqrt()
+    }
+
+    /// The overlapping region of `self` and `other`, if any
+    pub fn intersection(self, other: Bounds<Point>) -> Option<Bounds<Point>> {
+        if !self.overlaps(other) {
+            return None;
+        }
+        let mut result = self;
+        for i in 0..Point::len() {
+            if other.min[i] > result.min[i] {
+                result.min[i] = other.min[i];
+            }
+            if other.max[i] < result.max[i] {
+                result.max[i] = other.max[i];
+            }
+        }
+        Some(result)
+    }
+
     pub fn center(self) -> Point {
         self.min.midpoint(self.max)
     }
+
+    /// The range of distances along a ray for which it is inside these bounds, if any
+    pub fn intersect_ray(self, origin: Point, direction: Point::Diff) -> Option<(f32, f32)>
+    where
+        Point: EuclideanSpace<Scalar = f32>,
+        Point::Diff: std::ops::Index<usize, Output = f32>
+    {
+        let mut range_min = f32::NEG_INFINITY;
+        let mut range_max = f32::INFINITY;
+        for i in 0..Point::len() {
+            if direction[i] == 0f32 {
+                if origin[i] < self.min[i] || origin[i] > self.max[i] {
+                    return None;
+                }
+                continue;
+            }
+            let d0 = (self.min[i] - origin[i]) / direction[i];
+            let d1 = (self.max[i] - origin[i]) / direction[i];
+            range_min = range_min.max(d0.min(d1));
+            range_max = range_max.min(d0.max(d1));
+        }
+        if range_min <= range_max {
+            Some((range_min, range_max))
+        } else {
+            None
+        }
+    }
+}
+
+// Plain arrays are used as an interchange format, so that callers using a different math library
+// (or a different cgmath version) don't need to name this crate's point types.  Ray origins and
+// directions may be converted with `.into()`, as cgmath implements `From<[S; N]>` for its points.
+
+impl<S: Copy> Bounds<[S; 2]> {
+    /// Convert array bounds into the point type used throughout this crate
+    pub fn to_points(self) -> Bounds<Point2<S>> {
+        Bounds{min: self.min.into(), max: self.max.into()}
+    }
+}
+
+impl<S: Copy> Bounds<[S; 3]> {
+    /// Convert array bounds into the point type used throughout this crate
+    pub fn to_points(self) -> Bounds<Point3<S>> {
+        Bounds{min: self.min.into(), max: self.max.into()}
+    }
+}
+
+impl<S: Copy> Bounds<Point2<S>> {
+    /// Convert bounds into plain arrays, e.g. for use with another math library
+    pub fn to_arrays(self) -> Bounds<[S; 2]> {
+        Bounds{min: self.min.into(), max: self.max.into()}
+    }
+}
+
+impl<S: Copy> Bounds<Point3<S>> {
+    /// Convert bounds into plain arrays, e.g. for use with another math library
+    pub fn to_arrays(self) -> Bounds<[S; 3]> {
+        Bounds{min: self.min.into(), max: self.max.into()}
+    }
+}
+
+// With the `mint` feature, bounds also convert to and from mint's interchange types, which most math
+// libraries (including other cgmath versions) support.  Points and vectors convert with `.into()`.
+
+#[cfg(feature="mint")]
+impl<S: Copy> From<Bounds<mint::Point2<S>>> for Bounds<Point2<S>> {
+    fn from(bounds: Bounds<mint::Point2<S>>) -> Self {
+        Bounds{min: bounds.min.into(), max: bounds.max.into()}
+    }
+}
+
+#[cfg(feature="mint")]
+impl<S: Copy> From<Bounds<mint::Point3<S>>> for Bounds<Point3<S>> {
+    fn from(bounds: Bounds<mint::Point3<S>>) -> Self {
+        Bounds{min: bounds.min.into(), max: bounds.max.into()}
+    }
+}
+
+#[cfg(feature="mint")]
+impl<S: Copy> From<Bounds<Point2<S>>> for Bounds<mint::Point2<S>> {
+    fn from(bounds: Bounds<Point2<S>>) -> Self {
+        Bounds{min: bounds.min.into(), max: bounds.max.into()}
+    }
+}
+
+#[cfg(feature="mint")]
+impl<S: Copy> From<Bounds<Point3<S>>> for Bounds<mint::Point3<S>> {
+    fn from(bounds: Bounds<Point3<S>>) -> Self {
+        Bounds{min: bounds.min.into(), max: bounds.max.into()}
+    }
 }
 
 /// System bounds supporting conversions between local and global coordinates
@@ -149,7 +372,7 @@ where
         let size = self.sizef();
         let to_local = |global: PointGlobal, i| {
             // MAX_VALUE has 24 bits set because IEEE floats have 23 explicit + 1 implicit fractional bits
-            const MIN_VALUE: f32 = std::u32::MIN as f32;
+            const MIN_VALUE: f32 = u32::MIN as f32;
             const MAX_VALUE: f32 = 0xffff_ff00u32 as f32;
             const RANGE: f32 = MAX_VALUE - MIN_VALUE;
             ((global[i] - self.min[i]) / size[i] * RANGE + MIN_VALUE) as u32
@@ -166,7 +389,7 @@ where
         let size = self.sizef();
         let to_global = |local: PointLocal, i| {
             // MAX_VALUE has 24 bits set because IEEE floats have 23 explicit + 1 implicit fractional bits
-            const MIN_VALUE: f32 = std::u32::MIN as f32;
+            const MIN_VALUE: f32 = u32::MIN as f32;
             const MAX_VALUE: f32 = 0xffff_ff00u32 as f32;
             const RANGE: f32 = MAX_VALUE - MIN_VALUE;
             self.min[i] + (local[i] as f32 - MIN_VALUE) / RANGE * size[i]
@@ -180,73 +403,26 @@ where
     }
 }
 
-impl<Index> IndexGenerator<Index> for Bounds<Point2<u32>>
+impl<Index, Point> IndexGenerator<Index> for Bounds<Point>
 where
-    Index: SpatialIndex<Diff = Vector2<u32>, Point = Point2<u32>>
+    Index: SpatialIndex<Point = Point>,
+    Point: EuclideanSpace<Scalar = u32> + VecDim,
+    Point::Diff: ElementWise<u32> + Array<Element = u32>
 {
-    type Output = SmallVec<[Index; 4]>;
+    type Output = SmallVec<Point::Cells<Index>>;
 
     fn indices(self, min_depth: Option<u32>) -> Self::Output {
-        let max_axis = max_axis(self.sizei());
-        let mut depth = (max_axis - 1u32).leading_zeros();
-        if let Some(min_depth) = min_depth {
-            if depth < min_depth {
-                depth = min_depth;
-            }
-        }
-        depth = Index::clamp_depth(depth);
-        
-        self.indices_at_depth(depth)
+        self.indices_in_range(min_depth, None)
     }
 
-    fn indices_at_depth(self, depth: u32) -> Self::Output {
-        if depth == 0 {
-            return smallvec![Index::default()];
-        }
-
-        let min = self.min.map(|scalar| truncate_to_depth(scalar, depth));
-        let max = self.max.map(|scalar| truncate_to_depth(scalar, depth));
-
-        let mut indices: Self::Output = Self::Output::new();
-
-        let step = scale_at_depth(depth);
-        let mut y = min.y;
-        loop {
-            let mut x = min.x;
-            loop {
-                indices.push(Index::default()
-                    .set_depth(depth)
-                    .set_origin(Point2::new(x, y)));
-
-                if x >= max.x {
-                    break;
-                }
-                x += step;
-            }
-
-            if y >= max.y {
-                break;
-            }
-            y += step;
-        }
-
-        if indices.len() > 4 {
-            warn!("indices_at_depth generated more than 4 indices; decrease min_depth or split large objects to avoid heap allocations");
-        }
-
-        indices
-    }
-}
-
-impl<Index> IndexGenerator<Index> for Bounds<Point3<u32>>
-where
-    Index: SpatialIndex<Diff = Vector3<u32>, Point = Point3<u32>>
-{
-    type Output = SmallVec<[Index; 8]>;
-
-    fn indices(self, min_depth: Option<u32>) -> Self::Output {
+    fn indices_in_range(self, min_depth: Option<u32>, max_depth: Option<u32>) -> Self::Output {
         let max_axis = max_axis(self.sizei());
         let mut depth = (max_axis - 1u32).leading_zeros();
+        if let Some(max_depth) = max_depth {
+            if depth > max_depth {
+                depth = max_depth;
+            }
+        }
         if let Some(min_depth) = min_depth {
             if depth < min_depth {
                 depth = min_depth;
@@ -262,42 +438,34 @@ where
             return smallvec![Index::default()];
         }
 
-        let min = self.min.map(|scalar| truncate_to_depth(scalar, depth));
-        let max = self.max.map(|scalar| truncate_to_depth(scalar, depth));
+        let mut min = self.min;
+        let mut max = self.max;
+        init_arr(&mut min, |i| truncate_to_depth(self.min[i], depth));
+        init_arr(&mut max, |i| truncate_to_depth(self.max[i], depth));
 
         let mut indices: Self::Output = Self::Output::new();
 
+        // iterate over cells with the first axis varying fastest
         let step = scale_at_depth(depth);
-        let mut z = min.z;
-        loop {
-            let mut y = min.y;
-            loop {
-                let mut x = min.x;
-                loop {
-                    indices.push(Index::default()
-                        .set_depth(depth)
-                        .set_origin(Point3::new(x, y, z)));
-
-                    if x >= max.x {
-                        break;
-                    }
-                    x += step;
+        let mut origin = min;
+        'cells: loop {
+            indices.push(Index::default()
+                .set_depth(depth)
+                .set_origin(origin));
+
+            for axis in 0..Point::DIM {
+                if origin[axis] < max[axis] {
+                    origin[axis] += step;
+                    continue 'cells;
                 }
-
-                if y >= max.y {
-                    break;
-                }
-                y += step;
-            }
-
-            if z >= max.z {
-                break;
+                origin[axis] = min[axis];
             }
-            z += step;
+            break;
         }
 
-        if indices.len() > 8 {
-            warn!("indices_at_depth generated more than 8 indices; decrease min_depth or split large objects to avoid heap allocations");
+        let capacity = <Point::Cells<Index> as smallvec::Array>::size();
+        if indices.len() > capacity {
+            warn!("indices_at_depth generated more than {} indices; decrease min_depth or split large objects to avoid heap allocations", capacity);
         }
 
         indices
@@ -319,6 +487,9 @@ where
     }
 }
 
+/// The bit pattern of a `TestGeometry`, used to identify repeated queries
+pub type CacheKey = SmallVec<[u32; 16]>;
+
 /// [`Layer::test`]: struct.Layer.html#method.test
 /// A trait for implementing individual geometry tests
 /// 
@@ -343,8 +514,161 @@ pub trait TestGeometry: Sized + Debug {
 
     /// Return whether this geometry is valid and non-empty
     /// 
-    /// `nearest` may be `std::f32::INFINITY`
+    /// `nearest` may be `f32::INFINITY`
     fn should_test(&self, nearest: f32) -> bool;
+
+    /// [`FrozenLayer`]: struct.FrozenLayer.html
+    /// A key uniquely identifying this geometry, used by [`FrozenLayer`] to cache query results
+    /// 
+    /// Geometry returning `None` (the default) is never cached
+    fn cache_key(&self) -> Option<CacheKey> {
+        None
+    }
+}
+
+/// Test geometry which may be checked against objects' stored bounds, in addition to cells, see
+/// `LayerBuilder::with_refined_tests`
+pub(crate) trait RefinedTestGeometry<Point> {
+    /// Whether the tested shape overlaps `bounds`; this may be conservative
+    fn overlaps(&self, bounds: Bounds<Point>) -> bool;
+}
+
+impl<Point> RefinedTestGeometry<Point> for BoxTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32>
+{
+    fn overlaps(&self, bounds: Bounds<Point>) -> bool {
+        self.test_bounds.overlaps(bounds)
+    }
+}
+
+impl<Point> RefinedTestGeometry<Point> for SphereTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32>
+{
+    fn overlaps(&self, bounds: Bounds<Point>) -> bool {
+        SphereTestGeometry::overlaps(self, bounds)
+    }
+}
+
+impl<Point> RefinedTestGeometry<Point> for SweptBoxTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32>,
+    Point::Diff: Array<Element = f32>
+{
+    fn overlaps(&self, bounds: Bounds<Point>) -> bool {
+        SweptBoxTestGeometry::overlaps(self, bounds)
+    }
+}
+
+impl<Point> RefinedTestGeometry<Point> for PointTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32>
+{
+    fn overlaps(&self, bounds: Bounds<Point>) -> bool {
+        bounds.contains(Bounds::new(self.point, self.point))
+    }
+}
+
+impl<Point> RefinedTestGeometry<Point> for ConeTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32>,
+    Point::Diff: InnerSpace<Scalar = f32>
+{
+    fn overlaps(&self, bounds: Bounds<Point>) -> bool {
+        ConeTestGeometry::overlaps(self, bounds)
+    }
+}
+
+impl<Point> RefinedTestGeometry<Point> for EllipsoidTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32>,
+    Point::Diff: Array<Element = f32>
+{
+    fn overlaps(&self, bounds: Bounds<Point>) -> bool {
+        EllipsoidTestGeometry::overlaps(self, bounds)
+    }
+}
+
+impl<'a, Point, F> RefinedTestGeometry<Point> for SdfTestGeometry<'a, Point, F>
+where
+    Point: EuclideanSpace<Scalar = f32>,
+    F: Fn(Point) -> f32
+{
+    fn overlaps(&self, bounds: Bounds<Point>) -> bool {
+        SdfTestGeometry::overlaps(self, bounds)
+    }
+}
+
+impl<Point> RefinedTestGeometry<Point> for ShellTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32>
+{
+    fn overlaps(&self, bounds: Bounds<Point>) -> bool {
+        ShellTestGeometry::overlaps(self, bounds)
+    }
+}
+
+/// [`TestGeometry`]: trait.TestGeometry.html
+/// [`Layer::cast`]: struct.Layer.html#method.cast
+/// A [`TestGeometry`] with known bounds, which may be swept along a direction by [`Layer::cast`]
+pub trait BoundedTestGeometry<Point>: TestGeometry {
+    /// Bounds enclosing the tested shape (not the current cell)
+    fn shape_bounds(&self) -> Bounds<Point>;
+}
+
+impl<Point> BoundedTestGeometry<Point> for BoxTestGeometry<Point>
+where
+    Self: TestGeometry,
+    Point: EuclideanSpace<Scalar = f32>
+{
+    fn shape_bounds(&self) -> Bounds<Point> {
+        self.test_bounds
+    }
+}
+
+impl<Point> BoundedTestGeometry<Point> for PointTestGeometry<Point>
+where
+    Self: TestGeometry,
+    Point: EuclideanSpace<Scalar = f32>
+{
+    fn shape_bounds(&self) -> Bounds<Point> {
+        Bounds::new(self.point, self.point)
+    }
+}
+
+impl<Point> BoundedTestGeometry<Point> for SphereTestGeometry<Point>
+where
+    Self: TestGeometry,
+    Point: EuclideanSpace<Scalar = f32>,
+    Point::Diff: Array<Element = f32>
+{
+    fn shape_bounds(&self) -> Bounds<Point> {
+        let radius = Point::Diff::from_value(self.radius);
+        Bounds::new(self.center - radius, self.center + radius)
+    }
+}
+
+impl<Point> BoundedTestGeometry<Point> for EllipsoidTestGeometry<Point>
+where
+    Self: TestGeometry,
+    Point: EuclideanSpace<Scalar = f32>
+{
+    fn shape_bounds(&self) -> Bounds<Point> {
+        Bounds::new(self.center - self.radii, self.center + self.radii)
+    }
+}
+
+impl<Point> BoundedTestGeometry<Point> for ShellTestGeometry<Point>
+where
+    Self: TestGeometry,
+    Point: EuclideanSpace<Scalar = f32>,
+    Point::Diff: Array<Element = f32>
+{
+    fn shape_bounds(&self) -> Bounds<Point> {
+        let radius = Point::Diff::from_value(self.max_radius);
+        Bounds::new(self.center - radius, self.center + radius)
+    }
 }
 
 /// [`TestGeometry`]: trait.TestGeometry.html
@@ -376,315 +700,1542 @@ where
     }
 }
 
-impl TestGeometry for BoxTestGeometry<Point2<f32>> {
-    type SubdivideResult = [Self; 4];
-    type TestOrder = [usize; 4];
-
-    fn subdivide(&self) -> Self::SubdivideResult {
-        let center = self.cell_bounds.center();
-        let mut results: [Self; 4] = [
-            self.clone(),
-            self.clone(),
-            self.clone(),
-            self.clone()
-        ];
-        for (cell, result) in results.iter_mut().enumerate() {
-            let bounds = &mut result.cell_bounds;
-            #[allow(clippy::needless_range_loop)]
-            for axis in 0..2 {
-                let side = cell & (1 << axis) != 0;
-                if side {
-                    bounds.min[axis] = center[axis];
-                } else {
-                    bounds.max[axis] = center[axis];
-                }
-            }
+/// Split `bounds` into `2^DIM` sub-cells, in the same order as `SpatialIndex::subdivide`
+fn subdivide_bounds<Point>(bounds: &mut Bounds<Point>, center: Point, cell: usize)
+where
+    Point: EuclideanSpace<Scalar = f32> + VecDim
+{
+    for axis in 0..Point::DIM {
+        let side = cell & (1 << axis) != 0;
+        if side {
+            bounds.min[axis] = center[axis];
+        } else {
+            bounds.max[axis] = center[axis];
         }
-        results
-    }
-
-    fn test_order(&self) -> Self::TestOrder {
-        [0, 1, 2, 3]
-    }
-
-    fn should_test(&self, nearest: f32) -> bool {
-        debug_assert!(!nearest.is_finite(), "BoxTestGeometry does not support \"pick\" operations");
-        self.cell_bounds.overlaps(self.test_bounds)
     }
 }
 
-impl TestGeometry for BoxTestGeometry<Point3<f32>> {
-    type SubdivideResult = [Self; 8];
-    type TestOrder = [usize; 8];
+impl<Point> TestGeometry for BoxTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+    Point::Diff: Debug
+{
+    type SubdivideResult = Point::Cells<Self>;
+    type TestOrder = Point::Cells<usize>;
 
     fn subdivide(&self) -> Self::SubdivideResult {
         let center = self.cell_bounds.center();
-        let mut results: [Self; 8] = [
-            self.clone(),
-            self.clone(),
-            self.clone(),
-            self.clone(),
-            self.clone(),
-            self.clone(),
-            self.clone(),
-            self.clone()
-        ];
-        for (cell, result) in results.iter_mut().enumerate() {
-            let bounds = &mut result.cell_bounds;
-            #[allow(clippy::needless_range_loop)]
-            for axis in 0..3 {
-                let side = cell & (1 << axis) != 0;
-                if side {
-                    bounds.min[axis] = center[axis];
-                } else {
-                    bounds.max[axis] = center[axis];
-                }
-            }
-        }
-        results
+        CellArray::from_fn(|cell| {
+            let mut result = self.clone();
+            subdivide_bounds(&mut result.cell_bounds, center, cell);
+            result
+        })
     }
 
     fn test_order(&self) -> Self::TestOrder {
-        [0, 1, 2, 3, 4, 5, 6, 7]
+        CellArray::from_fn(|cell| cell)
     }
 
     fn should_test(&self, nearest: f32) -> bool {
         debug_assert!(!nearest.is_finite(), "BoxTestGeometry does not support \"pick\" operations");
         self.cell_bounds.overlaps(self.test_bounds)
     }
+    fn cache_key(&self) -> Option<CacheKey> {
+        let mut key = CacheKey::new();
+        push_bits(&mut key, self.cell_bounds.min);
+        push_bits(&mut key, self.cell_bounds.max);
+        push_bits(&mut key, self.test_bounds.min);
+        push_bits(&mut key, self.test_bounds.max);
+        Some(key)
+    }
 }
 
 /// [`TestGeometry`]: trait.TestGeometry.html
-/// A type implementing [`TestGeometry`] for rays
-#[derive(Clone)]
-pub struct RayTestGeometry<Point>
+/// A type implementing [`TestGeometry`] for spheres (or circles, in 2D)
+#[derive(Clone, Debug)]
+pub struct SphereTestGeometry<Point>
 where
     Point: EuclideanSpace<Scalar = f32>
 {
     cell_bounds: Bounds<Point>,
-    origin: Point,
-    direction: Point::Diff,
-    range_min: f32,
-    range_max: f32
+    center: Point,
+    radius: f32,
 }
 
-impl Debug for RayTestGeometry<Point2<f32>> {
-    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
-        write!(f, "RayTestGeometry{{{{({:}, {:}) - ({:}, {:})}}, ({:}, {:}), ({:}, {:}), {{{:}-{:}}}}}",
-            self.cell_bounds.min.x,
-            self.cell_bounds.min.y,
-            self.cell_bounds.max.x,
-            self.cell_bounds.max.y,
-            self.origin.x,
-            self.origin.y,
-            self.direction.x,
-            self.direction.y,
-            self.range_min,
-            self.range_max)
+impl<Point> SphereTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32>
+{
+    /// Construct sphere test geometry
+    pub fn with_system_bounds(
+        system_bounds: Bounds<Point>,
+        center: Point,
+        radius: f32) -> Self
+    {
+        Self{
+            cell_bounds: system_bounds,
+            center,
+            radius}
     }
-}
 
-impl Debug for RayTestGeometry<Point3<f32>> {
+    /// Whether the sphere overlaps `bounds`
+    pub(crate) fn overlaps(&self, bounds: Bounds<Point>) -> bool {
+        bounds.distance_to(self.center) <= self.radius
+    }
+}
+
+impl<Point> TestGeometry for SphereTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+    Point::Diff: Debug
+{
+    type SubdivideResult = Point::Cells<Self>;
+    type TestOrder = Point::Cells<usize>;
+
+    fn subdivide(&self) -> Self::SubdivideResult {
+        let center = self.cell_bounds.center();
+        CellArray::from_fn(|cell| {
+            let mut result = self.clone();
+            subdivide_bounds(&mut result.cell_bounds, center, cell);
+            result
+        })
+    }
+
+    fn test_order(&self) -> Self::TestOrder {
+        CellArray::from_fn(|cell| cell)
+    }
+
+    fn should_test(&self, nearest: f32) -> bool {
+        debug_assert!(!nearest.is_finite(), "SphereTestGeometry does not support \"pick\" operations");
+        self.overlaps(self.cell_bounds)
+    }
+
+    fn cache_key(&self) -> Option<CacheKey> {
+        let mut key = CacheKey::new();
+        push_bits(&mut key, self.cell_bounds.min);
+        push_bits(&mut key, self.cell_bounds.max);
+        push_bits(&mut key, self.center);
+        key.push(self.radius.to_bits());
+        Some(key)
+    }
+}
+
+/// [`TestGeometry`]: trait.TestGeometry.html
+/// A type implementing [`TestGeometry`] for spherical shells (or annuli, in 2D), e.g. "aggro rings" or
+/// level of detail bands
+/// 
+/// Cells entirely within `min_radius` of the center are skipped, rather than filtering the results
+/// of a sphere test.
+#[derive(Clone, Debug)]
+pub struct ShellTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32>
+{
+    cell_bounds: Bounds<Point>,
+    center: Point,
+    min_radius: f32,
+    max_radius: f32,
+}
+
+impl<Point> ShellTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32>
+{
+    /// Construct shell test geometry
+    pub fn with_system_bounds(
+        system_bounds: Bounds<Point>,
+        center: Point,
+        min_radius: f32,
+        max_radius: f32) -> Self
+    {
+        Self{
+            cell_bounds: system_bounds,
+            center,
+            min_radius,
+            max_radius}
+    }
+
+    /// Whether the shell overlaps `bounds`
+    pub(crate) fn overlaps(&self, bounds: Bounds<Point>) -> bool {
+        bounds.distance_to(self.center) <= self.max_radius &&
+        bounds.farthest_distance_to(self.center) >= self.min_radius
+    }
+}
+
+impl<Point> TestGeometry for ShellTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+    Point::Diff: Debug
+{
+    type SubdivideResult = Point::Cells<Self>;
+    type TestOrder = Point::Cells<usize>;
+
+    fn subdivide(&self) -> Self::SubdivideResult {
+        let center = self.cell_bounds.center();
+        CellArray::from_fn(|cell| {
+            let mut result = self.clone();
+            subdivide_bounds(&mut result.cell_bounds, center, cell);
+            result
+        })
+    }
+
+    fn test_order(&self) -> Self::TestOrder {
+        CellArray::from_fn(|cell| cell)
+    }
+
+    fn should_test(&self, nearest: f32) -> bool {
+        debug_assert!(!nearest.is_finite(), "ShellTestGeometry does not support \"pick\" operations");
+        self.min_radius <= self.max_radius && self.overlaps(self.cell_bounds)
+    }
+
+    fn cache_key(&self) -> Option<CacheKey> {
+        let mut key = CacheKey::new();
+        push_bits(&mut key, self.cell_bounds.min);
+        push_bits(&mut key, self.cell_bounds.max);
+        push_bits(&mut key, self.center);
+        key.push(self.min_radius.to_bits());
+        key.push(self.max_radius.to_bits());
+        Some(key)
+    }
+}
+
+/// [`TestGeometry`]: trait.TestGeometry.html
+/// [`Layer::pick`]: struct.Layer.html#method.pick
+/// A type implementing [`TestGeometry`] for axis-aligned ellipsoids (or ellipses, in 2D), e.g.
+/// approximations of character capsules, or spheres under non-uniform scale
+/// 
+/// This may also be used with [`Layer::pick`], to find the object nearest to `center`.  Distances
+/// are then scaled by `radii` along each axis, so that the surface of the ellipsoid is at a distance
+/// of one; see `scaled_distance_to`.
+#[derive(Clone, Debug)]
+pub struct EllipsoidTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32>
+{
+    cell_bounds: Bounds<Point>,
+    center: Point,
+    radii: Point::Diff,
+}
+
+impl<Point> EllipsoidTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32>,
+    Point::Diff: Array<Element = f32>
+{
+    /// Construct ellipsoid test geometry, with a radius along each axis
+    pub fn with_system_bounds(
+        system_bounds: Bounds<Point>,
+        center: Point,
+        radii: Point::Diff) -> Self
+    {
+        Self{
+            cell_bounds: system_bounds,
+            center,
+            radii}
+    }
+
+    /// The distance from `center` to the nearest point of `bounds`, with each axis scaled by the
+    /// inverse of its radius
+    /// 
+    /// This is zero if `bounds` contain `center`, and at most one if they overlap the ellipsoid.
+    pub fn scaled_distance_to(&self, bounds: Bounds<Point>) -> f32 {
+        (0..Point::len())
+            .map(|i| {
+                let nearest = self.center[i].clamp(bounds.min[i], bounds.max[i]);
+                let scaled = (nearest - self.center[i]) / self.radii[i];
+                scaled * scaled
+            })
+            .sum::<f32>()
+            .sqrt()
+    }
+
+    /// Whether the ellipsoid overlaps `bounds`
+    pub(crate) fn overlaps(&self, bounds: Bounds<Point>) -> bool {
+        self.scaled_distance_to(bounds) <= 1f32
+    }
+}
+
+impl<Point> TestGeometry for EllipsoidTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+    Point::Diff: Array<Element = f32> + Debug
+{
+    type SubdivideResult = Point::Cells<Self>;
+    type TestOrder = Point::Cells<usize>;
+
+    fn subdivide(&self) -> Self::SubdivideResult {
+        let center = self.cell_bounds.center();
+        CellArray::from_fn(|cell| {
+            let mut result = self.clone();
+            subdivide_bounds(&mut result.cell_bounds, center, cell);
+            result
+        })
+    }
+
+    fn test_order(&self) -> Self::TestOrder {
+        // the cell containing `center` first, then those nearest it along fewer axes
+        let center = self.cell_bounds.center();
+        let flip = (0..Point::DIM)
+            .filter(|&axis| self.center[axis] >= center[axis])
+            .fold(0, |flip, axis| flip | 1 << axis);
+        CellArray::from_fn(|cell| cell ^ flip)
+    }
+
+    fn should_test(&self, nearest: f32) -> bool {
+        let distance = self.scaled_distance_to(self.cell_bounds);
+        distance <= 1f32 && distance <= nearest
+    }
+
+    fn cache_key(&self) -> Option<CacheKey> {
+        let mut key = CacheKey::new();
+        push_bits(&mut key, self.cell_bounds.min);
+        push_bits(&mut key, self.cell_bounds.max);
+        push_bits(&mut key, self.center);
+        push_bits(&mut key, self.radii);
+        Some(key)
+    }
+}
+
+/// [`TestGeometry`]: trait.TestGeometry.html
+/// A type implementing [`TestGeometry`] for cones, e.g. spotlights or vision cones
+/// 
+/// The cone extends from `apex` along `direction`, up to `range` from the apex (so its far end is a
+/// spherical cap).  Cells are rejected conservatively, by testing their bounding spheres against the
+/// cone, so results may include some objects just outside of it.
+#[derive(Clone, Debug)]
+pub struct ConeTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32>
+{
+    cell_bounds: Bounds<Point>,
+    apex: Point,
+    direction: Point::Diff,
+    half_angle: Rad<f32>,
+    range: f32,
+}
+
+impl<Point> ConeTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32>,
+    Point::Diff: InnerSpace<Scalar = f32>
+{
+    /// Construct cone test geometry; `direction` needn't be normalized
+    pub fn with_system_bounds(
+        system_bounds: Bounds<Point>,
+        apex: Point,
+        direction: Point::Diff,
+        half_angle: Rad<f32>,
+        range: f32) -> Self
+    {
+        Self{
+            cell_bounds: system_bounds,
+            apex,
+            direction: direction.normalize(),
+            half_angle,
+            range}
+    }
+
+    /// Whether the cone may overlap `bounds`, testing the sphere enclosing them
+    pub(crate) fn overlaps(&self, bounds: Bounds<Point>) -> bool {
+        let center = bounds.center();
+        let radius = (bounds.max - center).magnitude();
+        let offset = center - self.apex;
+        let distance = offset.magnitude();
+        if distance <= radius {
+            return true;
+        }
+        if distance - radius > self.range {
+            return false;
+        }
+        let angle = (offset.dot(self.direction) / distance).clamp(-1f32, 1f32).acos();
+        angle - (radius / distance).asin() <= self.half_angle.0
+    }
+}
+
+impl<Point> TestGeometry for ConeTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+    Point::Diff: InnerSpace<Scalar = f32> + Array<Element = f32> + Debug
+{
+    type SubdivideResult = Point::Cells<Self>;
+    type TestOrder = Point::Cells<usize>;
+
+    fn subdivide(&self) -> Self::SubdivideResult {
+        let center = self.cell_bounds.center();
+        CellArray::from_fn(|cell| {
+            let mut result = self.clone();
+            subdivide_bounds(&mut result.cell_bounds, center, cell);
+            result
+        })
+    }
+
+    fn test_order(&self) -> Self::TestOrder {
+        CellArray::from_fn(|cell| cell)
+    }
+
+    fn should_test(&self, nearest: f32) -> bool {
+        debug_assert!(!nearest.is_finite(), "ConeTestGeometry does not support \"pick\" operations");
+        self.overlaps(self.cell_bounds)
+    }
+
+    fn cache_key(&self) -> Option<CacheKey> {
+        let mut key = CacheKey::new();
+        push_bits(&mut key, self.cell_bounds.min);
+        push_bits(&mut key, self.cell_bounds.max);
+        push_bits(&mut key, self.apex);
+        push_bits(&mut key, self.direction);
+        key.push(self.half_angle.0.to_bits());
+        key.push(self.range.to_bits());
+        Some(key)
+    }
+}
+
+/// [`TestGeometry`]: trait.TestGeometry.html
+/// A type implementing [`TestGeometry`] for points
+/// 
+/// Unlike a degenerate [`BoxTestGeometry`], this descends only into the single sub-cell containing
+/// the point at each depth.
+/// 
+/// [`BoxTestGeometry`]: struct.BoxTestGeometry.html
+#[derive(Clone, Debug)]
+pub struct PointTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32>
+{
+    cell_bounds: Bounds<Point>,
+    point: Point,
+}
+
+impl<Point> PointTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32>
+{
+    /// Construct point test geometry
+    pub fn with_system_bounds(
+        system_bounds: Bounds<Point>,
+        point: Point) -> Self
+    {
+        Self{
+            cell_bounds: system_bounds,
+            point}
+    }
+}
+
+impl<Point> TestGeometry for PointTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+    Point::Diff: Debug
+{
+    type SubdivideResult = Point::Cells<Self>;
+    type TestOrder = [usize; 1];
+
+    fn subdivide(&self) -> Self::SubdivideResult {
+        let center = self.cell_bounds.center();
+        CellArray::from_fn(|cell| {
+            let mut result = self.clone();
+            subdivide_bounds(&mut result.cell_bounds, center, cell);
+            result
+        })
+    }
+
+    fn test_order(&self) -> Self::TestOrder {
+        let center = self.cell_bounds.center();
+        [(0..Point::DIM)
+            .filter(|&axis| self.point[axis] >= center[axis])
+            .fold(0, |cell, axis| cell | 1 << axis)]
+    }
+
+    fn should_test(&self, nearest: f32) -> bool {
+        debug_assert!(!nearest.is_finite(), "PointTestGeometry does not support \"pick\" operations");
+        self.cell_bounds.contains(Bounds::new(self.point, self.point))
+    }
+
+    fn cache_key(&self) -> Option<CacheKey> {
+        let mut key = CacheKey::new();
+        push_bits(&mut key, self.cell_bounds.min);
+        push_bits(&mut key, self.cell_bounds.max);
+        push_bits(&mut key, self.point);
+        Some(key)
+    }
+}
+
+/// [`TestGeometry`]: trait.TestGeometry.html
+/// A type implementing [`TestGeometry`] for boxes swept along a displacement, e.g. fast-moving
+/// objects
+/// 
+/// Cells are tested against the volume swept by the box, rather than against a box enclosing its
+/// start and end, so long diagonal moves visit far fewer cells.
+#[derive(Clone, Debug)]
+pub struct SweptBoxTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32>
+{
+    cell_bounds: Bounds<Point>,
+    start_bounds: Bounds<Point>,
+    displacement: Point::Diff,
+}
+
+impl<Point> SweptBoxTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32>,
+    Point::Diff: Array<Element = f32>
+{
+    /// Construct swept box test geometry, for `start_bounds` moving by `displacement`
+    pub fn with_system_bounds(
+        system_bounds: Bounds<Point>,
+        start_bounds: Bounds<Point>,
+        displacement: Point::Diff) -> Self
+    {
+        Self{
+            cell_bounds: system_bounds,
+            start_bounds,
+            displacement}
+    }
+
+    /// Whether the swept volume overlaps `bounds`
+    pub(crate) fn overlaps(&self, bounds: Bounds<Point>) -> bool {
+        // sweep the center of the box through `bounds` expanded by its half-size
+        let half_size = (self.start_bounds.max - self.start_bounds.min) * 0.5f32;
+        let expanded = Bounds::new(bounds.min - half_size, bounds.max + half_size);
+        matches!(expanded.intersect_ray(self.start_bounds.center(), self.displacement),
+            Some((min, max)) if min <= 1f32 && max >= 0f32)
+    }
+}
+
+impl<Point> TestGeometry for SweptBoxTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+    Point::Diff: Array<Element = f32> + Debug
+{
+    type SubdivideResult = Point::Cells<Self>;
+    type TestOrder = Point::Cells<usize>;
+
+    fn subdivide(&self) -> Self::SubdivideResult {
+        let center = self.cell_bounds.center();
+        CellArray::from_fn(|cell| {
+            let mut result = self.clone();
+            subdivide_bounds(&mut result.cell_bounds, center, cell);
+            result
+        })
+    }
+
+    fn test_order(&self) -> Self::TestOrder {
+        CellArray::from_fn(|cell| cell)
+    }
+
+    fn should_test(&self, nearest: f32) -> bool {
+        debug_assert!(!nearest.is_finite(), "SweptBoxTestGeometry does not support \"pick\" operations");
+        self.overlaps(self.cell_bounds)
+    }
+
+    fn cache_key(&self) -> Option<CacheKey> {
+        let mut key = CacheKey::new();
+        push_bits(&mut key, self.cell_bounds.min);
+        push_bits(&mut key, self.cell_bounds.max);
+        push_bits(&mut key, self.start_bounds.min);
+        push_bits(&mut key, self.start_bounds.max);
+        push_bits(&mut key, self.displacement);
+        Some(key)
+    }
+}
+
+/// Why a ray was rejected, see `RayTestGeometry::try_with_system_bounds`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RayError {
+    /// Every component of the direction is zero
+    ZeroDirection,
+    /// A component of the direction is infinite or NaN
+    NonFiniteDirection,
+    /// A component of the origin is infinite or NaN
+    NonFiniteOrigin,
+    /// The minimum or maximum of the range is NaN
+    NanRange,
+}
+
+impl std::fmt::Display for RayError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            RayError::ZeroDirection => write!(f, "ray direction has zero length"),
+            RayError::NonFiniteDirection => write!(f, "ray direction is not finite"),
+            RayError::NonFiniteOrigin => write!(f, "ray origin is not finite"),
+            RayError::NanRange => write!(f, "ray range is NaN"),
+        }
+    }
+}
+
+impl std::error::Error for RayError {}
+
+/// [`TestGeometry`]: trait.TestGeometry.html
+/// A type implementing [`TestGeometry`] for rays
+#[derive(Clone)]
+pub struct RayTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32>
+{
+    cell_bounds: Bounds<Point>,
+    origin: Point,
+    direction: Point::Diff,
+    range_min: f32,
+    range_max: f32
+}
+
+impl<Point> Debug for RayTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32> + VecDim,
+    Point::Diff: std::ops::Index<usize, Output = f32>
+{
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        fn write_arr<Arr>(f: &mut Formatter, arr: &Arr, dim: usize) -> std::fmt::Result
+        where
+            Arr: std::ops::Index<usize, Output = f32>
+        {
+            write!(f, "(")?;
+            for axis in 0..dim {
+                if axis > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{:}", arr[axis])?;
+            }
+            write!(f, ")")
+        }
+
+        write!(f, "RayTestGeometry{{{{")?;
+        write_arr(f, &self.cell_bounds.min, Point::DIM)?;
+        write!(f, " - ")?;
+        write_arr(f, &self.cell_bounds.max, Point::DIM)?;
+        write!(f, "}}, ")?;
+        write_arr(f, &self.origin, Point::DIM)?;
+        write!(f, ", ")?;
+        write_arr(f, &self.direction, Point::DIM)?;
+        write!(f, ", {{{:}-{:}}}}}", self.range_min, self.range_max)
+    }
+}
+
+impl<Point> RayTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32> + VecDim
+{
+    /// Construct ray test geometry
+    /// 
+    /// `range_min` and `range_max` may be infinity or negative infinity, in which case
+    /// the ray will be clamped to system bounds
+    pub fn with_system_bounds(
+        system_bounds: Bounds<Point>,
+        origin: Point,
+        direction: Point::Diff,
+        mut range_min: f32,
+        mut range_max: f32) -> Self
+    where
+        Point: Debug,
+        Point::Diff: ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+    {
+        let distance_0 = (system_bounds.min - origin).div_element_wise(direction);
+        let distance_1 = (system_bounds.max - origin).div_element_wise(direction);
+        for axis in 0..<Point as VecDim>::DIM {
+            let is_forward = direction[axis] > 0f32;
+            let (d0, d1) = if is_forward {
+                    (distance_0[axis], distance_1[axis])
+                } else {
+                    (distance_1[axis], distance_0[axis])
+                };
+            if d0.is_finite() { range_min = range_min.max(d0); }
+            if d1.is_finite() { range_max = range_max.min(d1); }
+        }
+
+        Self{
+            cell_bounds: system_bounds,
+            origin,
+            direction,
+            range_min,
+            range_max}
+    }
+
+    /// [`RayError`]: enum.RayError.html
+    /// Construct ray test geometry, returning a [`RayError`] for a zero-length or non-finite
+    /// direction, a non-finite origin, or a NaN range
+    /// 
+    /// `with_system_bounds` accepts these, but the resulting traversal is degenerate (e.g. visiting
+    /// every cell, or none).  Infinite ranges are accepted, as with `with_system_bounds`.
+    pub fn try_with_system_bounds(
+        system_bounds: Bounds<Point>,
+        origin: Point,
+        direction: Point::Diff,
+        range_min: f32,
+        range_max: f32) -> Result<Self, RayError>
+    where
+        Point: Debug,
+        Point::Diff: ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+    {
+        Self::check(&origin, &direction, range_min, range_max)?;
+        Ok(Self::with_system_bounds(system_bounds, origin, direction, range_min, range_max))
+    }
+
+    /// Check a ray's parameters, see `try_with_system_bounds`
+    pub(crate) fn check(origin: &Point, direction: &Point::Diff, range_min: f32, range_max: f32) -> Result<(), RayError>
+    where
+        Point::Diff: std::ops::Index<usize, Output = f32>
+    {
+        let axes = 0..<Point as VecDim>::DIM;
+        if axes.clone().any(|axis| !origin[axis].is_finite()) {
+            Err(RayError::NonFiniteOrigin)
+        } else if axes.clone().any(|axis| !direction[axis].is_finite()) {
+            Err(RayError::NonFiniteDirection)
+        } else if axes.clone().all(|axis| direction[axis] == 0f32) {
+            Err(RayError::ZeroDirection)
+        } else if range_min.is_nan() || range_max.is_nan() {
+            Err(RayError::NanRange)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// The order in which a ray along `direction` enters sub-cells, see `TestGeometry::test_order`
+fn ray_test_order<Point>(direction: &Point::Diff) -> Point::Cells<usize>
+where
+    Point: EuclideanSpace<Scalar = f32> + VecDim,
+    Point::Diff: std::ops::Index<usize, Output = f32>
+{
+    // axes in order of increasing (absolute) direction; ties are kept in axis order
+    let mut axes: SmallVec<[usize; 4]> = (0..Point::DIM).collect();
+    axes.sort_by(|&lhs, &rhs| direction[lhs].abs()
+        .partial_cmp(&direction[rhs].abs())
+        .unwrap_or(std::cmp::Ordering::Equal));
+
+    CellArray::from_fn(|cell_src| {
+        axes.iter()
+            .enumerate()
+            .map(|(i, &axis)| {
+                let side = (cell_src & (1 << i) != 0) == (direction[axis] >= 0f32);
+                (side as usize) << axis
+            })
+            .fold(0, |cell_dst, bit| cell_dst | bit)
+    })
+}
+
+impl<Point> TestGeometry for RayTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+    Point::Diff: ElementWise + std::ops::Index<usize, Output = f32> + Array<Element = f32> + Debug
+{
+    type SubdivideResult = Point::Cells<Self>;
+    type TestOrder = Point::Cells<usize>;
+
+    fn subdivide(&self) -> Self::SubdivideResult {
+        let center = self.cell_bounds.center();
+        let distance = (self.cell_bounds.center() - self.origin).div_element_wise(self.direction);
+        CellArray::from_fn(|cell| {
+            let mut result = self.clone();
+            let range_min = &mut result.range_min;
+            let range_max = &mut result.range_max;
+            for axis in 0..Point::DIM {
+                let side = cell & (1 << axis) != 0;
+                if distance[axis].is_finite() {
+                    let is_towards = (self.direction[axis] > 0f32) != side;
+                    if is_towards {
+                        *range_max = range_max.min(distance[axis]);
+                    } else {
+                        *range_min = range_min.max(distance[axis]);
+                    }
+                } else if (self.origin[axis] > center[axis]) != side {
+                    *range_min = f32::INFINITY;
+                    *range_max = f32::NEG_INFINITY;
+                }
+            }
+            subdivide_bounds(&mut result.cell_bounds, center, cell);
+            result
+        })
+    }
+
+    fn test_order(&self) -> Self::TestOrder {
+        ray_test_order::<Point>(&self.direction)
+    }
+
+    fn should_test(&self, nearest: f32) -> bool {
+        // cells entered exactly at `nearest` may still contain equally-near objects
+        self.range_min < self.range_max && self.range_min <= nearest
+    }
+    fn cache_key(&self) -> Option<CacheKey> {
+        let mut key = CacheKey::new();
+        push_bits(&mut key, self.cell_bounds.min);
+        push_bits(&mut key, self.cell_bounds.max);
+        push_bits(&mut key, self.origin);
+        push_bits(&mut key, self.direction);
+        key.push(self.range_min.to_bits());
+        key.push(self.range_max.to_bits());
+        Some(key)
+    }
+}
+
+/// [`TestGeometry`]: trait.TestGeometry.html
+/// [`Layer::pick`]: struct.Layer.html#method.pick
+/// A type implementing [`TestGeometry`] for spheres swept along a ray, e.g. character-sized
+/// projectiles
+/// 
+/// This is intended for use with [`Layer::pick`], with distances measured along the ray to the
+/// center of the sphere.  Cells are tested as a ray against their bounds expanded by `radius`, which
+/// is conservative near their corners.
+#[derive(Clone, Debug)]
+pub struct SphereCastTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32>
+{
+    cell_bounds: Bounds<Point>,
+    origin: Point,
+    direction: Point::Diff,
+    radius: f32,
+    range_min: f32,
+    range_max: f32,
+}
+
+impl<Point> SphereCastTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32> + VecDim,
+    Point::Diff: std::ops::Index<usize, Output = f32>
+{
+    /// Construct swept sphere test geometry
+    pub fn with_system_bounds(
+        system_bounds: Bounds<Point>,
+        origin: Point,
+        direction: Point::Diff,
+        radius: f32,
+        range_min: f32,
+        range_max: f32) -> Self
+    {
+        Self{
+            cell_bounds: system_bounds,
+            origin,
+            direction,
+            radius,
+            range_min,
+            range_max}
+    }
+
+    /// The distance along the ray at which the sphere first touches `bounds`, if it does within range
+    pub fn entry_distance(&self, mut bounds: Bounds<Point>) -> Option<f32> {
+        for axis in 0..Point::DIM {
+            bounds.min[axis] -= self.radius;
+            bounds.max[axis] += self.radius;
+        }
+        bounds.intersect_ray(self.origin, self.direction)
+            .map(|(min, max)| (min.max(self.range_min), max.min(self.range_max)))
+            .filter(|&(min, max)| min <= max)
+            .map(|(min, _)| min)
+    }
+}
+
+impl<Point> TestGeometry for SphereCastTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+    Point::Diff: std::ops::Index<usize, Output = f32> + Array<Element = f32> + Debug
+{
+    type SubdivideResult = Point::Cells<Self>;
+    type TestOrder = Point::Cells<usize>;
+
+    fn subdivide(&self) -> Self::SubdivideResult {
+        let center = self.cell_bounds.center();
+        CellArray::from_fn(|cell| {
+            let mut result = self.clone();
+            subdivide_bounds(&mut result.cell_bounds, center, cell);
+            result
+        })
+    }
+
+    fn test_order(&self) -> Self::TestOrder {
+        ray_test_order::<Point>(&self.direction)
+    }
+
+    fn should_test(&self, nearest: f32) -> bool {
+        matches!(self.entry_distance(self.cell_bounds), Some(entry) if entry <= nearest)
+    }
+
+    fn cache_key(&self) -> Option<CacheKey> {
+        let mut key = CacheKey::new();
+        push_bits(&mut key, self.cell_bounds.min);
+        push_bits(&mut key, self.cell_bounds.max);
+        push_bits(&mut key, self.origin);
+        push_bits(&mut key, self.direction);
+        key.push(self.radius.to_bits());
+        key.push(self.range_min.to_bits());
+        key.push(self.range_max.to_bits());
+        Some(key)
+    }
+}
+
+/// [`TestGeometry`]: trait.TestGeometry.html
+/// [`Layer::cast`]: struct.Layer.html#method.cast
+/// A type implementing [`TestGeometry`] for boxes swept along a ray, as used by [`Layer::cast`]
+/// 
+/// Unlike `SweptBoxTestGeometry`, this supports "pick" operations: distances are measured along
+/// `direction` (scaled by its length, as for rays), and cells which the box would first touch beyond
+/// the nearest hit so far are skipped.
+#[derive(Clone, Debug)]
+pub struct CastTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32>
+{
+    cell_bounds: Bounds<Point>,
+    start_bounds: Bounds<Point>,
+    direction: Point::Diff,
+    range_min: f32,
+    range_max: f32,
+}
+
+impl<Point> CastTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32>,
+    Point::Diff: Array<Element = f32>
+{
+    /// Construct test geometry for `start_bounds` moving along `direction`
+    pub fn with_system_bounds(
+        system_bounds: Bounds<Point>,
+        start_bounds: Bounds<Point>,
+        direction: Point::Diff,
+        range_min: f32,
+        range_max: f32) -> Self
+    {
+        Self{
+            cell_bounds: system_bounds,
+            start_bounds,
+            direction,
+            range_min,
+            range_max}
+    }
+
+    /// The distance along `direction` at which the box first touches `bounds`, if it does within range
+    pub fn entry_distance(&self, bounds: Bounds<Point>) -> Option<f32> {
+        // sweep the center of the box through `bounds` expanded by its half-size
+        let half_size = (self.start_bounds.max - self.start_bounds.min) * 0.5f32;
+        let expanded = Bounds::new(bounds.min - half_size, bounds.max + half_size);
+        expanded.intersect_ray(self.start_bounds.center(), self.direction)
+            .map(|(min, max)| (min.max(self.range_min), max.min(self.range_max)))
+            .filter(|&(min, max)| min <= max)
+            .map(|(min, _)| min)
+    }
+}
+
+impl<Point> TestGeometry for CastTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+    Point::Diff: Array<Element = f32> + Debug
+{
+    type SubdivideResult = Point::Cells<Self>;
+    type TestOrder = Point::Cells<usize>;
+
+    fn subdivide(&self) -> Self::SubdivideResult {
+        let center = self.cell_bounds.center();
+        CellArray::from_fn(|cell| {
+            let mut result = self.clone();
+            subdivide_bounds(&mut result.cell_bounds, center, cell);
+            result
+        })
+    }
+
+    fn test_order(&self) -> Self::TestOrder {
+        ray_test_order::<Point>(&self.direction)
+    }
+
+    fn should_test(&self, nearest: f32) -> bool {
+        matches!(self.entry_distance(self.cell_bounds), Some(entry) if entry <= nearest)
+    }
+
+    fn cache_key(&self) -> Option<CacheKey> {
+        let mut key = CacheKey::new();
+        push_bits(&mut key, self.cell_bounds.min);
+        push_bits(&mut key, self.cell_bounds.max);
+        push_bits(&mut key, self.start_bounds.min);
+        push_bits(&mut key, self.start_bounds.max);
+        push_bits(&mut key, self.direction);
+        key.push(self.range_min.to_bits());
+        key.push(self.range_max.to_bits());
+        Some(key)
+    }
+}
+
+/// [`Layer::test_frustum_classified`]: struct.Layer.html#method.test_frustum_classified
+/// How an object is contained by a query volume, see [`Layer::test_frustum_classified`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Containment {
+    /// Some part of the object may be outside of the volume
+    Intersecting,
+    /// The object is entirely inside of the volume
+    Inside,
+}
+
+/// [`TestGeometry`]: trait.TestGeometry.html
+/// A type implementing [`TestGeometry`] for convex volumes bounded by planes, e.g. view frusta
+/// 
+/// Each plane is given as a `(normal, offset)` pair, where a point `p` is inside if
+/// `normal · p + offset >= 0`.  This is the form of planes extracted from the rows of a
+/// view-projection matrix.
+#[derive(Clone, Debug)]
+pub struct FrustumTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32>
+{
+    cell_bounds: Bounds<Point>,
+    /// planes which intersect `cell_bounds`; planes which contain it entirely are removed
+    planes: SmallVec<[(Point::Diff, f32); 6]>,
+    outside: bool,
+}
+
+impl<Point> FrustumTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32> + VecDim,
+    Point::Diff: Array<Element = f32>
+{
+    /// Construct frustum (or other convex volume) test geometry
+    pub fn with_system_bounds(
+        system_bounds: Bounds<Point>,
+        planes: &[(Point::Diff, f32)]) -> Self
+    {
+        let mut result = Self{
+            cell_bounds: system_bounds,
+            planes: planes.iter().cloned().collect(),
+            outside: false};
+        result.classify();
+        result
+    }
+
+    /// Whether the current cell is entirely inside of the volume
+    pub(crate) fn is_inside(&self) -> bool {
+        !self.outside && self.planes.is_empty()
+    }
+
+    /// Remove planes which contain the current cell, or mark it outside if any plane excludes it
+    fn classify(&mut self) {
+        let cell_bounds = self.cell_bounds;
+        let mut outside = false;
+        self.planes.retain(|&mut (normal, offset)| {
+            let (mut min, mut max) = (offset, offset);
+            for axis in 0..Point::DIM {
+                let (near, far) = if normal[axis] >= 0f32 {
+                        (cell_bounds.min[axis], cell_bounds.max[axis])
+                    } else {
+                        (cell_bounds.max[axis], cell_bounds.min[axis])
+                    };
+                min += normal[axis] * near;
+                max += normal[axis] * far;
+            }
+            outside |= max < 0f32;
+            min < 0f32
+        });
+        self.outside = outside;
+    }
+}
+
+impl<Point> FrustumTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32>,
+    Point::Diff: InnerSpace<Scalar = f32>
+{
+    /// Planes bounding an oriented box, for use with `with_system_bounds` or `Layer::test_frustum`
+    /// 
+    /// Each of `axes` is a `(direction, half_extent)` pair; directions should be unit length and
+    /// mutually perpendicular.  Two planes are returned for each axis, with normals facing `center`.
+    pub fn oriented_box_planes(center: Point, axes: &[(Point::Diff, f32)]) -> Vec<(Point::Diff, f32)> {
+        let center = center.to_vec();
+        axes.iter()
+            .flat_map(|&(axis, half_extent)| vec![
+                (axis * -1f32, half_extent + axis.dot(center)),
+                (axis, half_extent - axis.dot(center))])
+            .collect()
+    }
+}
+
+impl FrustumTestGeometry<Point3<f32>> {
+    /// Planes bounding a perspective view frustum, for use with `with_system_bounds` or
+    /// `Layer::test_frustum`
+    /// 
+    /// The camera is at `eye`, looking along `forward` with `up` towards the top of the view.  `fovy`
+    /// is the full vertical field of view and `aspect` the ratio of width to height, as with
+    /// `cgmath::perspective`.  Planes are returned in the order near, far, left, right, bottom, top,
+    /// with unit normals facing the inside of the frustum.
+    pub fn perspective_planes(
+        eye: Point3<f32>,
+        forward: Vector3<f32>,
+        up: Vector3<f32>,
+        fovy: Rad<f32>,
+        aspect: f32,
+        near: f32,
+        far: f32) -> [(Vector3<f32>, f32); 6]
+    {
+        let forward = forward.normalize();
+        let right = forward.cross(up).normalize();
+        let up = right.cross(forward);
+        let half_height = (fovy / 2f32).tan();
+        let half_width = half_height * aspect;
+        let plane = |normal: Vector3<f32>, offset: f32| {
+            let normal = normal.normalize();
+            (normal, offset - normal.dot(eye.to_vec()))
+        };
+        [
+            plane( forward, -near),
+            (-forward, far + forward.dot(eye.to_vec())),
+            plane(forward * half_width + right, 0f32),
+            plane(forward * half_width - right, 0f32),
+            plane(forward * half_height + up, 0f32),
+            plane(forward * half_height - up, 0f32),
+        ]
+    }
+}
+
+impl<Point> TestGeometry for FrustumTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+    Point::Diff: Array<Element = f32> + Debug
+{
+    type SubdivideResult = Point::Cells<Self>;
+    type TestOrder = Point::Cells<usize>;
+
+    fn subdivide(&self) -> Self::SubdivideResult {
+        let center = self.cell_bounds.center();
+        CellArray::from_fn(|cell| {
+            let mut result = self.clone();
+            subdivide_bounds(&mut result.cell_bounds, center, cell);
+            if !result.outside {
+                result.classify();
+            }
+            result
+        })
+    }
+
+    fn test_order(&self) -> Self::TestOrder {
+        CellArray::from_fn(|cell| cell)
+    }
+
+    fn should_test(&self, nearest: f32) -> bool {
+        debug_assert!(!nearest.is_finite(), "FrustumTestGeometry does not support \"pick\" operations");
+        !self.outside
+    }
+    fn cache_key(&self) -> Option<CacheKey> {
+        let mut key = CacheKey::new();
+        push_bits(&mut key, self.cell_bounds.min);
+        push_bits(&mut key, self.cell_bounds.max);
+        for &(normal, offset) in &self.planes {
+            push_bits(&mut key, normal);
+            key.push(offset.to_bits());
+        }
+        key.push(self.outside as u32);
+        Some(key)
+    }
+}
+
+/// [`FrustumTestGeometry`]: struct.FrustumTestGeometry.html
+/// Test geometry for arbitrary convex volumes given as a list of half-spaces (e.g. frusta, wedges, or
+/// oriented boxes, see `FrustumTestGeometry::oriented_box_planes`); an alias of [`FrustumTestGeometry`]
+pub type ConvexTestGeometry<Point> = FrustumTestGeometry<Point>;
+
+/// [`TestGeometry`]: trait.TestGeometry.html
+/// A type implementing [`TestGeometry`] for half-spaces, e.g. everything above a water surface or
+/// beyond a streaming boundary
+/// 
+/// As with [`FrustumTestGeometry`], a point `p` is inside if `normal · p + offset >= 0`.
+/// 
+/// [`FrustumTestGeometry`]: struct.FrustumTestGeometry.html
+#[derive(Clone)]
+pub struct PlaneTestGeometry<Point>(FrustumTestGeometry<Point>)
+where
+    Point: EuclideanSpace<Scalar = f32>;
+
+impl<Point> Debug for PlaneTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32> + Debug,
+    Point::Diff: Debug
+{
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
-        write!(f, "RayTestGeometry{{{{({:}, {:}, {:}) - ({:}, {:}, {:})}}, ({:}, {:}, {:}), ({:}, {:}, {:}), {{{:}-{:}}}}}",
-            self.cell_bounds.min.x,
-            self.cell_bounds.min.y,
-            self.cell_bounds.min.z,
-            self.cell_bounds.max.x,
-            self.cell_bounds.max.y,
-            self.cell_bounds.max.z,
-            self.origin.x,
-            self.origin.y,
-            self.origin.z,
-            self.direction.x,
-            self.direction.y,
-            self.direction.z,
-            self.range_min,
-            self.range_max)
+        write!(f, "PlaneTestGeometry{{{:?}}}", self.0)
     }
 }
 
-impl<Point> RayTestGeometry<Point>
+impl<Point> PlaneTestGeometry<Point>
 where
-    Point: EuclideanSpace<Scalar = f32> + VecDim
+    Point: EuclideanSpace<Scalar = f32> + VecDim,
+    Point::Diff: Array<Element = f32>
 {
-    /// Construct ray test geometry
-    /// 
-    /// `range_min` and `range_max` may be infinity or negative infinity, in which case
-    /// the ray will be clamped to system bounds
+    /// Construct half-space test geometry
     pub fn with_system_bounds(
         system_bounds: Bounds<Point>,
-        origin: Point,
-        direction: Point::Diff,
-        mut range_min: f32,
-        mut range_max: f32) -> Self
-    where
-        Point: Debug,
-        Point::Diff: ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        normal: Point::Diff,
+        offset: f32) -> Self
     {
-        let distance_0 = (system_bounds.min - origin).div_element_wise(direction);
-        let distance_1 = (system_bounds.max - origin).div_element_wise(direction);
-        for axis in 0..<Point as VecDim>::DIM {
-            let is_forward = direction[axis] > 0f32;
-            let (d0, d1) = if is_forward {
-                    (distance_0[axis], distance_1[axis])
-                } else {
-                    (distance_1[axis], distance_0[axis])
-                };
-            if d0.is_finite() { range_min = range_min.max(d0); }
-            if d1.is_finite() { range_max = range_max.min(d1); }
-        }
+        PlaneTestGeometry(FrustumTestGeometry::with_system_bounds(system_bounds, &[(normal, offset)]))
+    }
+}
 
-        Self{
-            cell_bounds: system_bounds,
-            origin,
-            direction,
-            range_min,
-            range_max}
+impl<Point> TestGeometry for PlaneTestGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+    Point::Diff: Array<Element = f32> + Debug
+{
+    type SubdivideResult = Point::Cells<Self>;
+    type TestOrder = Point::Cells<usize>;
+
+    fn subdivide(&self) -> Self::SubdivideResult {
+        let sub_tests = self.0.subdivide();
+        CellArray::from_fn(|cell| PlaneTestGeometry(sub_tests.as_ref()[cell].clone()))
+    }
+
+    fn test_order(&self) -> Self::TestOrder {
+        self.0.test_order()
+    }
+
+    fn should_test(&self, nearest: f32) -> bool {
+        debug_assert!(!nearest.is_finite(), "PlaneTestGeometry does not support \"pick\" operations");
+        !self.0.outside
+    }
+
+    fn cache_key(&self) -> Option<CacheKey> {
+        self.0.cache_key()
+    }
+}
+
+/// [`TestGeometry`]: trait.TestGeometry.html
+/// A type implementing [`TestGeometry`] for convex polygons in 2D, e.g. selection marquees, navmesh
+/// regions, or the ground footprint of an isometric camera
+/// 
+/// Vertices are given in counter-clockwise order.  Cells are tested against each edge and the
+/// polygon's bounding box, so (unlike an arbitrary `FrustumTestGeometry`) no cell is visited unless it
+/// overlaps the polygon.
+#[derive(Clone)]
+pub struct PolygonTestGeometry(FrustumTestGeometry<Point2<f32>>);
+
+impl Debug for PolygonTestGeometry {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "PolygonTestGeometry{{{:?}}}", self.0)
+    }
+}
+
+impl PolygonTestGeometry {
+    /// Construct convex polygon test geometry from counter-clockwise `vertices`
+    /// 
+    /// Panics if there are fewer than three vertices.
+    pub fn with_system_bounds(system_bounds: Bounds<Point2<f32>>, vertices: &[Point2<f32>]) -> Self {
+        PolygonTestGeometry(FrustumTestGeometry::with_system_bounds(
+            system_bounds,
+            &Self::polygon_planes(vertices)))
+    }
+
+    /// Planes bounding a convex polygon, for use with `FrustumTestGeometry`: one for each edge, with
+    /// normals facing the inside of the polygon, then four for its bounding box
+    /// 
+    /// Panics if there are fewer than three vertices.
+    pub fn polygon_planes(vertices: &[Point2<f32>]) -> Vec<(Vector2<f32>, f32)> {
+        assert!(vertices.len() >= 3, "a polygon requires at least three vertices");
+        let edges = vertices.iter().zip(vertices.iter().cycle().skip(1));
+        let mut planes: Vec<(Vector2<f32>, f32)> = edges
+            .map(|(&a, &b)| {
+                let edge = b - a;
+                let normal = Vector2::new(-edge.y, edge.x);
+                (normal, -normal.dot(a.to_vec()))
+            })
+            .collect();
+        let bounds = vertices[1..].iter().fold(
+            Bounds::new(vertices[0], vertices[0]),
+            |bounds, &vertex| bounds.union(Bounds::new(vertex, vertex)));
+        planes.extend_from_slice(&[
+            (Vector2::new( 1f32,  0f32), -bounds.min.x),
+            (Vector2::new(-1f32,  0f32),  bounds.max.x),
+            (Vector2::new( 0f32,  1f32), -bounds.min.y),
+            (Vector2::new( 0f32, -1f32),  bounds.max.y)]);
+        planes
     }
 }
 
-impl TestGeometry for RayTestGeometry<Point2<f32>> {
+impl TestGeometry for PolygonTestGeometry {
     type SubdivideResult = [Self; 4];
     type TestOrder = [usize; 4];
 
+    fn subdivide(&self) -> Self::SubdivideResult {
+        let sub_tests = self.0.subdivide();
+        CellArray::from_fn(|cell| PolygonTestGeometry(sub_tests[cell].clone()))
+    }
+
+    fn test_order(&self) -> Self::TestOrder {
+        self.0.test_order()
+    }
+
+    fn should_test(&self, nearest: f32) -> bool {
+        debug_assert!(!nearest.is_finite(), "PolygonTestGeometry does not support \"pick\" operations");
+        !self.0.outside
+    }
+
+    fn cache_key(&self) -> Option<CacheKey> {
+        self.0.cache_key()
+    }
+}
+
+/// [`TestGeometry`]: trait.TestGeometry.html
+/// A type implementing [`TestGeometry`] for arbitrary implicit shapes, given by a signed distance
+/// function (negative inside the shape, positive outside)
+/// 
+/// Each cell is tested by evaluating `sdf` at its center: it may overlap the shape if the distance
+/// there is no more than the distance from its center to its corners.  This is conservative so long
+/// as `sdf` never overestimates the distance to the shape; bounds (e.g. from combining shapes with
+/// `min` and `max`) are acceptable, but may visit more cells than necessary.
+pub struct SdfTestGeometry<'a, Point, F>
+where
+    Point: EuclideanSpace<Scalar = f32>,
+    F: Fn(Point) -> f32
+{
+    cell_bounds: Bounds<Point>,
+    sdf: &'a F,
+}
+
+impl<'a, Point, F> Clone for SdfTestGeometry<'a, Point, F>
+where
+    Point: EuclideanSpace<Scalar = f32>,
+    F: Fn(Point) -> f32
+{
+    fn clone(&self) -> Self {
+        Self{
+            cell_bounds: self.cell_bounds,
+            sdf: self.sdf}
+    }
+}
+
+impl<'a, Point, F> Debug for SdfTestGeometry<'a, Point, F>
+where
+    Point: EuclideanSpace<Scalar = f32> + Debug,
+    F: Fn(Point) -> f32
+{
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "SdfTestGeometry{{{:?}}}", self.cell_bounds)
+    }
+}
+
+impl<'a, Point, F> SdfTestGeometry<'a, Point, F>
+where
+    Point: EuclideanSpace<Scalar = f32>,
+    F: Fn(Point) -> f32
+{
+    /// Construct test geometry for the shape given by `sdf`
+    pub fn with_system_bounds(system_bounds: Bounds<Point>, sdf: &'a F) -> Self {
+        Self{
+            cell_bounds: system_bounds,
+            sdf}
+    }
+
+    /// Whether the shape may overlap `bounds`
+    pub(crate) fn overlaps(&self, bounds: Bounds<Point>) -> bool {
+        let half_diagonal = (0..Point::len())
+            .map(|i| {
+                let half_size = (bounds.max[i] - bounds.min[i]) * 0.5f32;
+                half_size * half_size
+            })
+            .sum::<f32>()
+            .sqrt();
+        (self.sdf)(bounds.center()) <= half_diagonal
+    }
+}
+
+impl<'a, Point, F> TestGeometry for SdfTestGeometry<'a, Point, F>
+where
+    Point: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+    F: Fn(Point) -> f32
+{
+    type SubdivideResult = Point::Cells<Self>;
+    type TestOrder = Point::Cells<usize>;
+
     fn subdivide(&self) -> Self::SubdivideResult {
         let center = self.cell_bounds.center();
-        let distance = (self.cell_bounds.center() - self.origin).div_element_wise(self.direction);
-        let mut results: [Self; 4] = [
-            self.clone(),
-            self.clone(),
-            self.clone(),
-            self.clone()
-        ];
-        for (cell, result) in results.iter_mut().enumerate() {
-            let range_min = &mut result.range_min;
-            let range_max = &mut result.range_max;
-            for axis in 0..2 {
-                let side = cell & (1 << axis) != 0;
-                if distance[axis].is_finite() {
-                    let is_towards = (self.direction[axis] > 0f32) != side;
-                    if is_towards {
-                        *range_max = range_max.min(distance[axis]);
-                    } else {
-                        *range_min = range_min.max(distance[axis]);
-                    }
-                } else if (self.origin[axis] > center[axis]) != side {
-                    *range_min = std::f32::INFINITY;
-                    *range_max = std::f32::NEG_INFINITY;
-                }
-            }
-            let bounds = &mut result.cell_bounds;
-            #[allow(clippy::needless_range_loop)]
-            for axis in 0..2 {
-                let side = cell & (1 << axis) != 0;
-                if side {
-                    bounds.min[axis] = center[axis];
-                } else {
-                    bounds.max[axis] = center[axis];
-                }
-            }
-        }
-        results
+        CellArray::from_fn(|cell| {
+            let mut result = self.clone();
+            subdivide_bounds(&mut result.cell_bounds, center, cell);
+            result
+        })
     }
 
     fn test_order(&self) -> Self::TestOrder {
-        let abs = self.direction.map(|x| x.abs());
-        #[allow(clippy::collapsible_if)]
-        let axes = if abs.x <= abs.y { [0, 1] } else { [1, 0] };
-
-        let mut order: [usize; 4] = [0; 4];
-        for (cell_src, cell_dst) in order.iter_mut().enumerate() {
-            let i0 = (cell_src & 1 != 0) == (self.direction[axes[0]] >= 0f32);
-            let i1 = (cell_src & 2 != 0) == (self.direction[axes[1]] >= 0f32);
-            *cell_dst =
-                ((i0 as usize) << axes[0]) |
-                ((i1 as usize) << axes[1]);
-        }
+        CellArray::from_fn(|cell| cell)
+    }
+
+    fn should_test(&self, nearest: f32) -> bool {
+        debug_assert!(!nearest.is_finite(), "SdfTestGeometry does not support \"pick\" operations");
+        self.overlaps(self.cell_bounds)
+    }
+}
+
+/// Subdivide two geometries together, pairing their children
+fn subdivide_pair<A, B, T, F>(lhs: &A, rhs: &B, pair: F) -> SmallVec<[T; 8]>
+where
+    A: TestGeometry + Clone,
+    B: TestGeometry + Clone,
+    F: Fn(A, B) -> T
+{
+    let lhs = lhs.subdivide();
+    let rhs = rhs.subdivide();
+    debug_assert_eq!(lhs.as_ref().len(), rhs.as_ref().len(), "combined geometries must have the same dimensions");
+    lhs.as_ref().iter()
+        .zip(rhs.as_ref())
+        .map(|(lhs, rhs)| pair(lhs.clone(), rhs.clone()))
+        .collect()
+}
+
+/// The cache key of two geometries combined by the operation `tag`, if both have one
+fn pair_cache_key<A, B>(tag: u32, lhs: &A, rhs: &B) -> Option<CacheKey>
+where
+    A: TestGeometry,
+    B: TestGeometry
+{
+    let lhs = lhs.cache_key()?;
+    let rhs = rhs.cache_key()?;
+    let mut key = CacheKey::new();
+    key.push(tag);
+    key.push(lhs.len() as u32);
+    key.extend(lhs);
+    key.extend(rhs);
+    Some(key)
+}
 
-        order
+/// [`TestGeometry`]: trait.TestGeometry.html
+/// A type implementing [`TestGeometry`] for the union of two geometries, so that a region made of
+/// several shapes is traversed once
+/// 
+/// Both geometries must be constructed with the same `system_bounds`.  Cells are tested in the order
+/// given by `lhs`.  This supports `pick` operations only if both geometries do.
+#[derive(Clone, Debug)]
+pub struct UnionTestGeometry<A, B> {
+    lhs: A,
+    rhs: B,
+}
+
+impl<A, B> UnionTestGeometry<A, B> {
+    /// Construct test geometry for the union of `lhs` and `rhs`
+    pub fn new(lhs: A, rhs: B) -> Self {
+        Self{lhs, rhs}
+    }
+}
+
+impl<A, B> TestGeometry for UnionTestGeometry<A, B>
+where
+    A: TestGeometry + Clone,
+    B: TestGeometry + Clone
+{
+    type SubdivideResult = SmallVec<[Self; 8]>;
+    type TestOrder = A::TestOrder;
+
+    fn subdivide(&self) -> Self::SubdivideResult {
+        subdivide_pair(&self.lhs, &self.rhs, Self::new)
+    }
+
+    fn test_order(&self) -> Self::TestOrder {
+        self.lhs.test_order()
     }
 
     fn should_test(&self, nearest: f32) -> bool {
-        self.range_min < self.range_max && self.range_min < nearest
+        self.lhs.should_test(nearest) || self.rhs.should_test(nearest)
+    }
+
+    fn cache_key(&self) -> Option<CacheKey> {
+        pair_cache_key(0, &self.lhs, &self.rhs)
+    }
+}
+
+/// [`TestGeometry`]: trait.TestGeometry.html
+/// A type implementing [`TestGeometry`] for the intersection of two geometries, e.g. a sphere
+/// clipped by a frustum
+/// 
+/// As each geometry is tested against cells rather than objects, results include objects in cells
+/// overlapping both geometries, even if those overlaps are disjoint.  Both geometries must be
+/// constructed with the same `system_bounds`.  Cells are tested in the order given by `lhs`.  This
+/// supports `pick` operations only if both geometries do.
+#[derive(Clone, Debug)]
+pub struct IntersectionTestGeometry<A, B> {
+    lhs: A,
+    rhs: B,
+}
+
+impl<A, B> IntersectionTestGeometry<A, B> {
+    /// Construct test geometry for the intersection of `lhs` and `rhs`
+    pub fn new(lhs: A, rhs: B) -> Self {
+        Self{lhs, rhs}
     }
 }
 
-impl TestGeometry for RayTestGeometry<Point3<f32>> {
-    type SubdivideResult = [Self; 8];
-    type TestOrder = [usize; 8];
+impl<A, B> TestGeometry for IntersectionTestGeometry<A, B>
+where
+    A: TestGeometry + Clone,
+    B: TestGeometry + Clone
+{
+    type SubdivideResult = SmallVec<[Self; 8]>;
+    type TestOrder = A::TestOrder;
 
     fn subdivide(&self) -> Self::SubdivideResult {
-        let center = self.cell_bounds.center();
-        let distance = (self.cell_bounds.center() - self.origin).div_element_wise(self.direction);
-        let mut results: [Self; 8] = [
-            self.clone(),
-            self.clone(),
-            self.clone(),
-            self.clone(),
-            self.clone(),
-            self.clone(),
-            self.clone(),
-            self.clone()
-        ];
-        for (cell, result) in results.iter_mut().enumerate() {
-            let range_min = &mut result.range_min;
-            let range_max = &mut result.range_max;
-            for axis in 0..3 {
-                let side = cell & (1 << axis) != 0;
-                if distance[axis].is_finite() {
-                    let is_towards = (self.direction[axis] > 0f32) != side;
-                    if is_towards {
-                        *range_max = range_max.min(distance[axis]);
-                    } else {
-                        *range_min = range_min.max(distance[axis]);
-                    }
-                } else if (self.origin[axis] > center[axis]) != side {
-                    *range_min = std::f32::INFINITY;
-                    *range_max = std::f32::NEG_INFINITY;
-                }
-            }
-            let bounds = &mut result.cell_bounds;
-            #[allow(clippy::needless_range_loop)]
-            for axis in 0..3 {
-                let side = cell & (1 << axis) != 0;
-                if side {
-                    bounds.min[axis] = center[axis];
-                } else {
-                    bounds.max[axis] = center[axis];
-                }
-            }
-        }
-        results
+        subdivide_pair(&self.lhs, &self.rhs, Self::new)
     }
 
     fn test_order(&self) -> Self::TestOrder {
-        let abs = self.direction.map(|x| x.abs());
-        #[allow(clippy::collapsible_if)]
-        let axes = if abs.x <= abs.y && abs.x <= abs.z {
-            if abs.y <= abs.z { [0, 1, 2] } else { [0, 2, 1] }
-        } else if abs.y <= abs.z {
-            if abs.x <= abs.z { [1, 0, 2] } else { [1, 2, 0] }
-        } else {
-            if abs.x <= abs.y { [2, 0, 1] } else { [2, 1, 0] }
-        };
+        self.lhs.test_order()
+    }
 
-        let mut order: [usize; 8] = [0; 8];
-        for (cell_src, cell_dst) in order.iter_mut().enumerate() {
-            let i0 = (cell_src & 1 != 0) == (self.direction[axes[0]] >= 0f32);
-            let i1 = (cell_src & 2 != 0) == (self.direction[axes[1]] >= 0f32);
-            let i2 = (cell_src & 4 != 0) == (self.direction[axes[2]] >= 0f32);
-            *cell_dst =
-                ((i0 as usize) << axes[0]) |
-                ((i1 as usize) << axes[1]) |
-                ((i2 as usize) << axes[2])
-        }
+    fn should_test(&self, nearest: f32) -> bool {
+        self.lhs.should_test(nearest) && self.rhs.should_test(nearest)
+    }
+
+    fn cache_key(&self) -> Option<CacheKey> {
+        pair_cache_key(1, &self.lhs, &self.rhs)
+    }
+}
+
+/// Old and new frusta traversed together, visiting only cells which are inside one but not the other
+/// 
+/// Cells which are entirely inside or outside of both contain no changes in visibility, so they're
+/// skipped.
+#[derive(Clone)]
+pub(crate) struct FrustumDeltaGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32>
+{
+    pub(crate) old: FrustumTestGeometry<Point>,
+    pub(crate) new: FrustumTestGeometry<Point>,
+}
+
+impl<Point> Debug for FrustumDeltaGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32> + Debug,
+    Point::Diff: Debug
+{
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "FrustumDeltaGeometry{{{:?}, {:?}}}", self.old, self.new)
+    }
+}
+
+impl<Point> FrustumDeltaGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32>
+{
+    /// Whether entries in the current cell would be found by a test of the old and new frustum
+    pub(crate) fn visibility(&self) -> (bool, bool) {
+        (!self.old.outside, !self.new.outside)
+    }
+}
+
+impl<Point> TestGeometry for FrustumDeltaGeometry<Point>
+where
+    Point: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+    Point::Diff: Array<Element = f32> + Debug
+{
+    type SubdivideResult = Point::Cells<Self>;
+    type TestOrder = Point::Cells<usize>;
+
+    fn subdivide(&self) -> Self::SubdivideResult {
+        let old = self.old.subdivide();
+        let new = self.new.subdivide();
+        CellArray::from_fn(|cell| Self{
+            old: old.as_ref()[cell].clone(),
+            new: new.as_ref()[cell].clone()})
+    }
 
-        order
+    fn test_order(&self) -> Self::TestOrder {
+        CellArray::from_fn(|cell| cell)
     }
 
     fn should_test(&self, nearest: f32) -> bool {
-        self.range_min < self.range_max && self.range_min < nearest
+        debug_assert!(!nearest.is_finite(), "FrustumDeltaGeometry does not support \"pick\" operations");
+        let both_outside = self.old.outside && self.new.outside;
+        let both_inside = self.old.is_inside() && self.new.is_inside();
+        !both_outside && !both_inside
     }
 }
 
@@ -692,6 +2243,26 @@ impl TestGeometry for RayTestGeometry<Point3<f32>> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn perspective_planes() {
+        let planes = FrustumTestGeometry::perspective_planes(
+            Point3::new(1f32, 2f32, 3f32),
+            Vector3::new(0f32, 0f32, -2f32),
+            Vector3::new(0f32, 1f32, 0f32),
+            Rad(std::f32::consts::FRAC_PI_2),
+            2f32,
+            1f32,
+            10f32);
+        let inside = |p: Point3<f32>| planes.iter().all(|&(normal, offset)| normal.dot(p.to_vec()) + offset >= 0f32);
+        assert!(inside(Point3::new(1f32, 2f32, -2f32)));
+        assert!(inside(Point3::new(10.5f32, 6.5f32, -2f32)));
+        assert!(!inside(Point3::new(1f32, 2f32, 2.5f32)));
+        assert!(!inside(Point3::new(1f32, 2f32, -7.5f32)));
+        assert!(!inside(Point3::new(11.5f32, 2f32, -2f32)));
+        assert!(!inside(Point3::new(1f32, 7.5f32, -2f32)));
+        assert!(!inside(Point3::new(1f32, -3.5f32, -2f32)));
+    }
+
     #[test]
     fn system_bounds() {
         let system_bounds = Bounds{
@@ -705,4 +2276,53 @@ mod tests {
         let actual = system_bounds.to_global(local);
         assert_eq!(actual, expected);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn ray_test_order() {
+        let system_bounds = Bounds{
+            min: Point3::new(0f32, 0f32, 0f32),
+            max: Point3::new(1f32, 1f32, 1f32)};
+        let ray = RayTestGeometry::with_system_bounds(
+            system_bounds,
+            Point3::new(0.1f32, 0.9f32, 0.1f32),
+            Vector3::new(1f32, -0.5f32, 0.25f32),
+            0f32,
+            f32::INFINITY);
+
+        // nearest cell (-x, +y, -z) first, farthest cell (+x, -y, +z) last
+        assert_eq!(ray.test_order(), [2, 6, 0, 4, 3, 7, 1, 5]);
+
+        let ray = RayTestGeometry::with_system_bounds(
+            Bounds{min: Point2::new(0f32, 0f32), max: Point2::new(1f32, 1f32)},
+            Point2::new(0.9f32, 0.1f32),
+            Vector2::new(-1f32, 0.5f32),
+            0f32,
+            f32::INFINITY);
+        assert_eq!(ray.test_order(), [1, 3, 0, 2]);
+    }
+
+    #[test]
+    fn array_conversions() {
+        let bounds = Bounds{min: [1f32, 2f32, 3f32], max: [4f32, 5f32, 6f32]};
+        let points = bounds.to_points();
+        assert_eq!(points.max, Point3::new(4f32, 5f32, 6f32));
+        assert_eq!(points.to_arrays(), bounds);
+
+        let bounds = Bounds{min: [1u32, 2u32], max: [3u32, 4u32]};
+        assert_eq!(bounds.to_points().to_arrays(), bounds);
+    }
+
+    #[cfg(feature="mint")]
+    #[test]
+    fn mint_conversions() {
+        let bounds = Bounds{
+            min: mint::Point3{x: 1f32, y: 2f32, z: 3f32},
+            max: mint::Point3{x: 4f32, y: 5f32, z: 6f32}};
+        let points: Bounds<Point3<f32>> = bounds.into();
+        assert_eq!(points.max, Point3::new(4f32, 5f32, 6f32));
+        assert_eq!(Bounds::<mint::Point3<f32>>::from(points), bounds);
+
+        let bounds = Bounds{min: mint::Point2{x: 1u32, y: 2u32}, max: mint::Point2{x: 3u32, y: 4u32}};
+        assert_eq!(Bounds::<mint::Point2<u32>>::from(Bounds::<Point2<u32>>::from(bounds)), bounds);
+    }
+}
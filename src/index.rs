@@ -3,6 +3,7 @@
 use cgmath::{Point2, Point3, Vector2, Vector3};
 use cgmath::prelude::*;
 use std::fmt::{Debug, Formatter};
+use std::hash::Hash;
 
 /// An index representing an object's position and scale
 /// 
@@ -30,14 +31,21 @@ use std::fmt::{Debug, Formatter};
 /// [`Index32_2D`]: struct.Index32_2D.html
 /// [`Index64_2D`]: struct.Index64_2D.html
 /// [`Index64_3D`]: struct.Index64_3D.html
+/// [`Index64_3DDepthHigh`]: struct.Index64_3DDepthHigh.html
 /// 
 /// * [`Index32_2D`]: A 32-bit 2D index type providing 14 bits' precision per axis
 /// * [`Index64_2D`]: A 64-bit 2D index type providing 29 bits' precision per axis
 /// * [`Index64_3D`]: A 64-bit 3D index type providing 19 bits' precision per axis
+/// * [`Index64_3DDepthHigh`]: Equivalent to [`Index64_3D`], but storing depth in the high bits
+/// 
+/// Index types with equal precision differ only in bit layout, which affects the cost of `depth`,
+/// `subdivide`, `overlaps`, and comparisons (and therefore sorting).  Which is fastest depends on the
+/// workload, so it is worth selecting one with a type alias (e.g. `type Index = Index64_3DDepthHigh;`)
+/// and comparing them; see `examples/index_layouts.rs`.
 
-pub trait SpatialIndex: Clone + Copy + Default + Ord + Send + std::fmt::Debug {
+pub trait SpatialIndex: Clone + Copy + Default + Hash + Ord + Send + std::fmt::Debug {
     type Diff: cgmath::VectorSpace<Scalar = u32>;
-    type Point: Copy + EuclideanSpace<Diff = Self::Diff, Scalar = u32>;
+    type Point: Copy + Send + Sync + EuclideanSpace<Diff = Self::Diff, Scalar = u32>;
 
     /// clamps a depth value to the representable range
     fn clamp_depth(_: u32) -> u32;
@@ -64,19 +72,22 @@ pub trait SpatialIndex: Clone + Copy + Default + Ord + Send + std::fmt::Debug {
 
 macro_rules! index_impl {
     (index: $name:ident, $dim:tt, $bits:tt, $depth_bits:tt, $axis_bits:tt) => {
-        #[derive(Clone, Copy, Default, Eq, Ord, PartialEq, PartialOrd)]
+        index_impl!{index: $name, $dim, $bits, $depth_bits, $axis_bits, depth_low}
+    };
+    (index: $name:ident, $dim:tt, $bits:tt, $depth_bits:tt, $axis_bits:tt, $layout:ident) => {
+        #[derive(Clone, Copy, Default, Eq, Hash, PartialEq)]
         #[cfg_attr(any(test, feature="serde"), derive(Deserialize, Serialize))]
+        #[cfg_attr(feature="bevy_reflect", derive(bevy_reflect::Reflect))]
         pub struct $name(index_impl!{primitive_type: $bits});
 
         impl $name {
             const DEPTH_BITS: u32 = $depth_bits;
-            const DEPTH_SHIFT: u32 = 0;
             const DEPTH_MASK: index_impl!{primitive_type: $bits} = (((1 as index_impl!{primitive_type: $bits}) << Self::DEPTH_BITS) - 1) << Self::DEPTH_SHIFT;
             const AXIS_BITS: u32 = $axis_bits;
             const ORIGIN_BITS: u32 = $dim * Self::AXIS_BITS;
-            const ORIGIN_SHIFT: u32 = Self::DEPTH_SHIFT + Self::DEPTH_BITS;
             const ORIGIN_MASK: index_impl!{primitive_type: $bits} = (((1 as index_impl!{primitive_type: $bits}) << (Self::ORIGIN_BITS)) - 1) << Self::ORIGIN_SHIFT;
 
+            index_impl!{layout: $layout, $bits}
             index_impl!{codec: $dim, $bits}
         
             fn level_mask(depth: u32) -> index_impl!{primitive_type: $bits} {
@@ -86,6 +97,18 @@ macro_rules! index_impl {
             }
         }
 
+        impl Ord for $name {
+            fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+                self.sort_key().cmp(&other.sort_key())
+            }
+        }
+
+        impl PartialOrd for $name {
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
         impl SpatialIndex for $name {
             type Diff  = index_impl!{vector_type: $dim};
             type Point = index_impl!{point_type: $dim};
@@ -122,6 +145,28 @@ macro_rules! index_impl {
             }
         }
     };
+    (layout: depth_low, $bits:tt) => {
+        const DEPTH_SHIFT: u32 = 0;
+        const ORIGIN_SHIFT: u32 = Self::DEPTH_SHIFT + Self::DEPTH_BITS;
+
+        /// origin bits are already higher-significance than depth bits
+        #[inline]
+        fn sort_key(self) -> index_impl!{primitive_type: $bits} {
+            let Self(index) = self;
+            index
+        }
+    };
+    (layout: depth_high, $bits:tt) => {
+        const DEPTH_SHIFT: u32 = $bits - Self::DEPTH_BITS;
+        const ORIGIN_SHIFT: u32 = 0;
+
+        /// rotate depth bits below origin bits, for a topological ordering
+        #[inline]
+        fn sort_key(self) -> index_impl!{primitive_type: $bits} {
+            let Self(index) = self;
+            index.rotate_left(Self::DEPTH_BITS)
+        }
+    };
     (primitive_type: 32) => {u32};
     (primitive_type: 64) => {u64};
     (vector_type: 2) => {Vector2<u32>};
@@ -293,6 +338,7 @@ macro_rules! index_impl {
 index_impl!{index: Index32_2D, 2, 32, 4, 14}
 index_impl!{index: Index64_2D, 2, 64, 5, 29}
 index_impl!{index: Index64_3D, 3, 64, 5, 19}
+index_impl!{index: Index64_3DDepthHigh, 3, 64, 5, 19, depth_high}
 
 impl Debug for Index64_3D {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
@@ -308,6 +354,20 @@ impl Debug for Index64_3D {
     }
 }
 
+impl Debug for Index64_3DDepthHigh {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let Self(index) = self;
+        let origin_bits = (index & Self::ORIGIN_MASK) >> Self::ORIGIN_SHIFT;
+        let origin = self.origin();
+        write!(f, "Index64_3DDepthHigh{{origin={{0o{:019o}, <0x{:08x}, 0x{:08x}, 0x{:08x}>}}, depth={:}}}",
+            origin_bits,
+            origin.x,
+            origin.y,
+            origin.z,
+            self.depth())
+    }
+}
+
 impl Debug for Index32_2D {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
         let Self(index) = self;
@@ -372,4 +432,31 @@ mod tests {
             assert_eq!(actual, expected);
         }
     }
+
+    #[test]
+    fn layouts_agree() {
+        let mut prng = rand_chacha::ChaChaRng::seed_from_u64(0);
+        let mut gen = || {
+            let depth = prng.gen_range(0, 20);
+            let mask = !(!0u32).checked_shr(depth).unwrap_or(0);
+            let origin = Point3::new(prng.gen::<u32>() & mask, prng.gen::<u32>() & mask, prng.gen::<u32>() & mask);
+            (
+                Index64_3D::default().set_origin(origin).set_depth(depth),
+                Index64_3DDepthHigh::default().set_origin(origin).set_depth(depth)
+            )
+        };
+        for _ in 0..10000 {
+            let (lhs_low, lhs_high) = gen();
+            let (rhs_low, rhs_high) = gen();
+            assert_eq!(lhs_low.origin(), lhs_high.origin());
+            assert_eq!(lhs_low.depth(), lhs_high.depth());
+            assert_eq!(lhs_low.cmp(&rhs_low), lhs_high.cmp(&rhs_high));
+            assert_eq!(lhs_low.overlaps(rhs_low), lhs_high.overlaps(rhs_high));
+            let (low, high) = (lhs_low.subdivide(), lhs_high.subdivide());
+            assert_eq!(low.is_some(), high.is_some());
+            for (low, high) in low.iter().flatten().zip(high.iter().flatten()) {
+                assert_eq!((low.origin(), low.depth()), (high.origin(), high.depth()));
+            }
+        }
+    }
 }
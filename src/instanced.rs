@@ -0,0 +1,234 @@
+// mlodato, 2020
+
+use crate::frozen::FrozenLayer;
+use crate::geom::{Bounds, BoxTestGeometry, IndexGenerator, RayTestGeometry, TestGeometry, VecDim};
+use crate::index::SpatialIndex;
+use crate::traits::ObjectID;
+
+use cgmath::prelude::*;
+
+use std::fmt::Debug;
+
+/// [`FrozenLayer`]: struct.FrozenLayer.html
+/// A single baked [`FrozenLayer`] placed at any number of instances, e.g. a prefab repeated
+/// throughout a world
+///
+/// Each instance has a transform mapping the shared (model) space of the `FrozenLayer` into world
+/// space.  Queries are given in world space, transformed into model space for each instance, and
+/// results are tagged with the index of the instance they were found in.  As with
+/// `Layer::test_box_transformed`, boxes are expanded to stay axis-aligned in model space.
+///
+/// Transforms must be invertible; the inverse of each is computed when it's added.
+#[derive(Clone)]
+pub struct InstancedLayer<Index, ID, T>
+where
+    Index: SpatialIndex,
+    ID: ObjectID,
+    Bounds<Index::Point>: IndexGenerator<Index>
+{
+    frozen: FrozenLayer<Index, ID>,
+    /// each instance's transform and its inverse
+    instances: Vec<(T, T)>,
+    test_results: Vec<(usize, ID)>,
+}
+
+impl<Index, ID, T> InstancedLayer<Index, ID, T>
+where
+    Index: SpatialIndex,
+    ID: ObjectID,
+    Bounds<Index::Point>: IndexGenerator<Index>
+{
+    /// Create an `InstancedLayer` with no instances
+    pub fn new(frozen: FrozenLayer<Index, ID>) -> Self {
+        Self{
+            frozen,
+            instances: Vec::new(),
+            test_results: Vec::new()}
+    }
+
+    /// The shared `FrozenLayer`
+    pub fn frozen(&self) -> &FrozenLayer<Index, ID> {
+        &self.frozen
+    }
+
+    /// The shared `FrozenLayer`, e.g. to enable its query cache
+    pub fn frozen_mut(&mut self) -> &mut FrozenLayer<Index, ID> {
+        &mut self.frozen
+    }
+
+    /// The number of instances
+    pub fn len(&self) -> usize {
+        self.instances.len()
+    }
+
+    /// Whether there are no instances
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+
+    /// The transform of instance `index`
+    pub fn instance(&self, index: usize) -> &T {
+        &self.instances[index].0
+    }
+
+    /// Remove all instances
+    pub fn clear(&mut self) {
+        self.instances.clear();
+    }
+
+    /// Add an instance placed by `transform`, returning its index
+    ///
+    /// Panics if `transform` isn't invertible.
+    pub fn add_instance<Point_>(&mut self, transform: T) -> usize
+    where
+        Point_: EuclideanSpace<Scalar = f32>,
+        T: Transform<Point_>
+    {
+        let inverse = transform.inverse_transform().expect("transform must be invertible");
+        self.instances.push((transform, inverse));
+        self.instances.len() - 1
+    }
+
+    /// Move instance `index`, e.g. for a prefab which isn't entirely static
+    ///
+    /// Panics if `transform` isn't invertible.
+    pub fn set_instance<Point_>(&mut self, index: usize, transform: T)
+    where
+        Point_: EuclideanSpace<Scalar = f32>,
+        T: Transform<Point_>
+    {
+        let inverse = transform.inverse_transform().expect("transform must be invertible");
+        self.instances[index] = (transform, inverse);
+    }
+
+    /// [`FrozenLayer::test_box`]: struct.FrozenLayer.html#method.test_box
+    /// Equivalent to [`FrozenLayer::test_box`] for every instance, returning `(instance, id)` pairs
+    ///
+    /// `test_bounds` are in world space; `system_bounds` are in model space, as provided when the
+    /// `FrozenLayer` was built.  Instances whose transformed `system_bounds` don't overlap
+    /// `test_bounds` are skipped.  Results are ordered by instance.
+    pub fn test_box<Point_>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        test_bounds: Bounds<Point_>,
+        max_depth: Option<u32>) -> &Vec<(usize, ID)>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + Debug,
+        Point_::Diff: ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        BoxTestGeometry<Point_>: TestGeometry,
+        T: Transform<Point_>
+    {
+        let Self{frozen, instances, test_results} = self;
+        test_results.clear();
+        for (instance, (transform, inverse)) in instances.iter().enumerate() {
+            if !system_bounds.transformed(transform).overlaps(test_bounds) {
+                continue;
+            }
+            let found = frozen.test_box(system_bounds, test_bounds.transformed(inverse), max_depth);
+            test_results.extend(found.iter().map(|&id| (instance, id)));
+        }
+        test_results
+    }
+
+    /// [`Layer::pick_ray_transformed`]: struct.Layer.html#method.pick_ray_transformed
+    /// Pick the nearest object along a ray through all instances, returning its distance, instance,
+    /// ID, and the (world space) point where it was hit, see [`Layer::pick_ray_transformed`]
+    ///
+    /// `get_dist` receives the instance index, and the origin and direction of the ray in model space.
+    /// Instances are visited in order, each limited to the nearest distance found so far.
+    #[allow(clippy::too_many_arguments)]
+    pub fn pick_ray<Point_, GetDist>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        origin   : Point_,
+        direction: Point_::Diff,
+        max_dist: f32,
+        max_depth: Option<u32>,
+        mut get_dist: GetDist) -> Option<(f32, usize, ID, Point_)>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+        Point_::Diff: VectorSpace<Scalar = f32> + ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        RayTestGeometry<Point_>: TestGeometry,
+        T: Transform<Point_>,
+        GetDist: FnMut(usize, &Point_, &Point_::Diff, f32, ID) -> f32
+    {
+        let Self{frozen, instances, ..} = self;
+        let mut nearest: Option<(f32, usize, ID)> = None;
+        for (instance, (_, inverse)) in instances.iter().enumerate() {
+            let max_dist = nearest.map_or(max_dist, |(dist, _, _)| dist);
+            let origin = inverse.transform_point(origin);
+            let direction = inverse.transform_vector(direction);
+            let test_geom = RayTestGeometry::with_system_bounds(
+                system_bounds,
+                origin,
+                direction,
+                0f32,
+                max_dist);
+            let hit = frozen.pick(&test_geom, max_dist, max_depth, |_, max_dist, id| {
+                get_dist(instance, &origin, &direction, max_dist, id)
+            });
+            if let Some((dist, id)) = hit {
+                nearest = Some((dist, instance, id));
+            }
+        }
+        nearest.map(|(dist, instance, id)| (dist, instance, id, origin + direction * dist))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InstancedLayer;
+    use crate::geom::Bounds;
+    use crate::index::Index64_2D;
+    use crate::layer::{Layer, LayerBuilder};
+
+    use cgmath::{Basis2, Decomposed, Point2, Rad, Vector2};
+    use cgmath::prelude::*;
+
+    #[test]
+    fn instanced_queries() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let objects: Vec<_> = (0..8u32)
+            .map(|id| {
+                let min = Point2::new(id as f32 * 2f32 + 0.25f32, 0.25f32);
+                (Bounds{min, max: min + Vector2::new(1.5f32, 1.5f32)}, id)
+            })
+            .collect();
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, objects.iter().cloned());
+
+        let mut instanced = InstancedLayer::new(layer.freeze());
+        let placed = |x: f32, angle: f32| Decomposed{
+            scale: 1f32,
+            rot: Basis2::from_angle(Rad(angle)),
+            disp: Vector2::new(x, 0f32)};
+        assert_eq!(instanced.add_instance(placed(100f32, 0f32)), 0);
+        assert_eq!(instanced.add_instance(placed(200f32, 0f32)), 1);
+        assert_eq!(instanced.add_instance(placed(300f32, std::f32::consts::FRAC_PI_2)), 2);
+        assert_eq!(instanced.len(), 3);
+
+        let test_bounds = Bounds{min: Point2::new(102.5f32, 0.5f32), max: Point2::new(104.5f32, 1f32)};
+        assert_eq!(instanced.test_box(system_bounds, test_bounds, None), &vec![(0, 1), (0, 2)]);
+
+        // a quarter turn, so model +x is world +y
+        let test_bounds = Bounds{min: Point2::new(298.5f32, 4.5f32), max: Point2::new(299.5f32, 5f32)};
+        assert_eq!(instanced.test_box(system_bounds, test_bounds, None), &vec![(2, 2)]);
+
+        let test_bounds = Bounds{min: Point2::new(0f32, 0f32), max: Point2::new(400f32, 1f32)};
+        let found = instanced.test_box(system_bounds, test_bounds, None).clone();
+        assert_eq!(found.iter().filter(|&&(instance, _)| instance == 1).count(), 8);
+
+        // a ray along +x at y = 1 passes through both unrotated instances
+        let hit = instanced.pick_ray(system_bounds, Point2::new(150f32, 1f32), Vector2::new(1f32, 0f32), 1000f32, None,
+            |_, origin, direction, _, id| {
+                let (bounds, _) = objects[id as usize];
+                bounds.intersect_ray(*origin, *direction).map_or(f32::INFINITY, |(min, _)| min)
+            });
+        let (dist, instance, id, point) = hit.unwrap();
+        assert_eq!((instance, id), (1, 0));
+        assert!((dist - 50.25f32).abs() < 1e-4);
+        assert!((point - Point2::new(200.25f32, 1f32)).magnitude() < 1e-4);
+    }
+}
@@ -1,23 +1,52 @@
 // mlodato, 20190806
 
+use crate::analysis::{LayerAnalysis, MemoryReport};
+use crate::bounds_table::BoundsTable;
+use crate::frozen::{FrozenLayer, NodeRanges};
 use crate::geom::{
     Bounds,
+    BoundedTestGeometry,
     BoxTestGeometry,
+    CastTestGeometry,
+    ConeTestGeometry,
+    Containment,
+    EllipsoidTestGeometry,
+    FrustumTestGeometry,
     IndexGenerator,
+    PlaneTestGeometry,
+    PointTestGeometry,
+    PolygonTestGeometry,
+    RayError,
     RayTestGeometry,
+    RefinedTestGeometry,
+    SdfTestGeometry,
+    ShellTestGeometry,
+    SphereCastTestGeometry,
+    SphereTestGeometry,
+    SweptBoxTestGeometry,
     SystemBounds,
     TestGeometry,
     VecDim,
 };
 use crate::index::SpatialIndex;
+use crate::observer::{Progress, TraversalObserver};
 use crate::traits::ObjectID;
 
 use cgmath::prelude::*;
-use rustc_hash::FxHashSet;
+use cgmath::{Point2, Rad};
+use rustc_hash::{FxHashMap, FxHashSet};
 use smallvec::SmallVec;
 
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
 use std::fmt::Debug;
-use std::ops::DerefMut;
+use std::mem::size_of;
+use std::ops::{DerefMut, Range};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+#[cfg(feature="bevy_reflect")]
+use bevy_reflect::Reflect;
 
 #[cfg(feature="parallel")]
 use rayon::prelude::*;
@@ -26,7 +55,310 @@ use rayon::prelude::*;
 use std::cell::{RefMut, RefCell};
 
 #[cfg(feature="parallel")]
-use thread_local::CachedThreadLocal;
+use std::sync::atomic::AtomicUsize;
+
+#[cfg(feature="parallel")]
+use thread_local::ThreadLocal;
+
+/// Allocated size of a `Vec`, in bytes
+#[allow(clippy::ptr_arg)] // capacity is not available from a slice
+fn vec_bytes<T>(vec: &Vec<T>) -> usize {
+    vec.capacity() * size_of::<T>()
+}
+
+/// Estimated allocated size of a hash map, in bytes (one control byte per bucket)
+fn map_bytes<K, V>(map: &FxHashMap<K, V>) -> usize {
+    map.capacity() * (size_of::<(K, V)>() + 1)
+}
+
+/// A new, globally unique, value for `Layer::stamp`
+pub(crate) fn next_stamp() -> u64 {
+    static NEXT_STAMP: AtomicU64 = AtomicU64::new(1);
+    NEXT_STAMP.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Estimated allocated size of a hash set, in bytes (one control byte per bucket)
+fn set_bytes<T>(set: &FxHashSet<T>) -> usize {
+    set.capacity() * (size_of::<T>() + 1)
+}
+
+/// Remove `value` from a sorted, deduplicated `Vec`, returning whether it was present
+fn remove_sorted<T: Ord>(values: &mut Vec<T>, value: T) -> bool {
+    match values.binary_search(&value) {
+        Ok(position) => {
+            values.remove(position);
+            true
+        },
+        Err(_) => false
+    }
+}
+
+/// Whether scans skip a pair, as both objects are inactive or they're in the same exclusion group
+/// (see `Layer::set_active` and `Layer::set_group`)
+fn skips_pair<ID: ObjectID>(inactive: &FxHashSet<ID>, groups: &FxHashMap<ID, u32>, a: ID, b: ID) -> bool {
+    (inactive.contains(&a) && inactive.contains(&b))
+        || (!groups.is_empty() && matches!((groups.get(&a), groups.get(&b)), (Some(lhs), Some(rhs)) if lhs == rhs))
+}
+
+/// Whether a query moving along `direction` passes through a one-way object, see `Layer::set_one_way`
+fn passes_one_way<ID, Diff>(one_way: &FxHashMap<ID, [f32; 3]>, id: ID, direction: &Diff, dims: usize) -> bool
+where
+    ID: ObjectID,
+    Diff: std::ops::Index<usize, Output = f32>
+{
+    matches!(one_way.get(&id), Some(normal)
+        if normal.iter().take(dims).enumerate().map(|(i, normal)| normal * direction[i]).sum::<f32>() > 0f32)
+}
+
+/// Adapt a `get_dist` callback returning only a distance to one returning an empty payload
+pub(crate) fn payload_dist<TestGeom, ID, GetDist>(mut get_dist: GetDist) -> impl FnMut(&TestGeom, f32, ID) -> Option<(f32, ())>
+where
+    GetDist: FnMut(&TestGeom, f32, ID) -> f32
+{
+    move |test_geom, nearest, id| Some((get_dist(test_geom, nearest, id), ()))
+}
+
+/// Whether an optional cancellation token has been set
+fn is_cancelled(cancel: Option<&AtomicBool>) -> bool {
+    matches!(cancel, Some(cancel) if cancel.load(Ordering::Relaxed))
+}
+
+/// Per-`Layer` parameters for `scan_impl`
+struct ScanParams<'a, Index, ID>
+where
+    Index: SpatialIndex
+{
+    dense_cell_threshold: Option<usize>,
+    bounds: Option<&'a BoundsTable<Index::Point, ID>>,
+}
+
+impl<'a, Index, ID> Clone for ScanParams<'a, Index, ID>
+where
+    Index: SpatialIndex
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, Index, ID> Copy for ScanParams<'a, Index, ID>
+where
+    Index: SpatialIndex
+{}
+
+/// [`Layer::extend`]: struct.Layer.html#method.extend
+/// How [`Layer::extend`] handles objects which are not contained by the system bounds
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(any(test, feature="serde"), derive(Deserialize, Serialize))]
+#[cfg_attr(feature="bevy_reflect", derive(Reflect))]
+pub enum OutOfBoundsPolicy {
+    /// Discard the object entirely (the default)
+    #[default]
+    Reject,
+    /// [`Layer::clipped`]: struct.Layer.html#method.clipped
+    /// Clip the object's bounds to the system bounds, indexing only the clipped region
+    /// 
+    /// Objects which lie entirely outside of the system bounds are still rejected.  Clipped objects are
+    /// listed by [`Layer::clipped`].
+    Clip,
+}
+
+/// [`Layer::validate`]: struct.Layer.html#method.validate
+/// An internal invariant violated by a `Layer`, see [`Layer::validate`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Violation<Index, ID> {
+    /// The layer is flagged as sorted, but the entry at `position` is out of order
+    Unsorted{position: usize},
+    /// An entry's depth exceeds the maximum depth representable by the index type
+    InvalidDepth{index: Index, id: ID},
+    /// An entry's origin is not truncated to the minimum bound of its cell
+    UntruncatedOrigin{index: Index, id: ID},
+    /// An entry is shallower than the layer's `min_depth`
+    BelowMinDepth{index: Index, id: ID},
+}
+
+/// A sweep-and-prune sublist for a persistently dense cell
+#[derive(Clone)]
+struct HotCell<ID> {
+    dense_frames: u32,
+    seen: bool,
+    order: Vec<ID>,
+}
+
+impl<ID> Default for HotCell<ID> {
+    fn default() -> Self {
+        Self{
+            dense_frames: 0,
+            seen: false,
+            order: Vec::new()}
+    }
+}
+
+/// Dense cells tracked across calls to `Layer::scan`
+#[derive(Clone)]
+struct HotCells<Index, ID>
+where
+    Index: SpatialIndex
+{
+    min_frames: u32,
+    cells: FxHashMap<Index, HotCell<ID>>,
+}
+
+/// The number of entries of active objects in each cell at `depth`, see
+/// `LayerBuilder::with_active_partitions`
+#[derive(Clone)]
+struct ActivePartitions<Index> {
+    counts: FxHashMap<Index, u32>,
+    depth: u32,
+    /// The `Layer::stamp` for which `counts` are valid, if any
+    stamp: Option<u64>,
+}
+
+/// Positions of committed entries, sorted by ID, see `LayerBuilder::with_id_index`
+#[derive(Clone, Default)]
+struct IdIndex {
+    order: Vec<usize>,
+    /// The `Layer::stamp` for which `order` is valid, if any
+    stamp: Option<u64>,
+}
+
+impl IdIndex {
+    /// The positions of `tree`'s entries sorted by ID, rebuilt if not valid for `stamp`
+    fn order<Index, ID: Ord>(&mut self, tree: &[(Index, ID)], stamp: u64) -> &[usize] {
+        if self.stamp != Some(stamp) {
+            self.order.clear();
+            self.order.extend(0..tree.len());
+            self.order.sort_unstable_by(|&lhs, &rhs| (&tree[lhs].1, lhs).cmp(&(&tree[rhs].1, rhs)));
+            self.stamp = Some(stamp);
+        }
+        &self.order
+    }
+}
+
+/// Changes to a `Layer` which have not yet been committed, see `Layer::commit`
+struct Staging<Index, ID>
+where
+    Index: SpatialIndex
+{
+    /// Whether committed contents are discarded on commit (i.e. `Layer::clear` was called)
+    reset: bool,
+    entries: Vec<(Index, ID)>,
+    /// The number of leading `entries` known to be sorted (see `Layer::extend_sorted_hint`)
+    sorted_len: usize,
+    unbounded: Vec<ID>,
+    clipped: Vec<ID>,
+    truncated: Vec<ID>,
+    /// objects which produced more entries than `Layer::entry_warning_threshold`, with their counts
+    heavy: Vec<(ID, usize)>,
+    bounds: FxHashMap<ID, Bounds<Index::Point>>,
+}
+
+impl<Index, ID> Staging<Index, ID>
+where
+    Index: SpatialIndex
+{
+    fn with_capacity(capacity: usize) -> Self {
+        Self{
+            reset: false,
+            entries: Vec::with_capacity(capacity),
+            sorted_len: 0,
+            unbounded: Vec::new(),
+            clipped: Vec::new(),
+            truncated: Vec::new(),
+            heavy: Vec::new(),
+            bounds: FxHashMap::default()}
+    }
+
+    fn is_empty(&self) -> bool {
+        !self.reset && self.entries.is_empty() && self.unbounded.is_empty()
+    }
+
+    fn memory_usage(&self) -> usize {
+        vec_bytes(&self.entries) + vec_bytes(&self.unbounded) + vec_bytes(&self.clipped) + vec_bytes(&self.truncated)
+            + vec_bytes(&self.heavy) + map_bytes(&self.bounds)
+    }
+}
+
+impl<Index, ID> Default for Staging<Index, ID>
+where
+    Index: SpatialIndex
+{
+    fn default() -> Self {
+        Self::with_capacity(0)
+    }
+}
+
+impl<Index, ID> Clone for Staging<Index, ID>
+where
+    Index: SpatialIndex,
+    ID: ObjectID
+{
+    fn clone(&self) -> Self {
+        Self{
+            reset: self.reset,
+            entries: self.entries.clone(),
+            sorted_len: self.sorted_len,
+            unbounded: self.unbounded.clone(),
+            clipped: self.clipped.clone(),
+            truncated: self.truncated.clone(),
+            heavy: self.heavy.clone(),
+            bounds: self.bounds.clone()}
+    }
+}
+
+/// [`Layer::test_owned`]: struct.Layer.html#method.test_owned
+/// Owned query results, as returned by [`Layer::test_owned`] and similar methods
+/// 
+/// Small result sets are stored inline, avoiding an allocation per query.
+pub type QueryResults<ID> = SmallVec<[ID; 16]>;
+
+/// [`Layer::scan_arrays`]: struct.Layer.html#method.scan_arrays
+/// Collision pairs as two parallel arrays, as returned by [`Layer::scan_arrays`]
+/// 
+/// `(ids_a[i], ids_b[i])` is the `i`th pair, in the same order as `Layer::scan`.  This layout may be
+/// more convenient for batched (e.g. SIMD) narrow-phase processing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PairArrays<ID> {
+    pub ids_a: Vec<ID>,
+    pub ids_b: Vec<ID>,
+}
+
+impl<ID> PairArrays<ID>
+where
+    ID: Copy
+{
+    pub fn len(&self) -> usize {
+        self.ids_a.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids_a.is_empty()
+    }
+
+    /// Iterate over pairs
+    pub fn iter(&self) -> impl Iterator<Item = (ID, ID)> + '_ {
+        self.ids_a.iter().cloned().zip(self.ids_b.iter().cloned())
+    }
+
+    /// Replace the contents with `pairs`, reusing existing allocations
+    fn assign(&mut self, pairs: &[(ID, ID)]) {
+        self.ids_a.clear();
+        self.ids_b.clear();
+        self.ids_a.extend(pairs.iter().map(|&(id, _)| id));
+        self.ids_b.extend(pairs.iter().map(|&(_, id)| id));
+    }
+}
+
+impl<ID> Default for PairArrays<ID> {
+    fn default() -> Self {
+        Self{
+            ids_a: Vec::new(),
+            ids_b: Vec::new()}
+    }
+}
+
+/// The entries of a cell, followed by the entries (and their offsets) of each of its sub-cells
+pub(crate) type SplitTree<'a, Index, ID> = (&'a [(Index, ID)], SmallVec<[(&'a [(Index, ID)], usize); 8]>);
 
 /// [`SpatialIndex`]: trait.SpatialIndex.html
 /// [`Index64_3D`]: struct.Index64_3D.html
@@ -36,8 +368,12 @@ use thread_local::CachedThreadLocal;
 /// `Index` must be a type implmenting [`SpatialIndex`], such as [`Index64_3D`]
 /// 
 /// `ID` is the type representing object IDs
-
-#[derive(Default)]
+/// 
+/// Building a `Layer` happens in two phases: mutations (`clear`, `extend`, `extend_unbounded`, and
+/// `merge`) are staged, and only become visible to queries once they are applied by `commit`.  Methods
+/// taking `&mut self` (e.g. `scan` or `test_box`) commit implicitly; the `*_committed` methods take
+/// `&self` and query only the committed state, so the previous frame may still be queried while the
+/// next one is being built.  Staged changes are not serialized.
 #[cfg_attr(any(test, feature="serde"), derive(Deserialize, Serialize))]
 pub struct Layer<Index, ID>
 where
@@ -47,8 +383,86 @@ where
 {
     // persistant state:
     min_depth: u32,
+    // committed entries, and whether they are sorted; this is only `false` for deserialized data
     tree: (Vec<(Index, ID)>, bool),
 
+    // not serialized to remain compatible with existing data:
+    #[cfg_attr(any(test, feature="serde"), serde(skip))]
+    unbounded: Vec<ID>,
+
+    #[cfg_attr(any(test, feature="serde"), serde(skip))]
+    clipped: Vec<ID>,
+
+    #[cfg_attr(any(test, feature="serde"), serde(skip))]
+    truncated: Vec<ID>,
+
+    #[cfg_attr(any(test, feature="serde"), serde(skip))]
+    heavy_objects: Vec<(ID, usize)>,
+
+    #[cfg_attr(any(test, feature="serde"), serde(skip))]
+    staging: Staging<Index, ID>,
+
+    // configuration and derived state; these are not serialized to remain compatible with existing data:
+    #[cfg_attr(any(test, feature="serde"), serde(skip))]
+    out_of_bounds: OutOfBoundsPolicy,
+
+    #[cfg_attr(any(test, feature="serde"), serde(skip))]
+    max_entries: Option<usize>,
+
+    #[cfg_attr(any(test, feature="serde"), serde(skip))]
+    target_entries: Option<usize>,
+
+    #[cfg_attr(any(test, feature="serde"), serde(skip))]
+    max_entries_per_object: Option<usize>,
+
+    #[cfg_attr(any(test, feature="serde"), serde(skip))]
+    entry_warning_threshold: Option<usize>,
+
+    #[cfg_attr(any(test, feature="serde"), serde(skip))]
+    dense_cell_threshold: Option<usize>,
+
+    #[cfg_attr(any(test, feature="serde"), serde(skip))]
+    bounds: Option<BoundsTable<Index::Point, ID>>,
+
+    #[cfg_attr(any(test, feature="serde"), serde(skip))]
+    refined_tests: bool,
+
+    #[cfg_attr(any(test, feature="serde"), serde(skip))]
+    deterministic: bool,
+
+    #[cfg_attr(any(test, feature="serde"), serde(skip))]
+    exclude_inactive: bool,
+
+    #[cfg_attr(any(test, feature="serde"), serde(skip))]
+    inactive: FxHashSet<ID>,
+
+    /// user-defined per-object flags, e.g. clickable; objects without an entry have no flags set
+    #[cfg_attr(any(test, feature="serde"), serde(skip))]
+    flags: FxHashMap<ID, u32>,
+
+    /// exclusion groups, whose members are never paired with each other by scans
+    #[cfg_attr(any(test, feature="serde"), serde(skip))]
+    groups: FxHashMap<ID, u32>,
+
+    /// normals of one-way objects, ignored by rays and swept boxes moving along them
+    #[cfg_attr(any(test, feature="serde"), serde(skip))]
+    one_way: FxHashMap<ID, [f32; 3]>,
+
+    #[cfg_attr(any(test, feature="serde"), serde(skip))]
+    hot_cells: Option<HotCells<Index, ID>>,
+    #[cfg_attr(any(test, feature="serde"), serde(skip))]
+    active_partitions: Option<ActivePartitions<Index>>,
+    #[cfg_attr(any(test, feature="serde"), serde(skip))]
+    id_index: Option<IdIndex>,
+
+    /// identifies the committed contents; this changes whenever they do
+    #[cfg_attr(any(test, feature="serde"), serde(skip, default="next_stamp"))]
+    stamp: u64,
+
+    /// The number of candidate pairs rejected by filtering during the last scan
+    #[cfg_attr(any(test, feature="serde"), serde(skip))]
+    filtered_pairs: usize,
+
     // temporary data used within a method:
     #[cfg_attr(any(test, feature="serde"), serde(skip))]
     collisions: Vec<(ID, ID)>,
@@ -62,9 +476,15 @@ where
     #[cfg_attr(any(test, feature="serde"), serde(skip))]
     invalid: Vec<ID>,
 
+    #[cfg_attr(any(test, feature="serde"), serde(skip))]
+    cell_results: Vec<(Index, ID)>,
+
+    #[cfg_attr(any(test, feature="serde"), serde(skip))]
+    pair_arrays: PairArrays<ID>,
+
     #[cfg(feature="parallel")]
     #[cfg_attr(any(test, feature="serde"), serde(skip))]
-    collisions_tls: CachedThreadLocal<RefCell<Vec<(ID, ID)>>>,
+    collisions_tls: ThreadLocal<RefCell<Vec<(ID, ID)>>>,
 }
 
 impl<Index, ID> Layer<Index, ID>
@@ -73,624 +493,7222 @@ where
     ID: ObjectID,
     Bounds<Index::Point>: IndexGenerator<Index>
 {
-    /// Iterate over all indices in the `Layer`
+    /// Iterate over all committed indices in the `Layer`
     /// 
     /// This is primarily intended for visualization + debugging
     pub fn iter(&self) -> std::slice::Iter<'_, (Index, ID)> {
         self.tree.0.iter()
     }
 
+    /// Iterate over all staged (uncommitted) indices in the `Layer`, in the order they were added
+    /// 
+    /// This is primarily intended for visualization + debugging
+    pub fn iter_staged(&self) -> std::slice::Iter<'_, (Index, ID)> {
+        self.staging.entries.iter()
+    }
+
+    /// Iterate over all committed indices in the `Layer`, grouped by object
+    /// 
+    /// Objects are yielded in order of ID, each with the cells it occupies, e.g. to show in tooling how
+    /// many (and which) cells each object is indexed in.  Unbounded objects, which have no entries,
+    /// aren't included.  If configured with `LayerBuilder::with_id_index`, the index is iterated
+    /// directly (and rebuilt first, if necessary); otherwise, each call allocates and sorts the
+    /// positions of all committed entries, which is O(n log n).
+    pub fn iter_by_object(&mut self) -> impl Iterator<Item = (ID, impl Iterator<Item = Index>)> + '_ {
+        let (tree, _) = &self.tree;
+        let order: Cow<'_, [usize]> = match &mut self.id_index {
+            Some(index) => Cow::Borrowed(index.order(tree, self.stamp)),
+            None => {
+                let mut order: Vec<usize> = (0..tree.len()).collect();
+                order.sort_unstable_by(|&lhs, &rhs| (&tree[lhs].1, lhs).cmp(&(&tree[rhs].1, rhs)));
+                Cow::Owned(order)
+            }
+        };
+
+        let mut start = 0;
+        std::iter::from_fn(move || {
+            let id = tree[*order.get(start)?].1;
+            let len = order[start..].iter().take_while(|&&position| tree[position].1 == id).count();
+            let cells: SmallVec<[Index; 8]> = order[start..start + len].iter()
+                .map(|&position| tree[position].0)
+                .collect();
+            start += len;
+            Some((id, cells.into_iter()))
+        })
+    }
+
     /// Clear all index-ID pairs
+    /// 
+    /// This is staged: the current contents remain visible to queries until the next `commit`
     pub fn clear(&mut self) {
-        let (tree, sorted) = &mut self.tree;
-        tree.clear();
-        *sorted = true;
+        let staging = &mut self.staging;
+        staging.reset = true;
+        staging.entries.clear();
+        staging.sorted_len = 0;
+        staging.unbounded.clear();
+        staging.clipped.clear();
+        staging.truncated.clear();
+        staging.heavy.clear();
+        staging.bounds.clear();
     }
 
-    /// Append multiple objects to the `Layer`
+    /// Remove an object's committed entries, returning the cells it occupied in sorted order
     /// 
-    /// Complex geometry may provide multiple bounds for a single object ID; this usage would be common
-    /// for static geometry, as it prevents extraneous self-collisions
-    pub fn extend<Iter, Point_>(&mut self, system_bounds: Bounds<Point_>, objects: Iter)
-    where
-        Iter: std::iter::Iterator<Item = (Bounds<Point_>, ID)>,
-        Point_: EuclideanSpace<Scalar = f32>,
-        Point_::Diff: ElementWise,
-        Bounds<Point_>: SystemBounds<Point_, Index::Point>
-    {
-        let (tree, sorted) = &mut self.tree;
+    /// Unlike most mutations, this takes effect immediately (after committing any staged changes).
+    /// The object's stored bounds and any record of it being unbounded, clipped, or truncated are also
+    /// removed, but its activation, flags, and groups are retained, as with `clear`.  Entries are
+    /// found by a scan of the `Layer`, or by binary search if configured with
+    /// `LayerBuilder::with_id_index`; either way, removing them shifts all later entries.
+    pub fn drain_object(&mut self, id: ID) -> Vec<Index> {
+        let positions = self.object_positions(id);
+        let was_unbounded = remove_sorted(&mut self.unbounded, id);
+        remove_sorted(&mut self.clipped, id);
+        remove_sorted(&mut self.truncated, id);
+        if let Some(bounds) = &mut self.bounds {
+            bounds.remove(id);
+        }
+        if positions.is_empty() && !was_unbounded {
+            return Vec::new();
+        }
 
-        if let (_, Some(max_objects)) = objects.size_hint() {
-            tree.reserve(max_objects);
+        self.stamp = next_stamp();
+        if let Some(partitions) = &mut self.active_partitions {
+            partitions.stamp = None;
         }
 
-        for (bounds, id) in objects {
-            if !system_bounds.contains(bounds) {
-                self.invalid.push(id);
-                continue
+        let (tree, _) = &mut self.tree;
+        let drained = positions.iter().map(|&position| tree[position].0).collect();
+        let mut position = 0;
+        tree.retain(|_| {
+            position += 1;
+            positions.binary_search(&(position - 1)).is_err()
+        });
+
+        // entries keep their order, so the index only needs to skip the removed positions
+        if let Some(index) = &mut self.id_index {
+            index.order.retain(|position| positions.binary_search(position).is_err());
+            for position in &mut index.order {
+                *position -= positions.partition_point(|&removed| removed < *position);
             }
+            index.stamp = Some(self.stamp);
+        }
 
-            tree.extend(system_bounds
-                .to_local(bounds)
-                .indices(Some(self.min_depth))
-                .into_iter()
-                .map(|index| (index, id)));
+        drained
+    }
 
-            *sorted = false;
+    /// [`drain_object`]: #method.drain_object
+    /// Remove an object's committed entries, returning whether it was present, see [`drain_object`]
+    pub fn remove(&mut self, id: ID) -> bool {
+        self.commit();
+        let unbounded = self.unbounded.binary_search(&id).is_ok();
+        !self.drain_object(id).is_empty() || unbounded
+    }
+
+    /// Commit, then find the positions of an object's entries in sorted order, using (and if
+    /// necessary rebuilding) the ID index if there is one
+    fn object_positions(&mut self, id: ID) -> Vec<usize> {
+        self.commit();
+
+        let (tree, _) = &self.tree;
+        match &mut self.id_index {
+            Some(index) => {
+                let order = index.order(tree, self.stamp);
+                let start = order.partition_point(|&position| tree[position].1 < id);
+                let len = order[start..].partition_point(|&position| tree[position].1 == id);
+                order[start..start + len].to_vec()
+            },
+            None => tree.iter()
+                .enumerate()
+                .filter(|&(_, &(_, id_))| id_ == id)
+                .map(|(position, _)| position)
+                .collect()
         }
     }
 
-    /// Merge another `Layer` into this `Layer`
+    /// Mark an object as active or inactive (e.g. sleeping), without removing its entries
     /// 
-    /// This may be used, for example, to merge static scene `Layer` into the current
-    /// frames' dynamic `Layer` without having to recalculate indices for the static data
-    pub fn merge(&mut self, other: &Layer<Index, ID>) {
-        let (lhs_tree, sorted) = &mut self.tree;
-        let (rhs_tree, _) = &other.tree;
-
-        if other.min_depth < self.min_depth {
-            warn!("merging layer of lesser min_depth (lhs: {}, rhs: {})", self.min_depth, other.min_depth);
-            self.min_depth = other.min_depth;
+    /// `scan` and related methods skip pairs of two inactive objects.  Inactive objects are still
+    /// included in the results of tests and picks, unless configured with
+    /// `LayerBuilder::with_inactive_excluded_from_queries`.  Unlike most mutations, this takes effect
+    /// immediately, rather than on the next `commit`.  Objects are active by default; flags apply to
+    /// IDs rather than entries, so they're retained by `clear` and are not serialized.
+    pub fn set_active(&mut self, id: ID, active: bool) {
+        let changed = if active {
+                self.inactive.remove(&id)
+            } else {
+                self.inactive.insert(id)
+            };
+        if changed {
+            self.activation_changed();
         }
+    }
 
-        lhs_tree.extend(rhs_tree.iter());
-        *sorted = false;
+    /// [`set_active`]: #method.set_active
+    /// Whether an object is active, see [`set_active`]
+    pub fn is_active(&self, id: ID) -> bool {
+        !self.inactive.contains(&id)
     }
 
-    /// [`par_scan_filtered`]: struct.Layer.html#method.par_scan_filtered
-    /// [`par_scan`]: struct.Layer.html#method.par_scan
-    /// Sort indices to ready data for detection (parallel)
+    /// [`set_active`]: #method.set_active
+    /// Mark all objects as active, see [`set_active`]
+    pub fn activate_all(&mut self) {
+        if !self.inactive.is_empty() {
+            self.activation_changed();
+        }
+        self.inactive.clear();
+    }
+
+    /// [`pick_ray_with_flags`]: #method.pick_ray_with_flags
+    /// Set user-defined flags for an object (e.g. clickable, or a material), for filtering picks with
+    /// [`pick_ray_with_flags`]
     /// 
-    /// This will be called implicitly when necessary (i.e. by [`par_scan_filtered`], [`par_scan`], etc.)
-    #[cfg(feature="parallel")]
-    pub fn par_sort(&mut self) {
-        let (tree, sorted) = &mut self.tree;
-        if !*sorted {
-            tree.par_sort_unstable();
-            *sorted = true;
+    /// As with `set_active`, flags apply to IDs rather than entries, take effect immediately, are
+    /// retained by `clear`, and are not serialized.  Objects have no flags set by default.
+    pub fn set_flags(&mut self, id: ID, flags: u32) {
+        if flags == 0 {
+            self.flags.remove(&id);
+        } else {
+            self.flags.insert(id, flags);
         }
     }
 
-    /// [`scan_filtered`]: struct.Layer.html#method.scan_filtered
-    /// [`scan`]: struct.Layer.html#method.scan
-    /// Sort indices to ready data for detection
+    /// [`set_flags`]: #method.set_flags
+    /// The flags of an object, see [`set_flags`]
+    pub fn flags(&self, id: ID) -> u32 {
+        self.flags.get(&id).cloned().unwrap_or(0)
+    }
+
+    /// [`set_flags`]: #method.set_flags
+    /// Clear the flags of all objects, see [`set_flags`]
+    pub fn clear_flags(&mut self) {
+        self.flags.clear();
+    }
+
+    /// Assign an object to an exclusion group (e.g. all parts of one ragdoll), or remove it from its
+    /// group with `None`
     /// 
-    /// This will be called implicitly when necessary (i.e. by [`scan_filtered`], [`scan`], etc.)
-    pub fn sort(&mut self) {
-        let (tree, sorted) = &mut self.tree;
-        if !*sorted {
-            tree.sort_unstable();
-            *sorted = true;
+    /// Scans never report pairs of objects in the same group.  These are skipped as pairs are found,
+    /// before duplicates are removed, so this is cheaper than rejecting them with a filter; they are
+    /// counted by `filtered_pairs`.  As with `set_active`, groups apply to IDs rather than entries,
+    /// take effect immediately, are retained by `clear`, and are not serialized.
+    pub fn set_group(&mut self, id: ID, group: Option<u32>) {
+        match group {
+            Some(group) => { self.groups.insert(id, group); },
+            None => { self.groups.remove(&id); }
         }
     }
 
-    fn test_impl<TestGeom, Callback>(
-        tree: &[(Index, ID)],
-        cell: Index,
-        test_geom: &TestGeom,
-        mut nearest: f32,
-        max_depth: Option<u32>,
-        callback: &mut Callback) -> f32
+    /// [`set_group`]: #method.set_group
+    /// The exclusion group of an object, if any, see [`set_group`]
+    pub fn group(&self, id: ID) -> Option<u32> {
+        self.groups.get(&id).cloned()
+    }
+
+    /// [`set_group`]: #method.set_group
+    /// Remove all objects from their exclusion groups, see [`set_group`]
+    pub fn clear_groups(&mut self) {
+        self.groups.clear();
+    }
+
+    /// [`pick_ray`]: #method.pick_ray
+    /// [`test_swept_box`]: #method.test_swept_box
+    /// Make an object one-way (e.g. a platform passable from below), or two-way again with `None`
+    /// 
+    /// Ray picks (see [`pick_ray`]) and swept box tests (see [`test_swept_box`]) ignore one-way objects
+    /// when moving along `normal`, i.e. when the dot product of their direction and `normal` is
+    /// positive; e.g. a platform with an upward normal blocks rays moving down or sideways, but not
+    /// up.  This is checked before any distance callback.  Other tests are unaffected.  As with
+    /// `set_active`, this applies to IDs rather than entries, takes effect immediately, is retained
+    /// by `clear`, and is not serialized.
+    pub fn set_one_way<Diff>(&mut self, id: ID, normal: Option<Diff>)
     where
-        TestGeom: TestGeometry,
-        Callback: FnMut(&TestGeom, f32, ID) -> f32
+        Diff: Array<Element = f32>
     {
-        use std::cmp::Ordering::{Less, Greater};
-
-        if tree.is_empty() || !test_geom.should_test(nearest) {
-            return nearest;
+        match normal {
+            Some(normal) => {
+                assert!(Diff::len() <= 3, "one-way normals support at most three dimensions");
+                let mut stored = [0f32; 3];
+                for (i, stored) in stored.iter_mut().enumerate().take(Diff::len()) {
+                    *stored = normal[i];
+                }
+                self.one_way.insert(id, stored);
+            },
+            None => { self.one_way.remove(&id); }
         }
+    }
 
-        if tree.first().unwrap().0 < cell || !cell.overlaps(tree.last().unwrap().0) {
-            panic!("test_impl called with non-overlapping indices");
-        }
+    /// [`set_one_way`]: #method.set_one_way
+    /// Whether an object is one-way, see [`set_one_way`]
+    pub fn is_one_way(&self, id: ID) -> bool {
+        self.one_way.contains_key(&id)
+    }
 
-        let depth = cell.depth();
-        if let Some(max_depth) = max_depth {
-            if depth >= max_depth {
-                return tree.iter()
-                    .map(|(_, id)| *id)
-                    .fold(nearest, |nearest, id|
-                        callback(test_geom, nearest, id).min(nearest));
-            }
-        }
+    /// [`set_one_way`]: #method.set_one_way
+    /// Make all objects two-way, see [`set_one_way`]
+    pub fn clear_one_way(&mut self) {
+        self.one_way.clear();
+    }
 
-        if let Some(sub_cells) = cell.subdivide() {
-            let mut sub_trees = sub_cells.as_ref().iter()
-                .map(|cell| Some(*cell))
-                .chain((0..1).map(|_| None))
-                .scan(tree, |tree, cell| {
-                    if let Some(cell) = cell {
-                        let i = tree.binary_search_by(|&(index, _)| {
-                            if index < cell { Less } else { Greater }
-                        }).err().unwrap();
-                        let (head, tail) = tree.split_at(i);
-                        *tree = tail;
-                        Some(head)
-                    } else {
-                        Some(tree)
-                    }
-                });
-            nearest = sub_trees.next().unwrap().iter()
-                .map(|(_, id)| *id)
-                .fold(nearest, |nearest, id|
-                    callback(test_geom, nearest, id).min(nearest));
+    /// [`set_active`]: #method.set_active
+    /// [`test_box`]: #method.test_box
+    /// Mark all objects overlapping `region` as active (e.g. to wake everything near an explosion),
+    /// see [`set_active`]
+    /// 
+    /// Objects are found as for [`test_box`], including inactive objects regardless of
+    /// `LayerBuilder::with_inactive_excluded_from_queries`.
+    pub fn activate_in_box<Point_>(&mut self, system_bounds: Bounds<Point_>, region: Bounds<Point_>)
+    where
+        Point_: EuclideanSpace<Scalar = f32> + Debug,
+        Point_::Diff: ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        BoxTestGeometry<Point_>: TestGeometry,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
+    {
+        if self.inactive.is_empty() {
+            return;
+        }
+        let mut changed = false;
+        for id in self.objects_in_box(system_bounds, region) {
+            changed |= self.inactive.remove(&id);
+        }
+        if changed {
+            self.activation_changed();
+        }
+    }
 
-            let sub_trees: SmallVec<[_; 8]> = sub_trees.collect();
-            let sub_tests = test_geom.subdivide();
+    /// [`set_active`]: #method.set_active
+    /// [`activate_in_box`]: #method.activate_in_box
+    /// Mark all objects which don't overlap `region` as inactive, see [`set_active`]
+    /// 
+    /// Objects are found as for [`activate_in_box`]; unbounded objects overlap every region, so
+    /// they're never deactivated.  Objects inside `region` are left unchanged.
+    pub fn deactivate_outside<Point_>(&mut self, system_bounds: Bounds<Point_>, region: Bounds<Point_>)
+    where
+        Point_: EuclideanSpace<Scalar = f32> + Debug,
+        Point_::Diff: ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        BoxTestGeometry<Point_>: TestGeometry,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
+    {
+        let inside = self.objects_in_box(system_bounds, region);
+        let mut changed = false;
+        for &(_, id) in &self.tree.0 {
+            if inside.binary_search(&id).is_err() {
+                changed |= self.inactive.insert(id);
+            }
+        }
+        if changed {
+            self.activation_changed();
+        }
+    }
 
-            for &i in test_geom.test_order().as_ref() {
-                nearest = Self::test_impl(
-                    sub_trees[i],
-                    sub_cells.as_ref()[i],
-                    &sub_tests.as_ref()[i],
-                    nearest,
-                    max_depth,
-                    callback);
+    fn activation_changed(&mut self) {
+        if self.exclude_inactive {
+            // cached query results may no longer be valid
+            self.stamp = next_stamp();
+        }
+        if let Some(partitions) = &mut self.active_partitions {
+            partitions.stamp = None;
+        }
+    }
+
+    /// Recount `active_partitions` if they're out of date
+    fn update_active_partitions(&mut self) {
+        let depth = Index::clamp_depth(self.min_depth);
+        let stamp = self.stamp;
+        let inactive = &self.inactive;
+        let (tree, _) = &self.tree;
+        if let Some(partitions) = &mut self.active_partitions {
+            if partitions.stamp == Some(stamp) && partitions.depth == depth {
+                return;
+            }
+            partitions.counts.clear();
+            partitions.depth = depth;
+            partitions.stamp = Some(stamp);
+            for &(index, id) in tree {
+                if !inactive.contains(&id) {
+                    *partitions.counts.entry(Self::truncate_index(index, depth)).or_insert(0) += 1;
+                }
             }
+        }
+    }
 
-            nearest
-        } else {
-            tree.iter()
-                .map(|(_, id)| *id)
-                .fold(nearest, |nearest, id|
-                    callback(test_geom, nearest, id).min(nearest))
+    /// Ranges of the committed entries which may contain pairs of active objects, skipping cells at
+    /// `min_depth` which contain only inactive objects if `active_partitions` are maintained
+    fn active_ranges(&mut self) -> Vec<Range<usize>> {
+        let len = self.tree.0.len();
+        if self.inactive.is_empty() || self.active_partitions.is_none() {
+            return std::iter::once(0..len).collect();
+        }
+        self.update_active_partitions();
+        let partitions = self.active_partitions.as_ref().unwrap();
+        let depth = partitions.depth;
+        if depth == 0 {
+            return std::iter::once(0..len).collect();
+        }
+
+        let (tree, _) = &self.tree;
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        let mut i = 0;
+        while i < tree.len() {
+            let cell = Self::truncate_index(tree[i].0, depth);
+            let end = i + tree[i..].partition_point(|&(index, _)| Index::same_cell_at_depth(index, cell, depth));
+            if partitions.counts.contains_key(&cell) {
+                match ranges.last_mut() {
+                    Some(last) if last.end == i => last.end = end,
+                    _ => ranges.push(i..end)
+                }
+            }
+            i = end;
         }
+        ranges
     }
 
-    /// Run a single test on some geometry
-    /// 
-    /// This occurs by repeatedly subdividing both this `Layer`'s index-ID list and the provided
-    /// `test_geom`, returning any items at a given depth where both the resulting index list
-    /// is non-empty and [`TestGeometry::subdivide`] returns a result
-    /// 
-    /// _note: this method may do an implicit, non-parallel sort; you may call [`par_sort`] prior
-    /// to calling this method to perform a parallel sort instead_
-    /// 
-    /// [`TestGeometry::subdivide`]: trait.TestGeometry.html#tymethod.subdivide
-    /// [`par_sort`]: #method.par_sort
-    pub fn test<'a, TestGeom>(
-        &'a mut self,
-        test_geom: &TestGeom,
-        max_depth: Option<u32>) -> &'a Vec<ID>
+    /// All objects overlapping `region` in sorted order, ignoring activation flags
+    fn objects_in_box<Point_>(&mut self, system_bounds: Bounds<Point_>, region: Bounds<Point_>) -> Vec<ID>
     where
-        TestGeom: TestGeometry
+        Point_: EuclideanSpace<Scalar = f32> + Debug,
+        Point_::Diff: ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        BoxTestGeometry<Point_>: TestGeometry,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
     {
-        self.sort();
+        self.commit();
 
-        self.test_results.clear();
-
-        let (tree, _) = &self.tree;
-        let results = &mut self.test_results;
+        let test_geom = BoxTestGeometry::with_system_bounds(system_bounds, region);
+        let mut results = Vec::new();
         Self::test_impl(
-            tree,
+            self.sorted_tree(),
+            0,
             Index::default(),
-            test_geom,
-            std::f32::INFINITY,
-            max_depth,
-            &mut |_, nearest, id| {
+            &test_geom,
+            f32::INFINITY,
+            None,
+            None,
+            &mut |_, nearest, _, id| {
                 results.push(id);
                 nearest
+            },
+            &mut ());
+        results.extend(self.unbounded.iter());
+
+        if let (true, Some(bounds)) = (self.refined_tests, &self.bounds) {
+            results.retain(|&id| match bounds.get(id) {
+                Some(bounds) => test_geom.overlaps(Self::global_bounds(&system_bounds, bounds)),
+                None => true
             });
+        }
 
-        results.sort();
+        results.sort_unstable();
         results.dedup();
-
         results
     }
 
-    /// A special case of [`test`] for bounding box tests, see [`BoxTestGeometry`]
-    /// 
-    /// The `system_bounds` provided to this method should, in most cases, be identical to the
-    /// `system_bounds` provided to [`extend`]
-    /// 
-    /// _note: this method may do an implicit, non-parallel sort; you may call [`par_sort`] prior
-    /// to calling this method to perform a parallel sort instead_
+    /// Whether an object may be included in the results of tests and picks
+    pub(crate) fn is_queryable(&self, id: ID) -> bool {
+        !self.exclude_inactive || !self.inactive.contains(&id)
+    }
+
+    /// The stored (local) bounds of an object, if bounds are stored
+    pub(crate) fn stored_bounds(&self, id: ID) -> Option<Bounds<Index::Point>> {
+        self.bounds.as_ref().and_then(|bounds| bounds.get(id))
+    }
+
+    pub(crate) fn min_depth(&self) -> u32 {
+        self.min_depth
+    }
+
+    /// A value identifying the committed contents, which changes on any commit which modifies them
+    pub(crate) fn stamp(&self) -> u64 {
+        self.stamp
+    }
+
+    /// The pairs found by the last scan, e.g. by `Layer::scan_async`
+    pub fn scan_results(&self) -> &Vec<(ID, ID)> {
+        &self.collisions
+    }
+
+    /// Whether the `Layer` has no staged changes, i.e. queries reflect all mutations so far
+    pub fn is_committed(&self) -> bool {
+        let (_, sorted) = &self.tree;
+        *sorted && self.staging.is_empty()
+    }
+
+    /// Append multiple unbounded objects to the `Layer`
     /// 
-    /// [`test`]: #method.test
-    /// [`extend`]: #method.extend
-    /// [`par_sort`]: #method.par_sort
-    /// [`BoxTestGeometry`]: struct.BoxTestGeometry.html
-    pub fn test_box<'a, Point_>(
-        &'a mut self,
-        system_bounds: Bounds<Point_>,
-        test_bounds: Bounds<Point_>,
-        max_depth: Option<u32>) -> &'a Vec<ID>
+    /// Unbounded objects (e.g. ground planes or kill planes) don't generate any indices.  Instead, they are
+    /// paired with every other object by `scan`, and included in the results of every `test` and `pick`.
+    /// Unbounded objects are not serialized.
+    pub fn extend_unbounded<Iter>(&mut self, ids: Iter)
     where
-        Point_: EuclideanSpace<Scalar = f32> + Debug,
-        Point_::Diff: ElementWise + std::ops::Index<usize, Output = f32> + Debug,
-        BoxTestGeometry<Point_>: TestGeometry
+        Iter: IntoIterator<Item = ID>
     {
-        let test_geom = BoxTestGeometry::with_system_bounds(
-            system_bounds,
-            test_bounds);
-
-        self.test(
-            &test_geom,
-            max_depth);
-
-        &self.test_results
+        self.staging.unbounded.extend(ids);
     }
 
-    /// A special case of [`test`] for ray-testing, see [`RayTestGeometry`]
+    /// [`OutOfBoundsPolicy::Clip`]: enum.OutOfBoundsPolicy.html#variant.Clip
+    /// Committed objects which have been clipped to the system bounds, in sorted order
     /// 
-    /// The `system_bounds` provided to this method should, in most cases, be identical to the
-    /// `system_bounds` provided to [`extend`]
+    /// This is only populated when using [`OutOfBoundsPolicy::Clip`]
+    pub fn clipped(&self) -> &[ID] {
+        &self.clipped
+    }
+
+    /// [`LayerBuilder::with_max_entries`]: struct.LayerBuilder.html#method.with_max_entries
+    /// [`LayerBuilder::with_max_entries_per_object`]: struct.LayerBuilder.html#method.with_max_entries_per_object
+    /// Committed objects for which some or all indices were discarded by an entry limit, in sorted order
     /// 
-    /// _note: this method may do an implicit, non-parallel sort; you may call [`par_sort`] prior
-    /// to calling this method to perform a parallel sort instead_
+    /// This is only populated when using [`LayerBuilder::with_max_entries`] or
+    /// [`LayerBuilder::with_max_entries_per_object`]
+    pub fn truncated(&self) -> &[ID] {
+        &self.truncated
+    }
+
+    /// [`LayerBuilder::with_entry_warning_threshold`]: struct.LayerBuilder.html#method.with_entry_warning_threshold
+    /// Objects added since the previous commit which produced more entries than the threshold set by
+    /// [`LayerBuilder::with_entry_warning_threshold`], with the number of entries each produced
     /// 
-    /// [`test`]: #method.test
-    /// [`extend`]: #method.extend
-    /// [`par_sort`]: #method.par_sort
-    /// [`RayTestGeometry`]: struct.RayTestGeometry.html
-    pub fn test_ray<'a, Point_>(
-        &'a mut self,
-        system_bounds: Bounds<Point_>,
-        origin   : Point_,
-        direction: Point_::Diff,
-        range_min: f32,
-        range_max: f32,
-        max_depth: Option<u32>) -> &'a Vec<ID>
-    where
-        Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
-        Point_::Diff: ElementWise + std::ops::Index<usize, Output = f32> + Debug,
-        RayTestGeometry<Point_>: TestGeometry
-    {
-        let test_geom = RayTestGeometry::with_system_bounds(
-            system_bounds,
-            origin,
-            direction,
-            range_min,
-            range_max);
+    /// Objects are listed once for each set of bounds exceeding the threshold, in the order they were
+    /// added.  Objects this large relative to the system bounds (or forced deep by `min_depth`) can
+    /// silently exhaust memory; see also `LayerBuilder::with_max_entries_per_object` for a hard cap.
+    /// This is replaced by each `commit` which adds entries.
+    pub fn heavy_objects(&self) -> &[(ID, usize)] {
+        &self.heavy_objects
+    }
 
-        self.test(
-            &test_geom,
-            max_depth);
+    /// [`extend_unbounded`]: struct.Layer.html#method.extend_unbounded
+    /// Committed unbounded objects added by [`extend_unbounded`], in sorted order
+    pub fn unbounded(&self) -> &[ID] {
+        &self.unbounded
+    }
 
-        &self.test_results
+    /// [`LayerBuilder::with_stored_bounds`]: struct.LayerBuilder.html#method.with_stored_bounds
+    /// The bounds of a committed object, in local (integer) coordinates
+    /// 
+    /// Returns `None` if the object is not present, or if bounds are not stored (see
+    /// [`LayerBuilder::with_stored_bounds`]).  Objects with multiple bounds return their union.
+    pub fn local_bounds(&self, id: ID) -> Option<Bounds<Index::Point>> {
+        self.bounds.as_ref().and_then(|bounds| bounds.get(id))
     }
 
-    /// Run a picking or hit-test operation
+    /// Append multiple objects to the `Layer`
     /// 
-    /// This is implemented similarly to [`test`], but differs in that it returns only the nearest
-    /// result and may stop searching as soon as the nearest result is found
+    /// Complex geometry may provide multiple bounds for a single object ID; this usage would be common
+    /// for static geometry, as it prevents extraneous self-collisions.  New objects are staged until
+    /// the next `commit`.
     /// 
-    /// _note: this method may do an implicit, non-parallel sort; you may call [`par_sort`] prior
-    /// to calling this method to perform a parallel sort instead_
+    /// If an entry limit is configured (see `LayerBuilder::with_max_entries`), objects exceeding it are
+    /// truncated, and once the limit is reached no further entries are added.  Truncated objects are
+    /// listed by `truncated` after the next `commit`.
+    pub fn extend<Iter, Point_>(&mut self, system_bounds: Bounds<Point_>, objects: Iter)
+    where
+        Iter: std::iter::Iterator<Item = (Bounds<Point_>, ID)>,
+        Point_: EuclideanSpace<Scalar = f32>,
+        Point_::Diff: ElementWise,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
+    {
+        self.extend_with_max_depth(system_bounds, objects.map(|(bounds, id)| (bounds, id, None)))
+    }
+
+    /// [`extend`]: #method.extend
+    /// Append multiple objects to the `Layer`, each with an optional maximum depth
     /// 
-    /// [`test`]: #method.test
-    /// [`par_sort`]: #method.par_sort
-    pub fn pick<TestGeom, GetDist>(
-        &mut self,
-        test_geom: &TestGeom,
-        max_dist: f32,
-        max_depth: Option<u32>,
-        mut get_dist: GetDist) -> Option<(f32, ID)>
+    /// This is otherwise equivalent to [`extend`].  Objects with a maximum depth are indexed no deeper
+    /// than it, so huge background objects may be indexed coarsely while small objects in the same
+    /// `Layer` get full precision.  Indexing an object coarsely generates fewer entries, but they're
+    /// tested against more objects.  The maximum depth doesn't override `min_depth`.
+    pub fn extend_with_max_depth<Iter, Point_>(&mut self, system_bounds: Bounds<Point_>, objects: Iter)
     where
-        TestGeom: TestGeometry,
-        GetDist: FnMut(&TestGeom, f32, ID) -> f32
+        Iter: std::iter::Iterator<Item = (Bounds<Point_>, ID, Option<u32>)>,
+        Point_: EuclideanSpace<Scalar = f32>,
+        Point_::Diff: ElementWise,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
     {
-        self.sort();
+        let staging = &mut self.staging;
 
-        self.processed.clear();
+        if let (_, Some(max_objects)) = objects.size_hint() {
+            staging.entries.reserve(max_objects);
+        }
 
-        let (tree, _) = &self.tree;
-        let processed = &mut self.processed;
-        let mut result: Option<ID> = None;
-        let dist = Self::test_impl(
-            tree,
-            Index::default(),
-            test_geom,
-            max_dist,
-            max_depth,
-            &mut |test_geom, nearest, id| {
-                if processed.insert(id) {
-                    let dist = get_dist(test_geom, nearest, id);
-                    if dist.is_finite() {
-                        if dist < nearest {
-                            result = Some(id);
-                        }
-                        dist
-                    } else {
-                        std::f32::INFINITY
+        // the number of entries which may still be added, if limited
+        let committed = if staging.reset { 0 } else { self.tree.0.len() };
+        let mut remaining = self.max_entries
+            .map(|max_entries| max_entries.saturating_sub(committed + staging.entries.len()));
+        let heavy = staging.heavy.len();
+
+        for (mut bounds, id, max_depth) in objects {
+            if remaining == Some(0) {
+                staging.truncated.push(id);
+                continue;
+            }
+
+            if !system_bounds.contains(bounds) {
+                match (self.out_of_bounds, system_bounds.intersection(bounds)) {
+                    (OutOfBoundsPolicy::Clip, Some(clipped)) => {
+                        bounds = clipped;
+                        staging.clipped.push(id);
+                    },
+                    _ => {
+                        self.invalid.push(id);
+                        continue
                     }
-                } else {
-                    std::f32::INFINITY
                 }
-            });
+            }
+
+            let local_bounds = system_bounds.to_local(bounds);
+
+            let max_depth = Self::budgeted_max_depth(
+                self.target_entries,
+                committed + staging.entries.len(),
+                local_bounds,
+                max_depth);
+
+            if self.bounds.is_some() {
+                staging.bounds.entry(id)
+                    .and_modify(|bounds| *bounds = bounds.union(local_bounds))
+                    .or_insert(local_bounds);
+            }
 
-        result.map(|id| (dist, id))
+            let mut indices = local_bounds
+                .indices_in_range(Some(self.min_depth), max_depth)
+                .into_iter();
+            let limit = match (remaining, self.max_entries_per_object) {
+                (Some(lhs), Some(rhs)) => Some(lhs.min(rhs)),
+                (lhs, rhs) => lhs.or(rhs)
+            };
+
+            let len = staging.entries.len();
+            match limit {
+                Some(limit) => {
+                    staging.entries.extend(indices.by_ref()
+                        .take(limit)
+                        .map(|index| (index, id)));
+                    if indices.next().is_some() {
+                        staging.truncated.push(id);
+                    }
+                    if let Some(remaining) = &mut remaining {
+                        *remaining -= staging.entries.len() - len;
+                    }
+                },
+                None => staging.entries.extend(indices.map(|index| (index, id)))
+            }
+
+            let added = staging.entries.len() - len;
+            if matches!(self.entry_warning_threshold, Some(threshold) if added > threshold) {
+                staging.heavy.push((id, added));
+            }
+        }
+
+        if staging.heavy.len() > heavy {
+            warn!("extend: {} objects produced more than {} entries each (see Layer::heavy_objects)",
+                staging.heavy.len() - heavy, self.entry_warning_threshold.unwrap_or(0));
+        }
     }
 
-    /// A special case of [`pick`] for ray-testing, see [`RayTestGeometry`]
+    /// The maximum depth at which to index an object, given the number of entries already held
     /// 
-    /// The `system_bounds` provided to this method should, in most cases, be identical to the
-    /// `system_bounds` provided to [`extend`]
+    /// Once over budget (see `LayerBuilder::with_target_entries`), objects are indexed coarsely, at the
+    /// depth of the smallest cell containing them.
+    fn budgeted_max_depth(
+        target_entries: Option<usize>,
+        entries: usize,
+        local_bounds: Bounds<Index::Point>,
+        max_depth: Option<u32>) -> Option<u32>
+    {
+        match target_entries {
+            Some(target) if entries >= target => {
+                let depth = (0..Index::Point::len())
+                    .map(|i| (local_bounds.min[i] ^ local_bounds.max[i]).leading_zeros())
+                    .min()
+                    .unwrap_or(0);
+                Some(max_depth.map_or(depth, |max_depth| max_depth.min(depth)))
+            },
+            _ => max_depth
+        }
+    }
+
+    /// [`extend`]: #method.extend
+    /// Append line segments as objects, e.g. laser beams or tripwires
     /// 
-    /// _note: this method may do an implicit, non-parallel sort; you may call [`par_sort`] prior
-    /// to calling this method to perform a parallel sort instead_
+    /// Each segment is split where it crosses the cells of a grid a few levels deeper than the smallest
+    /// cell containing it, and the pieces are indexed as multiple bounds of a single object (see
+    /// [`extend`]).  A diagonal segment is then indexed only in cells near it, rather than in every cell
+    /// overlapping its bounding box.  Stored bounds (see `LayerBuilder::with_stored_bounds`) are the
+    /// union of the pieces, i.e. the segment's bounding box.
     /// 
-    /// [`pick`]: #method.pick
-    /// [`extend`]: #method.extend
-    /// [`par_sort`]: #method.par_sort
-    /// [`RayTestGeometry`]: struct.RayTestGeometry.html
-    pub fn pick_ray<Point_, GetDist>(
-        &mut self,
-        system_bounds: Bounds<Point_>,
-        origin   : Point_,
-        direction: Point_::Diff,
-        max_dist: f32,
-        max_depth: Option<u32>,
-        mut get_dist: GetDist) -> Option<(f32, ID, Point_)>
+    /// Rays should be clipped to the system bounds first, e.g. with `Bounds::intersect_ray`.  Otherwise
+    /// this is equivalent to [`extend`], including its handling of entry limits and of objects outside
+    /// of the system bounds.
+    pub fn extend_segments<Iter, Point_>(&mut self, system_bounds: Bounds<Point_>, segments: Iter)
     where
-        Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
-        Point_::Diff: VectorSpace<Scalar = f32> + ElementWise + std::ops::Index<usize, Output = f32> + Debug,
-        RayTestGeometry<Point_>: TestGeometry,
-        GetDist: FnMut(&Point_, &Point_::Diff, f32, ID) -> f32
+        Iter: std::iter::Iterator<Item = (Point_, Point_, ID)>,
+        Point_: EuclideanSpace<Scalar = f32>,
+        Point_::Diff: ElementWise,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
     {
-        let test_geom = RayTestGeometry::with_system_bounds(
-            system_bounds,
-            origin,
-            direction,
-            0f32,
-            max_dist);
+        let point_bounds = |point: Point_| Bounds::new(point, point);
+        let mut pieces: Vec<(Bounds<Point_>, ID, Option<u32>)> = Vec::new();
+        let mut splits: Vec<f32> = Vec::new();
+        for (start, end, id) in segments {
+            let bounds = point_bounds(start).union(point_bounds(end));
+            let local_bounds = system_bounds.to_local(bounds);
+            let depth = (0..Index::Point::len())
+                .map(|i| (local_bounds.min[i] ^ local_bounds.max[i]).leading_zeros())
+                .min()
+                .unwrap_or(0);
+            // deep enough that the pieces' bounding boxes cover little more than the segment
+            let cells = (1u64 << Index::clamp_depth(depth + 3)) as f32;
 
-        self.pick(&test_geom, max_dist, max_depth, |_, max_dist, id| {
-                get_dist(&origin, &direction, max_dist, id)
-            })
-            .map(|(dist, id)| {
-                let point = origin + direction * dist;
-                (dist, id, point)
-            })
+            splits.clear();
+            splits.push(0f32);
+            for i in 0..Point_::len() {
+                let delta = end[i] - start[i];
+                if delta == 0f32 {
+                    continue;
+                }
+                let size = (system_bounds.max[i] - system_bounds.min[i]) / cells;
+                let first = ((bounds.min[i] - system_bounds.min[i]) / size).floor() as i64 + 1;
+                let last = ((bounds.max[i] - system_bounds.min[i]) / size).ceil() as i64 - 1;
+                splits.extend((first..=last).map(|k| {
+                    let plane = system_bounds.min[i] + k as f32 * size;
+                    ((plane - start[i]) / delta).clamp(0f32, 1f32)
+                }));
+            }
+            splits.push(1f32);
+            splits.sort_unstable_by(|lhs, rhs| lhs.partial_cmp(rhs).unwrap_or(std::cmp::Ordering::Equal));
+            splits.dedup();
+
+            let at = |t: f32| point_bounds(start + (end - start) * t);
+            pieces.extend(splits.windows(2).map(|t| (at(t[0]).union(at(t[1])), id, None)));
+        }
+        self.extend_with_max_depth(system_bounds, pieces.into_iter());
     }
 
-    /// Detects collisions between all objects in the `Layer`
-    pub fn scan<'a>(&'a mut self)
-        -> &'a Vec<(ID, ID)>
+    /// [`extend`]: #method.extend
+    /// Append multiple moving objects to the `Layer`, expanding each object's bounds to cover its
+    /// motion over the next `dt`
+    /// 
+    /// Each object's bounds are swept along `velocity * dt`, giving speculative coverage for the next
+    /// step without re-indexing.  Swept bounds are clamped to the system bounds, so objects which are
+    /// in bounds aren't clipped or rejected merely for moving towards the edge.  This is otherwise
+    /// equivalent to [`extend`].
+    pub fn extend_predicted<Iter, Point_>(&mut self, system_bounds: Bounds<Point_>, objects: Iter, dt: f32)
+    where
+        Iter: std::iter::Iterator<Item = (Bounds<Point_>, Point_::Diff, ID)>,
+        Point_: EuclideanSpace<Scalar = f32>,
+        Point_::Diff: ElementWise,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
     {
-        self.scan_filtered(|_, _| true)
+        self.extend(system_bounds, objects.map(|(bounds, velocity, id)| {
+            let swept = bounds.swept(velocity * dt);
+            if system_bounds.contains(bounds) {
+                (swept.intersection(system_bounds).unwrap_or(bounds), id)
+            } else {
+                (swept, id)
+            }
+        }))
     }
 
-    /// Detects collisions between all objects in the `Layer`, returning only those which pass a user-specified test
+    /// [`LayerBuilder::with_stored_bounds`]: struct.LayerBuilder.html#method.with_stored_bounds
+    /// [`extend`]: #method.extend
+    /// Update the bounds of many objects, regenerating entries only for objects whose cells changed
     /// 
-    /// Collisions are filtered prior to duplicate removal.  This may be faster or slower than filtering
-    /// post-duplicate-removal (i.e. by `scan().iter().filter()`) depending on the complexity
-    /// of the filter.
-    pub fn scan_filtered<'a, F>(&'a mut self, filter: F)
-        -> &'a Vec<(ID, ID)>
+    /// Each object's new bounds are compared against its stored bounds (see
+    /// [`LayerBuilder::with_stored_bounds`], which is required).  Objects which are still covered by
+    /// the same cells, the common case for slow-moving objects, only have their stored bounds updated;
+    /// other objects have their entries replaced.  Objects which aren't present are added as by
+    /// [`extend`], and objects which are out of bounds are removed (unless clipped, see
+    /// `LayerBuilder::with_out_of_bounds_policy`).  Returns the number of objects which had their
+    /// entries replaced, added, or removed.
+    /// 
+    /// Unlike [`extend`], this commits any staged changes, then updates the committed state directly.
+    /// Each object is treated as having a single bounding box; per-object maximum depths aren't
+    /// retained, and with quantized bounds every object is re-indexed, since cell coverage can't be
+    /// recovered exactly.  Entry limits (see `LayerBuilder::with_max_entries`) and the entry budget (see
+    /// `LayerBuilder::with_target_entries`) apply to the updated contents, as they do for [`extend`].
+    pub fn update_all<Iter, Point_>(&mut self, system_bounds: Bounds<Point_>, objects: Iter) -> usize
     where
-        F: FnMut(ID, ID) -> bool
+        Iter: std::iter::Iterator<Item = (ID, Bounds<Point_>)>,
+        Point_: EuclideanSpace<Scalar = f32>,
+        Point_::Diff: ElementWise,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
     {
-        self.sort();
-        
-        self.collisions.clear();
-        self.invalid.clear();
+        assert!(self.bounds.is_some(), "update_all requires stored bounds");
 
-        let (tree, _) = &self.tree;
-        Self::scan_impl(tree.as_slice(), &mut self.collisions, filter);
+        self.commit();
 
-        self.collisions.sort_unstable();
-        self.collisions.dedup();
+        let stored_bounds = self.bounds.as_mut().unwrap();
+        let quantized = stored_bounds.is_quantized();
+        let mut updated = FxHashSet::default();
+        let mut moved = FxHashSet::default();
+        let mut pending: Vec<(ID, Bounds<Index::Point>)> = Vec::new();
+        let mut clipped = Vec::new();
+        let mut truncated = Vec::new();
+        for (id, mut bounds) in objects {
+            updated.insert(id);
+            if !system_bounds.contains(bounds) {
+                match (self.out_of_bounds, system_bounds.intersection(bounds)) {
+                    (OutOfBoundsPolicy::Clip, Some(clipped_bounds)) => {
+                        bounds = clipped_bounds;
+                        clipped.push(id);
+                    },
+                    _ => {
+                        self.invalid.push(id);
+                        stored_bounds.remove(id);
+                        moved.insert(id);
+                        continue
+                    }
+                }
+            }
 
-        &self.collisions
-    }
+            let local_bounds = system_bounds.to_local(bounds);
+            let indices: SmallVec<[Index; 8]> = local_bounds.indices(Some(self.min_depth)).into_iter().collect();
+            let unchanged = !quantized && matches!(stored_bounds.get(id),
+                Some(old_bounds) if old_bounds.indices(Some(self.min_depth)).into_iter().eq(indices.iter().cloned()));
+            stored_bounds.replace(id, local_bounds);
+            if unchanged {
+                continue;
+            }
 
-    /// [`scan`]: struct.Layer.html#method.scan
-    /// Parallel version of [`scan`]
-    #[cfg(feature="parallel")]
-    pub fn par_scan<'a>(&'a mut self)
-        -> &'a Vec<(ID, ID)>
-    where
-        Index: Send + Sync
-    {
-        self.par_scan_filtered(|_, _| true)
-    }
+            moved.insert(id);
+            pending.push((id, local_bounds));
+        }
 
-    /// [`scan_filtered`]: struct.Layer.html#method.scan_filtered
-    /// Parallel version of [`scan_filtered`]
-    #[cfg(feature="parallel")]
-    pub fn par_scan_filtered<'a, F>(&'a mut self, filter: F)
-        -> &'a Vec<(ID, ID)>
-    where
-        Index: Send + Sync,
-        F: Copy + Send + Sync + FnMut(ID, ID) -> bool
-    {
-        self.par_sort();
+        self.clipped.retain(|id| !updated.contains(id));
+        self.clipped.append(&mut clipped);
+        self.clipped.sort_unstable();
+        self.clipped.dedup();
 
-        self.collisions.clear();
-        self.invalid.clear();
-        for set in self.collisions_tls.iter_mut() {
-            set.borrow_mut().clear();
-        }
+        if !moved.is_empty() {
+            let (tree, sorted) = &mut self.tree;
+            let depth = Index::clamp_depth(self.min_depth);
+            let stamp = self.stamp;
+            let inactive = &self.inactive;
+            let mut partitions = self.active_partitions.as_mut()
+                .filter(|partitions| partitions.stamp == Some(stamp) && partitions.depth == depth);
+            let mut count = |index: Index, id: ID, added: bool| {
+                if let (Some(partitions), false) = (partitions.as_mut(), inactive.contains(&id)) {
+                    let count = partitions.counts.entry(Self::truncate_index(index, depth)).or_insert(0);
+                    if added {
+                        *count += 1;
+                    } else {
+                        *count -= 1;
+                        if *count == 0 {
+                            partitions.counts.remove(&Self::truncate_index(index, depth));
+                        }
+                    }
+                }
+            };
+            tree.retain(|&(index, id)| {
+                let removed = moved.contains(&id);
+                if removed {
+                    count(index, id, false);
+                }
+                !removed
+            });
 
-        self.par_scan_impl(rayon::current_num_threads(), self.tree.0.as_slice(), filter);
+            // the number of entries which may still be added, if limited, as in `extend`
+            let mut remaining = self.max_entries
+                .map(|max_entries| max_entries.saturating_sub(tree.len()));
+            let mut entries = Vec::new();
+            for (id, local_bounds) in pending {
+                if remaining == Some(0) {
+                    truncated.push(id);
+                    continue;
+                }
 
-        for set in self.collisions_tls.iter_mut() {
-            use std::borrow::Borrow;
-            let set_: RefMut<Vec<(ID, ID)>> = set.borrow_mut();
-            let set__: &Vec<(ID, ID)> = set_.borrow();
-            self.collisions.extend(set__.iter());
+                let max_depth = Self::budgeted_max_depth(self.target_entries, tree.len() + entries.len(), local_bounds, None);
+                let mut indices = local_bounds
+                    .indices_in_range(Some(self.min_depth), max_depth)
+                    .into_iter();
+                let limit = match (remaining, self.max_entries_per_object) {
+                    (Some(lhs), Some(rhs)) => Some(lhs.min(rhs)),
+                    (lhs, rhs) => lhs.or(rhs)
+                };
+
+                let len = entries.len();
+                match limit {
+                    Some(limit) => {
+                        entries.extend(indices.by_ref()
+                            .take(limit)
+                            .map(|index| (index, id)));
+                        if indices.next().is_some() {
+                            truncated.push(id);
+                        }
+                        if let Some(remaining) = &mut remaining {
+                            *remaining -= entries.len() - len;
+                        }
+                    },
+                    None => entries.extend(indices.map(|index| (index, id)))
+                }
+            }
+
+            for &(index, id) in &entries {
+                count(index, id, true);
+            }
+            tree.append(&mut entries);
+            *sorted = false;
+            self.stamp = next_stamp();
+            if let Some(partitions) = partitions {
+                partitions.stamp = Some(self.stamp);
+            }
+            self.commit();
         }
 
-        self.collisions.par_sort_unstable();
-        self.collisions.dedup();
+        self.truncated.retain(|id| !moved.contains(id));
+        self.truncated.append(&mut truncated);
+        self.truncated.sort_unstable();
+        self.truncated.dedup();
 
-        &self.collisions
+        moved.len()
     }
 
-    #[cfg(feature="parallel")]
-    fn par_scan_impl<F>(&self, threads: usize, tree: &[(Index, ID)], filter: F)
+    /// [`extend`]: #method.extend
+    /// Remove all entries in cells inside `region`, then add `objects` in their place, e.g. for
+    /// destructible terrain which invalidates a localized area
+    /// 
+    /// Only entries whose cells lie entirely inside `region` are removed, so objects which extend
+    /// outside of it keep their other entries; `objects` should typically replace everything in the
+    /// region.  Objects left without any entries are forgotten entirely (including stored bounds), as
+    /// though they had never been added.  Unbounded objects are unaffected.
+    /// 
+    /// Unlike [`extend`], this commits any staged changes, then updates the committed state directly,
+    /// and commits again.  Returns the number of entries removed.
+    pub fn rebuild_region<Iter, Point_>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        region: Bounds<Point_>,
+        objects: Iter) -> usize
     where
-        Index: Send + Sync,
-        F: Copy + Send + Sync + FnMut(ID, ID) -> bool
+        Iter: std::iter::Iterator<Item = (Bounds<Point_>, ID)>,
+        Point_: EuclideanSpace<Scalar = f32>,
+        Point_::Diff: ElementWise,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>,
+        Bounds<Index::Point>: From<Index>
     {
-        const SPLIT_THRESHOLD: usize = 64;
-        if threads <= 1 || tree.len() <= SPLIT_THRESHOLD {
-            let collisions = self.collisions_tls.get_or(|| RefCell::new(Vec::new()));
-            Self::scan_impl(tree, collisions.borrow_mut(), filter);
-        } else {
-            let n = tree.len();
-            let mut i = n / 2;
-            while i < n {
-                let (last, _) = tree[i-1];
-                let (next, _) = tree[i];
-                if !Index::same_cell_at_depth(last, next, self.min_depth) {
-                    break;
+        self.commit();
+
+        // floats converted to local coordinates are only precise to 256 units (24 of 32 bits), so allow
+        // cells to extend that far past the region, e.g. where its edge coincides with theirs, but by
+        // less than half of their own size; i.e. the region is snapped to the nearest cell boundary
+        let region: Bounds<Index::Point> = system_bounds.to_local(region);
+        let contains = |cell: Bounds<Index::Point>| (0..Index::Point::len()).all(|i| {
+            let slack = ((cell.max[i] - cell.min[i]) / 2).min(0x100);
+            region.min[i].saturating_sub(slack) <= cell.min[i] && cell.max[i] <= region.max[i].saturating_add(slack)
+        });
+        let (tree, _) = &mut self.tree;
+        let mut removed: FxHashSet<ID> = FxHashSet::default();
+        let len = tree.len();
+        tree.retain(|&(index, id)| {
+            let inside = contains(Bounds::from(index));
+            if inside {
+                removed.insert(id);
+            }
+            !inside
+        });
+        let removed_count = len - tree.len();
+
+        if removed_count > 0 {
+            // removal preserves order, so the committed entries remain sorted
+            for &(_, id) in tree.iter() {
+                removed.remove(&id);
+            }
+            if let Some(bounds) = &mut self.bounds {
+                for &id in &removed {
+                    bounds.remove(id);
                 }
-                i += 1;
             }
-            let (head, tail) = tree.split_at(i);
-            rayon::join(
-                || self.par_scan_impl(threads >> 1, head, filter),
-                || self.par_scan_impl(threads >> 1, tail, filter));
+            self.clipped.retain(|id| !removed.contains(id));
+            self.truncated.retain(|id| !removed.contains(id));
+            self.stamp = next_stamp();
         }
+
+        self.extend(system_bounds, objects);
+        self.commit();
+
+        removed_count
     }
 
-    fn scan_impl<C, F>(tree: &[(Index, ID)], mut collisions: C, mut filter: F)
+    /// [`extend`]: #method.extend
+    /// [`sort_objects_by_cell`]: #method.sort_objects_by_cell
+    /// Append multiple objects to the `Layer`, which produce entries in sorted order
+    /// 
+    /// This is otherwise equivalent to [`extend`].  The caller asserts that, after sorting each object's
+    /// own entries, entries (index-ID pairs) arrive in non-decreasing order, e.g. because objects are
+    /// iterated in Morton order (see [`sort_objects_by_cell`]) and IDs increase within each cell.  If
+    /// all staged entries were added this way, and follow all committed entries, the next `commit`
+    /// skips sorting entirely.
+    /// 
+    /// The ordering is checked as entries are added, at a constant cost per object.  If it doesn't
+    /// hold, a warning is logged and entries are sorted as usual; in debug builds, this panics.
+    pub fn extend_sorted_hint<Iter, Point_>(&mut self, system_bounds: Bounds<Point_>, objects: Iter)
     where
-        C: DerefMut<Target = Vec<(ID, ID)>>,
-        F: FnMut(ID, ID) -> bool
+        Iter: std::iter::Iterator<Item = (Bounds<Point_>, ID)>,
+        Point_: EuclideanSpace<Scalar = f32>,
+        Point_::Diff: ElementWise,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
     {
-        let mut stack: SmallVec<[(Index, ID); 256]> = SmallVec::new();
-        for &(index, id) in tree {
-            while let Some(&(index_, _)) = stack.last() {
-                if index.overlaps(index_) {
-                    break;
-                }
-                stack.pop();
-            }
-            if stack.iter().any(|&(_, id_)| id == id_) {
+        for object in objects {
+            let start = self.staging.entries.len();
+            let sorted = self.staging.sorted_len == start;
+            self.extend(system_bounds, std::iter::once(object));
+
+            let entries = &mut self.staging.entries;
+            entries[start..].sort_unstable();
+            if !sorted {
                 continue;
             }
-            for &(_, id_) in &stack {
-                if id != id_ && filter(id, id_) {
-                    collisions.push((id, id_));
-                }
+            if start > 0 && start < entries.len() && entries[start - 1] > entries[start] {
+                warn!("extend_sorted_hint: entries out of order ({:?} > {:?})", entries[start - 1], entries[start]);
+                debug_assert!(false, "extend_sorted_hint: entries out of order");
+                continue;
             }
-            stack.push((index, id))
+            self.staging.sorted_len = entries.len();
         }
     }
-}
 
-impl<Index, ID> PartialEq<Self> for Layer<Index, ID>
-where
-    Index: SpatialIndex,
-    ID: ObjectID,
-    Bounds<Index::Point>: IndexGenerator<Index>
-{
-    fn eq(&self, other: &Self) -> bool {
-        self.min_depth == other.min_depth &&
-        self.tree      == other.tree
+    /// [`extend_sorted_hint`]: #method.extend_sorted_hint
+    /// Sort objects by their primary (i.e. first) cell in this `Layer`, then by ID
+    /// 
+    /// Adding objects in this (Morton) order improves memory locality of `extend`, and objects which
+    /// each occupy a single cell produce entries in sorted order, as required by
+    /// [`extend_sorted_hint`].  Objects which are out of bounds are sorted by the cell of their
+    /// clipped bounds, or last if they don't overlap `system_bounds`.
+    pub fn sort_objects_by_cell<Point_>(&self, system_bounds: Bounds<Point_>, objects: &mut [(Bounds<Point_>, ID)])
+    where
+        Point_: EuclideanSpace<Scalar = f32>,
+        Point_::Diff: ElementWise,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
+    {
+        objects.sort_by_cached_key(|&(bounds, id)| {
+            let cell = system_bounds.intersection(bounds).and_then(|bounds| {
+                system_bounds.to_local(bounds)
+                    .indices(Some(self.min_depth))
+                    .into_iter()
+                    .min()
+            });
+            (cell.is_none(), cell, id)
+        });
     }
-}
-
-impl<Index, ID> Eq for Layer<Index, ID>
-where
-    Index: SpatialIndex,
-    ID: ObjectID,
-    Bounds<Index::Point>: IndexGenerator<Index>
-{}
 
-impl<Index, ID> Clone for Layer<Index, ID>
-where
-    Index: SpatialIndex,
-    ID: ObjectID,
-    Bounds<Index::Point>: IndexGenerator<Index>
-{
-    fn clone(&self) -> Self {
-        Layer{
-            min_depth: self.min_depth,
-            tree: self.tree.clone(),
+    /// Merge another `Layer` into this `Layer`
+    /// 
+    /// This may be used, for example, to merge static scene `Layer` into the current
+    /// frames' dynamic `Layer` without having to recalculate indices for the static data.  The contents
+    /// `other` would have after its next `commit` (i.e. including its staged changes) are merged, and are
+    /// staged until the next `commit` of this `Layer`.
+    pub fn merge(&mut self, other: &Layer<Index, ID>) {
+        self.merge_mapped(other, |id| id);
+    }
 
-            // don't bother cloning the contents of temporary buffers
-            collisions: Vec::with_capacity(self.collisions.capacity()),
-            test_results: Vec::with_capacity(self.test_results.capacity()),
-            processed: FxHashSet::default(),
-            invalid: Vec::new(),
+    /// [`merge`]: #method.merge
+    /// Equivalent to [`merge`], replacing each ID of `other` with `map(id)`
+    /// 
+    /// This may be used to combine layers whose ID spaces collide, e.g. to merge a prefab's local IDs
+    /// into world entity IDs.  `map` should be injective over the IDs of `other`; objects mapped to the
+    /// same ID are merged, as are objects mapped to an ID already in this `Layer`.  Per-object state
+    /// (e.g. activation flags) isn't merged, as with [`merge`].
+    pub fn merge_mapped<F>(&mut self, other: &Layer<Index, ID>, map: F)
+    where
+        F: Fn(ID) -> ID
+    {
+        let staging = &mut self.staging;
+        let rhs_staging = &other.staging;
+        let (rhs_tree, _) = &other.tree;
 
-            #[cfg(feature="parallel")]
-            collisions_tls: CachedThreadLocal::new()
+        if other.min_depth < self.min_depth {
+            warn!("merging layer of lesser min_depth (lhs: {}, rhs: {})", self.min_depth, other.min_depth);
+            self.min_depth = other.min_depth;
         }
-    }
-}
 
-/// A builder for `Layer`s
-#[derive(Default)]
-pub struct LayerBuilder {
-    min_depth: u32,
-    index_capacity: Option<usize>,
-    collision_capacity: Option<usize>,
-    test_capacity: Option<usize>
-}
+        if !rhs_staging.reset {
+            staging.entries.extend(rhs_tree.iter().map(|&(index, id)| (index, map(id))));
+            staging.unbounded.extend(other.unbounded.iter().map(|&id| map(id)));
+            staging.clipped.extend(other.clipped.iter().map(|&id| map(id)));
+            staging.truncated.extend(other.truncated.iter().map(|&id| map(id)));
+        }
+        staging.entries.extend(rhs_staging.entries.iter().map(|&(index, id)| (index, map(id))));
+        staging.unbounded.extend(rhs_staging.unbounded.iter().map(|&id| map(id)));
+        staging.clipped.extend(rhs_staging.clipped.iter().map(|&id| map(id)));
+        staging.truncated.extend(rhs_staging.truncated.iter().map(|&id| map(id)));
 
-impl LayerBuilder {
-    pub fn new() -> Self {
-        Self::default()
+        if let (Some(_), Some(rhs_bounds)) = (&self.bounds, &other.bounds) {
+            let rhs_committed = Some(rhs_bounds).filter(|_| !rhs_staging.reset);
+            for (id, bounds) in rhs_committed.into_iter().flat_map(BoundsTable::iter)
+                .chain(rhs_staging.bounds.iter().map(|(&id, &bounds)| (id, bounds)))
+            {
+                staging.bounds.entry(map(id))
+                    .and_modify(|lhs| *lhs = lhs.union(bounds))
+                    .or_insert(bounds);
+            }
+        }
     }
 
-    /// Set a minimum depth for index generation.
+    /// [`RecenteringBounds`]: struct.RecenteringBounds.html
+    /// Re-map existing indices after moving the system bounds by a whole number of cells at `depth`
     /// 
-    /// This parameter is important for parallel processing.  A higher value improves the partitioning of data and
-    /// improves workload balancing.  However, it can also create many more indices/object than is necessary.  A
-    /// setting which is too high may result in an excessive number of dynamic allocations and duplication of
-    /// intermediate collision pairs, ultimately hurting worst-case performance.
+    /// `cells` is the (signed) number of cells the system bounds have moved along each axis; components
+    /// beyond the dimension of `Index` are ignored.  Indices at or below `depth` are translated directly.
+    /// Coarser indices are re-generated at their original depth, which may yield additional indices.
+    /// Entries which are moved entirely outside of the system bounds are removed, and entries which are
+    /// moved partially outside are clipped.  See [`RecenteringBounds`].
     /// 
-    /// A value of zero is the safest performance-wise for _single-threaded_ operations.
+    /// _note: staged changes are committed first, and the result is re-sorted (non-parallel)_
+    pub fn translate(&mut self, depth: u32, cells: [i32; 3])
+    where
+        Bounds<Index::Point>: From<Index>
+    {
+        self.commit();
+
+        let depth = Index::clamp_depth(depth);
+        assert!(depth > 0, "cannot translate by cells at zero depth");
+
+        let dim = Index::Point::len();
+        let scale = 1i64 << (32 - depth);
+        let shift = |i: usize| i64::from(cells[i]) * scale;
+
+        // translates local bounds, returning `None` if they move outside of the system bounds
+        let translate = |bounds: Bounds<Index::Point>| -> Option<(Bounds<Index::Point>, bool)> {
+            let mut result = bounds;
+            let mut clipped = false;
+            for i in 0..dim {
+                let min = i64::from(bounds.min[i]) - shift(i);
+                let max = i64::from(bounds.max[i]) - shift(i);
+                if max < 0 || min > i64::from(u32::MAX) {
+                    return None;
+                }
+                clipped |= min < 0 || max > i64::from(u32::MAX);
+                result.min[i] = min.max(0) as u32;
+                result.max[i] = max.min(i64::from(u32::MAX)) as u32;
+            }
+            Some((result, clipped))
+        };
+
+        self.stamp = next_stamp();
+        let (tree, sorted) = &mut self.tree;
+        let entries = std::mem::replace(tree, Vec::with_capacity(tree.capacity()));
+        for (index, id) in entries {
+            if index.depth() == 0 {
+                tree.push((index, id));
+                continue;
+            }
+            match translate(Bounds::from(index)) {
+                Some((bounds, false)) if index.depth() >= depth => {
+                    tree.push((index.set_origin(bounds.min), id));
+                },
+                Some((bounds, _)) => {
+                    tree.extend(bounds
+                        .indices_at_depth(index.depth())
+                        .into_iter()
+                        .map(|index| (index, id)));
+                },
+                None => ()
+            }
+        }
+        tree.sort_unstable();
+        *sorted = true;
+
+        if let Some(bounds) = &mut self.bounds {
+            let mut moved: Vec<(ID, Bounds<Index::Point>)> = Vec::with_capacity(bounds.len());
+            moved.extend(bounds.iter()
+                .filter_map(|(id, bounds)| translate(bounds).map(|(bounds, _)| (id, bounds))));
+            bounds.clear();
+            for (id, moved) in moved {
+                bounds.insert(id, moved);
+            }
+        }
+
+        if let Some(hot_cells) = &mut self.hot_cells {
+            hot_cells.cells.clear();
+        }
+    }
+
+    /// All entries within the cell represented by `index`, in sorted order
     /// 
-    /// When using multi-threaded methods, try a value that between
-    /// _log<sub>4</sub> number_of_processors_ (2D) or
-    /// _log<sub>8</sub> number_of_processors_ (3D) and
-    /// _&minus;log<sub>2</sub>(max_object_size/system_bounds_size)_
+    /// `index` must have an origin truncated to its depth (as generated by `Layer`, or as returned by
+    /// `SpatialIndex::subdivide`).  Entries in larger cells containing `index` are not included.  This is
+    /// intended for debugging and for custom traversals.
     /// 
-    /// __It is generally better to set this too low than too high__
-    pub fn with_min_depth(&mut self, depth: u32) -> &mut Self {
-        self.min_depth = depth;
-        self
-    }
+    /// _note: this method may do an implicit, non-parallel commit_
+    pub fn ids_in_cell(&mut self, index: Index) -> &[(Index, ID)] {
+        use std::cmp::Ordering::{Less, Greater};
 
-    /// Set an _initial_ capacity for the index list.
-    pub fn with_index_capacity(&mut self, capacity: usize) -> &mut Self {
-        self.index_capacity = Some(capacity);
-        self
+        self.commit();
+
+        let (tree, _) = &self.tree;
+        let depth = index.depth();
+        let start = tree.binary_search_by(|&(index_, _)| {
+            if index_ < index { Less } else { Greater }
+        }).err().unwrap();
+        let len = tree[start..].binary_search_by(|&(index_, _)| {
+            if index_.depth() >= depth && Index::same_cell_at_depth(index_, index, depth) { Less } else { Greater }
+        }).err().unwrap();
+
+        &tree[start..start+len]
     }
 
-    /// Set an _initial_ capacity for the collision results list, used by `Layer::scan`.
-    pub fn with_collision_capacity(&mut self, capacity: usize) -> &mut Self {
-        self.collision_capacity = Some(capacity);
-        self
+    /// Verify internal invariants, returning a list of violations
+    /// 
+    /// This is intended for checking layers deserialized from untrusted or older data.  A valid layer
+    /// returns an empty list.
+    pub fn validate(&self) -> Vec<Violation<Index, ID>> {
+        let mut violations = Vec::new();
+        let (tree, sorted) = &self.tree;
+        let min_depth = Index::clamp_depth(self.min_depth);
+
+        if *sorted {
+            if let Some(position) = tree.windows(2).position(|pair| pair[0] > pair[1]) {
+                violations.push(Violation::Unsorted{position: position + 1});
+            }
+        }
+
+        for &(index, id) in tree {
+            let depth = index.depth();
+            if Index::clamp_depth(depth) != depth {
+                violations.push(Violation::InvalidDepth{index, id});
+                continue;
+            }
+            if Self::truncate_index(index, depth) != index {
+                violations.push(Violation::UntruncatedOrigin{index, id});
+            }
+            if depth < min_depth {
+                violations.push(Violation::BelowMinDepth{index, id});
+            }
+        }
+
+        violations
     }
 
-    /// Set an _initial_ capacity for the test results list, used by `Layer::test` and `Layer::pick`.
-    pub fn with_test_capacity(&mut self, capacity: usize) -> &mut Self {
-        self.test_capacity = Some(capacity);
-        self
+    /// The cell containing `index` at `depth`, or `index` itself if it is shallower than `depth`
+    fn truncate_index(index: Index, depth: u32) -> Index {
+        let depth = std::cmp::min(depth, index.depth());
+        let mut origin = index.origin();
+        for i in 0..Index::Point::len() {
+            origin[i] = origin[i].checked_shr(32 - depth).unwrap_or(0).checked_shl(32 - depth).unwrap_or(0);
+        }
+        index.set_depth(depth).set_origin(origin)
     }
 
-    pub fn build<Index, ID>(&self) -> Layer<Index, ID>
+    /// All entries overlapping a region, grouped by cell at a fixed `depth`
+    /// 
+    /// Returns cell-ID pairs, sorted by cell, without duplicates.  Entries shallower than `depth` are
+    /// grouped under their own (larger) cell.  This may be convenient for chunk-based systems or for
+    /// building density fields; it is a linear pass over all entries.
+    /// 
+    /// _note: this method may do an implicit, non-parallel commit_
+    pub fn ids_in_cells_at_depth<Point_>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        region_bounds: Bounds<Point_>,
+        depth: u32) -> &Vec<(Index, ID)>
     where
-        Index: SpatialIndex,
-        ID: ObjectID,
-        Bounds<Index::Point>: IndexGenerator<Index>
+        Point_: EuclideanSpace<Scalar = f32>,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>,
+        Bounds<Index::Point>: From<Index>
     {
-        Layer{
-            min_depth: self.min_depth,
-            tree: (match self.index_capacity {
-                    Some(capacity) => Vec::with_capacity(capacity),
-                    None => Vec::new()
-                }, true),
-            collisions: match self.collision_capacity {
-                    Some(capacity) => Vec::with_capacity(capacity),
-                    None => Vec::new()
+        self.commit();
+
+        self.cell_results.clear();
+
+        let region: Bounds<Index::Point> = system_bounds.to_local(region_bounds);
+        let depth = Index::clamp_depth(depth);
+        let (tree, _) = &self.tree;
+        self.cell_results.extend(tree.iter()
+            .filter(|&&(index, _)| index.depth() == 0 || Bounds::from(index).overlaps(region))
+            .map(|&(index, id)| (Self::truncate_index(index, depth), id)));
+
+        // entries sharing a cell are contiguous, but IDs within a cell are not necessarily in order
+        self.cell_results.sort_unstable();
+        self.cell_results.dedup();
+
+        &self.cell_results
+    }
+
+    /// [`LayerAnalysis`]: struct.LayerAnalysis.html
+    /// Summarize the distribution of entries, see [`LayerAnalysis`]
+    /// 
+    /// Entries are counted per-cell at `cell_depth`, and up to `max_hot_cells` of the most
+    /// heavily-occupied cells are reported.
+    /// 
+    /// _note: this method may do an implicit, non-parallel commit_
+    pub fn analysis(&mut self, cell_depth: u32, max_hot_cells: usize) -> LayerAnalysis<Index> {
+        self.commit();
+
+        let (tree, _) = &self.tree;
+        let cell_depth = Index::clamp_depth(cell_depth);
+        let mut depth_histogram: Vec<usize> = Vec::new();
+        let mut cells: Vec<(Index, usize)> = Vec::new();
+        let mut object_entries: FxHashMap<ID, usize> = FxHashMap::default();
+
+        // entries within a cell are contiguous in sorted order
+        for &(index, id) in tree {
+            *object_entries.entry(id).or_insert(0) += 1;
+
+            let depth = index.depth() as usize;
+            if depth_histogram.len() <= depth {
+                depth_histogram.resize(depth + 1, 0);
+            }
+            depth_histogram[depth] += 1;
+
+            let cell = Self::truncate_index(index, cell_depth);
+            match cells.last_mut() {
+                Some((last, count)) if *last == cell => {
+                    *count += 1;
                 },
-            test_results: match self.test_capacity {
-                    Some(capacity) => Vec::with_capacity(capacity),
-                    None => Vec::new()
+                _ => cells.push((cell, 1))
+            }
+        }
+
+        let occupied_cells = cells.len();
+        cells.sort_by(|(lhs_index, lhs), (rhs_index, rhs)| rhs.cmp(lhs).then(lhs_index.cmp(rhs_index)));
+        cells.truncate(max_hot_cells);
+
+        let max_object_entries = object_entries.values().cloned().max().unwrap_or(0);
+        let heavy_objects = self.entry_warning_threshold.map_or(0, |threshold|
+            object_entries.values().filter(|&&count| count > threshold).count());
+
+        LayerAnalysis{
+            entries: tree.len(),
+            depth_histogram,
+            cell_depth,
+            occupied_cells,
+            hot_cells: cells,
+            max_object_entries,
+            heavy_objects}
+    }
+
+    /// [`MemoryReport`]: struct.MemoryReport.html
+    /// Report the heap memory held by this `Layer`, by category, see [`MemoryReport`]
+    /// 
+    /// This does not commit; `&mut self` is only required to visit per-thread buffers.
+    pub fn memory_usage(&mut self) -> MemoryReport {
+        let hot_cells = self.hot_cells.as_ref().map_or(0, |hot_cells| {
+            map_bytes(&hot_cells.cells) + hot_cells.cells.values()
+                .map(|cell| vec_bytes(&cell.order))
+                .sum::<usize>()
+        });
+
+        #[cfg(feature="parallel")]
+        let thread_local = self.collisions_tls.iter_mut()
+            .map(|collisions| vec_bytes(collisions.get_mut()))
+            .sum();
+        #[cfg(not(feature="parallel"))]
+        let thread_local = 0;
+
+        MemoryReport{
+            tree: vec_bytes(&self.tree.0),
+            staging: self.staging.memory_usage(),
+            objects: vec_bytes(&self.unbounded) + vec_bytes(&self.clipped) + vec_bytes(&self.truncated)
+                + vec_bytes(&self.heavy_objects) + self.bounds.as_ref().map_or(0, BoundsTable::memory_usage) + set_bytes(&self.inactive) + map_bytes(&self.flags) + map_bytes(&self.groups) + map_bytes(&self.one_way)
+                + self.id_index.as_ref().map_or(0, |index| vec_bytes(&index.order))
+                + self.active_partitions.as_ref().map_or(0, |partitions| map_bytes(&partitions.counts)),
+            hot_cells,
+            scratch: vec_bytes(&self.test_results) + set_bytes(&self.processed) + vec_bytes(&self.invalid) + vec_bytes(&self.cell_results),
+            thread_local,
+            pair_cache: vec_bytes(&self.collisions) + vec_bytes(&self.pair_arrays.ids_a) + vec_bytes(&self.pair_arrays.ids_b)}
+    }
+
+    /// [`LayerBuilder`]: struct.LayerBuilder.html
+    /// A [`LayerBuilder`] which builds an empty `Layer` with the same configuration as this one
+    /// 
+    /// Capacities reflect the current size of this `Layer`'s buffers, rather than those originally
+    /// requested.
+    pub fn builder_config(&self) -> LayerBuilder {
+        let capacity = |capacity: usize| Some(capacity).filter(|&capacity| capacity > 0);
+        LayerBuilder{
+            min_depth: self.min_depth,
+            index_capacity: capacity(self.tree.0.capacity().max(self.staging.entries.capacity())),
+            collision_capacity: capacity(self.collisions.capacity()),
+            test_capacity: capacity(self.test_results.capacity()),
+            out_of_bounds: self.out_of_bounds,
+            max_entries: self.max_entries,
+            target_entries: self.target_entries,
+            max_entries_per_object: self.max_entries_per_object,
+            entry_warning_threshold: self.entry_warning_threshold,
+            dense_cell_threshold: self.dense_cell_threshold,
+            stored_bounds: self.bounds.is_some(),
+            quantized_bounds: matches!(&self.bounds, Some(bounds) if bounds.is_quantized()),
+            refined_tests: self.refined_tests,
+            deterministic: self.deterministic,
+            exclude_inactive: self.exclude_inactive,
+            active_partitions: self.active_partitions.is_some(),
+            hot_cell_frames: self.hot_cells.as_ref().map(|hot_cells| hot_cells.min_frames),
+            id_index: self.id_index.is_some()}
+    }
+
+    /// [`FrozenLayer`]: struct.FrozenLayer.html
+    /// Sort this `Layer` and convert it into a read-only [`FrozenLayer`]
+    pub fn freeze(self) -> FrozenLayer<Index, ID> {
+        FrozenLayer::new(self, None)
+    }
+
+    /// [`FrozenLayer`]: struct.FrozenLayer.html
+    /// Sort this `Layer` and convert it into a read-only [`FrozenLayer`], precomputing the range of
+    /// entries contained by each occupied cell up to `range_depth`
+    /// 
+    /// Queries on the resulting `FrozenLayer` use these ranges to jump directly to the entries for a
+    /// given cell rather than repeatedly searching for them.  Deeper `range_depth` values trade memory
+    /// for query speed.
+    pub fn freeze_with_ranges(self, range_depth: u32) -> FrozenLayer<Index, ID> {
+        FrozenLayer::new(self, Some(range_depth))
+    }
+
+    /// [`freeze`]: #method.freeze
+    /// [`freeze_with_ranges`]: #method.freeze_with_ranges
+    /// Equivalent to [`freeze`] or [`freeze_with_ranges`], reporting the fraction of work completed
+    /// (from 0 to 1) to `progress`
+    /// 
+    /// This is intended for progress bars in tooling which freezes very large static layers.  Sorting
+    /// is reported as a single step.
+    pub fn freeze_with_progress<P>(self, range_depth: Option<u32>, progress: P) -> FrozenLayer<Index, ID>
+    where
+        P: FnMut(f32)
+    {
+        FrozenLayer::with_progress(self, range_depth, progress)
+    }
+
+    /// [`par_commit`]: struct.Layer.html#method.par_commit
+    /// Equivalent to [`par_commit`]
+    #[cfg(feature="parallel")]
+    pub fn par_sort(&mut self) {
+        self.par_commit();
+    }
+
+    /// [`commit`]: struct.Layer.html#method.commit
+    /// Equivalent to [`commit`]
+    pub fn sort(&mut self) {
+        self.commit();
+    }
+
+    /// [`commit`]: struct.Layer.html#method.commit
+    /// [`par_scan_filtered`]: struct.Layer.html#method.par_scan_filtered
+    /// [`par_scan`]: struct.Layer.html#method.par_scan
+    /// Parallel version of [`commit`]
+    /// 
+    /// This will be called implicitly when necessary (i.e. by [`par_scan_filtered`], [`par_scan`], etc.)
+    #[cfg(feature="parallel")]
+    pub fn par_commit(&mut self) {
+        if self.deterministic {
+            return self.commit();
+        }
+        self.apply_staging();
+        let (tree, sorted) = &mut self.tree;
+        if !*sorted {
+            tree.par_sort_unstable();
+            *sorted = true;
+        }
+        strict_assert!(self.validate().is_empty(), "invalid layer: {:?}", self.validate());
+    }
+
+    /// [`scan_filtered`]: struct.Layer.html#method.scan_filtered
+    /// [`scan`]: struct.Layer.html#method.scan
+    /// Apply staged changes and sort indices, making them visible to queries
+    /// 
+    /// This will be called implicitly when necessary (i.e. by [`scan_filtered`], [`scan`], etc.)
+    pub fn commit(&mut self) {
+        self.apply_staging();
+        let (tree, sorted) = &mut self.tree;
+        if !*sorted {
+            if self.deterministic {
+                tree.sort();
+            } else {
+                tree.sort_unstable();
+            }
+            *sorted = true;
+        }
+        strict_assert!(self.validate().is_empty(), "invalid layer: {:?}", self.validate());
+    }
+
+    /// Discard all contents (committed and staged), returning the now-empty committed entries to be
+    /// refilled in sorted order
+    #[cfg(any(feature="stream", feature="mmap"))]
+    pub(crate) fn reset_committed(&mut self, min_depth: u32) -> &mut Vec<(Index, ID)> {
+        self.clear();
+        self.apply_staging();
+        self.min_depth = min_depth;
+        if let Some(partitions) = &mut self.active_partitions {
+            partitions.stamp = None;
+        }
+        let (tree, sorted) = &mut self.tree;
+        *sorted = true;
+        tree
+    }
+
+    /// Move staged changes into the committed state, leaving entries unsorted
+    fn apply_staging(&mut self) {
+        let staging = &mut self.staging;
+        let (tree, sorted) = &mut self.tree;
+
+        // count new entries, if existing counts are up to date
+        let mut partitions = self.active_partitions.as_mut();
+        if let Some(partitions_) = partitions.take() {
+            let depth = Index::clamp_depth(self.min_depth);
+            if staging.reset {
+                partitions_.counts.clear();
+                partitions_.depth = depth;
+                partitions_.stamp = Some(self.stamp);
+            }
+            if partitions_.stamp == Some(self.stamp) && partitions_.depth == depth {
+                for &(index, id) in &staging.entries {
+                    if !self.inactive.contains(&id) {
+                        *partitions_.counts.entry(Self::truncate_index(index, depth)).or_insert(0) += 1;
+                    }
+                }
+                partitions = Some(partitions_);
+            }
+        }
+
+        if staging.reset || !staging.entries.is_empty() || !staging.unbounded.is_empty() {
+            self.stamp = next_stamp();
+
+            // reported per commit, so replaced only when something was added
+            self.heavy_objects.clear();
+            self.heavy_objects.append(&mut staging.heavy);
+        }
+        if let Some(partitions) = partitions {
+            partitions.stamp = Some(self.stamp);
+        }
+
+        if staging.reset {
+            staging.reset = false;
+
+            // swap buffers so that both retain their capacity across frames
+            tree.clear();
+            std::mem::swap(tree, &mut staging.entries);
+            *sorted = staging.sorted_len == tree.len();
+
+            self.unbounded.clear();
+            self.clipped.clear();
+            self.truncated.clear();
+            if let Some(bounds) = &mut self.bounds {
+                bounds.clear();
+            }
+        }
+
+        if !staging.entries.is_empty() {
+            // entries known to be sorted may be appended without invalidating the sort
+            *sorted = *sorted
+                && staging.sorted_len == staging.entries.len()
+                && !matches!((tree.last(), staging.entries.first()), (Some(last), Some(first)) if last > first);
+            tree.append(&mut staging.entries);
+        }
+        staging.sorted_len = 0;
+
+        if !staging.unbounded.is_empty() {
+            self.unbounded.append(&mut staging.unbounded);
+            self.unbounded.sort_unstable();
+            self.unbounded.dedup();
+        }
+
+        if !staging.clipped.is_empty() {
+            self.clipped.append(&mut staging.clipped);
+            self.clipped.sort_unstable();
+            self.clipped.dedup();
+        }
+
+        if !staging.truncated.is_empty() {
+            self.truncated.append(&mut staging.truncated);
+            self.truncated.sort_unstable();
+            self.truncated.dedup();
+        }
+
+        if let Some(bounds) = &mut self.bounds {
+            for (id, staged) in staging.bounds.drain() {
+                bounds.insert(id, staged);
+            }
+        }
+    }
+
+    pub(crate) fn split_tree<'a>(
+        tree: &'a [(Index, ID)],
+        offset: usize,
+        sub_cells: &[Index]) -> SplitTree<'a, Index, ID>
+    {
+        use std::cmp::Ordering::{Less, Greater};
+
+        let mut offset = offset;
+        let mut tail = tree;
+        let mut sub_trees: SmallVec<[_; 8]> = sub_cells.iter()
+            .map(|&cell| {
+                let i = tail.binary_search_by(|&(index, _)| {
+                    if index < cell { Less } else { Greater }
+                }).err().unwrap();
+                let (head, tail_) = tail.split_at(i);
+                let result = (head, offset);
+                tail = tail_;
+                offset += i;
+                result
+            })
+            .collect();
+        sub_trees.push((tail, offset));
+
+        // the first sub-tree holds entries in the parent cell; shift the rest down to match sub_cells
+        let (head, _) = sub_trees.remove(0);
+        (head, sub_trees)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn test_impl<TestGeom, Callback, Observer>(
+        tree: &[(Index, ID)],
+        offset: usize,
+        cell: Index,
+        test_geom: &TestGeom,
+        mut nearest: f32,
+        max_depth: Option<u32>,
+        ranges: Option<&NodeRanges<Index>>,
+        callback: &mut Callback,
+        observer: &mut Observer) -> f32
+    where
+        TestGeom: TestGeometry,
+        Callback: FnMut(&TestGeom, f32, Index, ID) -> f32,
+        Observer: TraversalObserver<Index>
+    {
+        if tree.is_empty() || !test_geom.should_test(nearest) {
+            return nearest;
+        }
+
+        observer.on_cell_visited(cell, tree.len());
+
+        if tree.first().unwrap().0 < cell || !cell.overlaps(tree.last().unwrap().0) {
+            panic!("test_impl called with non-overlapping indices");
+        }
+
+        let depth = cell.depth();
+        if let Some(max_depth) = max_depth {
+            if depth >= max_depth {
+                return tree.iter()
+                    .fold(nearest, |nearest, &(index, id)|
+                        callback(test_geom, nearest, index, id).min(nearest));
+            }
+        }
+
+        if let Some(sub_cells) = cell.subdivide() {
+            let (head, sub_trees) = match ranges {
+                Some(ranges) if depth < ranges.depth() => {
+                    let split = ranges.split_tree(tree, offset, sub_cells.as_ref());
+                    // offsets of empty sub-trees are irrelevant, so only compare slices
+                    strict_assert!({
+                            let (head_, sub_trees_) = Self::split_tree(tree, offset, sub_cells.as_ref());
+                            split.0 == head_ && split.1.iter().map(|&(sub_tree, _)| sub_tree)
+                                .eq(sub_trees_.iter().map(|&(sub_tree, _)| sub_tree))
+                        },
+                        "precomputed ranges do not match the tree");
+                    split
                 },
-            processed: FxHashSet::default(),
-            invalid: Vec::new(),
-            #[cfg(feature="parallel")]
-            collisions_tls: CachedThreadLocal::new()
+                _ => Self::split_tree(tree, offset, sub_cells.as_ref())
+            };
+            strict_assert!(sub_trees.iter().zip(sub_cells.as_ref()).all(|((sub_tree, _), &sub_cell)|
+                sub_tree.iter().all(|&(index, _)| index.depth() >= sub_cell.depth() && sub_cell.overlaps(index))),
+                "sub-tree contains entries outside of its cell");
+            nearest = head.iter()
+                .fold(nearest, |nearest, &(index, id)|
+                    callback(test_geom, nearest, index, id).min(nearest));
+
+            observer.on_subdivide(depth);
+            let sub_tests = test_geom.subdivide();
+
+            for &i in test_geom.test_order().as_ref() {
+                let (sub_tree, sub_offset) = sub_trees[i];
+                nearest = Self::test_impl(
+                    sub_tree,
+                    sub_offset,
+                    sub_cells.as_ref()[i],
+                    &sub_tests.as_ref()[i],
+                    nearest,
+                    max_depth,
+                    ranges,
+                    callback,
+                    observer);
+            }
+
+            nearest
+        } else {
+            tree.iter()
+                .fold(nearest, |nearest, &(index, id)|
+                    callback(test_geom, nearest, index, id).min(nearest))
+        }
+    }
+
+    /// Run a single test on some geometry
+    /// 
+    /// This occurs by repeatedly subdividing both this `Layer`'s index-ID list and the provided
+    /// `test_geom`, returning any items at a given depth where both the resulting index list
+    /// is non-empty and [`TestGeometry::subdivide`] returns a result
+    /// 
+    /// _note: this method may do an implicit, non-parallel commit; you may call [`par_commit`] prior
+    /// to calling this method to perform a parallel commit instead_
+    /// 
+    /// [`TestGeometry::subdivide`]: trait.TestGeometry.html#tymethod.subdivide
+    /// [`par_commit`]: #method.par_commit
+    pub fn test<'a, TestGeom>(
+        &'a mut self,
+        test_geom: &TestGeom,
+        max_depth: Option<u32>) -> &'a Vec<ID>
+    where
+        TestGeom: TestGeometry
+    {
+        self.test_with_ranges(test_geom, max_depth, None, None)
+    }
+
+    /// `entries`, if provided, are sorted entries queried in place of this `Layer`'s own
+    pub(crate) fn test_with_ranges<'a, TestGeom>(
+        &'a mut self,
+        test_geom: &TestGeom,
+        max_depth: Option<u32>,
+        ranges: Option<&NodeRanges<Index>>,
+        entries: Option<&[(Index, ID)]>) -> &'a Vec<ID>
+    where
+        TestGeom: TestGeometry
+    {
+        self.commit();
+
+        let mut results = std::mem::take(&mut self.test_results);
+        let tree = entries.unwrap_or_else(|| self.sorted_tree());
+        self.test_sorted(tree, test_geom, max_depth, ranges, &mut results, &mut ());
+        self.test_results = results;
+
+        &self.test_results
+    }
+
+    /// [`test`]: #method.test
+    /// Equivalent to [`test`] for each of `test_geoms`, returning the union of their results
+    /// 
+    /// Results are sorted and deduplicated once for the whole batch, e.g. to find every object visible
+    /// to any of several shadow cascades, rather than once per test and again when merging.
+    /// 
+    /// _note: this method may do an implicit, non-parallel commit; you may call [`par_commit`] prior
+    /// to calling this method to perform a parallel commit instead_
+    /// 
+    /// [`par_commit`]: #method.par_commit
+    pub fn test_union<'a, TestGeom>(
+        &'a mut self,
+        test_geoms: &[TestGeom],
+        max_depth: Option<u32>) -> &'a Vec<ID>
+    where
+        TestGeom: TestGeometry
+    {
+        self.commit();
+
+        let mut results = std::mem::take(&mut self.test_results);
+        self.test_sorted_union(self.sorted_tree(), test_geoms, max_depth, None, &mut results, &mut ());
+        self.test_results = results;
+
+        &self.test_results
+    }
+
+    /// [`test`]: #method.test
+    /// Equivalent to [`test`] for each of `test_geoms`, returning the results of each separately, in
+    /// the same order
+    /// 
+    /// The tree is descended once for the whole batch, carrying only the geometries which overlap each
+    /// cell, so hundreds of small region queries cost far less than a descent each.  Each result is
+    /// sorted and deduplicated.
+    /// 
+    /// _note: this method may do an implicit, non-parallel commit; you may call [`par_commit`] prior
+    /// to calling this method to perform a parallel commit instead_
+    /// 
+    /// [`par_commit`]: #method.par_commit
+    pub fn test_many<TestGeom>(
+        &mut self,
+        test_geoms: &[TestGeom],
+        max_depth: Option<u32>) -> Vec<Vec<ID>>
+    where
+        TestGeom: TestGeometry
+    {
+        self.commit();
+
+        let mut results: Vec<Vec<ID>> = vec![Vec::new(); test_geoms.len()];
+        let test_geoms: Vec<(usize, &TestGeom)> = test_geoms.iter().enumerate().collect();
+        Self::test_many_impl(self.sorted_tree(), 0, Index::default(), &test_geoms, max_depth, &mut results);
+
+        for results in &mut results {
+            results.extend(self.unbounded.iter());
+            if self.exclude_inactive && !self.inactive.is_empty() {
+                results.retain(|&id| !self.inactive.contains(&id));
+            }
+            results.sort_unstable();
+            results.dedup();
         }
+        results
+    }
+
+    /// [`test`]: #method.test
+    /// [`commit`]: #method.commit
+    /// Equivalent to [`test`], but queries only the committed state and never commits implicitly
+    /// 
+    /// Panics if the `Layer` has never been committed since being deserialized, see [`commit`]
+    pub fn test_committed<TestGeom>(
+        &self,
+        test_geom: &TestGeom,
+        max_depth: Option<u32>) -> Vec<ID>
+    where
+        TestGeom: TestGeometry
+    {
+        let mut results = Vec::new();
+        self.test_sorted(self.sorted_tree(), test_geom, max_depth, None, &mut results, &mut ());
+        results
+    }
+
+    /// [`test`]: #method.test
+    /// [`TraversalObserver`]: trait.TraversalObserver.html
+    /// Equivalent to [`test`], reporting the traversal to `observer`, see [`TraversalObserver`]
+    pub fn test_observed<'a, TestGeom, Observer>(
+        &'a mut self,
+        test_geom: &TestGeom,
+        max_depth: Option<u32>,
+        observer: &mut Observer) -> &'a Vec<ID>
+    where
+        TestGeom: TestGeometry,
+        Observer: TraversalObserver<Index>
+    {
+        self.commit();
+
+        let mut results = std::mem::take(&mut self.test_results);
+        self.test_sorted(self.sorted_tree(), test_geom, max_depth, None, &mut results, observer);
+        self.test_results = results;
+
+        &self.test_results
+    }
+
+    /// The committed entries, which must be sorted
+    pub(crate) fn sorted_tree(&self) -> &[(Index, ID)] {
+        let (tree, sorted) = &self.tree;
+        assert!(*sorted, "layer must be sorted");
+        tree
     }
-}
\ No newline at end of file
+
+    /// Implementation of `test` for sorted entries `tree`, with a caller-provided result buffer
+    pub(crate) fn test_sorted<TestGeom, Observer>(
+        &self,
+        tree: &[(Index, ID)],
+        test_geom: &TestGeom,
+        max_depth: Option<u32>,
+        ranges: Option<&NodeRanges<Index>>,
+        results: &mut Vec<ID>,
+        observer: &mut Observer)
+    where
+        TestGeom: TestGeometry,
+        Observer: TraversalObserver<Index>
+    {
+        self.test_sorted_union(tree, std::slice::from_ref(test_geom), max_depth, ranges, results, observer);
+    }
+
+    /// Equivalent to `test_impl` for several `(position, geometry)` pairs at once, appending the IDs
+    /// found by each to `results[position]`
+    fn test_many_impl<TestGeom>(
+        tree: &[(Index, ID)],
+        offset: usize,
+        cell: Index,
+        test_geoms: &[(usize, &TestGeom)],
+        max_depth: Option<u32>,
+        results: &mut [Vec<ID>])
+    where
+        TestGeom: TestGeometry
+    {
+        if tree.is_empty() {
+            return;
+        }
+        let test_geoms: SmallVec<[(usize, &TestGeom); 8]> = test_geoms.iter()
+            .filter(|(_, test_geom)| test_geom.should_test(f32::INFINITY))
+            .cloned()
+            .collect();
+        if test_geoms.is_empty() {
+            return;
+        }
+
+        let mut found = |entries: &[(Index, ID)]| {
+            for &(i, _) in &test_geoms {
+                results[i].extend(entries.iter().map(|&(_, id)| id));
+            }
+        };
+
+        let depth = cell.depth();
+        if matches!(max_depth, Some(max_depth) if depth >= max_depth) {
+            found(tree);
+            return;
+        }
+        let sub_cells = match cell.subdivide() {
+            Some(sub_cells) => sub_cells,
+            None => {
+                found(tree);
+                return;
+            }
+        };
+
+        let (head, sub_trees) = Self::split_tree(tree, offset, sub_cells.as_ref());
+        found(head);
+
+        let sub_tests: SmallVec<[(usize, TestGeom::SubdivideResult); 8]> = test_geoms.iter()
+            .map(|&(i, test_geom)| (i, test_geom.subdivide()))
+            .collect();
+        let mut sub_geoms: SmallVec<[(usize, &TestGeom); 8]> = SmallVec::new();
+        for (cell_index, &(sub_tree, sub_offset)) in sub_trees.iter().enumerate() {
+            sub_geoms.clear();
+            sub_geoms.extend(sub_tests.iter().map(|(i, sub_tests)| (*i, &sub_tests.as_ref()[cell_index])));
+            Self::test_many_impl(sub_tree, sub_offset, sub_cells.as_ref()[cell_index], &sub_geoms, max_depth, results);
+        }
+    }
+
+    /// Equivalent to `test_sorted`, finding the union of the results of several tests
+    fn test_sorted_union<TestGeom, Observer>(
+        &self,
+        tree: &[(Index, ID)],
+        test_geoms: &[TestGeom],
+        max_depth: Option<u32>,
+        ranges: Option<&NodeRanges<Index>>,
+        results: &mut Vec<ID>,
+        observer: &mut Observer)
+    where
+        TestGeom: TestGeometry,
+        Observer: TraversalObserver<Index>
+    {
+        results.clear();
+
+        for test_geom in test_geoms {
+            Self::test_impl(
+                tree,
+                0,
+                Index::default(),
+                test_geom,
+                f32::INFINITY,
+                max_depth,
+                ranges,
+                &mut |_, nearest, _, id| {
+                    results.push(id);
+                    nearest
+                },
+                observer);
+        }
+        results.extend(self.unbounded.iter());
+        if self.exclude_inactive && !self.inactive.is_empty() {
+            results.retain(|&id| !self.inactive.contains(&id));
+        }
+
+        results.sort();
+        results.dedup();
+    }
+
+    /// Retain only the test results whose stored bounds overlap `test_geom`, if configured with
+    /// `LayerBuilder::with_refined_tests`
+    fn refine_by_stored_bounds<TestGeom, Point_>(&mut self, test_geom: &TestGeom, system_bounds: Bounds<Point_>)
+    where
+        TestGeom: RefinedTestGeometry<Point_>,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
+    {
+        if let (true, Some(bounds)) = (self.refined_tests, &self.bounds) {
+            self.test_results.retain(|&id| match bounds.get(id) {
+                Some(bounds) => test_geom.overlaps(Self::global_bounds(&system_bounds, bounds)),
+                None => true
+            });
+        }
+    }
+
+    /// The global bounds enclosing local bounds, e.g. stored bounds or those of a cell
+    fn global_bounds<Point_>(system_bounds: &Bounds<Point_>, mut bounds: Bounds<Index::Point>) -> Bounds<Point_>
+    where
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
+    {
+        // local coordinates are truncated, so extend to the end of the last local unit
+        for i in 0..Index::Point::len() {
+            bounds.max[i] = bounds.max[i].saturating_add(1);
+        }
+        system_bounds.to_global(bounds)
+    }
+
+    /// A special case of [`test`] for bounding box tests, see [`BoxTestGeometry`]
+    /// 
+    /// If configured with `LayerBuilder::with_refined_tests`, results are checked against stored
+    /// bounds, and so exclude objects which merely share a cell with `test_bounds`.
+    /// 
+    /// The `system_bounds` provided to this method should, in most cases, be identical to the
+    /// `system_bounds` provided to [`extend`]
+    /// 
+    /// _note: this method may do an implicit, non-parallel commit; you may call [`par_commit`] prior
+    /// to calling this method to perform a parallel commit instead_
+    /// 
+    /// [`test`]: #method.test
+    /// [`extend`]: #method.extend
+    /// [`par_commit`]: #method.par_commit
+    /// [`BoxTestGeometry`]: struct.BoxTestGeometry.html
+    pub fn test_box<Point_>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        test_bounds: Bounds<Point_>,
+        max_depth: Option<u32>) -> &Vec<ID>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + Debug,
+        Point_::Diff: ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        BoxTestGeometry<Point_>: TestGeometry,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
+    {
+        let test_geom = BoxTestGeometry::with_system_bounds(
+            system_bounds,
+            test_bounds);
+
+        self.test(
+            &test_geom,
+            max_depth);
+
+        self.refine_by_stored_bounds(&test_geom, system_bounds);
+
+        &self.test_results
+    }
+
+    /// A special case of [`test`] for point tests, see [`PointTestGeometry`]
+    /// 
+    /// This finds objects whose cells contain `point`, visiting a single cell at each depth.  If
+    /// configured with `LayerBuilder::with_refined_tests`, results are checked against stored bounds,
+    /// and so include only objects whose bounds contain `point`.
+    /// 
+    /// The `system_bounds` provided to this method should, in most cases, be identical to the
+    /// `system_bounds` provided to [`extend`]
+    /// 
+    /// [`test`]: #method.test
+    /// [`extend`]: #method.extend
+    /// [`PointTestGeometry`]: struct.PointTestGeometry.html
+    pub fn test_point<Point_>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        point: Point_,
+        max_depth: Option<u32>) -> &Vec<ID>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + Debug,
+        PointTestGeometry<Point_>: TestGeometry,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
+    {
+        let test_geom = PointTestGeometry::with_system_bounds(
+            system_bounds,
+            point);
+
+        self.test(
+            &test_geom,
+            max_depth);
+
+        self.refine_by_stored_bounds(&test_geom, system_bounds);
+
+        &self.test_results
+    }
+
+    /// A special case of [`test`] for cones, see [`ConeTestGeometry`]
+    /// 
+    /// This finds objects within `range` of `apex`, and within `half_angle` of `direction`, e.g. shadow
+    /// casters for a spotlight, or objects in an AI's field of view.  Results are conservative.  If
+    /// configured with `LayerBuilder::with_refined_tests`, the spheres enclosing stored bounds are also
+    /// tested against the cone.
+    /// 
+    /// The `system_bounds` provided to this method should, in most cases, be identical to the
+    /// `system_bounds` provided to [`extend`]
+    /// 
+    /// [`test`]: #method.test
+    /// [`extend`]: #method.extend
+    /// [`ConeTestGeometry`]: struct.ConeTestGeometry.html
+    pub fn test_cone<Point_>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        apex: Point_,
+        direction: Point_::Diff,
+        half_angle: Rad<f32>,
+        range: f32,
+        max_depth: Option<u32>) -> &Vec<ID>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + Debug,
+        Point_::Diff: InnerSpace<Scalar = f32>,
+        ConeTestGeometry<Point_>: TestGeometry,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
+    {
+        let test_geom = ConeTestGeometry::with_system_bounds(
+            system_bounds,
+            apex,
+            direction,
+            half_angle,
+            range);
+
+        self.test(
+            &test_geom,
+            max_depth);
+
+        self.refine_by_stored_bounds(&test_geom, system_bounds);
+
+        &self.test_results
+    }
+
+    /// A special case of [`test`] for sphere tests, see [`SphereTestGeometry`]
+    /// 
+    /// This finds objects within `radius` of `center`, with far fewer false positives than a box
+    /// enclosing the sphere.  If configured with `LayerBuilder::with_refined_tests`, results are
+    /// checked against stored bounds, and so exclude objects which merely share a cell with the sphere.
+    /// 
+    /// The `system_bounds` provided to this method should, in most cases, be identical to the
+    /// `system_bounds` provided to [`extend`]
+    /// 
+    /// [`test`]: #method.test
+    /// [`extend`]: #method.extend
+    /// [`SphereTestGeometry`]: struct.SphereTestGeometry.html
+    pub fn test_sphere<Point_>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        center: Point_,
+        radius: f32,
+        max_depth: Option<u32>) -> &Vec<ID>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + Debug,
+        SphereTestGeometry<Point_>: TestGeometry,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
+    {
+        let test_geom = SphereTestGeometry::with_system_bounds(
+            system_bounds,
+            center,
+            radius);
+
+        self.test(
+            &test_geom,
+            max_depth);
+
+        self.refine_by_stored_bounds(&test_geom, system_bounds);
+
+        &self.test_results
+    }
+
+    /// A special case of [`test`] for spherical shells (or annuli, in 2D), see [`ShellTestGeometry`]
+    /// 
+    /// This finds objects at least `min_radius` and at most `max_radius` from `center`, e.g. enemies
+    /// in an "aggro ring", without testing the entire inner sphere.  Objects which straddle either
+    /// radius are included.  If configured with `LayerBuilder::with_refined_tests`, results are checked
+    /// against stored bounds.
+    /// 
+    /// The `system_bounds` provided to this method should, in most cases, be identical to the
+    /// `system_bounds` provided to [`extend`]
+    /// 
+    /// [`test`]: #method.test
+    /// [`extend`]: #method.extend
+    /// [`ShellTestGeometry`]: struct.ShellTestGeometry.html
+    pub fn test_shell<Point_>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        center: Point_,
+        min_radius: f32,
+        max_radius: f32,
+        max_depth: Option<u32>) -> &Vec<ID>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + Debug,
+        ShellTestGeometry<Point_>: TestGeometry,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
+    {
+        let test_geom = ShellTestGeometry::with_system_bounds(
+            system_bounds,
+            center,
+            min_radius,
+            max_radius);
+
+        self.test(
+            &test_geom,
+            max_depth);
+
+        self.refine_by_stored_bounds(&test_geom, system_bounds);
+
+        &self.test_results
+    }
+
+    /// A special case of [`test`] for circles in 2D layers, see [`test_sphere`]
+    /// 
+    /// Cells are rejected by their distance from `center`, so objects in cells overlapping only the
+    /// corners of the enclosing box are excluded.
+    /// 
+    /// [`test`]: #method.test
+    /// [`test_sphere`]: #method.test_sphere
+    pub fn test_circle(
+        &mut self,
+        system_bounds: Bounds<Point2<f32>>,
+        center: Point2<f32>,
+        radius: f32,
+        max_depth: Option<u32>) -> &Vec<ID>
+    where
+        Bounds<Point2<f32>>: SystemBounds<Point2<f32>, Index::Point>
+    {
+        self.test_sphere(system_bounds, center, radius, max_depth)
+    }
+
+    /// A special case of [`test`] for arbitrary implicit shapes given by a signed distance function,
+    /// see [`SdfTestGeometry`]
+    /// 
+    /// `sdf` must not overestimate the distance to the shape, or objects may be missed.  If configured
+    /// with `LayerBuilder::with_refined_tests`, results are checked against stored bounds, in the same
+    /// (conservative) way as cells.
+    /// 
+    /// The `system_bounds` provided to this method should, in most cases, be identical to the
+    /// `system_bounds` provided to [`extend`]
+    /// 
+    /// [`test`]: #method.test
+    /// [`extend`]: #method.extend
+    /// [`SdfTestGeometry`]: struct.SdfTestGeometry.html
+    pub fn test_sdf<Point_, F>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        sdf: F,
+        max_depth: Option<u32>) -> &Vec<ID>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+        F: Fn(Point_) -> f32,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
+    {
+        let test_geom = SdfTestGeometry::with_system_bounds(system_bounds, &sdf);
+
+        self.test(
+            &test_geom,
+            max_depth);
+
+        self.refine_by_stored_bounds(&test_geom, system_bounds);
+
+        &self.test_results
+    }
+
+    /// A special case of [`test`] for ellipsoid tests, see [`EllipsoidTestGeometry`]
+    /// 
+    /// This finds objects within the axis-aligned ellipsoid at `center` with a radius along each axis
+    /// given by `radii`.  If configured with `LayerBuilder::with_refined_tests`, results are checked
+    /// against stored bounds.
+    /// 
+    /// The `system_bounds` provided to this method should, in most cases, be identical to the
+    /// `system_bounds` provided to [`extend`]
+    /// 
+    /// [`test`]: #method.test
+    /// [`extend`]: #method.extend
+    /// [`EllipsoidTestGeometry`]: struct.EllipsoidTestGeometry.html
+    pub fn test_ellipsoid<Point_>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        center: Point_,
+        radii: Point_::Diff,
+        max_depth: Option<u32>) -> &Vec<ID>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + Debug,
+        Point_::Diff: Array<Element = f32>,
+        EllipsoidTestGeometry<Point_>: TestGeometry,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
+    {
+        let test_geom = EllipsoidTestGeometry::with_system_bounds(
+            system_bounds,
+            center,
+            radii);
+
+        self.test(
+            &test_geom,
+            max_depth);
+
+        self.refine_by_stored_bounds(&test_geom, system_bounds);
+
+        &self.test_results
+    }
+
+    /// A special case of [`test`] for boxes moving by `displacement`, see [`SweptBoxTestGeometry`]
+    /// 
+    /// This finds objects which `start_bounds` would touch at any point during the move, e.g. for
+    /// fast-moving objects, without visiting every cell of a box enclosing the whole move.  If
+    /// configured with `LayerBuilder::with_refined_tests`, results are checked against stored bounds.
+    /// One-way objects are excluded when moving along their normals, see `set_one_way`.
+    /// 
+    /// The `system_bounds` provided to this method should, in most cases, be identical to the
+    /// `system_bounds` provided to [`extend`]
+    /// 
+    /// [`test`]: #method.test
+    /// [`extend`]: #method.extend
+    /// [`SweptBoxTestGeometry`]: struct.SweptBoxTestGeometry.html
+    pub fn test_swept_box<Point_>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        start_bounds: Bounds<Point_>,
+        displacement: Point_::Diff,
+        max_depth: Option<u32>) -> &Vec<ID>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + Debug,
+        Point_::Diff: Array<Element = f32>,
+        SweptBoxTestGeometry<Point_>: TestGeometry,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
+    {
+        let test_geom = SweptBoxTestGeometry::with_system_bounds(
+            system_bounds,
+            start_bounds,
+            displacement);
+
+        self.test(
+            &test_geom,
+            max_depth);
+
+        if !self.one_way.is_empty() {
+            let one_way = &self.one_way;
+            self.test_results.retain(|&id| !passes_one_way(one_way, id, &displacement, Point_::len()));
+        }
+
+        self.refine_by_stored_bounds(&test_geom, system_bounds);
+
+        &self.test_results
+    }
+
+    /// [`test_swept_box`]: #method.test_swept_box
+    /// Find objects within `radius` of the segment from `a` to `b`, e.g. for laser beams or rope links
+    /// 
+    /// This is equivalent to [`test_swept_box`] for a box of half-size `radius` centered on `a`, moving
+    /// to `b`; the volume tested is therefore conservative, with square rather than rounded edges.  A
+    /// `radius` of zero tests the segment itself.
+    pub fn test_segment<Point_>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        a: Point_,
+        b: Point_,
+        radius: f32,
+        max_depth: Option<u32>) -> &Vec<ID>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + Debug,
+        Point_::Diff: Array<Element = f32>,
+        SweptBoxTestGeometry<Point_>: TestGeometry,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
+    {
+        let half_size = Point_::Diff::from_value(radius);
+        let start_bounds = Bounds::new(a - half_size, a + half_size);
+        self.test_swept_box(system_bounds, start_bounds, b - a, max_depth)
+    }
+
+    /// [`test_box`]: #method.test_box
+    /// Equivalent to [`test_box`], for a `Layer` whose contents are placed by `transform`
+    /// 
+    /// `transform` maps this `Layer`'s (model) space into the space of `test_bounds` (e.g. world
+    /// space), typically an isometry such as `cgmath::Decomposed`.  The query, not the `Layer`, is
+    /// transformed: `test_bounds` is mapped into model space and expanded to stay axis-aligned, so a
+    /// `Layer` baked once (e.g. a reusable building interior) may be queried for each of its instances.
+    /// `system_bounds` are in model space, as provided to [`extend`].  Panics if `transform` isn't
+    /// invertible.
+    /// 
+    /// [`extend`]: #method.extend
+    pub fn test_box_transformed<'a, Point_, T>(
+        &'a mut self,
+        system_bounds: Bounds<Point_>,
+        test_bounds: Bounds<Point_>,
+        transform: &T,
+        max_depth: Option<u32>) -> &'a Vec<ID>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + Debug,
+        Point_::Diff: ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        BoxTestGeometry<Point_>: TestGeometry,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>,
+        T: Transform<Point_>
+    {
+        let inverse = transform.inverse_transform().expect("transform must be invertible");
+        self.test_box(system_bounds, test_bounds.transformed(&inverse), max_depth)
+    }
+
+    /// A special case of [`test`] for ray-testing, see [`RayTestGeometry`]
+    /// 
+    /// If configured with `LayerBuilder::with_refined_tests`, results are checked against stored
+    /// bounds, and so exclude objects which merely share a cell with the ray.
+    /// 
+    /// The `system_bounds` provided to this method should, in most cases, be identical to the
+    /// `system_bounds` provided to [`extend`]
+    /// 
+    /// _note: this method may do an implicit, non-parallel commit; you may call [`par_commit`] prior
+    /// to calling this method to perform a parallel commit instead_
+    /// 
+    /// [`test`]: #method.test
+    /// [`extend`]: #method.extend
+    /// [`par_commit`]: #method.par_commit
+    /// [`RayTestGeometry`]: struct.RayTestGeometry.html
+    pub fn test_ray<Point_>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        origin   : Point_,
+        direction: Point_::Diff,
+        range_min: f32,
+        range_max: f32,
+        max_depth: Option<u32>) -> &Vec<ID>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+        Point_::Diff: ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        RayTestGeometry<Point_>: TestGeometry,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
+    {
+        let test_geom = RayTestGeometry::with_system_bounds(
+            system_bounds,
+            origin,
+            direction,
+            range_min,
+            range_max);
+
+        self.test(
+            &test_geom,
+            max_depth);
+
+        if let (true, Some(bounds)) = (self.refined_tests, &self.bounds) {
+            self.test_results.retain(|&id|
+                Self::ray_hits_stored_bounds(bounds, system_bounds, origin, direction, range_min, range_max, id));
+        }
+
+        &self.test_results
+    }
+
+    /// [`test_ray`]: #method.test_ray
+    /// [`RayError`]: enum.RayError.html
+    /// Equivalent to [`test_ray`], returning a [`RayError`] rather than testing a degenerate ray (a
+    /// zero-length or non-finite direction, a non-finite origin, or a NaN range)
+    /// 
+    /// This is intended for rays built from untrusted input, e.g. gameplay scripts, see
+    /// `RayTestGeometry::try_with_system_bounds`.
+    pub fn try_test_ray<Point_>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        origin   : Point_,
+        direction: Point_::Diff,
+        range_min: f32,
+        range_max: f32,
+        max_depth: Option<u32>) -> Result<&Vec<ID>, RayError>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+        Point_::Diff: ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        RayTestGeometry<Point_>: TestGeometry,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
+    {
+        RayTestGeometry::check(&origin, &direction, range_min, range_max)?;
+        Ok(self.test_ray(system_bounds, origin, direction, range_min, range_max, max_depth))
+    }
+
+    /// [`test_ray`]: #method.test_ray
+    /// Equivalent to [`test_ray`], but also returns the depth of the cell in which each object was found
+    /// 
+    /// Objects indexed in deeper cells are more tightly bounded, so this may be used to order
+    /// narrow-phase work or to accept hits depending on level of detail.  Objects with multiple entries
+    /// report the deepest of them, and unbounded objects report a depth of zero.  Results are sorted by
+    /// ID.
+    pub fn test_ray_with_depth<Point_>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        origin   : Point_,
+        direction: Point_::Diff,
+        range_min: f32,
+        range_max: f32,
+        max_depth: Option<u32>) -> Vec<(ID, u32)>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+        Point_::Diff: ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        RayTestGeometry<Point_>: TestGeometry,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
+    {
+        let test_geom = RayTestGeometry::with_system_bounds(
+            system_bounds,
+            origin,
+            direction,
+            range_min,
+            range_max);
+
+        self.commit();
+
+        let mut results: Vec<(ID, u32)> = Vec::new();
+        Self::test_impl(
+            self.sorted_tree(),
+            0,
+            Index::default(),
+            &test_geom,
+            f32::INFINITY,
+            max_depth,
+            None,
+            &mut |_, nearest, index, id| {
+                results.push((id, index.depth()));
+                nearest
+            },
+            &mut ());
+        results.extend(self.unbounded.iter().map(|&id| (id, 0)));
+        results.retain(|&(id, _)| self.is_queryable(id));
+
+        // keep the deepest entry for each object
+        results.sort_unstable_by_key(|&(id, depth)| (id, Reverse(depth)));
+        results.dedup_by_key(|&mut (id, _)| id);
+
+        if let (true, Some(bounds)) = (self.refined_tests, &self.bounds) {
+            results.retain(|&(id, _)|
+                Self::ray_hits_stored_bounds(bounds, system_bounds, origin, direction, range_min, range_max, id));
+        }
+
+        results
+    }
+
+    /// Whether a ray intersects an object's stored bounds, or `true` if none are stored
+    fn ray_hits_stored_bounds<Point_>(
+        bounds: &BoundsTable<Index::Point, ID>,
+        system_bounds: Bounds<Point_>,
+        origin   : Point_,
+        direction: Point_::Diff,
+        range_min: f32,
+        range_max: f32,
+        id: ID) -> bool
+    where
+        Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+        Point_::Diff: ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
+    {
+        let bounds = match bounds.get(id) {
+            Some(bounds) => Self::global_bounds(&system_bounds, bounds),
+            None => return true
+        };
+        matches!(bounds.intersect_ray(origin, direction),
+            Some((min, max)) if min <= range_max && max >= range_min)
+    }
+
+    /// [`test`]: #method.test
+    /// [`QueryResults`]: type.QueryResults.html
+    /// Equivalent to [`test`], returning owned [`QueryResults`] which don't borrow the `Layer`
+    /// 
+    /// This allows further queries to be issued while holding the results of an earlier one
+    pub fn test_owned<TestGeom>(
+        &mut self,
+        test_geom: &TestGeom,
+        max_depth: Option<u32>) -> QueryResults<ID>
+    where
+        TestGeom: TestGeometry
+    {
+        SmallVec::from_slice(self.test(test_geom, max_depth))
+    }
+
+    /// [`test_box`]: #method.test_box
+    /// [`QueryResults`]: type.QueryResults.html
+    /// Equivalent to [`test_box`], returning owned [`QueryResults`] which don't borrow the `Layer`
+    pub fn test_box_owned<Point_>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        test_bounds: Bounds<Point_>,
+        max_depth: Option<u32>) -> QueryResults<ID>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + Debug,
+        Point_::Diff: ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        BoxTestGeometry<Point_>: TestGeometry,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
+    {
+        SmallVec::from_slice(self.test_box(system_bounds, test_bounds, max_depth))
+    }
+
+    /// [`test_ray`]: #method.test_ray
+    /// [`QueryResults`]: type.QueryResults.html
+    /// Equivalent to [`test_ray`], returning owned [`QueryResults`] which don't borrow the `Layer`
+    pub fn test_ray_owned<Point_>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        origin   : Point_,
+        direction: Point_::Diff,
+        range_min: f32,
+        range_max: f32,
+        max_depth: Option<u32>) -> QueryResults<ID>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+        Point_::Diff: ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        RayTestGeometry<Point_>: TestGeometry,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
+    {
+        SmallVec::from_slice(self.test_ray(system_bounds, origin, direction, range_min, range_max, max_depth))
+    }
+
+    /// [`test_box`]: #method.test_box
+    /// A uniformly random sample of up to `count` objects which would be returned by [`test_box`], in
+    /// no particular order
+    /// 
+    /// Objects are sampled as they're found (reservoir sampling), rather than collected first, which is
+    /// useful for e.g. AI target selection in dense scenes.  `rng(n)` must return a uniformly
+    /// distributed integer in `0..n`, such as `|n| prng.gen_range(0, n)` using the `rand` crate.
+    pub fn sample_in_box<Point_, Rng>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        region: Bounds<Point_>,
+        count: usize,
+        mut rng: Rng) -> Vec<ID>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + Debug,
+        Point_::Diff: ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        BoxTestGeometry<Point_>: TestGeometry,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>,
+        Rng: FnMut(usize) -> usize
+    {
+        self.commit();
+
+        let test_geom = BoxTestGeometry::with_system_bounds(system_bounds, region);
+        let local_region: Bounds<Index::Point> = system_bounds.to_local(region);
+        let stored_bounds = if self.refined_tests { self.bounds.as_ref() } else { None };
+        let mut processed = std::mem::take(&mut self.processed);
+        processed.clear();
+
+        let mut sample = Vec::with_capacity(count);
+        let mut seen = 0usize;
+        let mut consider = |id: ID| {
+            if !self.is_queryable(id) || matches!(stored_bounds.and_then(|bounds| bounds.get(id)),
+                Some(bounds) if !bounds.overlaps(local_region))
+            {
+                return;
+            }
+            seen += 1;
+            if sample.len() < count {
+                sample.push(id);
+            } else {
+                let i = rng(seen);
+                if i < count {
+                    sample[i] = id;
+                }
+            }
+        };
+        Self::test_impl(
+            self.sorted_tree(),
+            0,
+            Index::default(),
+            &test_geom,
+            f32::INFINITY,
+            None,
+            None,
+            &mut |_, nearest, _, id| {
+                if processed.insert(id) {
+                    consider(id);
+                }
+                nearest
+            },
+            &mut ());
+        for &id in &self.unbounded {
+            if !processed.contains(&id) {
+                consider(id);
+            }
+        }
+
+        self.processed = processed;
+        sample
+    }
+
+    /// [`test_box`]: #method.test_box
+    /// [`LayerBuilder::with_stored_bounds`]: struct.LayerBuilder.html#method.with_stored_bounds
+    /// Equivalent to [`test_box`], also returning the fraction of each object's bounds which lies
+    /// inside `test_bounds`
+    /// 
+    /// This is intended for e.g. trigger volumes which should only fire once an object is mostly
+    /// inside.  Fractions are computed from stored bounds, which are required (see
+    /// [`LayerBuilder::with_stored_bounds`]); objects which don't overlap `test_bounds` are excluded,
+    /// as are unbounded objects.  Along any axis where an object's bounds are flat, that axis counts as
+    /// entirely inside.
+    pub fn test_box_detailed<Point_>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        test_bounds: Bounds<Point_>,
+        max_depth: Option<u32>) -> Vec<(ID, f32)>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + Debug,
+        Point_::Diff: ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        BoxTestGeometry<Point_>: TestGeometry,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
+    {
+        assert!(self.bounds.is_some(), "test_box_detailed requires stored bounds");
+
+        self.test_box(system_bounds, test_bounds, max_depth);
+
+        let bounds = self.bounds.as_ref().unwrap();
+        self.test_results.iter()
+            .filter_map(|&id| {
+                let local = bounds.get(id)?;
+                let object_bounds = system_bounds.to_global(local);
+                let overlap = object_bounds.intersection(test_bounds)?;
+                let fraction = (0..Point_::len())
+                    .map(|i| {
+                        let size = object_bounds.max[i] - object_bounds.min[i];
+                        if size > 0f32 {
+                            (overlap.max[i] - overlap.min[i]) / size
+                        } else {
+                            1f32
+                        }
+                    })
+                    .product();
+                Some((id, fraction))
+            })
+            .collect()
+    }
+
+    /// [`test_box`]: #method.test_box
+    /// [`LayerBuilder::with_stored_bounds`]: struct.LayerBuilder.html#method.with_stored_bounds
+    /// Equivalent to [`test_box`], also returning the distance of each object's bounds from `center`,
+    /// nearest first
+    /// 
+    /// This is intended for e.g. iterating nearby interactables nearest-first.  Distances are computed
+    /// from stored bounds, which are required (see [`LayerBuilder::with_stored_bounds`]), and are zero
+    /// for objects containing `center`.  Objects which don't overlap `test_bounds` are excluded, as are
+    /// unbounded objects.  Objects at equal distances are ordered by ID.
+    /// 
+    /// Cells are visited nearest-first, so results are produced in order rather than sorted afterwards.
+    pub fn test_box_sorted_by_distance<Point_>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        test_bounds: Bounds<Point_>,
+        center: Point_,
+        max_depth: Option<u32>) -> Vec<(ID, f32)>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + Debug,
+        Point_::Diff: ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        BoxTestGeometry<Point_>: TestGeometry,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>,
+        Bounds<Index::Point>: From<Index>
+    {
+        assert!(self.bounds.is_some(), "test_box_sorted_by_distance requires stored bounds");
+
+        self.commit();
+
+        let bounds = self.bounds.as_ref().unwrap();
+        let cell_distance = |cell: Index| Self::global_bounds(&system_bounds, Bounds::from(cell)).distance_to(center);
+        // distances are non-negative, so their bit patterns are ordered like the distances themselves;
+        // cells have no ID, so they're expanded before any entries at an equal distance
+        let key = |distance: f32, id: Option<ID>, cell: usize| Reverse((distance.to_bits(), id, cell));
+
+        let mut cells = vec![(self.sorted_tree(), Index::default(), BoxTestGeometry::with_system_bounds(
+            system_bounds,
+            test_bounds))];
+        let mut queue = BinaryHeap::new();
+        // the root cell spans the system bounds, so its distance is irrelevant
+        queue.push(key(0f32, None, 0));
+        let mut found = FxHashSet::default();
+        let mut results: Vec<(ID, f32)> = Vec::new();
+        while let Some(Reverse((distance, id, cell))) = queue.pop() {
+            if let Some(id) = id {
+                // an object is never nearer than the cells containing it, so its first entry is final
+                if found.insert(id) {
+                    results.push((id, f32::from_bits(distance)));
+                }
+                continue;
+            }
+
+            let (tree, cell, test_geom) = cells[cell].clone();
+            if tree.is_empty() || !test_geom.should_test(f32::INFINITY) {
+                continue;
+            }
+
+            let sub_cells = match max_depth {
+                Some(max_depth) if cell.depth() >= max_depth => None,
+                _ => cell.subdivide()
+            };
+            let entries = match sub_cells {
+                Some(sub_cells) => {
+                    let (head, sub_trees) = Self::split_tree(tree, 0, sub_cells.as_ref());
+                    let sub_tests = test_geom.subdivide();
+                    for (i, &(sub_tree, _)) in sub_trees.iter().enumerate() {
+                        if !sub_tree.is_empty() {
+                            let sub_cell = sub_cells.as_ref()[i];
+                            queue.push(key(cell_distance(sub_cell), None, cells.len()));
+                            cells.push((sub_tree, sub_cell, sub_tests.as_ref()[i].clone()));
+                        }
+                    }
+                    head
+                },
+                None => tree
+            };
+
+            for &(_, id) in entries {
+                let object_bounds = match bounds.get(id) {
+                    Some(bounds) => Self::global_bounds(&system_bounds, bounds),
+                    None => continue
+                };
+                if object_bounds.overlaps(test_bounds) && self.is_queryable(id) {
+                    queue.push(key(object_bounds.distance_to(center), Some(id), 0));
+                }
+            }
+        }
+        results
+    }
+
+    /// A special case of [`test`] for view frusta and other convex volumes, see [`FrustumTestGeometry`]
+    /// 
+    /// `planes` are `(normal, offset)` pairs, with points `p` inside where `normal · p + offset >= 0`.
+    /// Planes for a perspective camera may be built with `FrustumTestGeometry::perspective_planes`.
+    /// 
+    /// _note: this method may do an implicit, non-parallel commit; you may call [`par_commit`] prior
+    /// to calling this method to perform a parallel commit instead_
+    /// 
+    /// [`test`]: #method.test
+    /// [`par_commit`]: #method.par_commit
+    /// [`FrustumTestGeometry`]: struct.FrustumTestGeometry.html
+    pub fn test_frustum<'a, Point_>(
+        &'a mut self,
+        system_bounds: Bounds<Point_>,
+        planes: &[(Point_::Diff, f32)],
+        max_depth: Option<u32>) -> &'a Vec<ID>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+        Point_::Diff: Array<Element = f32> + Debug
+    {
+        let test_geom = FrustumTestGeometry::with_system_bounds(system_bounds, planes);
+        self.test(&test_geom, max_depth)
+    }
+
+    /// A special case of [`test`] for half-spaces, see [`PlaneTestGeometry`]
+    /// 
+    /// This finds objects on the side of a plane where `normal · p + offset >= 0`, e.g. everything
+    /// below a water surface, or beyond a level streaming boundary.
+    /// 
+    /// _note: this method may do an implicit, non-parallel commit; you may call [`par_commit`] prior
+    /// to calling this method to perform a parallel commit instead_
+    /// 
+    /// [`test`]: #method.test
+    /// [`par_commit`]: #method.par_commit
+    /// [`PlaneTestGeometry`]: struct.PlaneTestGeometry.html
+    pub fn test_half_space<Point_>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        normal: Point_::Diff,
+        offset: f32,
+        max_depth: Option<u32>) -> &Vec<ID>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+        Point_::Diff: Array<Element = f32> + Debug
+    {
+        let test_geom = PlaneTestGeometry::with_system_bounds(system_bounds, normal, offset);
+        self.test(&test_geom, max_depth)
+    }
+
+    /// A special case of [`test`] for convex polygons in 2D layers, see [`PolygonTestGeometry`]
+    /// 
+    /// `vertices` are given in counter-clockwise order, e.g. the corners of a rotated selection marquee
+    /// or the ground footprint of an isometric camera.
+    /// 
+    /// _note: this method may do an implicit, non-parallel commit; you may call [`par_commit`] prior
+    /// to calling this method to perform a parallel commit instead_
+    /// 
+    /// [`test`]: #method.test
+    /// [`par_commit`]: #method.par_commit
+    /// [`PolygonTestGeometry`]: struct.PolygonTestGeometry.html
+    pub fn test_polygon<'a>(
+        &'a mut self,
+        system_bounds: Bounds<Point2<f32>>,
+        vertices: &[Point2<f32>],
+        max_depth: Option<u32>) -> &'a Vec<ID>
+    {
+        let test_geom = PolygonTestGeometry::with_system_bounds(system_bounds, vertices);
+        self.test(&test_geom, max_depth)
+    }
+
+    /// [`test_frustum`]: #method.test_frustum
+    /// Equivalent to [`test_frustum`], also classifying each object as inside or intersecting the
+    /// frustum
+    /// 
+    /// Objects are `Containment::Inside` if every cell they're indexed in is entirely inside the
+    /// frustum, so renderers may skip finer culling for them.  This is conservative: objects near the
+    /// boundary, or indexed in coarse cells, may be `Containment::Intersecting` despite being inside.
+    /// Unbounded objects are always `Containment::Intersecting`.  Results are sorted by ID.
+    pub fn test_frustum_classified<Point_>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        planes: &[(Point_::Diff, f32)],
+        max_depth: Option<u32>) -> Vec<(ID, Containment)>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+        Point_::Diff: Array<Element = f32> + Debug
+    {
+        let test_geom = FrustumTestGeometry::with_system_bounds(system_bounds, planes);
+
+        self.commit();
+
+        let mut results: Vec<(ID, Containment)> = Vec::new();
+        Self::test_impl(
+            self.sorted_tree(),
+            0,
+            Index::default(),
+            &test_geom,
+            f32::INFINITY,
+            max_depth,
+            None,
+            &mut |test_geom, nearest, _, id| {
+                let containment = if test_geom.is_inside() {
+                        Containment::Inside
+                    } else {
+                        Containment::Intersecting
+                    };
+                results.push((id, containment));
+                nearest
+            },
+            &mut ());
+        results.extend(self.unbounded.iter().map(|&id| (id, Containment::Intersecting)));
+        results.retain(|&(id, _)| self.is_queryable(id));
+
+        // objects are only inside if all of their entries are
+        results.sort_unstable_by_key(|&(id, containment)| (id, containment == Containment::Inside));
+        results.dedup_by_key(|&mut (id, _)| id);
+        results
+    }
+
+    /// Run a picking or hit-test operation
+    /// 
+    /// This is implemented similarly to [`test`], but differs in that it returns only the nearest
+    /// result and may stop searching as soon as the nearest result is found.  If multiple objects are
+    /// at exactly the nearest distance, the one with the lowest ID is returned.
+    /// 
+    /// _note: this method may do an implicit, non-parallel commit; you may call [`par_commit`] prior
+    /// to calling this method to perform a parallel commit instead_
+    /// 
+    /// [`test`]: #method.test
+    /// [`par_commit`]: #method.par_commit
+    pub fn pick<TestGeom, GetDist>(
+        &mut self,
+        test_geom: &TestGeom,
+        max_dist: f32,
+        max_depth: Option<u32>,
+        get_dist: GetDist) -> Option<(f32, ID)>
+    where
+        TestGeom: TestGeometry,
+        GetDist: FnMut(&TestGeom, f32, ID) -> f32
+    {
+        self.pick_prioritized(test_geom, max_dist, max_depth, get_dist, |_| 0)
+    }
+
+    /// [`pick`]: #method.pick
+    /// Equivalent to [`pick`], but when multiple objects are at the same (nearest) distance, the one
+    /// with the highest `priority` is returned
+    /// 
+    /// This allows, for example, interactable objects to win over debris at equal distance.  Objects
+    /// with equal distance and priority are resolved by the lowest ID.
+    pub fn pick_prioritized<TestGeom, GetDist, Priority>(
+        &mut self,
+        test_geom: &TestGeom,
+        max_dist: f32,
+        max_depth: Option<u32>,
+        get_dist: GetDist,
+        priority: Priority) -> Option<(f32, ID)>
+    where
+        TestGeom: TestGeometry,
+        GetDist: FnMut(&TestGeom, f32, ID) -> f32,
+        Priority: FnMut(ID) -> u32
+    {
+        self.pick_with_ranges(test_geom, max_dist, max_depth, None, None, payload_dist(get_dist), priority)
+            .map(|(dist, id, _)| (dist, id))
+    }
+
+    /// [`pick`]: #method.pick
+    /// Equivalent to [`pick`], but `get_dist` also returns a payload for each hit, which is returned
+    /// along with the nearest object
+    /// 
+    /// This avoids recomputing narrow-phase data (e.g. hit normals, triangle indices, or materials) for
+    /// the picked object after this method returns.  `get_dist` returns `None` for a miss.
+    pub fn pick_with_payload<T, TestGeom, GetDist>(
+        &mut self,
+        test_geom: &TestGeom,
+        max_dist: f32,
+        max_depth: Option<u32>,
+        get_dist: GetDist) -> Option<(f32, ID, T)>
+    where
+        TestGeom: TestGeometry,
+        GetDist: FnMut(&TestGeom, f32, ID) -> Option<(f32, T)>
+    {
+        self.pick_with_ranges(test_geom, max_dist, max_depth, None, None, get_dist, |_| 0)
+    }
+
+    /// [`pick`]: #method.pick
+    /// Equivalent to [`pick`], but returns every object within `epsilon` of the nearest distance, in
+    /// sorted order
+    /// 
+    /// This is intended for cases where returning an arbitrary one of several coincident objects is
+    /// wrong, e.g. selecting overlapping editor gizmo handles.
+    pub fn pick_all_nearest<'a, TestGeom, GetDist>(
+        &'a mut self,
+        test_geom: &TestGeom,
+        max_dist: f32,
+        max_depth: Option<u32>,
+        epsilon: f32,
+        mut get_dist: GetDist) -> Option<(f32, &'a Vec<ID>)>
+    where
+        TestGeom: TestGeometry,
+        GetDist: FnMut(&TestGeom, f32, ID) -> f32
+    {
+        self.commit();
+
+        let mut processed = std::mem::take(&mut self.processed);
+        let mut results = std::mem::take(&mut self.test_results);
+        let mut candidates: Vec<(f32, ID)> = Vec::new();
+        processed.clear();
+        results.clear();
+
+        // the search is bounded by `nearest + epsilon`, so that near-ties are not pruned, but never
+        // extends past `max_dist`
+        let mut nearest = max_dist;
+        let mut consider = |bound: f32, id: ID, candidates: &mut Vec<(f32, ID)>| {
+            let dist = get_dist(test_geom, bound, id);
+            if dist.is_finite() && dist <= (nearest + epsilon).min(max_dist) {
+                nearest = nearest.min(dist);
+                candidates.push((dist, id));
+                dist + epsilon
+            } else {
+                f32::INFINITY
+            }
+        };
+        let bound = Self::test_impl(
+            self.sorted_tree(),
+            0,
+            Index::default(),
+            test_geom,
+            max_dist,
+            max_depth,
+            None,
+            &mut |_, bound, _, id| {
+                if processed.insert(id) && self.is_queryable(id) {
+                    consider(bound, id, &mut candidates)
+                } else {
+                    f32::INFINITY
+                }
+            },
+            &mut ());
+        for &id in &self.unbounded {
+            if !processed.contains(&id) && self.is_queryable(id) {
+                consider(bound, id, &mut candidates);
+            }
+        }
+
+        let nearest = candidates.iter().fold(f32::INFINITY, |nearest, &(dist, _)| nearest.min(dist));
+        results.extend(candidates.iter()
+            .filter(|&&(dist, _)| dist <= nearest + epsilon)
+            .map(|&(_, id)| id));
+        results.sort();
+        results.dedup();
+
+        self.processed = processed;
+        self.test_results = results;
+
+        if self.test_results.is_empty() {
+            None
+        } else {
+            Some((nearest, &self.test_results))
+        }
+    }
+
+    /// `entries`, if provided, are sorted entries queried in place of this `Layer`'s own
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn pick_with_ranges<T, TestGeom, GetDist, Priority>(
+        &mut self,
+        test_geom: &TestGeom,
+        max_dist: f32,
+        max_depth: Option<u32>,
+        ranges: Option<&NodeRanges<Index>>,
+        entries: Option<&[(Index, ID)]>,
+        get_dist: GetDist,
+        priority: Priority) -> Option<(f32, ID, T)>
+    where
+        TestGeom: TestGeometry,
+        GetDist: FnMut(&TestGeom, f32, ID) -> Option<(f32, T)>,
+        Priority: FnMut(ID) -> u32
+    {
+        self.commit();
+
+        let mut processed = std::mem::take(&mut self.processed);
+        let tree = entries.unwrap_or_else(|| self.sorted_tree());
+        let result = self.pick_sorted(tree, test_geom, max_dist, max_depth, ranges, &mut processed, get_dist, priority);
+        self.processed = processed;
+
+        result
+    }
+
+    /// [`pick`]: #method.pick
+    /// [`commit`]: #method.commit
+    /// Equivalent to [`pick`], but queries only the committed state and never commits implicitly
+    /// 
+    /// Panics if the `Layer` has never been committed since being deserialized, see [`commit`]
+    pub fn pick_committed<TestGeom, GetDist>(
+        &self,
+        test_geom: &TestGeom,
+        max_dist: f32,
+        max_depth: Option<u32>,
+        get_dist: GetDist) -> Option<(f32, ID)>
+    where
+        TestGeom: TestGeometry,
+        GetDist: FnMut(&TestGeom, f32, ID) -> f32
+    {
+        let mut processed = FxHashSet::default();
+        self.pick_sorted(self.sorted_tree(), test_geom, max_dist, max_depth, None, &mut processed, payload_dist(get_dist), |_| 0)
+            .map(|(dist, id, _)| (dist, id))
+    }
+
+    /// Implementation of `pick` for sorted entries `tree`, with a caller-provided buffer
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn pick_sorted<T, TestGeom, GetDist, Priority>(
+        &self,
+        tree: &[(Index, ID)],
+        test_geom: &TestGeom,
+        max_dist: f32,
+        max_depth: Option<u32>,
+        ranges: Option<&NodeRanges<Index>>,
+        processed: &mut FxHashSet<ID>,
+        mut get_dist: GetDist,
+        mut priority: Priority) -> Option<(f32, ID, T)>
+    where
+        TestGeom: TestGeometry,
+        GetDist: FnMut(&TestGeom, f32, ID) -> Option<(f32, T)>,
+        Priority: FnMut(ID) -> u32
+    {
+        processed.clear();
+
+        // the nearest object so far, its priority, and its payload
+        let mut result: Option<(ID, u32, T)> = None;
+        let mut consider = |nearest: f32, dist: f32, id: ID, payload: T| {
+            if dist < nearest {
+                result = Some((id, priority(id), payload));
+            } else if dist == nearest {
+                // ties are broken by priority, then by the lowest ID, regardless of traversal order
+                if let Some((result_id, result_priority, _)) = result {
+                    let priority = priority(id);
+                    if priority > result_priority || (priority == result_priority && id < result_id) {
+                        result = Some((id, priority, payload));
+                    }
+                }
+            }
+        };
+
+        let mut dist = Self::test_impl(
+            tree,
+            0,
+            Index::default(),
+            test_geom,
+            max_dist,
+            max_depth,
+            ranges,
+            &mut |test_geom, nearest, _, id| {
+                if processed.insert(id) && self.is_queryable(id) {
+                    match get_dist(test_geom, nearest, id) {
+                        Some((dist, payload)) if dist.is_finite() => {
+                            consider(nearest, dist, id, payload);
+                            dist
+                        },
+                        _ => f32::INFINITY
+                    }
+                } else {
+                    f32::INFINITY
+                }
+            },
+            &mut ());
+
+        for &id in &self.unbounded {
+            if processed.contains(&id) || !self.is_queryable(id) {
+                continue;
+            }
+            if let Some((dist_, payload)) = get_dist(test_geom, dist, id) {
+                if dist_.is_finite() {
+                    consider(dist, dist_, id, payload);
+                    dist = dist.min(dist_);
+                }
+            }
+        }
+
+        result.map(|(id, _, payload)| (dist, id, payload))
+    }
+
+    /// A special case of [`pick`] for ray-testing, see [`RayTestGeometry`]
+    /// 
+    /// One-way objects are skipped when the ray moves along their normals, see `set_one_way`.
+    /// 
+    /// The `system_bounds` provided to this method should, in most cases, be identical to the
+    /// `system_bounds` provided to [`extend`]
+    /// 
+    /// _note: this method may do an implicit, non-parallel commit; you may call [`par_commit`] prior
+    /// to calling this method to perform a parallel commit instead_
+    /// 
+    /// [`pick`]: #method.pick
+    /// [`extend`]: #method.extend
+    /// [`par_commit`]: #method.par_commit
+    /// [`RayTestGeometry`]: struct.RayTestGeometry.html
+    pub fn pick_ray<Point_, GetDist>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        origin   : Point_,
+        direction: Point_::Diff,
+        max_dist: f32,
+        max_depth: Option<u32>,
+        get_dist: GetDist) -> Option<(f32, ID, Point_)>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+        Point_::Diff: VectorSpace<Scalar = f32> + ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        RayTestGeometry<Point_>: TestGeometry,
+        GetDist: FnMut(&Point_, &Point_::Diff, f32, ID) -> f32
+    {
+        self.pick_ray_prioritized(system_bounds, origin, direction, max_dist, max_depth, get_dist, |_| 0)
+    }
+
+    /// A special case of [`pick`] for spheres swept along a ray, see [`SphereCastTestGeometry`]
+    /// 
+    /// This finds the nearest object hit by a sphere of `radius` moving from `origin` along
+    /// `direction`, e.g. a character-sized projectile, without inflating the bounds of every object.
+    /// `get_toi` returns the distance along the ray at which the sphere first touches an object (or
+    /// infinity if it doesn't), and the center of the sphere at that distance is returned with the
+    /// hit.  As with [`pick_ray`], one-way objects are skipped when the sphere moves along their
+    /// normals.
+    /// 
+    /// The `system_bounds` provided to this method should, in most cases, be identical to the
+    /// `system_bounds` provided to [`extend`]
+    /// 
+    /// [`pick`]: #method.pick
+    /// [`pick_ray`]: #method.pick_ray
+    /// [`extend`]: #method.extend
+    /// [`SphereCastTestGeometry`]: struct.SphereCastTestGeometry.html
+    #[allow(clippy::too_many_arguments)]
+    pub fn pick_sphere_cast<Point_, GetToi>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        origin   : Point_,
+        direction: Point_::Diff,
+        radius: f32,
+        max_dist: f32,
+        max_depth: Option<u32>,
+        mut get_toi: GetToi) -> Option<(f32, ID, Point_)>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+        Point_::Diff: VectorSpace<Scalar = f32> + std::ops::Index<usize, Output = f32> + Debug,
+        SphereCastTestGeometry<Point_>: TestGeometry,
+        GetToi: FnMut(&Point_, &Point_::Diff, f32, ID) -> f32
+    {
+        let test_geom = SphereCastTestGeometry::with_system_bounds(
+            system_bounds,
+            origin,
+            direction,
+            radius,
+            0f32,
+            max_dist);
+
+        let one_way = std::mem::take(&mut self.one_way);
+        let result = self.pick(&test_geom, max_dist, max_depth, |_, max_dist, id| {
+                if passes_one_way(&one_way, id, &direction, Point_::len()) {
+                    return f32::INFINITY;
+                }
+                get_toi(&origin, &direction, max_dist, id)
+            })
+            .map(|(dist, id)| {
+                let center = origin + direction * dist;
+                (dist, id, center)
+            });
+        self.one_way = one_way;
+        result
+    }
+
+    /// Sweep `geom` along `direction`, returning the earliest time of impact and the ID of the object hit
+    /// 
+    /// The traversal sweeps the bounds of `geom` (see [`BoundedTestGeometry`]), visiting cells nearest
+    /// first and skipping those it would enter later than the earliest impact so far.  `toi` receives
+    /// `geom`, `direction`, and the current limit, and returns the distance along `direction` (scaled
+    /// by its length, as for rays) at which `geom` first touches an object, or infinity if it doesn't;
+    /// this is where exact shape casts against narrow-phase geometry belong.  As with [`pick_ray`],
+    /// one-way objects are skipped when moving along their normals.
+    /// 
+    /// The `system_bounds` provided to this method should, in most cases, be identical to the
+    /// `system_bounds` provided to [`extend`]
+    /// 
+    /// [`pick_ray`]: #method.pick_ray
+    /// [`extend`]: #method.extend
+    /// [`BoundedTestGeometry`]: trait.BoundedTestGeometry.html
+    pub fn cast<Point_, TestGeom, Toi>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        geom: &TestGeom,
+        direction: Point_::Diff,
+        max_dist: f32,
+        max_depth: Option<u32>,
+        mut toi: Toi) -> Option<(f32, ID)>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+        Point_::Diff: Array<Element = f32> + Debug,
+        TestGeom: BoundedTestGeometry<Point_>,
+        CastTestGeometry<Point_>: TestGeometry,
+        Toi: FnMut(&TestGeom, &Point_::Diff, f32, ID) -> f32
+    {
+        let test_geom = CastTestGeometry::with_system_bounds(
+            system_bounds,
+            geom.shape_bounds(),
+            direction,
+            0f32,
+            max_dist);
+
+        let one_way = std::mem::take(&mut self.one_way);
+        let result = self.pick(&test_geom, max_dist, max_depth, |_, max_dist, id| {
+                if passes_one_way(&one_way, id, &direction, Point_::len()) {
+                    return f32::INFINITY;
+                }
+                toi(geom, &direction, max_dist, id)
+            });
+        self.one_way = one_way;
+        result
+    }
+
+    /// [`pick_ray`]: #method.pick_ray
+    /// [`RayError`]: enum.RayError.html
+    /// Equivalent to [`pick_ray`], returning a [`RayError`] rather than picking along a degenerate ray,
+    /// see [`try_test_ray`]
+    /// 
+    /// [`try_test_ray`]: #method.try_test_ray
+    pub fn try_pick_ray<Point_, GetDist>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        origin   : Point_,
+        direction: Point_::Diff,
+        max_dist: f32,
+        max_depth: Option<u32>,
+        get_dist: GetDist) -> Result<Option<(f32, ID, Point_)>, RayError>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+        Point_::Diff: VectorSpace<Scalar = f32> + ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        RayTestGeometry<Point_>: TestGeometry,
+        GetDist: FnMut(&Point_, &Point_::Diff, f32, ID) -> f32
+    {
+        RayTestGeometry::check(&origin, &direction, 0f32, max_dist)?;
+        Ok(self.pick_ray(system_bounds, origin, direction, max_dist, max_depth, get_dist))
+    }
+
+    /// [`pick_ray`]: #method.pick_ray
+    /// [`pick_prioritized`]: #method.pick_prioritized
+    /// Equivalent to [`pick_ray`], preferring objects with a higher `priority` at equal distances, see
+    /// [`pick_prioritized`]
+    #[allow(clippy::too_many_arguments)]
+    pub fn pick_ray_prioritized<Point_, GetDist, Priority>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        origin   : Point_,
+        direction: Point_::Diff,
+        max_dist: f32,
+        max_depth: Option<u32>,
+        mut get_dist: GetDist,
+        priority: Priority) -> Option<(f32, ID, Point_)>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+        Point_::Diff: VectorSpace<Scalar = f32> + ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        RayTestGeometry<Point_>: TestGeometry,
+        GetDist: FnMut(&Point_, &Point_::Diff, f32, ID) -> f32,
+        Priority: FnMut(ID) -> u32
+    {
+        let test_geom = RayTestGeometry::with_system_bounds(
+            system_bounds,
+            origin,
+            direction,
+            0f32,
+            max_dist);
+
+        let one_way = std::mem::take(&mut self.one_way);
+        let result = self.pick_prioritized(&test_geom, max_dist, max_depth, |_, max_dist, id| {
+                if passes_one_way(&one_way, id, &direction, Point_::len()) {
+                    return f32::INFINITY;
+                }
+                get_dist(&origin, &direction, max_dist, id)
+            }, priority)
+            .map(|(dist, id)| {
+                let point = origin + direction * dist;
+                (dist, id, point)
+            });
+        self.one_way = one_way;
+        result
+    }
+
+    /// [`pick_ray`]: #method.pick_ray
+    /// [`set_flags`]: #method.set_flags
+    /// Equivalent to [`pick_ray`], considering only objects whose flags (see [`set_flags`]) include all
+    /// of `required` and none of `excluded`
+    /// 
+    /// Flags are checked before calling `get_dist`, so it's never invoked for filtered objects.
+    #[allow(clippy::too_many_arguments)]
+    pub fn pick_ray_with_flags<Point_, GetDist>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        origin   : Point_,
+        direction: Point_::Diff,
+        max_dist: f32,
+        max_depth: Option<u32>,
+        required: u32,
+        excluded: u32,
+        mut get_dist: GetDist) -> Option<(f32, ID, Point_)>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+        Point_::Diff: VectorSpace<Scalar = f32> + ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        RayTestGeometry<Point_>: TestGeometry,
+        GetDist: FnMut(&Point_, &Point_::Diff, f32, ID) -> f32
+    {
+        let flags = std::mem::take(&mut self.flags);
+        let result = self.pick_ray(system_bounds, origin, direction, max_dist, max_depth,
+            |origin, direction, max_dist, id| {
+                let flags = flags.get(&id).cloned().unwrap_or(0);
+                if flags & required == required && flags & excluded == 0 {
+                    get_dist(origin, direction, max_dist, id)
+                } else {
+                    f32::INFINITY
+                }
+            });
+        self.flags = flags;
+        result
+    }
+
+    /// [`pick_ray`]: #method.pick_ray
+    /// [`test_box_transformed`]: #method.test_box_transformed
+    /// Equivalent to [`pick_ray`], for a `Layer` whose contents are placed by `transform`, see
+    /// [`test_box_transformed`]
+    /// 
+    /// `origin` and `direction` are in the transformed (e.g. world) space, as is the returned point.
+    /// They're passed to `get_dist` in model space; distances are measured along the ray (in units of
+    /// `direction`), so they're the same in either space.  Panics if `transform` isn't invertible.
+    #[allow(clippy::too_many_arguments)]
+    pub fn pick_ray_transformed<Point_, T, GetDist>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        origin   : Point_,
+        direction: Point_::Diff,
+        transform: &T,
+        max_dist: f32,
+        max_depth: Option<u32>,
+        get_dist: GetDist) -> Option<(f32, ID, Point_)>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+        Point_::Diff: VectorSpace<Scalar = f32> + ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        RayTestGeometry<Point_>: TestGeometry,
+        T: Transform<Point_>,
+        GetDist: FnMut(&Point_, &Point_::Diff, f32, ID) -> f32
+    {
+        let inverse = transform.inverse_transform().expect("transform must be invertible");
+        self.pick_ray(
+                system_bounds,
+                inverse.transform_point(origin),
+                inverse.transform_vector(direction),
+                max_dist,
+                max_depth,
+                get_dist)
+            .map(|(dist, id, _)| (dist, id, origin + direction * dist))
+    }
+
+    /// [`pick_ray`]: #method.pick_ray
+    /// [`extend_predicted`]: #method.extend_predicted
+    /// Pick along a ray at a time between two frames, given the layers built for the `previous` and
+    /// `current` frames and an interpolation factor `alpha` (typically in `[0, 1]`), see [`pick_ray`]
+    /// 
+    /// This is intended for render-thread picking, where objects are drawn at interpolated positions.
+    /// Candidates are found in both layers, and `get_dist` receives `alpha` so that it can measure the
+    /// distance to each object at its interpolated position.  `get_dist` is called at most once per
+    /// object, even if it is found in both layers.  The layer nearest in time to `alpha` is searched
+    /// first, and the other is then limited to the nearest distance found.
+    /// 
+    /// An object is only found if the ray passes through its bounds in either frame.  Objects moving
+    /// further than their own size in a frame may be missed at intermediate times, unless their
+    /// motion is included in their bounds (e.g. with [`extend_predicted`]).
+    #[allow(clippy::too_many_arguments)]
+    pub fn pick_ray_interpolated<Point_, GetDist>(
+        previous: &mut Self,
+        current: &mut Self,
+        alpha: f32,
+        system_bounds: Bounds<Point_>,
+        origin   : Point_,
+        direction: Point_::Diff,
+        max_dist: f32,
+        max_depth: Option<u32>,
+        mut get_dist: GetDist) -> Option<(f32, ID, Point_)>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+        Point_::Diff: VectorSpace<Scalar = f32> + ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        RayTestGeometry<Point_>: TestGeometry,
+        GetDist: FnMut(&Point_, &Point_::Diff, f32, f32, ID) -> f32
+    {
+        let (first, second) = if alpha < 0.5f32 {
+            (previous, current)
+        } else {
+            (current, previous)
+        };
+
+        // distances only depend on `alpha`, so they can be shared between layers
+        let mut dists: FxHashMap<ID, f32> = FxHashMap::default();
+        let mut get_dist = |origin: &Point_, direction: &Point_::Diff, max_dist: f32, id: ID| {
+            *dists.entry(id).or_insert_with(|| get_dist(origin, direction, alpha, max_dist, id))
+        };
+        let nearest = first.pick_ray(system_bounds, origin, direction, max_dist, max_depth, &mut get_dist);
+        let max_dist = nearest.map_or(max_dist, |(dist, _, _)| dist);
+        match (nearest, second.pick_ray(system_bounds, origin, direction, max_dist, max_depth, &mut get_dist)) {
+            (Some(lhs), Some(rhs)) if lhs.0 <= rhs.0 => Some(lhs),
+            (lhs, rhs) => rhs.or(lhs)
+        }
+    }
+
+    /// [`pick_ray`]: #method.pick_ray
+    /// [`pick_with_payload`]: #method.pick_with_payload
+    /// Equivalent to [`pick_ray`], also returning a payload computed by `get_dist`, see
+    /// [`pick_with_payload`]
+    #[allow(clippy::type_complexity)]
+    pub fn pick_ray_with_payload<T, Point_, GetDist>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        origin   : Point_,
+        direction: Point_::Diff,
+        max_dist: f32,
+        max_depth: Option<u32>,
+        mut get_dist: GetDist) -> Option<(f32, ID, Point_, T)>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+        Point_::Diff: VectorSpace<Scalar = f32> + ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        RayTestGeometry<Point_>: TestGeometry,
+        GetDist: FnMut(&Point_, &Point_::Diff, f32, ID) -> Option<(f32, T)>
+    {
+        let test_geom = RayTestGeometry::with_system_bounds(
+            system_bounds,
+            origin,
+            direction,
+            0f32,
+            max_dist);
+
+        let one_way = std::mem::take(&mut self.one_way);
+        let result = self.pick_with_payload(&test_geom, max_dist, max_depth, |_, max_dist, id| {
+                if passes_one_way(&one_way, id, &direction, Point_::len()) {
+                    return None;
+                }
+                get_dist(&origin, &direction, max_dist, id)
+            })
+            .map(|(dist, id, payload)| {
+                let point = origin + direction * dist;
+                (dist, id, point, payload)
+            });
+        self.one_way = one_way;
+        result
+    }
+
+    /// [`pick_ray`]: #method.pick_ray
+    /// [`pick_all_nearest`]: #method.pick_all_nearest
+    /// Equivalent to [`pick_ray`], returning every object within `epsilon` of the nearest distance, see
+    /// [`pick_all_nearest`]
+    #[allow(clippy::too_many_arguments)]
+    pub fn pick_ray_all_nearest<Point_, GetDist>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        origin   : Point_,
+        direction: Point_::Diff,
+        max_dist: f32,
+        max_depth: Option<u32>,
+        epsilon: f32,
+        mut get_dist: GetDist) -> Option<(f32, &Vec<ID>, Point_)>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+        Point_::Diff: VectorSpace<Scalar = f32> + ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        RayTestGeometry<Point_>: TestGeometry,
+        GetDist: FnMut(&Point_, &Point_::Diff, f32, ID) -> f32
+    {
+        let test_geom = RayTestGeometry::with_system_bounds(
+            system_bounds,
+            origin,
+            direction,
+            0f32,
+            max_dist);
+
+        let one_way = std::mem::take(&mut self.one_way);
+        let nearest = self.pick_all_nearest(&test_geom, max_dist, max_depth, epsilon, |_, max_dist, id| {
+                if passes_one_way(&one_way, id, &direction, Point_::len()) {
+                    return f32::INFINITY;
+                }
+                get_dist(&origin, &direction, max_dist, id)
+            })
+            .map(|(dist, _)| dist);
+        self.one_way = one_way;
+        nearest.map(move |dist| {
+            let point = origin + direction * dist;
+            (dist, &self.test_results, point)
+        })
+    }
+
+    /// Detects collisions between all objects in the `Layer`
+    pub fn scan(&mut self)
+        -> &Vec<(ID, ID)>
+    {
+        self.scan_filtered(|_, _| true)
+    }
+
+    /// Detects collisions between all objects in the `Layer`, returning only those which pass a user-specified test
+    /// 
+    /// Collisions are filtered prior to duplicate removal.  This may be faster or slower than filtering
+    /// post-duplicate-removal (i.e. by `scan().iter().filter()`) depending on the complexity
+    /// of the filter.
+    pub fn scan_filtered<F>(&mut self, filter: F)
+        -> &Vec<(ID, ID)>
+    where
+        F: FnMut(ID, ID) -> bool
+    {
+        self.scan_filtered_observed(filter, &mut ())
+    }
+
+    /// [`scan`]: #method.scan
+    /// [`TraversalObserver`]: trait.TraversalObserver.html
+    /// Equivalent to [`scan`], reporting the traversal to `observer`, see [`TraversalObserver`]
+    pub fn scan_observed<'a, Observer>(&'a mut self, observer: &mut Observer)
+        -> &'a Vec<(ID, ID)>
+    where
+        Observer: TraversalObserver<Index>
+    {
+        self.scan_filtered_observed(|_, _| true, observer)
+    }
+
+    /// [`scan_filtered`]: #method.scan_filtered
+    /// [`TraversalObserver`]: trait.TraversalObserver.html
+    /// Equivalent to [`scan_filtered`], reporting the traversal to `observer`, see [`TraversalObserver`]
+    pub fn scan_filtered_observed<'a, F, Observer>(&'a mut self, filter: F, observer: &mut Observer)
+        -> &'a Vec<(ID, ID)>
+    where
+        F: FnMut(ID, ID) -> bool,
+        Observer: TraversalObserver<Index>
+    {
+        self.scan_filtered_impl(filter, observer, None);
+        &self.collisions
+    }
+
+    /// [`scan`]: #method.scan
+    /// Equivalent to [`scan`], reporting the fraction of entries processed (from 0 to 1) to `progress`
+    /// 
+    /// This is intended for progress bars in tooling which scans very large static layers; `progress`
+    /// is called at most about once per percent.  The implicit commit is not reported.
+    pub fn scan_with_progress<P>(&mut self, progress: P)
+        -> &Vec<(ID, ID)>
+    where
+        P: FnMut(f32)
+    {
+        self.commit();
+
+        let mut progress = Progress::new(progress, self.tree.0.len());
+        self.scan_filtered_impl(|_, _| true, &mut progress, None);
+        progress.finish();
+
+        &self.collisions
+    }
+
+    /// [`scan`]: #method.scan
+    /// Equivalent to [`scan`], but stops early and returns `None` once `cancel` is set
+    /// 
+    /// `cancel` is checked periodically while scanning, so that e.g. an editor may abort a very slow
+    /// scan of a pathological scene from another thread.  The implicit commit is not cancellable.
+    pub fn scan_cancellable<'a>(&'a mut self, cancel: &AtomicBool)
+        -> Option<&'a Vec<(ID, ID)>>
+    {
+        if self.scan_filtered_impl(|_, _| true, &mut (), Some(cancel)) {
+            Some(&self.collisions)
+        } else {
+            None
+        }
+    }
+
+    /// Returns `false` if cancelled, leaving no collisions
+    fn scan_filtered_impl<F, Observer>(&mut self, mut filter: F, observer: &mut Observer, cancel: Option<&AtomicBool>) -> bool
+    where
+        F: FnMut(ID, ID) -> bool,
+        Observer: TraversalObserver<Index>
+    {
+        self.commit();
+
+        let ranges = self.active_ranges();
+        if self.inactive.is_empty() && self.groups.is_empty() {
+            return self.scan_active_impl(filter, ranges, observer, cancel);
+        }
+        let inactive = std::mem::take(&mut self.inactive);
+        let groups = std::mem::take(&mut self.groups);
+        let result = self.scan_active_impl(
+            |a, b| !skips_pair(&inactive, &groups, a, b) && filter(a, b),
+            ranges,
+            observer,
+            cancel);
+        self.inactive = inactive;
+        self.groups = groups;
+        result
+    }
+
+    /// Equivalent to `scan_filtered_impl` for `ranges` of the committed entries, ignoring activation
+    /// flags
+    fn scan_active_impl<F, Observer>(
+        &mut self,
+        mut filter: F,
+        ranges: Vec<Range<usize>>,
+        observer: &mut Observer,
+        cancel: Option<&AtomicBool>) -> bool
+    where
+        F: FnMut(ID, ID) -> bool,
+        Observer: TraversalObserver<Index>
+    {
+        self.collisions.clear();
+        self.invalid.clear();
+
+        let mut filtered_pairs = 0;
+        let mut filter = |a, b| {
+            let keep = filter(a, b);
+            if !keep {
+                filtered_pairs += 1;
+            }
+            keep
+        };
+
+        let (tree, _) = &self.tree;
+        let params = ScanParams{
+            dense_cell_threshold: self.dense_cell_threshold,
+            bounds: self.bounds.as_ref()};
+        if let Some(hot_cells) = &mut self.hot_cells {
+            for cell in hot_cells.cells.values_mut() {
+                cell.seen = false;
+            }
+        }
+        for range in ranges {
+            if !Self::scan_impl(&tree[range], params, self.hot_cells.as_mut(), &mut self.collisions, &mut filter, observer, cancel) {
+                // hot cells which weren't reached are kept, as they may still be dense
+                self.collisions.clear();
+                return false;
+            }
+        }
+        if let Some(hot_cells) = &mut self.hot_cells {
+            hot_cells.cells.retain(|_, cell| cell.seen);
+        }
+        self.scan_unbounded(filter);
+        self.filtered_pairs = filtered_pairs;
+
+        self.collisions.sort_unstable();
+        self.collisions.dedup();
+
+        true
+    }
+
+    /// The number of candidate pairs rejected during the last completed scan, either by its filter or
+    /// because both objects are inactive
+    /// 
+    /// Filters run as pairs are found, including within each thread of a parallel scan, so rejected
+    /// pairs are never buffered.  This may be used to measure the effect of collision masks.  Pairs
+    /// found in multiple cells are counted for each cell.
+    pub fn filtered_pairs(&self) -> usize {
+        self.filtered_pairs
+    }
+
+    /// [`LayerBuilder::with_stored_bounds`]: struct.LayerBuilder.html#method.with_stored_bounds
+    /// Find all pairs of objects whose bounds are within `distance` of each other, with the distance
+    /// between their bounds
+    /// 
+    /// This is intended for proximity queries such as steering or flocking, whose consumers would
+    /// otherwise recompute each pair's distance.  Distances are between the nearest points of stored
+    /// bounds, which are required (see [`LayerBuilder::with_stored_bounds`]), and are zero for
+    /// overlapping objects.  Each object's bounds are expanded by `distance` and tested against the
+    /// `Layer`, so this costs a box test per object rather than a single pass like `scan`.
+    /// 
+    /// Pairs are ordered `(lower ID, higher ID)` and sorted; unbounded objects are excluded, and as for
+    /// `scan`, pairs of two inactive objects or of objects in the same exclusion group are skipped.
+    pub fn scan_within<Point_>(&mut self, system_bounds: Bounds<Point_>, distance: f32) -> Vec<(ID, ID, f32)>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + Debug,
+        Point_::Diff: Array<Element = f32> + ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        BoxTestGeometry<Point_>: TestGeometry,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
+    {
+        assert!(self.bounds.is_some(), "scan_within requires stored bounds");
+
+        self.commit();
+
+        let mut pairs = self.pairs_within(system_bounds, self, distance, |a, b|
+            b <= a || skips_pair(&self.inactive, &self.groups, a, b));
+        pairs.sort_unstable_by_key(|&(a, b, _)| (a, b));
+        pairs
+    }
+
+    /// [`scan_within`]: #method.scan_within
+    /// Find all pairs of an object in this `Layer` and an object in `other` whose bounds are within
+    /// `distance` of each other, with the distance between their bounds, e.g. characters near trigger
+    /// volumes
+    /// 
+    /// Both layers must store bounds and be built with the same `system_bounds`.  As for
+    /// [`scan_within`], the bounds of each object in `other` are expanded by `distance` and tested
+    /// against this `Layer`, so `other` should usually be the smaller of the two.
+    /// 
+    /// Pairs are ordered `(ID in self, ID in other)` and sorted; pairs of objects which are both
+    /// inactive in their respective layers are skipped.
+    pub fn near_pairs_with<Point_>(
+        &mut self,
+        other: &mut Self,
+        system_bounds: Bounds<Point_>,
+        distance: f32) -> Vec<(ID, ID, f32)>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + Debug,
+        Point_::Diff: Array<Element = f32> + ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        BoxTestGeometry<Point_>: TestGeometry,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
+    {
+        assert!(self.bounds.is_some() && other.bounds.is_some(), "near_pairs_with requires stored bounds");
+
+        self.commit();
+        other.commit();
+
+        let mut pairs: Vec<(ID, ID, f32)> = self
+            .pairs_within(system_bounds, other, distance, |a, b|
+                other.inactive.contains(&a) && self.inactive.contains(&b))
+            .into_iter()
+            .map(|(a, b, gap)| (b, a, gap))
+            .collect();
+        pairs.sort_unstable_by_key(|&(a, b, _)| (a, b));
+        pairs
+    }
+
+    /// Find pairs of each object in `probes` and objects in this `Layer` within `distance`, as
+    /// `(ID in probes, ID in self, distance)`, except those for which `skip` returns true
+    /// 
+    /// Both layers must be committed and store bounds.
+    fn pairs_within<Point_, Skip>(
+        &self,
+        system_bounds: Bounds<Point_>,
+        probes: &Self,
+        distance: f32,
+        skip: Skip) -> Vec<(ID, ID, f32)>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + Debug,
+        Point_::Diff: Array<Element = f32> + ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        BoxTestGeometry<Point_>: TestGeometry,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>,
+        Skip: Fn(ID, ID) -> bool
+    {
+        let bounds = self.bounds.as_ref().unwrap();
+        let global = |local: Bounds<Index::Point>| system_bounds.to_global(local);
+        let margin = Point_::Diff::from_value(distance);
+        let mut candidates: Vec<ID> = Vec::new();
+        let mut pairs: Vec<(ID, ID, f32)> = Vec::new();
+        for (a, a_bounds) in probes.bounds.as_ref().unwrap().iter() {
+            let a_bounds = global(a_bounds);
+            let expanded = Bounds::new(a_bounds.min - margin, a_bounds.max + margin);
+            let test_bounds = match expanded.intersection(system_bounds) {
+                Some(test_bounds) => test_bounds,
+                None => continue
+            };
+            let test_geom = BoxTestGeometry::with_system_bounds(system_bounds, test_bounds);
+            self.test_sorted(self.sorted_tree(), &test_geom, None, None, &mut candidates, &mut ());
+            for &b in &candidates {
+                if skip(a, b) {
+                    continue;
+                }
+                if let Some(b_bounds) = bounds.get(b) {
+                    let gap = a_bounds.distance_to_bounds(global(b_bounds));
+                    if gap <= distance {
+                        pairs.push((a, b, gap));
+                    }
+                }
+            }
+        }
+        pairs
+    }
+
+    /// [`scan`]: struct.Layer.html#method.scan
+    /// Equivalent to [`scan`], but returns at most `max_pairs` pairs involving any single object
+    /// 
+    /// This bounds the cost of piles of many objects (e.g. thousands of pieces of debris in one spot),
+    /// whose pairs would otherwise grow quadratically.  For each entry, pairs are considered starting
+    /// with its own cell and then its ancestors, so pairs from deeper (more precise) cells are
+    /// preferred.  Objects with multiple bounds may receive fewer than `max_pairs` pairs.  The result is
+    /// sorted and deterministic, as for [`scan`].
+    pub fn scan_capped_per_object(&mut self, max_pairs: usize)
+        -> &Vec<(ID, ID)>
+    {
+        let mut counts: FxHashMap<ID, usize> = FxHashMap::default();
+        self.scan_filtered(|a, b| {
+            let full = |id| matches!(counts.get(&id), Some(&count) if count >= max_pairs);
+            if max_pairs == 0 || full(a) || full(b) {
+                return false;
+            }
+            *counts.entry(a).or_insert(0) += 1;
+            *counts.entry(b).or_insert(0) += 1;
+            true
+        })
+    }
+
+    /// [`scan`]: struct.Layer.html#method.scan
+    /// Equivalent to [`scan`], but returns at most `max_pairs` pairs, preferring those with the highest
+    /// `priority`
+    /// 
+    /// This bounds the amount of narrow-phase work under extreme load, e.g. by prioritizing pairs near
+    /// the player.  Pairs of equal priority are preferred in sorted order, so the result is deterministic.
+    /// The result is sorted, as for [`scan`].
+    pub fn scan_capped<P>(&mut self, max_pairs: usize, priority: P)
+        -> &Vec<(ID, ID)>
+    where
+        P: Fn(ID, ID) -> u32
+    {
+        self.scan();
+
+        let collisions = &mut self.collisions;
+        if collisions.len() > max_pairs {
+            if max_pairs > 0 {
+                collisions.select_nth_unstable_by_key(max_pairs - 1, |&(a, b)| (Reverse(priority(a, b)), a, b));
+            }
+            collisions.truncate(max_pairs);
+            collisions.sort_unstable();
+        }
+
+        &self.collisions
+    }
+
+    /// [`scan`]: struct.Layer.html#method.scan
+    /// [`PairArrays`]: struct.PairArrays.html
+    /// Equivalent to [`scan`], returning pairs as parallel arrays, see [`PairArrays`]
+    pub fn scan_arrays(&mut self)
+        -> &PairArrays<ID>
+    {
+        self.scan_filtered_arrays(|_, _| true)
+    }
+
+    /// [`scan_filtered`]: struct.Layer.html#method.scan_filtered
+    /// [`PairArrays`]: struct.PairArrays.html
+    /// Equivalent to [`scan_filtered`], returning pairs as parallel arrays, see [`PairArrays`]
+    pub fn scan_filtered_arrays<F>(&mut self, filter: F)
+        -> &PairArrays<ID>
+    where
+        F: FnMut(ID, ID) -> bool
+    {
+        self.scan_filtered(filter);
+        self.pair_arrays.assign(&self.collisions);
+        &self.pair_arrays
+    }
+
+    /// [`scan`]: struct.Layer.html#method.scan
+    /// Parallel version of [`scan`]
+    #[cfg(feature="parallel")]
+    pub fn par_scan(&mut self)
+        -> &Vec<(ID, ID)>
+    where
+        Index: Send + Sync
+    {
+        self.par_scan_filtered(|_, _| true)
+    }
+
+    /// [`scan_filtered`]: struct.Layer.html#method.scan_filtered
+    /// Parallel version of [`scan_filtered`]
+    #[cfg(feature="parallel")]
+    pub fn par_scan_filtered<F>(&mut self, filter: F)
+        -> &Vec<(ID, ID)>
+    where
+        Index: Send + Sync,
+        F: Copy + Send + Sync + FnMut(ID, ID) -> bool
+    {
+        self.par_scan_filtered_impl(filter, None);
+        &self.collisions
+    }
+
+    /// [`par_scan`]: #method.par_scan
+    /// [`scan_cancellable`]: #method.scan_cancellable
+    /// Equivalent to [`par_scan`], but stops early and returns `None` once `cancel` is set, see
+    /// [`scan_cancellable`]
+    #[cfg(feature="parallel")]
+    pub fn par_scan_cancellable<'a>(&'a mut self, cancel: &AtomicBool)
+        -> Option<&'a Vec<(ID, ID)>>
+    where
+        Index: Send + Sync
+    {
+        if self.par_scan_filtered_impl(|_, _| true, Some(cancel)) {
+            Some(&self.collisions)
+        } else {
+            None
+        }
+    }
+
+    /// Returns `false` if cancelled, leaving no collisions
+    #[cfg(feature="parallel")]
+    fn par_scan_filtered_impl<F>(&mut self, filter: F, cancel: Option<&AtomicBool>) -> bool
+    where
+        Index: Send + Sync,
+        F: Copy + Send + Sync + FnMut(ID, ID) -> bool
+    {
+        self.par_commit();
+
+        let ranges = self.active_ranges();
+        if self.inactive.is_empty() && self.groups.is_empty() {
+            return self.par_scan_active_impl(filter, ranges, cancel);
+        }
+        let inactive = std::mem::take(&mut self.inactive);
+        let groups = std::mem::take(&mut self.groups);
+        let inactive_ref = &inactive;
+        let groups_ref = &groups;
+        let result = self.par_scan_active_impl(
+            move |a, b| {
+                let mut filter = filter;
+                !skips_pair(inactive_ref, groups_ref, a, b) && filter(a, b)
+            },
+            ranges,
+            cancel);
+        self.inactive = inactive;
+        self.groups = groups;
+        result
+    }
+
+    /// Equivalent to `par_scan_filtered_impl` for `ranges` of the committed entries, ignoring
+    /// activation flags
+    #[cfg(feature="parallel")]
+    fn par_scan_active_impl<F>(&mut self, filter: F, ranges: Vec<Range<usize>>, cancel: Option<&AtomicBool>) -> bool
+    where
+        Index: Send + Sync,
+        F: Copy + Send + Sync + FnMut(ID, ID) -> bool
+    {
+        if self.deterministic {
+            return self.scan_active_impl(filter, ranges, &mut (), cancel);
+        }
+
+        self.collisions.clear();
+        self.invalid.clear();
+        for set in self.collisions_tls.iter_mut() {
+            set.borrow_mut().clear();
+        }
+
+        let filtered_pairs = AtomicUsize::new(0);
+        for range in ranges {
+            if !self.par_scan_impl(rayon::current_num_threads(), &self.tree.0[range], filter, &filtered_pairs, cancel) {
+                return false;
+            }
+        }
+
+        for set in self.collisions_tls.iter_mut() {
+            use std::borrow::Borrow;
+            let set_: RefMut<Vec<(ID, ID)>> = set.borrow_mut();
+            let set__: &Vec<(ID, ID)> = set_.borrow();
+            self.collisions.extend(set__.iter());
+        }
+        let mut filtered_pairs = filtered_pairs.into_inner();
+        let mut filter = filter;
+        self.scan_unbounded(|a, b| {
+            let keep = filter(a, b);
+            if !keep {
+                filtered_pairs += 1;
+            }
+            keep
+        });
+        self.filtered_pairs = filtered_pairs;
+
+        self.collisions.par_sort_unstable();
+        self.collisions.dedup();
+
+        true
+    }
+
+    /// Pair unbounded objects with all other objects
+    fn scan_unbounded<F>(&mut self, filter: F)
+    where
+        F: FnMut(ID, ID) -> bool
+    {
+        let (tree, _) = &self.tree;
+        Self::scan_unbounded_impl(tree, &self.unbounded, &mut self.processed, &mut self.collisions, filter, self.deterministic);
+    }
+
+    fn scan_unbounded_impl<F>(
+        tree: &[(Index, ID)],
+        unbounded: &[ID],
+        processed: &mut FxHashSet<ID>,
+        collisions: &mut Vec<(ID, ID)>,
+        mut filter: F,
+        sorted: bool)
+    where
+        F: FnMut(ID, ID) -> bool
+    {
+        if unbounded.is_empty() {
+            return;
+        }
+
+        processed.clear();
+        processed.extend(tree.iter().map(|&(_, id)| id));
+        processed.extend(unbounded.iter());
+
+        // hash-set order is consistent, but depends on the history of the set
+        let ordered: Option<Vec<ID>> = if sorted {
+            let mut ids: Vec<ID> = processed.iter().cloned().collect();
+            ids.sort_unstable();
+            Some(ids)
+        } else {
+            None
+        };
+
+        let mut pair = |id_: ID, id: ID| {
+            // pairs of unbounded objects are only reported once
+            if id_ >= id && unbounded.binary_search(&id_).is_ok() {
+                return;
+            }
+            if filter(id_, id) {
+                collisions.push((id_, id));
+            }
+        };
+        for &id in unbounded {
+            match &ordered {
+                Some(ids) => ids.iter().for_each(|&id_| pair(id_, id)),
+                None => processed.iter().for_each(|&id_| pair(id_, id))
+            }
+        }
+    }
+
+    /// [`scan_filtered`]: #method.scan_filtered
+    /// Equivalent to [`scan_filtered`], returning only pairs in which at least one object is in `ids`
+    /// 
+    /// This is intended for scans concerning a few objects among many (e.g. just projectiles).
+    /// Entries are counted in a single pass over the sorted tree, after which the scan skips every
+    /// subtree which contains no entries of `ids` and isn't contained by one, as well as cells
+    /// containing only inactive objects (see `LayerBuilder::with_active_partitions`).  Dense-cell handling
+    /// (see `LayerBuilder::with_dense_cell_threshold`) isn't used, so pairs which it would refine away
+    /// may be included.
+    pub fn scan_involving<'a>(&'a mut self, ids: &FxHashSet<ID>) -> &'a Vec<(ID, ID)> {
+        self.commit();
+
+        self.collisions.clear();
+        self.invalid.clear();
+
+        let ranges = self.active_ranges();
+        let (tree, _) = &self.tree;
+        let collisions = &mut self.collisions;
+        let inactive = &self.inactive;
+        let groups = &self.groups;
+        let involved = |a: ID, b: ID| (ids.contains(&a) || ids.contains(&b))
+            && !skips_pair(inactive, groups, a, b);
+
+        // the number of entries of `ids` preceding each position in the tree
+        let mut involved_before = Vec::with_capacity(tree.len() + 1);
+        let mut count = 0usize;
+        involved_before.push(count);
+        for &(_, id) in tree {
+            if ids.contains(&id) {
+                count += 1;
+            }
+            involved_before.push(count);
+        }
+
+        let mut stack: SmallVec<[(Index, ID); 256]> = SmallVec::new();
+        for range in ranges {
+            // cells in separate ranges never overlap
+            stack.clear();
+            let mut involved_in_stack = 0usize;
+            let mut i = range.start;
+            while i < range.end {
+                let (index, id) = tree[i];
+                while let Some(&(index_, id_)) = stack.last() {
+                    if index.overlaps(index_) {
+                        break;
+                    }
+                    stack.pop();
+                    if ids.contains(&id_) {
+                        involved_in_stack -= 1;
+                    }
+                }
+                if involved_in_stack == 0 {
+                    // a cell's descendants immediately follow it in sorted order
+                    let end = i + tree[i..range.end].partition_point(|&(index_, _)| index.overlaps(index_));
+                    if involved_before[end] == involved_before[i] {
+                        i = end;
+                        continue;
+                    }
+                }
+                i += 1;
+                if stack.iter().any(|&(_, id_)| id == id_) {
+                    continue;
+                }
+                for &(_, id_) in stack.iter().rev() {
+                    if id != id_ && involved(id, id_) {
+                        collisions.push((id, id_));
+                    }
+                }
+                if ids.contains(&id) {
+                    involved_in_stack += 1;
+                }
+                stack.push((index, id));
+            }
+        }
+        Self::scan_unbounded_impl(tree, &self.unbounded, &mut self.processed, collisions, involved, self.deterministic);
+
+        collisions.sort_unstable();
+        collisions.dedup();
+        collisions
+    }
+
+    /// [`scan_involving`]: #method.scan_involving
+    /// Find all objects sharing a cell with `id`, i.e. those it would be paired with by `scan`
+    /// 
+    /// This visits only the cells containing, or contained by, the entries of `id`, which are found by
+    /// a scan of the `Layer`, or by binary search if configured with `LayerBuilder::with_id_index`.  As
+    /// for [`scan_involving`], dense-cell handling isn't used, and pairs of two inactive objects or of
+    /// objects in the same exclusion group are skipped.  Results are sorted.
+    pub fn collisions_with(&mut self, id: ID) -> &Vec<ID> {
+        let positions = self.object_positions(id);
+
+        let (tree, _) = &self.tree;
+        let test_results = &mut self.test_results;
+        test_results.clear();
+        if self.unbounded.binary_search(&id).is_ok() {
+            test_results.extend(tree.iter().map(|&(_, id_)| id_));
+        }
+        for &position in &positions {
+            let index = tree[position].0;
+            // entries in this cell and its ancestors
+            for depth in 0..=index.depth() {
+                let cell = Self::truncate_index(index, depth);
+                let start = tree.partition_point(|&(index_, _)| index_ < cell);
+                test_results.extend(tree[start..].iter()
+                    .take_while(|&&(index_, _)| index_ == cell)
+                    .map(|&(_, id_)| id_));
+            }
+            // a cell's descendants immediately follow it in sorted order
+            let end = position + tree[position..].partition_point(|&(index_, _)| index.overlaps(index_));
+            test_results.extend(tree[position..end].iter().map(|&(_, id_)| id_));
+        }
+        test_results.extend(self.unbounded.iter().cloned());
+
+        let (inactive, groups) = (&self.inactive, &self.groups);
+        test_results.retain(|&id_| id_ != id && !skips_pair(inactive, groups, id, id_));
+        test_results.sort_unstable();
+        test_results.dedup();
+        test_results
+    }
+
+    /// [`scan_filtered`]: #method.scan_filtered
+    /// [`commit`]: #method.commit
+    /// Equivalent to [`scan_filtered`], but queries only the committed state and never commits implicitly
+    /// 
+    /// Persistent dense-cell state (see `LayerBuilder::with_hot_cell_sap`) is neither used nor updated.
+    /// Panics if the `Layer` has never been committed since being deserialized, see [`commit`].
+    pub fn scan_committed<F>(&self, mut filter: F) -> Vec<(ID, ID)>
+    where
+        F: FnMut(ID, ID) -> bool
+    {
+        let mut processed = FxHashSet::default();
+        let mut collisions = Vec::new();
+        let inactive = &self.inactive;
+        let groups = &self.groups;
+        self.scan_sorted(
+            self.sorted_tree(),
+            |a, b| !skips_pair(inactive, groups, a, b) && filter(a, b),
+            &mut processed,
+            &mut collisions);
+        collisions
+    }
+
+    /// Equivalent to `scan`, for sorted entries `tree` in place of this `Layer`'s own
+    /// 
+    /// Persistent dense-cell state (see `LayerBuilder::with_hot_cell_sap`) is neither used nor updated
+    #[cfg(feature="mmap")]
+    pub(crate) fn scan_entries<'a>(&'a mut self, tree: &[(Index, ID)]) -> &'a Vec<(ID, ID)> {
+        self.commit();
+
+        let mut processed = std::mem::take(&mut self.processed);
+        let mut collisions = std::mem::take(&mut self.collisions);
+        self.scan_sorted(tree, |_, _| true, &mut processed, &mut collisions);
+        self.processed = processed;
+        self.collisions = collisions;
+
+        &self.collisions
+    }
+
+    /// Implementation of `scan_filtered` for sorted entries `tree`, with caller-provided buffers
+    /// 
+    /// Persistent dense-cell state (see `LayerBuilder::with_hot_cell_sap`) is neither used nor updated
+    pub(crate) fn scan_sorted<F>(
+        &self,
+        tree: &[(Index, ID)],
+        mut filter: F,
+        processed: &mut FxHashSet<ID>,
+        collisions: &mut Vec<(ID, ID)>)
+    where
+        F: FnMut(ID, ID) -> bool
+    {
+        collisions.clear();
+
+        let params = ScanParams{
+            dense_cell_threshold: self.dense_cell_threshold,
+            bounds: self.bounds.as_ref()};
+        Self::scan_impl(tree, params, None, &mut *collisions, &mut filter, &mut (), None);
+        Self::scan_unbounded_impl(tree, &self.unbounded, processed, collisions, filter, self.deterministic);
+
+        collisions.sort_unstable();
+        collisions.dedup();
+    }
+
+    /// Returns `false` if cancelled
+    #[cfg(feature="parallel")]
+    fn par_scan_impl<F>(
+        &self,
+        threads: usize,
+        tree: &[(Index, ID)],
+        filter: F,
+        filtered_pairs: &AtomicUsize,
+        cancel: Option<&AtomicBool>) -> bool
+    where
+        Index: Send + Sync,
+        F: Copy + Send + Sync + FnMut(ID, ID) -> bool
+    {
+        const SPLIT_THRESHOLD: usize = 64;
+        if is_cancelled(cancel) {
+            false
+        } else if threads <= 1 || tree.len() <= SPLIT_THRESHOLD {
+            let collisions = self.collisions_tls.get_or(|| RefCell::new(Vec::new()));
+            let params = ScanParams{
+                dense_cell_threshold: self.dense_cell_threshold,
+                bounds: self.bounds.as_ref()};
+            // count locally, so that threads only contend once per subtree
+            let mut filter = filter;
+            let mut filtered = 0;
+            let result = Self::scan_impl(tree, params, None, collisions.borrow_mut(), |a, b| {
+                    let keep = filter(a, b);
+                    if !keep {
+                        filtered += 1;
+                    }
+                    keep
+                }, &mut (), cancel);
+            filtered_pairs.fetch_add(filtered, Ordering::Relaxed);
+            result
+        } else {
+            let n = tree.len();
+            let mut i = n / 2;
+            while i < n {
+                let (last, _) = tree[i-1];
+                let (next, _) = tree[i];
+                if !Index::same_cell_at_depth(last, next, self.min_depth) {
+                    break;
+                }
+                i += 1;
+            }
+            let (head, tail) = tree.split_at(i);
+            let (head, tail) = rayon::join(
+                || self.par_scan_impl(threads >> 1, head, filter, filtered_pairs, cancel),
+                || self.par_scan_impl(threads >> 1, tail, filter, filtered_pairs, cancel));
+            head && tail
+        }
+    }
+
+    /// Returns `false` if cancelled
+    fn scan_impl<C, F, Observer>(
+        tree: &[(Index, ID)],
+        params: ScanParams<Index, ID>,
+        mut hot_cells: Option<&mut HotCells<Index, ID>>,
+        mut collisions: C,
+        mut filter: F,
+        observer: &mut Observer,
+        cancel: Option<&AtomicBool>) -> bool
+    where
+        C: DerefMut<Target = Vec<(ID, ID)>>,
+        F: FnMut(ID, ID) -> bool,
+        Observer: TraversalObserver<Index>
+    {
+        const CANCEL_INTERVAL: usize = 4096;
+        let mut stack: SmallVec<[(Index, ID); 256]> = SmallVec::new();
+        // the start of the current run of entries with equal indices, reported to the observer
+        let mut run_start = 0;
+        let mut next_cancel_check = 0;
+        let mut i = 0;
+        while i < tree.len() {
+            if i >= next_cancel_check {
+                if is_cancelled(cancel) {
+                    return false;
+                }
+                next_cancel_check = i + CANCEL_INTERVAL;
+            }
+            let (index, id) = tree[i];
+            if tree[run_start].0 != index {
+                observer.on_cell_visited(tree[run_start].0, i - run_start);
+                run_start = i;
+            }
+            while let Some(&(index_, _)) = stack.last() {
+                if index.overlaps(index_) {
+                    break;
+                }
+                stack.pop();
+            }
+            if let Some(threshold) = params.dense_cell_threshold {
+                if matches!(tree.get(i + threshold), Some(&(index_, _)) if index_ == index) {
+                    let n = tree[i..].iter()
+                        .take_while(|&&(index_, _)| index_ == index)
+                        .count();
+                    Self::scan_dense_cell(&tree[i..i+n], params, hot_cells.as_deref_mut(), &mut stack, &mut *collisions, &mut filter);
+                    i += n;
+                    continue;
+                }
+            }
+            i += 1;
+            if stack.iter().any(|&(_, id_)| id == id_) {
+                continue;
+            }
+            // deepest (i.e. most precise) cells first
+            for &(index_, id_) in stack.iter().rev() {
+                strict_assert!(index.overlaps(index_), "paired non-overlapping cells: {:?}, {:?}", index, index_);
+                if id != id_ && filter(id, id_) {
+                    collisions.push((id, id_));
+                }
+            }
+            stack.push((index, id))
+        }
+        if run_start < tree.len() {
+            observer.on_cell_visited(tree[run_start].0, tree.len() - run_start);
+        }
+        true
+    }
+
+    /// Handle a run of entries which all share a single cell
+    /// 
+    /// Entries in such a run all overlap one another, so there's no need to maintain the stack while
+    /// processing them; this is a simple double-loop, optionally refined using stored bounds.  Cells which
+    /// remain dense for long enough are instead handled by a sweep-and-prune over a persistent sublist.
+    fn scan_dense_cell<F>(
+        cell: &[(Index, ID)],
+        params: ScanParams<Index, ID>,
+        hot_cells: Option<&mut HotCells<Index, ID>>,
+        stack: &mut SmallVec<[(Index, ID); 256]>,
+        collisions: &mut Vec<(ID, ID)>,
+        filter: &mut F)
+    where
+        F: FnMut(ID, ID) -> bool
+    {
+        let overlaps = |lhs: ID, rhs: ID| match params.bounds {
+            Some(bounds) => match (bounds.get(lhs), bounds.get(rhs)) {
+                (Some(lhs), Some(rhs)) => lhs.overlaps(rhs),
+                _ => true
+            },
+            None => true
+        };
+
+        // entries with equal indices are sorted by ID, so duplicates are adjacent
+        let mut ids: Vec<ID> = cell.iter().map(|&(_, id)| id).collect();
+        ids.dedup();
+        ids.retain(|&id| !stack.iter().any(|&(_, id_)| id == id_));
+
+        let (index, _) = cell[0];
+
+        let hot_cell = match (hot_cells, params.bounds) {
+            (Some(hot_cells), Some(bounds)) => {
+                let min_frames = hot_cells.min_frames;
+                let hot_cell = hot_cells.cells.entry(index).or_default();
+                hot_cell.seen = true;
+                hot_cell.dense_frames = hot_cell.dense_frames.saturating_add(1);
+                Some((hot_cell, min_frames, bounds))
+            },
+            _ => None
+        };
+
+        match hot_cell {
+            Some((hot_cell, min_frames, bounds)) if hot_cell.dense_frames > min_frames => {
+                Self::sweep_hot_cell(&ids, bounds, &mut hot_cell.order, collisions, filter);
+            },
+            hot_cell => {
+                for (j, &id) in ids.iter().enumerate() {
+                    for &id_ in &ids[..j] {
+                        if overlaps(id, id_) && filter(id, id_) {
+                            collisions.push((id, id_));
+                        }
+                    }
+                }
+                if let Some((hot_cell, _, _)) = hot_cell {
+                    hot_cell.order.clear();
+                    hot_cell.order.extend(ids.iter().cloned());
+                }
+            }
+        }
+
+        // as in `scan_impl`, deepest (i.e. most precise) cells first
+        for &id in &ids {
+            for &(index_, id_) in stack.iter().rev() {
+                strict_assert!(index.overlaps(index_), "paired non-overlapping cells: {:?}, {:?}", index, index_);
+                if overlaps(id, id_) && filter(id, id_) {
+                    collisions.push((id, id_));
+                }
+            }
+        }
+
+        stack.extend(ids.into_iter().map(|id| (index, id)));
+    }
+}
+
+impl<Index, ID> Layer<Index, ID>
+where
+    Index: SpatialIndex,
+    ID: ObjectID,
+    Bounds<Index::Point>: IndexGenerator<Index>
+{
+    /// Sweep-and-prune along the X axis, using (and updating) the order from the previous scan
+    /// 
+    /// `ids` must be sorted.  The order is restored by an insertion sort, which is nearly linear for
+    /// objects which move only slightly between frames.
+    fn sweep_hot_cell<F>(
+        ids: &[ID],
+        bounds: &BoundsTable<Index::Point, ID>,
+        order: &mut Vec<ID>,
+        collisions: &mut Vec<(ID, ID)>,
+        filter: &mut F)
+    where
+        F: FnMut(ID, ID) -> bool
+    {
+        let mut retained: Vec<ID> = Vec::with_capacity(order.len());
+        order.retain(|&id| ids.binary_search(&id).is_ok() && bounds.contains(id));
+        retained.extend(order.iter().cloned());
+        retained.sort_unstable();
+        order.extend(ids.iter()
+            .filter(|&&id| retained.binary_search(&id).is_err() && bounds.contains(id))
+            .cloned());
+
+        let min_x = |&id: &ID| bounds.get(id).unwrap().min[0];
+        for i in 1..order.len() {
+            let mut j = i;
+            while j > 0 && min_x(&order[j - 1]) > min_x(&order[j]) {
+                order.swap(j - 1, j);
+                j -= 1;
+            }
+        }
+
+        for (i, &lhs) in order.iter().enumerate() {
+            let lhs_bounds = bounds.get(lhs).unwrap();
+            for &rhs in &order[i+1..] {
+                let rhs_bounds = bounds.get(rhs).unwrap();
+                if rhs_bounds.min[0] > lhs_bounds.max[0] {
+                    break;
+                }
+                if lhs_bounds.overlaps(rhs_bounds) && filter(rhs, lhs) {
+                    collisions.push((rhs, lhs));
+                }
+            }
+        }
+
+        // objects without stored bounds can't be sorted, so pair them with everything
+        for (j, &id) in ids.iter().enumerate() {
+            if bounds.contains(id) {
+                continue;
+            }
+            for &id_ in ids[..j].iter().chain(ids[j+1..].iter()) {
+                if (id_ < id || bounds.contains(id_)) && filter(id, id_) {
+                    collisions.push((id, id_));
+                }
+            }
+        }
+    }
+}
+
+impl<Index, ID> PartialEq<Self> for Layer<Index, ID>
+where
+    Index: SpatialIndex,
+    ID: ObjectID,
+    Bounds<Index::Point>: IndexGenerator<Index>
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.min_depth == other.min_depth &&
+        self.tree      == other.tree &&
+        self.unbounded == other.unbounded
+    }
+}
+
+impl<Index, ID> Eq for Layer<Index, ID>
+where
+    Index: SpatialIndex,
+    ID: ObjectID,
+    Bounds<Index::Point>: IndexGenerator<Index>
+{}
+
+// implemented manually, as `#[derive(Default)]` would require `Index::Point: Default`
+impl<Index, ID> Default for Layer<Index, ID>
+where
+    Index: SpatialIndex,
+    ID: ObjectID,
+    Bounds<Index::Point>: IndexGenerator<Index>
+{
+    fn default() -> Self {
+        Layer{
+            min_depth: 0,
+            tree: (Vec::new(), true),
+            unbounded: Vec::new(),
+            clipped: Vec::new(),
+            truncated: Vec::new(),
+            heavy_objects: Vec::new(),
+            staging: Staging::default(),
+
+            out_of_bounds: OutOfBoundsPolicy::Reject,
+            max_entries: None,
+            target_entries: None,
+            max_entries_per_object: None,
+            entry_warning_threshold: None,
+
+            dense_cell_threshold: None,
+            bounds: None,
+            refined_tests: false,
+            deterministic: false,
+            exclude_inactive: false,
+            inactive: FxHashSet::default(),
+            flags: FxHashMap::default(),
+            groups: FxHashMap::default(),
+            one_way: FxHashMap::default(),
+            hot_cells: None,
+            active_partitions: None,
+            id_index: None,
+            stamp: next_stamp(),
+            filtered_pairs: 0,
+
+            collisions: Vec::new(),
+            test_results: Vec::new(),
+            processed: FxHashSet::default(),
+            invalid: Vec::new(),
+            cell_results: Vec::new(),
+            pair_arrays: PairArrays::default(),
+
+            #[cfg(feature="parallel")]
+            collisions_tls: ThreadLocal::new()
+        }
+    }
+}
+
+impl<Index, ID> Clone for Layer<Index, ID>
+where
+    Index: SpatialIndex,
+    ID: ObjectID,
+    Bounds<Index::Point>: IndexGenerator<Index>
+{
+    fn clone(&self) -> Self {
+        Layer{
+            min_depth: self.min_depth,
+            tree: self.tree.clone(),
+            unbounded: self.unbounded.clone(),
+            clipped: self.clipped.clone(),
+            truncated: self.truncated.clone(),
+            heavy_objects: self.heavy_objects.clone(),
+            staging: self.staging.clone(),
+
+            out_of_bounds: self.out_of_bounds,
+            max_entries: self.max_entries,
+            target_entries: self.target_entries,
+            max_entries_per_object: self.max_entries_per_object,
+            entry_warning_threshold: self.entry_warning_threshold,
+
+            dense_cell_threshold: self.dense_cell_threshold,
+            bounds: self.bounds.clone(),
+            refined_tests: self.refined_tests,
+            deterministic: self.deterministic,
+            exclude_inactive: self.exclude_inactive,
+            inactive: self.inactive.clone(),
+            flags: self.flags.clone(),
+            groups: self.groups.clone(),
+            one_way: self.one_way.clone(),
+            hot_cells: self.hot_cells.clone(),
+            active_partitions: self.active_partitions.clone(),
+            id_index: self.id_index.clone(),
+            stamp: self.stamp,
+            filtered_pairs: self.filtered_pairs,
+
+            // don't bother cloning the contents of temporary buffers
+            collisions: Vec::with_capacity(self.collisions.capacity()),
+            test_results: Vec::with_capacity(self.test_results.capacity()),
+            processed: FxHashSet::default(),
+            invalid: Vec::new(),
+            cell_results: Vec::new(),
+            pair_arrays: PairArrays::default(),
+
+            #[cfg(feature="parallel")]
+            collisions_tls: ThreadLocal::new()
+        }
+    }
+}
+
+/// [`Layer::builder_config`]: struct.Layer.html#method.builder_config
+/// A builder for `Layer`s
+/// 
+/// With the `serde` feature, a `LayerBuilder` may be serialized (e.g. as part of an engine's settings);
+/// omitted fields take their default values.  With the `bevy_reflect` feature, it implements `Reflect`,
+/// so settings may be edited live in an inspector or saved with a scene.  The configuration of an
+/// existing `Layer` may be recovered with [`Layer::builder_config`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(any(test, feature="serde"), derive(Deserialize, Serialize))]
+#[cfg_attr(any(test, feature="serde"), serde(default))]
+#[cfg_attr(feature="bevy_reflect", derive(Reflect))]
+pub struct LayerBuilder {
+    min_depth: u32,
+    index_capacity: Option<usize>,
+    collision_capacity: Option<usize>,
+    test_capacity: Option<usize>,
+    out_of_bounds: OutOfBoundsPolicy,
+    max_entries: Option<usize>,
+    target_entries: Option<usize>,
+    max_entries_per_object: Option<usize>,
+    entry_warning_threshold: Option<usize>,
+    dense_cell_threshold: Option<usize>,
+    stored_bounds: bool,
+    quantized_bounds: bool,
+    refined_tests: bool,
+    deterministic: bool,
+    exclude_inactive: bool,
+    active_partitions: bool,
+    hot_cell_frames: Option<u32>,
+    id_index: bool
+}
+
+impl LayerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set a minimum depth for index generation.
+    /// 
+    /// This parameter is important for parallel processing.  A higher value improves the partitioning of data and
+    /// improves workload balancing.  However, it can also create many more indices/object than is necessary.  A
+    /// setting which is too high may result in an excessive number of dynamic allocations and duplication of
+    /// intermediate collision pairs, ultimately hurting worst-case performance.
+    /// 
+    /// A value of zero is the safest performance-wise for _single-threaded_ operations.
+    /// 
+    /// When using multi-threaded methods, try a value that between
+    /// _log<sub>4</sub> number_of_processors_ (2D) or
+    /// _log<sub>8</sub> number_of_processors_ (3D) and
+    /// _&minus;log<sub>2</sub>(max_object_size/system_bounds_size)_
+    /// 
+    /// __It is generally better to set this too low than too high__
+    pub fn with_min_depth(&mut self, depth: u32) -> &mut Self {
+        self.min_depth = depth;
+        self
+    }
+
+    /// Set an _initial_ capacity for the index list.
+    pub fn with_index_capacity(&mut self, capacity: usize) -> &mut Self {
+        self.index_capacity = Some(capacity);
+        self
+    }
+
+    /// Set an _initial_ capacity for the collision results list, used by `Layer::scan`.
+    pub fn with_collision_capacity(&mut self, capacity: usize) -> &mut Self {
+        self.collision_capacity = Some(capacity);
+        self
+    }
+
+    /// Set an _initial_ capacity for the test results list, used by `Layer::test` and `Layer::pick`.
+    pub fn with_test_capacity(&mut self, capacity: usize) -> &mut Self {
+        self.test_capacity = Some(capacity);
+        self
+    }
+
+    /// [`OutOfBoundsPolicy`]: enum.OutOfBoundsPolicy.html
+    /// Set how objects which are not contained by the system bounds are handled, see [`OutOfBoundsPolicy`]
+    pub fn with_out_of_bounds_policy(&mut self, policy: OutOfBoundsPolicy) -> &mut Self {
+        self.out_of_bounds = policy;
+        self
+    }
+
+    /// [`Layer::truncated`]: struct.Layer.html#method.truncated
+    /// Limit the total number of entries (index-ID pairs) added by `Layer::extend`
+    /// 
+    /// Once the limit is reached, `extend` adds no further entries, and the affected objects are listed
+    /// by [`Layer::truncated`].  This guards against unbounded memory growth, e.g. from objects with
+    /// mistakenly huge bounds.  `Layer::merge` does not enforce this limit.
+    pub fn with_max_entries(&mut self, max_entries: usize) -> &mut Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    /// Set a soft limit on the total number of entries (index-ID pairs), trading precision for memory
+    /// 
+    /// Once a `Layer` holds `target_entries` (committed and staged), `Layer::extend` and
+    /// `Layer::update_all` index each further object in the smallest cell containing it entirely, typically a single entry rather than up to
+    /// four (2D) or eight (3D), but in a larger cell which will be tested against more objects.
+    /// `min_depth` still applies.  This bounds memory in worst-case scenes (e.g. explosions spawning
+    /// thousands of fragments) without discarding anything, unlike `with_max_entries`.
+    pub fn with_target_entries(&mut self, target_entries: usize) -> &mut Self {
+        self.target_entries = Some(target_entries);
+        self
+    }
+
+    /// [`Layer::truncated`]: struct.Layer.html#method.truncated
+    /// Limit the number of entries added by `Layer::extend` for each set of object bounds
+    /// 
+    /// Objects generating more indices (typically large objects forced deep by `min_depth`) keep only
+    /// the first `max_entries` of them, and are listed by [`Layer::truncated`].
+    pub fn with_max_entries_per_object(&mut self, max_entries: usize) -> &mut Self {
+        self.max_entries_per_object = Some(max_entries);
+        self
+    }
+
+    /// [`Layer::heavy_objects`]: struct.Layer.html#method.heavy_objects
+    /// Report objects producing more than `entries` entries for a single set of bounds
+    /// 
+    /// Such objects are logged as a warning by `Layer::extend`, and listed with their entry counts by
+    /// [`Layer::heavy_objects`] after the next commit.  Unlike `with_max_entries_per_object`, this
+    /// doesn't discard any entries; the two may be combined to both cap and report large objects.
+    pub fn with_entry_warning_threshold(&mut self, entries: usize) -> &mut Self {
+        self.entry_warning_threshold = Some(entries);
+        self
+    }
+
+    /// Set a threshold above which entries sharing a single cell are handled by a simple double-loop
+    /// 
+    /// Dense piles of objects, either clustered at the maximum depth or forced into a single cell by
+    /// `min_depth`, may otherwise cause excessive work in `Layer::scan`.  If bounds are stored (see
+    /// `with_stored_bounds`), pairs within such a cell are also checked for an exact bounding-box overlap.
+    pub fn with_dense_cell_threshold(&mut self, threshold: usize) -> &mut Self {
+        self.dense_cell_threshold = Some(threshold);
+        self
+    }
+
+    /// Store the (local) bounds of each object alongside its indices
+    /// 
+    /// This enables more precise tests at the cost of additional memory.  Stored bounds are not
+    /// serialized.
+    pub fn with_stored_bounds(&mut self) -> &mut Self {
+        self.stored_bounds = true;
+        self
+    }
+
+    /// Store bounds as with `with_stored_bounds`, quantized to 16 bits per axis
+    /// 
+    /// Quantized bounds take 12 bytes per object rather than 16 (2D) or 24 (3D).  Each axis is
+    /// rounded outward to 1/65536th of the system bounds, so refined tests, dense cells, and
+    /// `Layer::test_box_detailed` may accept slightly more than with exact bounds, but never less.
+    /// Quantized bounds support at most three dimensions.
+    pub fn with_quantized_bounds(&mut self) -> &mut Self {
+        self.stored_bounds = true;
+        self.quantized_bounds = true;
+        self
+    }
+
+    /// Check stored bounds exactly in `Layer::test_box` and `Layer::test_ray`
+    /// 
+    /// Objects whose stored bounds don't intersect the test box or ray are removed from the results,
+    /// rather than being returned as candidates because they share a cell with it.  This requires
+    /// `with_stored_bounds`; unbounded objects are always returned.
+    pub fn with_refined_tests(&mut self) -> &mut Self {
+        self.refined_tests = true;
+        self
+    }
+
+    /// Make all observable behavior independent of thread count and hash-table iteration order
+    /// 
+    /// Query results are always sorted, so they're already reproducible given identical inputs; this
+    /// extends that to the order in which callbacks (e.g. `scan_filtered` filters) are invoked:
+    /// 
+    /// * Parallel methods (`par_commit`, `par_scan`, etc.) run serially
+    /// * Unbounded objects are paired in sorted order, rather than hash-set order
+    /// * Entries are sorted with a stable sort
+    /// 
+    /// All hashing uses `rustc_hash`, which is unseeded, and traversal orders are fixed.
+    pub fn with_deterministic(&mut self) -> &mut Self {
+        self.deterministic = true;
+        self
+    }
+
+    /// [`Layer::set_active`]: struct.Layer.html#method.set_active
+    /// Exclude inactive objects (see [`Layer::set_active`]) from the results of tests and picks
+    /// 
+    /// By default, inactive objects are only excluded from scans, and only from pairs in which both
+    /// objects are inactive.
+    pub fn with_inactive_excluded_from_queries(&mut self) -> &mut Self {
+        self.exclude_inactive = true;
+        self
+    }
+
+    /// [`Layer::set_active`]: struct.Layer.html#method.set_active
+    /// Count the entries of active objects (see [`Layer::set_active`]) in each cell at `min_depth`
+    /// 
+    /// Scans then skip cells at `min_depth` which contain only inactive objects, without visiting
+    /// their entries, e.g. for large sleeping regions of a world.  Counts are updated incrementally as
+    /// entries are committed, but are recounted on the next scan after activation flags change.  This
+    /// has no effect when `min_depth` is zero.
+    pub fn with_active_partitions(&mut self) -> &mut Self {
+        self.active_partitions = true;
+        self
+    }
+
+    /// Maintain a sweep-and-prune sublist for cells which remain dense for more than `min_frames` scans
+    /// 
+    /// This reduces the cost of persistent piles of objects (e.g. heaps of debris or stacked crates).  It
+    /// requires both `with_dense_cell_threshold` and `with_stored_bounds`, and applies to `Layer::scan`
+    /// only; `Layer::par_scan` falls back to a simple double-loop for dense cells.
+    pub fn with_hot_cell_sap(&mut self, min_frames: u32) -> &mut Self {
+        self.hot_cell_frames = Some(min_frames);
+        self
+    }
+
+    /// [`Layer::remove`]: struct.Layer.html#method.remove
+    /// [`Layer::drain_object`]: struct.Layer.html#method.drain_object
+    /// [`Layer::collisions_with`]: struct.Layer.html#method.collisions_with
+    /// Maintain a secondary permutation of committed entries sorted by ID
+    /// 
+    /// This allows [`Layer::remove`], [`Layer::drain_object`], and [`Layer::collisions_with`] to find
+    /// an object's entries by binary search, rather than by a scan of every entry, at the cost of one
+    /// `usize` per entry.  The permutation is rebuilt lazily, by the first of these calls after the
+    /// committed contents change, and is kept up to date by removals.
+    pub fn with_id_index(&mut self) -> &mut Self {
+        self.id_index = true;
+        self
+    }
+
+    pub fn build<Index, ID>(&self) -> Layer<Index, ID>
+    where
+        Index: SpatialIndex,
+        ID: ObjectID,
+        Bounds<Index::Point>: IndexGenerator<Index>
+    {
+        Layer{
+            min_depth: self.min_depth,
+            dense_cell_threshold: self.dense_cell_threshold,
+            bounds: if self.stored_bounds {
+                    Some(BoundsTable::new(self.quantized_bounds))
+                } else {
+                    None
+                },
+            refined_tests: self.refined_tests,
+            deterministic: self.deterministic,
+            exclude_inactive: self.exclude_inactive,
+            inactive: FxHashSet::default(),
+            flags: FxHashMap::default(),
+            groups: FxHashMap::default(),
+            one_way: FxHashMap::default(),
+            stamp: next_stamp(),
+            filtered_pairs: 0,
+            active_partitions: if self.active_partitions {
+                    Some(ActivePartitions{counts: FxHashMap::default(), depth: 0, stamp: None})
+                } else {
+                    None
+                },
+            hot_cells: self.hot_cell_frames.map(|min_frames| HotCells{
+                    min_frames,
+                    cells: FxHashMap::default()}),
+            id_index: if self.id_index {
+                    Some(IdIndex::default())
+                } else {
+                    None
+                },
+            unbounded: Vec::new(),
+            clipped: Vec::new(),
+            truncated: Vec::new(),
+            heavy_objects: Vec::new(),
+            staging: Staging::with_capacity(self.index_capacity.unwrap_or(0)),
+            out_of_bounds: self.out_of_bounds,
+            max_entries: self.max_entries,
+            target_entries: self.target_entries,
+            max_entries_per_object: self.max_entries_per_object,
+            entry_warning_threshold: self.entry_warning_threshold,
+            tree: (match self.index_capacity {
+                    Some(capacity) => Vec::with_capacity(capacity),
+                    None => Vec::new()
+                }, true),
+            collisions: match self.collision_capacity {
+                    Some(capacity) => Vec::with_capacity(capacity),
+                    None => Vec::new()
+                },
+            test_results: match self.test_capacity {
+                    Some(capacity) => Vec::with_capacity(capacity),
+                    None => Vec::new()
+                },
+            processed: FxHashSet::default(),
+            invalid: Vec::new(),
+            cell_results: Vec::new(),
+            pair_arrays: PairArrays::default(),
+            #[cfg(feature="parallel")]
+            collisions_tls: ThreadLocal::new()
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index64_2D;
+
+    use cgmath::{Point2, Vector2};
+
+    #[test]
+    fn dense_cell_stored_bounds() {
+        // disjoint horizontal bars sharing a single cell, and one object overlapping all of them
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let object = |y: f32, id: u32| (Bounds{
+            min: Point2::new(0f32, y),
+            max: Point2::new(3f32, y + 0.1f32)}, id);
+        let objects: Vec<_> = (0..8u32).map(|id| object(id as f32 * 0.4f32, id))
+            .chain(std::iter::once((Bounds{
+                min: Point2::new(1f32, 0f32),
+                max: Point2::new(1.5f32, 3.5f32)}, 8)))
+            .collect();
+
+        let mut sparse: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_min_depth(2)
+            .build();
+        sparse.extend(system_bounds, objects.iter().cloned());
+        let mut expected: Vec<_> = sparse.scan().iter().map(|&(l, r)| (l.min(r), l.max(r))).collect();
+        expected.sort();
+        expected.dedup();
+
+        let mut dense: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_min_depth(2)
+            .with_dense_cell_threshold(4)
+            .with_stored_bounds()
+            .build();
+        dense.extend(system_bounds, objects.iter().cloned());
+        let mut pairs: Vec<_> = dense.scan().iter().map(|&(l, r)| (l.min(r), l.max(r))).collect();
+        pairs.sort();
+        pairs.dedup();
+
+        assert_eq!(expected.len(), 36);
+        assert_eq!(pairs, (0..8).map(|id| (id, 8)).collect::<Vec<_>>());
+        assert_eq!(dense.local_bounds(8), Some(system_bounds.to_local(objects[8].0)));
+    }
+
+    #[test]
+    fn unbounded_objects() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, vec![
+            (Bounds{min: Point2::new(1f32, 1f32), max: Point2::new(2f32, 2f32)}, 0),
+            (Bounds{min: Point2::new(9f32, 9f32), max: Point2::new(10f32, 10f32)}, 1)].into_iter());
+        layer.extend_unbounded(vec![8, 9]);
+
+        let mut pairs: Vec<_> = layer.scan().iter().map(|&(l, r)| (l.min(r), l.max(r))).collect();
+        pairs.sort();
+        assert_eq!(pairs, vec![(0, 8), (0, 9), (1, 8), (1, 9), (8, 9)]);
+
+        let results = layer.test_ray(system_bounds, Point2::new(0f32, 15.5f32), Vector2::new(1f32, 0f32), 0f32, 16f32, None);
+        assert_eq!(results, &vec![8, 9]);
+    }
+
+    #[test]
+    fn iter_by_object() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        for id_index in [false, true] {
+            let mut builder = LayerBuilder::new();
+            if id_index {
+                builder.with_id_index();
+            }
+            let mut layer: Layer<Index64_2D, u32> = builder.build();
+            layer.extend(system_bounds, vec![
+                (Bounds{min: Point2::new(9f32, 9f32), max: Point2::new(10f32, 10f32)}, 1),
+                // straddles the center, so it's indexed in all four quadrants
+                (Bounds{min: Point2::new(7.5f32, 7.5f32), max: Point2::new(8.5f32, 8.5f32)}, 0)].into_iter());
+            layer.extend_unbounded(std::iter::once(2));
+            layer.commit();
+
+            let objects: Vec<(u32, Vec<Index64_2D>)> = layer.iter_by_object()
+                .map(|(id, cells)| (id, cells.collect()))
+                .collect();
+            assert_eq!(objects.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![0, 1]);
+            assert_eq!(objects[0].1.len(), 4);
+            assert_eq!(objects[1].1.len(), 1);
+            for (id, cells) in &objects {
+                let mut expected: Vec<Index64_2D> = layer.iter()
+                    .filter(|&&(_, other)| other == *id)
+                    .map(|&(index, _)| index)
+                    .collect();
+                expected.sort();
+                assert_eq!(cells, &expected);
+            }
+        }
+    }
+
+    #[test]
+    fn id_index() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let objects: Vec<_> = (0..64u32)
+            .map(|id| {
+                let min = Point2::new((id * 7 % 15) as f32, (id * 5 % 13) as f32);
+                let size = 0.5f32 + (id % 4) as f32;
+                (Bounds{min, max: min + Vector2::new(size, size)}, id)
+            })
+            .collect();
+        let build = |indexed: bool| {
+            let mut builder = LayerBuilder::new();
+            builder.with_stored_bounds();
+            if indexed {
+                builder.with_id_index();
+            }
+            let mut layer: Layer<Index64_2D, u32> = builder.build();
+            layer.extend(system_bounds, objects.iter().cloned());
+            layer.extend_unbounded(std::iter::once(100));
+            layer
+        };
+        let collisions = |layer: &mut Layer<Index64_2D, u32>, id: u32| {
+            let mut expected: Vec<u32> = layer.scan().iter()
+                .filter_map(|&(a, b)| if a == id { Some(b) } else if b == id { Some(a) } else { None })
+                .collect();
+            expected.sort_unstable();
+            expected.dedup();
+            assert_eq!(layer.collisions_with(id), &expected);
+            expected
+        };
+
+        let mut indexed = build(true);
+        let mut scanned = build(false);
+        for id in [3u32, 17, 42, 100].iter().cloned() {
+            assert_eq!(collisions(&mut indexed, id), collisions(&mut scanned, id));
+        }
+
+        // removals keep the index consistent with the (shifted) entries
+        for id in [5u32, 17, 6, 63, 0].iter().cloned() {
+            let expected: Vec<Index64_2D> = indexed.iter().filter(|&&(_, id_)| id_ == id).map(|&(index, _)| index).collect();
+            assert_eq!(indexed.drain_object(id), expected);
+            assert_eq!(scanned.drain_object(id), expected);
+            assert!(indexed.drain_object(id).is_empty());
+            assert_eq!(indexed.local_bounds(id), None);
+        }
+        assert_eq!(indexed.iter().collect::<Vec<_>>(), scanned.iter().collect::<Vec<_>>());
+        for id in [3u32, 18, 42].iter().cloned() {
+            assert_eq!(collisions(&mut indexed, id), collisions(&mut scanned, id));
+        }
+        assert!(!collisions(&mut indexed, 100).contains(&17));
+
+        assert!(indexed.remove(100));
+        assert!(!indexed.remove(100));
+        assert!(indexed.unbounded().is_empty());
+        assert!(indexed.remove(1));
+        assert!(indexed.validate().is_empty());
+    }
+
+    #[test]
+    fn try_ray() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, vec![
+            (Bounds{min: Point2::new(1f32, 1f32), max: Point2::new(2f32, 2f32)}, 0),
+            (Bounds{min: Point2::new(9f32, 1f32), max: Point2::new(10f32, 2f32)}, 1)].into_iter());
+
+        let origin = Point2::new(0f32, 1.5f32);
+        let expected = layer.test_ray(system_bounds, origin, Vector2::new(1f32, 0f32), 0f32, 16f32, None).clone();
+        assert_eq!(layer.try_test_ray(system_bounds, origin, Vector2::new(1f32, 0f32), 0f32, 16f32, None), Ok(&expected));
+        assert_eq!(
+            layer.try_test_ray(system_bounds, origin, Vector2::new(0f32, 0f32), 0f32, 16f32, None),
+            Err(RayError::ZeroDirection));
+        assert_eq!(
+            layer.try_test_ray(system_bounds, origin, Vector2::new(f32::NAN, 1f32), 0f32, 16f32, None),
+            Err(RayError::NonFiniteDirection));
+        assert_eq!(
+            layer.try_test_ray(system_bounds, Point2::new(f32::INFINITY, 0f32), Vector2::new(1f32, 0f32), 0f32, 16f32, None),
+            Err(RayError::NonFiniteOrigin));
+        assert_eq!(
+            layer.try_test_ray(system_bounds, origin, Vector2::new(1f32, 0f32), 0f32, f32::NAN, None),
+            Err(RayError::NanRange));
+
+        let get_dist = |_: &Point2<f32>, _: &Vector2<f32>, _: f32, id: u32| id as f32 * 8f32 + 1f32;
+        let hit = layer.try_pick_ray(system_bounds, origin, Vector2::new(1f32, 0f32), f32::INFINITY, None, get_dist);
+        assert_eq!(hit.unwrap().map(|(_, id, _)| id), Some(0));
+        let hit = layer.try_pick_ray(system_bounds, origin, Vector2::new(0f32, 0f32), f32::INFINITY, None, get_dist);
+        assert_eq!(hit, Err(RayError::ZeroDirection));
+        assert_eq!(RayError::ZeroDirection.to_string(), "ray direction has zero length");
+    }
+
+    #[test]
+    fn pick_sphere_cast() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let objects = [
+            // beside the path of the sphere's center, but within reach of a larger sphere
+            (Bounds{min: Point2::new(4f32, 9.5f32), max: Point2::new(5f32, 10.5f32)}, 0),
+            (Bounds{min: Point2::new(10f32, 7.5f32), max: Point2::new(11f32, 8.5f32)}, 1),
+            (Bounds{min: Point2::new(2f32, 14f32), max: Point2::new(3f32, 15f32)}, 2)];
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, objects.iter().cloned());
+
+        let origin = Point2::new(0.5f32, 8f32);
+        let direction = Vector2::new(1f32, 0f32);
+        let cast = |layer: &mut Layer<Index64_2D, u32>, radius: f32| {
+            layer.pick_sphere_cast(system_bounds, origin, direction, radius, 16f32, None, |origin, direction, _, id| {
+                let test_geom = SphereCastTestGeometry::with_system_bounds(
+                    system_bounds, *origin, *direction, radius, 0f32, f32::INFINITY);
+                test_geom.entry_distance(objects[id as usize].0).unwrap_or(f32::INFINITY)
+            })
+        };
+
+        let (dist, id, center) = cast(&mut layer, 0.25f32).unwrap();
+        assert_eq!(id, 1);
+        assert!((dist - 9.25f32).abs() < 1e-4);
+        assert!((center - Point2::new(9.75f32, 8f32)).magnitude() < 1e-4);
+
+        let (dist, id, _) = cast(&mut layer, 2f32).unwrap();
+        assert_eq!(id, 0);
+        assert!((dist - 1.5f32).abs() < 1e-4);
+
+        layer.set_one_way(0, Some(Vector2::new(1f32, 0f32)));
+        assert_eq!(cast(&mut layer, 2f32).map(|(_, id, _)| id), Some(1));
+        assert_eq!(cast(&mut layer, 0f32).map(|(_, id, _)| id), Some(1));
+    }
+
+    #[test]
+    fn extend_segments() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let (start, end) = (Point2::new(0.5f32, 0.5f32), Point2::new(15.5f32, 14.5f32));
+        let objects = [
+            // on the segment, and off of it but within its bounding box
+            (Bounds{min: Point2::new(7.5f32, 7f32), max: Point2::new(8f32, 7.5f32)}, 1),
+            (Bounds{min: Point2::new(13f32, 2f32), max: Point2::new(14f32, 3f32)}, 2)];
+
+        let mut boxed: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        boxed.extend(system_bounds, std::iter::once((Bounds{min: start, max: end}, 0)));
+        boxed.extend(system_bounds, objects.iter().cloned());
+        let mut pairs: Vec<_> = boxed.scan().iter().map(|&(l, r)| (l.min(r), l.max(r))).collect();
+        pairs.sort();
+        assert_eq!(pairs, vec![(0, 1), (0, 2)]);
+
+        let mut segments: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_stored_bounds()
+            .build();
+        segments.extend_segments(system_bounds, vec![
+            (start, end, 0),
+            (Point2::new(3f32, 3f32), Point2::new(3f32, 3f32), 3)].into_iter());
+        segments.extend(system_bounds, objects.iter().cloned());
+        let mut pairs: Vec<_> = segments.scan().iter().map(|&(l, r)| (l.min(r), l.max(r))).collect();
+        pairs.sort();
+        pairs.dedup();
+        assert_eq!(pairs, vec![(0, 1), (0, 3)]);
+        assert_eq!(segments.local_bounds(0), Some(system_bounds.to_local(Bounds{min: start, max: end})));
+        assert!(segments.iter().filter(|&&(_, id)| id == 0).count() > 8);
+    }
+
+    #[test]
+    fn cast() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let objects: Vec<_> = (0..8u32)
+            .map(|id| {
+                let min = Point2::new(id as f32 * 2f32 + 0.25f32, id as f32 * 1.5f32);
+                (Bounds{min, max: min + Vector2::new(1f32, 1f32)}, id)
+            })
+            .collect();
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, objects.iter().cloned());
+
+        // a box whose bottom edge passes just over object 3, so the first hit is object 4
+        let start = Bounds{min: Point2::new(0f32, 5.75f32), max: Point2::new(2f32, 6.75f32)};
+        let geom = BoxTestGeometry::with_system_bounds(system_bounds, start);
+        let direction = Vector2::new(1f32, 0f32);
+        let toi = |geom: &BoxTestGeometry<Point2<f32>>, direction: &Vector2<f32>, max_dist: f32, id: u32| {
+            CastTestGeometry::with_system_bounds(system_bounds, geom.shape_bounds(), *direction, 0f32, max_dist)
+                .entry_distance(objects[id as usize].0)
+                .unwrap_or(f32::INFINITY)
+        };
+        let (dist, id) = layer.cast(system_bounds, &geom, direction, 16f32, None, toi).unwrap();
+        assert_eq!(id, 4);
+        assert!((dist - 6.25f32).abs() < 1e-4);
+        assert_eq!(layer.cast(system_bounds, &geom, direction, 6f32, None, toi), None);
+
+        // other geometry is swept by its bounds
+        let geom = SphereTestGeometry::with_system_bounds(system_bounds, Point2::new(1f32, 3.5f32), 0.25f32);
+        let (dist, id) = layer.cast(system_bounds, &geom, direction, 16f32, None, |geom, direction, max_dist, id| {
+                CastTestGeometry::with_system_bounds(system_bounds, geom.shape_bounds(), *direction, 0f32, max_dist)
+                    .entry_distance(objects[id as usize].0)
+                    .unwrap_or(f32::INFINITY)
+            })
+            .unwrap();
+        assert_eq!(id, 2);
+        assert!((dist - 3f32).abs() < 1e-4);
+    }
+
+    #[test]
+    fn clip_out_of_bounds() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let objects = vec![
+            (Bounds{min: Point2::new(1f32, 1f32), max: Point2::new(2f32, 2f32)}, 0),
+            (Bounds{min: Point2::new(-100f32, -1f32), max: Point2::new(100f32, 1.5f32)}, 1),
+            (Bounds{min: Point2::new(20f32, 20f32), max: Point2::new(24f32, 24f32)}, 2)];
+
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, objects.iter().cloned());
+        assert!(layer.scan().is_empty());
+        assert!(layer.clipped().is_empty());
+
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_out_of_bounds_policy(OutOfBoundsPolicy::Clip)
+            .build();
+        layer.extend(system_bounds, objects.into_iter());
+        assert_eq!(layer.scan(), &vec![(0, 1)]);
+        assert_eq!(layer.clipped(), &[1]);
+    }
+
+    #[test]
+    fn analysis() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, (0..6u32).map(|id| (Bounds{
+            min: Point2::new(id as f32 * 0.5f32 + 0.01f32, 0.01f32),
+            max: Point2::new(id as f32 * 0.5f32 + 0.11f32, 0.11f32)}, id)));
+        layer.extend(system_bounds, std::iter::once((Bounds{
+            min: Point2::new(12.01f32, 12.01f32),
+            max: Point2::new(12.11f32, 12.11f32)}, 6)));
+
+        let analysis = layer.analysis(2, 1);
+        assert_eq!(analysis.entries, 7);
+        assert_eq!(analysis.depth_histogram.iter().sum::<usize>(), 7);
+        assert_eq!(analysis.occupied_cells, 2);
+        assert_eq!(analysis.hot_cells, vec![(Index64_2D::default().set_depth(2), 6)]);
+        assert_eq!(analysis.max_object_entries, 1);
+        assert_eq!(analysis.heavy_objects, 0);
+    }
+
+    #[test]
+    fn heavy_objects() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_min_depth(3)
+            .with_entry_warning_threshold(8)
+            .build();
+        layer.extend(system_bounds, vec![
+            (Bounds{min: Point2::new(1f32, 1f32), max: Point2::new(1.5f32, 1.5f32)}, 0),
+            (Bounds{min: Point2::new(0.5f32, 0.5f32), max: Point2::new(7.5f32, 7.5f32)}, 1),
+            (Bounds{min: Point2::new(9f32, 9f32), max: Point2::new(9.5f32, 9.5f32)}, 2)].into_iter());
+        assert!(layer.heavy_objects().is_empty());
+        layer.commit();
+        assert_eq!(layer.heavy_objects(), &[(1, 16)]);
+
+        let analysis = layer.analysis(3, 1);
+        assert_eq!(analysis.max_object_entries, 16);
+        assert_eq!(analysis.heavy_objects, 1);
+
+        // replaced by the next commit which adds entries
+        layer.commit();
+        assert_eq!(layer.heavy_objects(), &[(1, 16)]);
+        layer.extend(system_bounds, std::iter::once((Bounds{min: Point2::new(3f32, 3f32), max: Point2::new(3.5f32, 3.5f32)}, 3)));
+        layer.commit();
+        assert!(layer.heavy_objects().is_empty());
+    }
+
+    #[test]
+    fn ids_in_cell() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, vec![
+            (Bounds{min: Point2::new(1f32, 1f32), max: Point2::new(2f32, 2f32)}, 0),
+            (Bounds{min: Point2::new(5f32, 1f32), max: Point2::new(6f32, 2f32)}, 1),
+            (Bounds{min: Point2::new(1f32, 1f32), max: Point2::new(7f32, 7f32)}, 2),
+            (Bounds{min: Point2::new(9f32, 9f32), max: Point2::new(10f32, 10f32)}, 3)].into_iter());
+
+        let quadrant = Index64_2D::default().set_depth(1);
+        let mut ids: Vec<u32> = layer.ids_in_cell(quadrant).iter().map(|&(_, id)| id).collect();
+        ids.dedup();
+        assert_eq!(ids, vec![2, 0, 1]);
+
+        let cell = quadrant.subdivide().unwrap()[0];
+        let mut ids: Vec<u32> = layer.ids_in_cell(cell).iter().map(|&(_, id)| id).collect();
+        ids.dedup();
+        assert_eq!(ids, vec![0]);
+    }
+
+    #[test]
+    fn ids_in_cells_at_depth() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, vec![
+            (Bounds{min: Point2::new(1.1f32, 1.1f32), max: Point2::new(1.9f32, 1.9f32)}, 0),
+            (Bounds{min: Point2::new(2.1f32, 1.1f32), max: Point2::new(2.9f32, 1.9f32)}, 1),
+            (Bounds{min: Point2::new(5.1f32, 1.1f32), max: Point2::new(5.9f32, 1.9f32)}, 2),
+            (Bounds{min: Point2::new(13.1f32, 13.1f32), max: Point2::new(13.9f32, 13.9f32)}, 3)].into_iter());
+
+        let region = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(7.9f32, 7.9f32)};
+        let cells = layer.ids_in_cells_at_depth(system_bounds, region, 2);
+        let cell = |x: u32| Index64_2D::default().set_depth(2).set_origin(Point2::new(x << 30, 0));
+        assert_eq!(cells, &vec![(cell(0), 0), (cell(0), 1), (cell(1), 2)]);
+    }
+
+    #[test]
+    fn validate() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_min_depth(2)
+            .build();
+        layer.extend(system_bounds, (0..8u32).map(|id| (Bounds{
+            min: Point2::new(id as f32 * 1.5f32, 1f32),
+            max: Point2::new(id as f32 * 1.5f32 + 1f32, 2f32)}, id)));
+        layer.sort();
+        assert_eq!(layer.validate(), vec![]);
+
+        let shallow = Index64_2D::default().set_depth(1);
+        let untruncated = Index64_2D::default().set_depth(2).set_origin(Point2::new(1 << 20, 0));
+        layer.tree.0.push((shallow, 8));
+        layer.tree.0.push((untruncated, 9));
+        assert_eq!(layer.validate(), vec![
+            Violation::Unsorted{position: layer.tree.0.len() - 2},
+            Violation::BelowMinDepth{index: shallow, id: 8},
+            Violation::UntruncatedOrigin{index: untruncated, id: 9}]);
+    }
+
+    #[test]
+    fn hot_cell_sap() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_min_depth(2)
+            .with_dense_cell_threshold(2)
+            .with_stored_bounds()
+            .with_hot_cell_sap(1)
+            .build();
+
+        for frame in 0..4 {
+            // vertical bars sharing a single cell, shuffled between frames
+            let x = |id: u32| ((id * 5 + frame * 3) % 12) as f32 * 0.3f32;
+            let objects: Vec<_> = (0..6u32).map(|id| (Bounds{
+                min: Point2::new(x(id), 0f32),
+                max: Point2::new(x(id) + 0.2f32, 3f32)}, id)).collect();
+
+            let mut expected = Vec::new();
+            for &(lhs_bounds, lhs) in &objects {
+                for &(rhs_bounds, rhs) in &objects[..lhs as usize] {
+                    let lhs_local: Bounds<Point2<u32>> = system_bounds.to_local(lhs_bounds);
+                    if lhs_local.overlaps(system_bounds.to_local(rhs_bounds)) {
+                        expected.push((rhs, lhs));
+                    }
+                }
+            }
+            expected.sort();
+
+            layer.clear();
+            layer.extend(system_bounds, objects.into_iter());
+            let mut pairs: Vec<_> = layer.scan().iter().map(|&(l, r)| (l.min(r), l.max(r))).collect();
+            pairs.sort();
+            assert_eq!(pairs, expected);
+        }
+    }
+
+    #[test]
+    fn staged_changes() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let object_bounds = |x: f32| Bounds{
+            min: Point2::new(x, x),
+            max: Point2::new(x + 1.5f32, x + 1.5f32)};
+        let test_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let pairs = |layer: &Layer<Index64_2D, u32>| {
+            let mut pairs: Vec<_> = layer.scan_committed(|_, _| true).iter().map(|&(l, r)| (l.min(r), l.max(r))).collect();
+            pairs.sort();
+            pairs
+        };
+
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, (0..2u32).map(|id| (object_bounds(id as f32), id)));
+        assert!(!layer.is_committed());
+        assert!(layer.test_committed(&BoxTestGeometry::with_system_bounds(system_bounds, test_bounds), None).is_empty());
+
+        layer.commit();
+        assert!(layer.is_committed());
+        assert_eq!(pairs(&layer), vec![(0, 1)]);
+
+        // the previous frame remains visible until the next commit
+        layer.clear();
+        layer.extend(system_bounds, (4..6u32).map(|id| (object_bounds(id as f32), id)));
+        let mut other: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        other.extend_unbounded(std::iter::once(9));
+        layer.merge(&other);
+        assert_eq!(pairs(&layer), vec![(0, 1)]);
+
+        let test_geom = BoxTestGeometry::with_system_bounds(system_bounds, test_bounds);
+        assert_eq!(layer.test_committed(&test_geom, None), vec![0, 1]);
+        assert_eq!(layer.test(&test_geom, None), &vec![4, 5, 9]);
+        assert_eq!(pairs(&layer), vec![(4, 5), (4, 9), (5, 9)]);
+    }
+
+    #[test]
+    fn owned_results() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, (0..8u32).map(|id| (Bounds{
+            min: Point2::new(id as f32 * 2f32 + 0.1f32, 0.1f32),
+            max: Point2::new(id as f32 * 2f32 + 1.9f32, 1.9f32)}, id)));
+
+        let lhs = layer.test_box_owned(system_bounds, Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(3.5f32, 3.5f32)}, None);
+        let rhs = layer.test_ray_owned(system_bounds, Point2::new(15f32, 1f32), Vector2::new(-1f32, 0f32), 0f32, 5f32, None);
+        assert_eq!(lhs.as_slice(), &[0, 1]);
+        assert_eq!(rhs.as_slice(), &[5, 6, 7]);
+    }
+
+    #[test]
+    fn pair_arrays() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, (0..8u32).map(|id| (Bounds{
+            min: Point2::new(id as f32 * 1.5f32 + 0.1f32, 0.1f32),
+            max: Point2::new(id as f32 * 1.5f32 + 1.9f32, 1.9f32)}, id)));
+
+        let expected = layer.scan().clone();
+        let arrays = layer.scan_arrays();
+        assert_eq!(arrays.len(), expected.len());
+        assert_eq!(arrays.iter().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn entry_limits() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        // a single index each
+        let small = |id: u32| (Bounds{
+            min: Point2::new(id as f32 * 2f32 + 1.25f32, 0.25f32),
+            max: Point2::new(id as f32 * 2f32 + 1.75f32, 0.75f32)}, id);
+        let huge = (Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)}, 99);
+
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_min_depth(3)
+            .with_max_entries_per_object(4)
+            .build();
+        layer.extend(system_bounds, vec![small(0), huge, small(1)].into_iter());
+        layer.commit();
+        assert_eq!(layer.truncated(), &[99]);
+        assert_eq!(layer.iter().filter(|&&(_, id)| id == 99).count(), 4);
+        assert_eq!(layer.iter().len(), 6);
+
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_max_entries(3)
+            .build();
+        layer.extend(system_bounds, (0..2).map(small));
+        layer.extend(system_bounds, (2..5).map(small));
+        layer.commit();
+        assert_eq!(layer.iter().len(), 3);
+        assert_eq!(layer.truncated(), &[3, 4]);
+
+        // the limit applies to the contents after the next commit
+        layer.clear();
+        layer.extend(system_bounds, (5..7).map(small));
+        layer.commit();
+        assert_eq!(layer.iter().len(), 2);
+        assert!(layer.truncated().is_empty());
+    }
+
+    #[test]
+    fn pick_prioritized() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        // objects 0 and 1 coincide, while 2 and 3 only touch
+        let objects = [
+            Bounds{min: Point2::new(2.5f32, 0.5f32), max: Point2::new(3.5f32, 1.5f32)},
+            Bounds{min: Point2::new(2.5f32, 0.5f32), max: Point2::new(3.5f32, 1.5f32)},
+            Bounds{min: Point2::new(6.5f32, 7.0f32), max: Point2::new(7.5f32, 8.0f32)},
+            Bounds{min: Point2::new(6.5f32, 8.0f32), max: Point2::new(7.5f32, 9.0f32)}];
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, objects.iter().cloned().zip(0..));
+
+        let mut pick = |origin: Point2<f32>, priority: &dyn Fn(u32) -> u32| {
+            let direction = Vector2::new(1f32, 0f32);
+            layer.pick_ray_prioritized(system_bounds, origin, direction, 16f32, None, |origin, direction, _, id| {
+                    objects[id as usize].intersect_ray(*origin, *direction).map_or(f32::INFINITY, |(dist, _)| dist)
+                }, priority)
+                .map(|(_, id, _)| id)
+        };
+        assert_eq!(pick(Point2::new(0f32, 1f32), &|id| id), Some(1));
+        assert_eq!(pick(Point2::new(0f32, 1f32), &|id| 10 - id), Some(0));
+        // a ray along the cell boundary, which reaches both objects at the same distance
+        assert_eq!(pick(Point2::new(0f32, 8f32), &|id| id), Some(3));
+        assert_eq!(pick(Point2::new(0f32, 8f32), &|id| 10 - id), Some(2));
+    }
+
+    #[test]
+    fn pick_with_payload() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let objects = [
+            Bounds{min: Point2::new(8.5f32, 0.5f32), max: Point2::new(9.5f32, 1.5f32)},
+            Bounds{min: Point2::new(2.5f32, 0.5f32), max: Point2::new(3.5f32, 1.5f32)},
+            Bounds{min: Point2::new(4.5f32, 4.5f32), max: Point2::new(5.5f32, 5.5f32)}];
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, objects.iter().cloned().zip(0..));
+
+        let picked = layer.pick_ray_with_payload(system_bounds, Point2::new(0f32, 1f32), Vector2::new(1f32, 0f32), 16f32, None,
+            |origin, direction, _, id| objects[id as usize].intersect_ray(*origin, *direction)
+                .map(|(dist, exit)| (dist, exit - dist)));
+        // the payload here is the length of the ray inside the object
+        assert_eq!(picked, Some((2.5f32, 1, Point2::new(2.5f32, 1f32), 1f32)));
+    }
+
+    #[test]
+    fn pick_ties() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let near = Bounds{min: Point2::new(2.5f32, 0.5f32), max: Point2::new(3.5f32, 1.5f32)};
+        let far = Bounds{min: Point2::new(4.5f32, 0.5f32), max: Point2::new(5.5f32, 1.5f32)};
+
+        // the result must not depend on insertion (and thus traversal) order
+        for ids in &[[5u32, 2, 9], [9, 5, 2], [2, 9, 5]] {
+            let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+            layer.extend(system_bounds, std::iter::once((far, 1)));
+            layer.extend(system_bounds, ids.iter().map(|&id| (near, id)));
+            layer.extend_unbounded(std::iter::once(7));
+
+            let picked = layer.pick_ray(system_bounds, Point2::new(0f32, 1f32), Vector2::new(1f32, 0f32), 16f32, None,
+                |origin, direction, _, id| match id {
+                    1 => far.intersect_ray(*origin, *direction).unwrap().0,
+                    // unbounded, but coincident with the nearest objects
+                    7 => 2.5f32,
+                    _ => near.intersect_ray(*origin, *direction).unwrap().0
+                });
+            assert_eq!(picked.map(|(dist, id, _)| (dist, id)), Some((2.5f32, 2)));
+        }
+    }
+
+    #[test]
+    fn pick_all_nearest() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let at = |x: f32| Bounds{min: Point2::new(x, 0.5f32), max: Point2::new(x + 1f32, 1.5f32)};
+        let objects = [at(4.5f32), at(2.5f32), at(2.5f32), at(2.501f32), at(2.6f32), at(12.5f32)];
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, objects.iter().cloned().zip(0..));
+
+        let pick = |layer: &mut Layer<Index64_2D, u32>, max_dist: f32, epsilon: f32| {
+            layer.pick_ray_all_nearest(system_bounds, Point2::new(0f32, 1f32), Vector2::new(1f32, 0f32), max_dist, None, epsilon,
+                    |origin, direction, _, id| objects[id as usize].intersect_ray(*origin, *direction).unwrap().0)
+                .map(|(dist, ids, _)| (dist, ids.clone()))
+        };
+        assert_eq!(pick(&mut layer, 16f32, 0f32), Some((2.5f32, vec![1, 2])));
+        assert_eq!(pick(&mut layer, 16f32, 0.01f32), Some((2.5f32, vec![1, 2, 3])));
+        assert_eq!(pick(&mut layer, 16f32, 0.2f32), Some((2.5f32, vec![1, 2, 3, 4])));
+
+        // near-ties beyond `max_dist` are excluded
+        assert_eq!(pick(&mut layer, 2.55f32, 0.2f32), Some((2.5f32, vec![1, 2, 3])));
+        assert_eq!(pick(&mut layer, 2.4f32, 0.2f32), None);
+
+        layer.clear();
+        assert_eq!(pick(&mut layer, 16f32, 0f32), None);
+    }
+
+    #[test]
+    fn refined_tests() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let objects = [
+            Bounds{min: Point2::new(0.25f32, 0.25f32), max: Point2::new(1.75f32, 0.75f32)},
+            Bounds{min: Point2::new(0.25f32, 1.25f32), max: Point2::new(0.75f32, 1.75f32)},
+            Bounds{min: Point2::new(1.25f32, 1.25f32), max: Point2::new(1.75f32, 1.75f32)}];
+        let build = |builder: &LayerBuilder| {
+            let mut layer: Layer<Index64_2D, u32> = builder.build();
+            layer.extend(system_bounds, objects.iter().cloned().zip(0..));
+            layer.extend_unbounded(std::iter::once(3));
+            layer
+        };
+        let mut coarse = build(LayerBuilder::new().with_stored_bounds());
+        let mut refined = build(LayerBuilder::new().with_stored_bounds().with_refined_tests());
+
+        let test_bounds = Bounds{min: Point2::new(1.125f32, 1.125f32), max: Point2::new(1.5f32, 1.5f32)};
+        assert!(coarse.test_box(system_bounds, test_bounds, None).len() > 2);
+        assert_eq!(refined.test_box(system_bounds, test_bounds, None), &[2, 3]);
+
+        let origin = Point2::new(0f32, 1.5f32);
+        let direction = Vector2::new(1f32, 0f32);
+        assert!(coarse.test_ray(system_bounds, origin, direction, 0f32, 16f32, None).len() > 3);
+        assert_eq!(refined.test_ray(system_bounds, origin, direction, 0f32, 16f32, None), &[1, 2, 3]);
+        assert_eq!(refined.test_ray(system_bounds, origin, direction, 1f32, 16f32, None), &[2, 3]);
+    }
+
+    #[test]
+    fn quantized_bounds() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let objects: Vec<(Bounds<Point2<f32>>, u32)> = (0..256u32)
+            .map(|id| {
+                let x = (id % 16) as f32 + (id % 7) as f32 * 0.0001f32;
+                let y = (id / 16) as f32 + (id % 5) as f32 * 0.0001f32;
+                (Bounds{min: Point2::new(x, y), max: Point2::new(x + 0.0002f32, y + 0.0002f32)}, id)
+            })
+            .collect();
+        let build = |builder: &LayerBuilder| {
+            let mut layer: Layer<Index64_2D, u32> = builder.build();
+            layer.extend(system_bounds, objects.iter().cloned());
+            layer.commit();
+            layer
+        };
+        let mut exact = build(LayerBuilder::new().with_stored_bounds().with_refined_tests());
+        let mut quantized = build(LayerBuilder::new().with_quantized_bounds().with_refined_tests());
+        let config = quantized.builder_config();
+        assert!(config.stored_bounds && config.quantized_bounds);
+        assert!(quantized.memory_usage().objects < exact.memory_usage().objects);
+
+        for &(bounds, id) in &objects {
+            let quantized_bounds = quantized.local_bounds(id).unwrap();
+            assert!(quantized_bounds.contains(exact.local_bounds(id).unwrap()));
+            for test_bounds in [
+                bounds,
+                Bounds{min: bounds.max, max: bounds.max + Vector2::new(0.1f32, 0.1f32)},
+                Bounds{min: bounds.min - Vector2::new(0.1f32, 0.1f32), max: bounds.min}].iter().cloned()
+            {
+                let expected = exact.test_box(system_bounds, test_bounds, None).clone();
+                let results = quantized.test_box(system_bounds, test_bounds, None);
+                assert!(expected.iter().all(|id| results.contains(id)));
+            }
+        }
+    }
+
+    #[test]
+    fn extend_with_max_depth() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let small = Bounds{min: Point2::new(1f32, 1f32), max: Point2::new(1.5f32, 1.5f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().with_min_depth(1).build();
+        layer.extend_with_max_depth(system_bounds, vec![
+            (small, 0, None),
+            (small, 1, Some(2)),
+            (small, 2, Some(0)),
+            (Bounds{min: Point2::new(9f32, 9f32), max: Point2::new(9.5f32, 9.5f32)}, 3, Some(2))].into_iter());
+        layer.sort();
+
+        let depths = |id: u32| -> Vec<u32> {
+            layer.iter().filter(|&&(_, id_)| id_ == id).map(|&(index, _)| index.depth()).collect()
+        };
+        assert!(depths(0).iter().all(|&depth| depth > 2));
+        assert_eq!(depths(1), vec![2]);
+        assert_eq!(depths(2), vec![1]);
+        assert_eq!(depths(3), vec![2]);
+        assert_eq!(layer.scan(), &[(0, 1), (0, 2), (1, 2)]);
+    }
+
+    #[test]
+    fn test_ray_with_depth() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, vec![
+            (Bounds{min: Point2::new(1.1f32, 1.1f32), max: Point2::new(1.2f32, 1.2f32)}, 0),
+            (Bounds{min: Point2::new(4.5f32, 0.5f32), max: Point2::new(7.5f32, 3.5f32)}, 1),
+            (Bounds{min: Point2::new(9.1f32, 9.1f32), max: Point2::new(9.2f32, 9.2f32)}, 2)].into_iter());
+        layer.extend_unbounded(std::iter::once(3));
+
+        let origin = Point2::new(0f32, 1.15f32);
+        let direction = Vector2::new(1f32, 0f32);
+        let results = layer.test_ray_with_depth(system_bounds, origin, direction, 0f32, 16f32, None);
+        let ids: Vec<u32> = results.iter().map(|&(id, _)| id).collect();
+        assert_eq!(&ids, layer.test_ray(system_bounds, origin, direction, 0f32, 16f32, None));
+        assert_eq!(ids, vec![0, 1, 3]);
+        assert!(results[0].1 > results[1].1);
+        assert!(results[1].1 > 0);
+        assert_eq!(results[2].1, 0);
+    }
+
+    #[test]
+    fn test_frustum_classified() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, vec![
+            (Bounds{min: Point2::new(1.1f32, 1.1f32), max: Point2::new(1.2f32, 1.2f32)}, 0),
+            (Bounds{min: Point2::new(6.5f32, 1.1f32), max: Point2::new(8.5f32, 1.2f32)}, 1),
+            (Bounds{min: Point2::new(12.5f32, 1.1f32), max: Point2::new(12.6f32, 1.2f32)}, 2),
+            (Bounds{min: Point2::new(1.1f32, 12.5f32), max: Point2::new(1.2f32, 12.6f32)}, 3)].into_iter());
+        layer.extend_unbounded(std::iter::once(4));
+
+        // the half-plane x <= 8, and y <= 8
+        let planes = [(Vector2::new(-1f32, 0f32), 8f32), (Vector2::new(0f32, -1f32), 8f32)];
+        assert_eq!(layer.test_frustum(system_bounds, &planes, None), &[0, 1, 4]);
+        assert_eq!(layer.test_frustum_classified(system_bounds, &planes, None), vec![
+            (0, Containment::Inside),
+            (1, Containment::Intersecting),
+            (4, Containment::Intersecting)]);
+    }
+
+    #[test]
+    fn test_box_detailed() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let objects = [
+            Bounds{min: Point2::new(1f32, 1f32), max: Point2::new(3f32, 3f32)},
+            Bounds{min: Point2::new(2f32, 4f32), max: Point2::new(6f32, 4f32)},
+            Bounds{min: Point2::new(8f32, 8f32), max: Point2::new(9f32, 9f32)}];
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().with_stored_bounds().build();
+        layer.extend(system_bounds, objects.iter().cloned().zip(0..));
+        layer.extend_unbounded(std::iter::once(3));
+
+        let test_bounds = Bounds{min: Point2::new(2f32, 2f32), max: Point2::new(5f32, 5f32)};
+        let results = layer.test_box_detailed(system_bounds, test_bounds, None);
+        assert_eq!(results.iter().map(|&(id, _)| id).collect::<Vec<_>>(), vec![0, 1]);
+        assert!((results[0].1 - 0.25f32).abs() < 1e-4f32);
+        assert!((results[1].1 - 0.75f32).abs() < 1e-4f32);
+    }
+
+    #[test]
+    fn observer() {
+        #[derive(Default)]
+        struct Counts {
+            cells: usize,
+            entries: usize,
+            subdivisions: usize,
+        }
+
+        impl TraversalObserver<Index64_2D> for Counts {
+            fn on_cell_visited(&mut self, _index: Index64_2D, entry_count: usize) {
+                self.cells += 1;
+                self.entries += entry_count;
+            }
+
+            fn on_subdivide(&mut self, _depth: u32) {
+                self.subdivisions += 1;
+            }
+        }
+
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, (0..16u32).map(|id| {
+            let x = id as f32 + 0.25f32;
+            (Bounds{min: Point2::new(x, x), max: Point2::new(x + 0.5f32, x + 0.5f32)}, id)
+        }));
+
+        let expected = layer.scan().clone();
+        let mut counts = Counts::default();
+        assert_eq!(layer.scan_observed(&mut counts), &expected);
+        assert_eq!(counts.entries, layer.tree.0.len());
+        assert!(counts.cells >= 16 && counts.cells <= counts.entries);
+        assert_eq!(counts.subdivisions, 0);
+
+        let test_geom = BoxTestGeometry::with_system_bounds(
+            system_bounds,
+            Bounds{min: Point2::new(0f32, 0f32), max: Point2::new(3f32, 3f32)});
+        let expected = layer.test(&test_geom, None).clone();
+        let mut counts = Counts::default();
+        assert_eq!(layer.test_observed(&test_geom, None, &mut counts), &expected);
+        assert!(counts.cells > 0 && counts.subdivisions > 0);
+    }
+
+    #[test]
+    fn scan_cancellable() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, (0..64u32).map(|id| {
+            let x = (id % 8) as f32 * 1.5f32 + 0.5f32;
+            let y = (id / 8) as f32 * 1.5f32 + 0.5f32;
+            (Bounds{min: Point2::new(x, y), max: Point2::new(x + 2f32, y + 2f32)}, id)
+        }));
+
+        let expected = layer.scan().clone();
+        let cancel = AtomicBool::new(false);
+        assert_eq!(layer.scan_cancellable(&cancel), Some(&expected));
+        cancel.store(true, Ordering::Relaxed);
+        assert_eq!(layer.scan_cancellable(&cancel), None);
+        assert!(layer.collisions.is_empty());
+
+        #[cfg(feature="parallel")]
+        {
+            assert_eq!(layer.par_scan_cancellable(&cancel), None);
+            cancel.store(false, Ordering::Relaxed);
+            assert_eq!(layer.par_scan_cancellable(&cancel), Some(&expected));
+        }
+    }
+
+    #[test]
+    fn progress() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, (0..1024u32).map(|id| {
+            let x = (id % 32) as f32 * 0.5f32 + 0.125f32;
+            let y = (id / 32) as f32 * 0.5f32 + 0.125f32;
+            (Bounds{min: Point2::new(x, y), max: Point2::new(x + 0.25f32, y + 0.25f32)}, id)
+        }));
+
+        let check = |reported: &[f32]| {
+            assert_eq!(reported.first(), Some(&0f32));
+            assert_eq!(reported.last(), Some(&1f32));
+            assert!(reported.len() > 10);
+            assert!(reported.windows(2).all(|pair| pair[0] < pair[1]));
+        };
+
+        let expected = layer.scan().clone();
+        let mut reported = Vec::new();
+        assert_eq!(layer.scan_with_progress(|fraction| reported.push(fraction)), &expected);
+        check(&reported);
+
+        let entries = layer.iter().count();
+        let mut reported = Vec::new();
+        let frozen = layer.freeze_with_progress(Some(6), |fraction| reported.push(fraction));
+        check(&reported);
+        assert!(reported.contains(&0.5f32));
+        assert_eq!(frozen.iter().count(), entries);
+    }
+
+    #[test]
+    fn deterministic() {
+        use std::sync::Mutex;
+
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let objects: Vec<_> = (0..256u32)
+            .map(|id| {
+                // a simple LCG, so that objects overlap irregularly
+                let hash = id.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let x = (hash % 60) as f32 * 0.25f32;
+                let y = (hash / 60 % 60) as f32 * 0.25f32;
+                let size = (hash / 3600 % 4 + 1) as f32 * 0.25f32;
+                (Bounds{min: Point2::new(x, y), max: Point2::new(x + size, y + size)}, id)
+            })
+            .collect();
+
+        // returns all observable outputs, including the order in which the filter is called
+        let run = |parallel: bool| {
+            let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+                .with_deterministic()
+                .build();
+            layer.extend(system_bounds, objects.iter().cloned());
+            layer.extend_unbounded([1000, 1001]);
+
+            let calls = Mutex::new(Vec::new());
+            let filter = |a: u32, b: u32| {
+                calls.lock().unwrap().push((a, b));
+                (a ^ b) & 1 == 0
+            };
+            let pairs = if parallel {
+                #[cfg(feature="parallel")]
+                {
+                    layer.par_scan_filtered(filter).clone()
+                }
+                #[cfg(not(feature="parallel"))]
+                {
+                    layer.scan_filtered(filter).clone()
+                }
+            } else {
+                layer.scan_filtered(filter).clone()
+            };
+            let test_bounds = Bounds{min: Point2::new(4f32, 4f32), max: Point2::new(9f32, 7f32)};
+            let tested = layer.test_box(system_bounds, test_bounds, None).clone();
+            let picked = layer.pick_ray(system_bounds, Point2::new(0f32, 5f32), Vector2::new(1f32, 0f32), 16f32, None,
+                |origin, direction, _, id| objects.get(id as usize)
+                    .and_then(|(bounds, _)| bounds.intersect_ray(*origin, *direction))
+                    .map_or(f32::INFINITY, |(dist, _)| dist));
+            (pairs, calls.into_inner().unwrap(), tested, picked)
+        };
+
+        let expected = run(false);
+        assert!(!expected.0.is_empty());
+        assert!(expected.3.is_some());
+        assert_eq!(run(false), expected);
+        assert_eq!(run(true), expected);
+    }
+
+    #[test]
+    fn scan_capped() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, (0..8u32).map(|id| (Bounds{
+            min: Point2::new(id as f32 * 1.5f32 + 0.1f32, 0.1f32),
+            max: Point2::new(id as f32 * 1.5f32 + 1.9f32, 1.9f32)}, id)));
+
+        let all = layer.scan().clone();
+        assert!(all.len() > 3);
+        let priority = |a: u32, b: u32| a.max(b);
+        let mut expected = all.clone();
+        expected.sort_by_key(|&(a, b)| (Reverse(priority(a, b)), a, b));
+        expected.truncate(3);
+        expected.sort();
+        assert_eq!(layer.scan_capped(3, priority), &expected);
+        assert_eq!(layer.scan_capped(all.len(), priority), &all);
+        assert!(layer.scan_capped(0, priority).is_empty());
+    }
+
+    #[test]
+    fn scan_capped_per_object() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let pile = Bounds{min: Point2::new(1.1f32, 1.1f32), max: Point2::new(1.2f32, 1.2f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().with_dense_cell_threshold(8).build();
+        layer.extend(system_bounds, (0..64u32).map(|id| (pile, id)));
+        layer.extend(system_bounds, vec![
+            (Bounds{min: Point2::new(9.1f32, 9.1f32), max: Point2::new(9.2f32, 9.2f32)}, 100),
+            (Bounds{min: Point2::new(9.1f32, 9.1f32), max: Point2::new(9.2f32, 9.2f32)}, 101)].into_iter());
+
+        assert_eq!(layer.scan().len(), 64 * 63 / 2 + 1);
+        let capped = layer.scan_capped_per_object(4).clone();
+        for id in (0..64).chain(100..102) {
+            let count = capped.iter().filter(|&&(a, b)| a == id || b == id).count();
+            assert!(count <= 4);
+        }
+        assert!(capped.len() >= 64 * 4 / 2 / 2);
+        assert!(capped.iter().any(|&(a, b)| a.min(b) == 100 && a.max(b) == 101));
+        assert!(layer.scan_capped_per_object(0).is_empty());
+
+        // pairs within a dense cell are preferred over those with an ancestor, as for sparse cells
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().with_dense_cell_threshold(2).build();
+        layer.extend(system_bounds, std::iter::once((
+            Bounds{min: Point2::new(0.5f32, 0.5f32), max: Point2::new(3.5f32, 3.5f32)}, 0)));
+        layer.extend(system_bounds, (1..4u32).map(|id| (pile, id)));
+        assert_eq!(layer.scan().len(), 6);
+        let mut capped: Vec<_> = layer.scan_capped_per_object(1).iter().map(|&(a, b)| (a.min(b), a.max(b))).collect();
+        capped.sort();
+        assert_eq!(capped, vec![(0, 3), (1, 2)]);
+    }
+
+    #[test]
+    fn builder_config() {
+        let mut builder = LayerBuilder::new();
+        builder
+            .with_min_depth(2)
+            .with_index_capacity(64)
+            .with_out_of_bounds_policy(OutOfBoundsPolicy::Clip)
+            .with_max_entries_per_object(16)
+            .with_dense_cell_threshold(8)
+            .with_stored_bounds()
+            .with_hot_cell_sap(4);
+        let layer: Layer<Index64_2D, u32> = builder.build();
+        let config = layer.builder_config();
+        assert_eq!(config.min_depth, 2);
+        assert!(config.index_capacity.unwrap() >= 64);
+        assert_eq!(LayerBuilder{index_capacity: Some(64), ..config.clone()}, builder);
+        assert_eq!(config.build::<Index64_2D, u32>().builder_config(), config);
+
+        let bytes = bincode::serialize(&builder).unwrap();
+        assert_eq!(bincode::deserialize::<LayerBuilder>(&bytes).unwrap(), builder);
+    }
+
+    #[cfg(feature="bevy_reflect")]
+    #[test]
+    fn builder_reflect() {
+        use bevy_reflect::{GetField, Struct};
+
+        let mut builder = LayerBuilder::new();
+        *builder.get_field_mut::<u32>("min_depth").unwrap() = 3;
+        builder.field_mut("out_of_bounds").unwrap().apply(&OutOfBoundsPolicy::Clip);
+        assert_eq!(builder.get_field::<bool>("stored_bounds"), Some(&false));
+
+        let mut layer: Layer<Index64_2D, u32> = builder.build();
+        assert_eq!(layer.builder_config().min_depth, 3);
+        assert_eq!(layer.builder_config().out_of_bounds, OutOfBoundsPolicy::Clip);
+
+        let analysis = layer.analysis(4, 8);
+        assert_eq!(analysis.get_field::<usize>("entries"), Some(&0));
+    }
+
+    #[test]
+    fn memory_usage() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        assert_eq!(layer.memory_usage().total(), 0);
+
+        layer.extend(system_bounds, (0..8u32).map(|id| (Bounds{
+            min: Point2::new(id as f32 * 1.5f32 + 0.1f32, 0.1f32),
+            max: Point2::new(id as f32 * 1.5f32 + 1.9f32, 1.9f32)}, id)));
+        let staged = layer.memory_usage();
+        assert!(staged.staging > 0);
+        assert_eq!(staged.tree, 0);
+
+        layer.scan();
+        let report = layer.memory_usage();
+        assert!(report.tree >= layer.iter().len() * std::mem::size_of::<(Index64_2D, u32)>());
+        assert!(report.pair_cache > 0);
+        assert_eq!(report.total(), report.tree + report.staging + report.objects + report.hot_cells
+            + report.scratch + report.thread_local + report.pair_cache);
+    }
+
+    #[test]
+    fn activation() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let objects = [
+            (Bounds{min: Point2::new(1f32, 1f32), max: Point2::new(3f32, 3f32)}, 0u32),
+            (Bounds{min: Point2::new(2f32, 2f32), max: Point2::new(4f32, 4f32)}, 1u32),
+            (Bounds{min: Point2::new(2.5f32, 2.5f32), max: Point2::new(3.5f32, 3.5f32)}, 2u32)];
+        let test_bounds = Bounds{min: Point2::new(0f32, 0f32), max: Point2::new(8f32, 8f32)};
+        let sorted = |pairs: &Vec<(u32, u32)>| {
+            let mut pairs: Vec<_> = pairs.iter().map(|&(a, b)| (a.min(b), a.max(b))).collect();
+            pairs.sort();
+            pairs
+        };
+
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, objects.iter().cloned());
+        assert_eq!(sorted(layer.scan()), vec![(0, 1), (0, 2), (1, 2)]);
+
+        layer.set_active(0, false);
+        layer.set_active(1, false);
+        assert!(!layer.is_active(0));
+        assert!(layer.is_active(2));
+        assert_eq!(sorted(layer.scan()), vec![(0, 2), (1, 2)]);
+        assert_eq!(sorted(&layer.scan_committed(|_, _| true)), vec![(0, 2), (1, 2)]);
+        #[cfg(feature="parallel")]
+        assert_eq!(sorted(layer.par_scan()), vec![(0, 2), (1, 2)]);
+        assert_eq!(layer.test_box(system_bounds, test_bounds, None), &vec![0, 1, 2]);
+
+        // flags are retained by `clear`
+        layer.clear();
+        layer.extend(system_bounds, objects.iter().cloned());
+        assert_eq!(sorted(layer.scan()), vec![(0, 2), (1, 2)]);
+
+        layer.set_active(1, true);
+        assert_eq!(sorted(layer.scan()), vec![(0, 1), (0, 2), (1, 2)]);
+
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_inactive_excluded_from_queries()
+            .build();
+        layer.extend(system_bounds, objects.iter().cloned());
+        layer.extend_unbounded(std::iter::once(1000));
+        layer.set_active(1, false);
+        layer.set_active(1000, false);
+        assert_eq!(layer.test_box(system_bounds, test_bounds, None), &vec![0, 2]);
+        layer.activate_all();
+        assert_eq!(layer.test_box(system_bounds, test_bounds, None), &vec![0, 1, 2, 1000]);
+    }
+
+    #[test]
+    fn activate_in_box() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_stored_bounds()
+            .with_refined_tests()
+            .build();
+        layer.extend(system_bounds, (0..8u32).map(|id| {
+            let min = Point2::new(id as f32 * 2f32 + 0.25f32, 0.25f32);
+            (Bounds{min, max: min + Vector2::new(1.5f32, 1.5f32)}, id)
+        }));
+        layer.extend_unbounded(std::iter::once(1000));
+
+        let region = Bounds{min: Point2::new(3f32, 0f32), max: Point2::new(7f32, 2f32)};
+        layer.deactivate_outside(system_bounds, region);
+        let active: Vec<u32> = (0..8u32).chain(std::iter::once(1000))
+            .filter(|&id| layer.is_active(id))
+            .collect();
+        assert_eq!(active, vec![1, 2, 3, 1000]);
+
+        let region = Bounds{min: Point2::new(12.5f32, 0f32), max: Point2::new(13f32, 1f32)};
+        layer.activate_in_box(system_bounds, region);
+        let active: Vec<u32> = (0..8u32)
+            .filter(|&id| layer.is_active(id))
+            .collect();
+        assert_eq!(active, vec![1, 2, 3, 6]);
+    }
+
+    #[test]
+    fn sample_in_box() {
+        use rand::prelude::*;
+
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, (0..64u32).map(|id| {
+            let min = Point2::new((id % 8) as f32 * 2f32 + 0.25f32, (id / 8) as f32 * 2f32 + 0.25f32);
+            (Bounds{min, max: min + Vector2::new(1.5f32, 1.5f32)}, id)
+        }));
+        let region = Bounds{min: Point2::new(0f32, 0f32), max: Point2::new(7.9f32, 7.9f32)};
+        let candidates = layer.test_box(system_bounds, region, None).clone();
+        assert_eq!(candidates.len(), 16);
+
+        let mut prng = rand_chacha::ChaChaRng::seed_from_u64(0);
+        assert_eq!(layer.sample_in_box(system_bounds, region, 0, |n| prng.gen_range(0, n)), vec![]);
+        let mut all = layer.sample_in_box(system_bounds, region, 100, |n| prng.gen_range(0, n));
+        all.sort();
+        assert_eq!(all, candidates);
+
+        let mut counts = FxHashMap::default();
+        for _ in 0..1000 {
+            let sample = layer.sample_in_box(system_bounds, region, 4, |n| prng.gen_range(0, n));
+            assert_eq!(sample.len(), 4);
+            for id in sample {
+                assert!(candidates.contains(&id));
+                *counts.entry(id).or_insert(0) += 1;
+            }
+        }
+        // each candidate is expected 250 times
+        assert_eq!(counts.len(), 16);
+        assert!(counts.values().all(|&count| count > 150 && count < 350));
+    }
+
+    #[test]
+    fn update_all() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let object = |id: u32, x: f32| (Bounds{min: Point2::new(x, 1.25f32), max: Point2::new(x + 1.5f32, 2.75f32)}, id);
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_stored_bounds()
+            .build();
+        layer.extend(system_bounds, vec![object(0, 1.25f32), object(1, 5.25f32)].into_iter());
+        assert!(layer.scan().is_empty());
+
+        // still within the same cells
+        let (bounds, id) = object(0, 1.5f32);
+        assert_eq!(layer.update_all(system_bounds, std::iter::once((id, bounds))), 0);
+        assert_eq!(layer.local_bounds(0), Some(system_bounds.to_local(bounds)));
+
+        // crossing cell boundaries, and adding a new object
+        let updated = [object(0, 4.5f32), object(2, 9.25f32)];
+        assert_eq!(layer.update_all(system_bounds, updated.iter().map(|&(bounds, id)| (id, bounds))), 2);
+        assert_eq!(layer.scan(), &vec![(1, 0)]);
+
+        let mut expected: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        expected.extend(system_bounds, vec![object(0, 4.5f32), object(1, 5.25f32), object(2, 9.25f32)].into_iter());
+        expected.commit();
+        assert_eq!(layer.iter().collect::<Vec<_>>(), expected.iter().collect::<Vec<_>>());
+
+        // out of bounds objects are removed
+        let (bounds, id) = object(2, 20f32);
+        assert_eq!(layer.update_all(system_bounds, std::iter::once((id, bounds))), 1);
+        assert!(layer.iter().all(|&(_, id)| id != 2));
+        assert_eq!(layer.local_bounds(2), None);
+
+        // entry limits apply to the updated contents, as in `extend`
+        let small = |id: u32| (id, Bounds{
+            min: Point2::new(id as f32 * 4f32 + 1.25f32, 0.25f32),
+            max: Point2::new(id as f32 * 4f32 + 1.75f32, 0.75f32)});
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_stored_bounds()
+            .with_max_entries(3)
+            .build();
+        layer.extend(system_bounds, (0..3).map(small).map(|(id, bounds)| (bounds, id)));
+        layer.commit();
+        assert_eq!(layer.iter().len(), 3);
+
+        // straddling a cell boundary, so two entries are needed where only one remains
+        let straddling = Bounds{min: Point2::new(1.75f32, 0.25f32), max: Point2::new(2.25f32, 0.75f32)};
+        assert_eq!(layer.update_all(system_bounds, vec![(0, straddling), small(3)].into_iter()), 2);
+        assert_eq!(layer.iter().len(), 3);
+        assert_eq!(layer.truncated(), &[0, 3]);
+    }
+
+    #[test]
+    fn extend_predicted() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_stored_bounds()
+            .build();
+        let objects = vec![
+            (Bounds{min: Point2::new(1.25f32, 1.25f32), max: Point2::new(2.75f32, 2.75f32)}, Vector2::new(4f32, 0f32), 0u32),
+            (Bounds{min: Point2::new(5.25f32, 1.25f32), max: Point2::new(6.75f32, 2.75f32)}, Vector2::new(0f32, 0f32), 1u32),
+            (Bounds{min: Point2::new(13.25f32, 1.25f32), max: Point2::new(14.75f32, 2.75f32)}, Vector2::new(8f32, -8f32), 2u32)];
+        layer.extend_predicted(system_bounds, objects.into_iter(), 0.5f32);
+
+        assert_eq!(layer.scan(), &vec![(1, 0)]);
+        assert!(layer.invalid.is_empty());
+        assert!(layer.clipped().is_empty());
+        let swept: Bounds<Point2<f32>> = system_bounds.to_global(layer.local_bounds(2).unwrap());
+        assert_eq!(swept.min, Point2::new(13.25f32, 0f32));
+        assert!(swept.max.x > 15.99f32);
+    }
+
+    #[test]
+    fn filtered_pairs() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, (0..256u32).map(|id| {
+            let min = Point2::new((id % 16) as f32 * 0.9f32 + 0.1f32, (id / 16) as f32 * 0.9f32 + 0.1f32);
+            (Bounds{min, max: min + Vector2::new(1f32, 1f32)}, id)
+        }));
+
+        layer.scan();
+        assert_eq!(layer.filtered_pairs(), 0);
+
+        // e.g. only even objects collide with odd ones
+        let mask = |a: u32, b: u32| (a ^ b) & 1 == 1;
+        let pairs = layer.scan_filtered(mask).len();
+        let filtered = layer.filtered_pairs();
+        assert!(pairs > 0);
+        assert!(filtered > 0);
+
+        #[cfg(feature="parallel")]
+        {
+            assert_eq!(layer.par_scan_filtered(mask).len(), pairs);
+            assert_eq!(layer.filtered_pairs(), filtered);
+        }
+    }
+
+    #[test]
+    fn extend_sorted_hint() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        // each object lies within a single cell, so objects may be ordered by that cell
+        let mut objects: Vec<_> = (0..64u32)
+            .map(|id| {
+                let min = Point2::new((id % 8) as f32 * 2f32 + 0.25f32, (id / 8) as f32 * 2f32 + 0.25f32);
+                (Bounds{min, max: min + Vector2::new(0.5f32, 0.5f32)}, id)
+            })
+            .collect();
+        objects.reverse();
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.sort_objects_by_cell(system_bounds, &mut objects);
+        layer.extend_sorted_hint(system_bounds, objects[..32].iter().cloned());
+        layer.commit();
+        layer.extend_sorted_hint(system_bounds, objects[32..].iter().cloned());
+        layer.apply_staging();
+        assert!(layer.tree.1);
+        assert!(layer.validate().is_empty());
+
+        let mut expected: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        expected.extend(system_bounds, objects.iter().cloned());
+        expected.commit();
+        assert_eq!(layer.iter().collect::<Vec<_>>(), expected.iter().collect::<Vec<_>>());
+
+        // entries preceding committed entries still require a sort
+        layer.extend_sorted_hint(system_bounds, objects[..1].iter().cloned());
+        layer.apply_staging();
+        assert!(!layer.tree.1);
+    }
+
+    #[test]
+    fn scan_involving() {
+        use rand::prelude::*;
+
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut prng = rand_chacha::ChaChaRng::seed_from_u64(0);
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, (0..512u32).map(|id| {
+            let min = Point2::new(prng.gen_range(0f32, 15f32), prng.gen_range(0f32, 15f32));
+            let size = Vector2::new(prng.gen_range(0f32, 1f32), prng.gen_range(0f32, 1f32));
+            (Bounds{min, max: min + size}, id)
+        }));
+        layer.extend_unbounded(std::iter::once(1000));
+
+        let ids: FxHashSet<u32> = (0..512u32).step_by(37).collect();
+        let expected = layer.scan_filtered(|a, b| ids.contains(&a) || ids.contains(&b)).clone();
+        assert!(expected.len() > ids.len());
+        assert_eq!(layer.scan_involving(&ids), &expected);
+
+        let ids: FxHashSet<u32> = std::iter::once(1000).collect();
+        assert_eq!(layer.scan_involving(&ids).len(), 512);
+        assert!(layer.scan_involving(&FxHashSet::default()).is_empty());
+    }
+
+    #[test]
+    fn active_partitions() {
+        use rand::prelude::*;
+
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut prng = rand_chacha::ChaChaRng::seed_from_u64(0);
+        let objects: Vec<_> = (0..512u32)
+            .map(|id| {
+                let min = Point2::new(prng.gen_range(0f32, 15f32), prng.gen_range(0f32, 15f32));
+                let size = Vector2::new(prng.gen_range(0f32, 1f32), prng.gen_range(0f32, 1f32));
+                (Bounds{min, max: min + size}, id)
+            })
+            .collect();
+        let run = |layer: &mut Layer<Index64_2D, u32>| {
+            let pairs = layer.scan().clone();
+            #[cfg(feature="parallel")]
+            assert_eq!(layer.par_scan(), &pairs);
+            let ids: FxHashSet<u32> = (0..512u32).step_by(7).collect();
+            (pairs, layer.scan_involving(&ids).clone())
+        };
+
+        let mut expected: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_min_depth(2)
+            .with_stored_bounds()
+            .build();
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_min_depth(2)
+            .with_stored_bounds()
+            .with_active_partitions()
+            .build();
+        for layer in [&mut expected, &mut layer].iter_mut() {
+            layer.extend(system_bounds, objects[..256].iter().cloned());
+            layer.deactivate_outside(system_bounds, Bounds{min: Point2::new(0f32, 0f32), max: Point2::new(4f32, 16f32)});
+        }
+        assert_eq!(run(&mut layer), run(&mut expected));
+        // with most cells skipped, the filter isn't called for their pairs
+        assert!(layer.filtered_pairs() < expected.filtered_pairs());
+
+        // counts are updated incrementally, or recounted after activation changes
+        let recounted = |layer: &mut Layer<Index64_2D, u32>| {
+            let counts = layer.active_partitions.as_ref().unwrap().counts.clone();
+            layer.active_partitions.as_mut().unwrap().stamp = None;
+            layer.update_active_partitions();
+            counts == layer.active_partitions.as_ref().unwrap().counts
+        };
+        for layer in [&mut expected, &mut layer].iter_mut() {
+            layer.extend(system_bounds, objects[256..].iter().cloned());
+            layer.set_active(300, false);
+        }
+        assert_eq!(run(&mut layer), run(&mut expected));
+        let moved: Vec<_> = objects[..64].iter().map(|&(bounds, id)| (id, Bounds{
+            min: bounds.min + Vector2::new(0.5f32, 0f32),
+            max: bounds.max + Vector2::new(0.5f32, 0f32)}))
+            .collect();
+        for layer in [&mut expected, &mut layer].iter_mut() {
+            layer.update_all(system_bounds, moved.iter().cloned());
+        }
+        assert_eq!(layer.active_partitions.as_ref().unwrap().stamp, Some(layer.stamp));
+        assert!(recounted(&mut layer));
+        assert_eq!(run(&mut layer), run(&mut expected));
+
+        for layer in [&mut expected, &mut layer].iter_mut() {
+            layer.clear();
+            layer.extend(system_bounds, objects.iter().cloned());
+            layer.commit();
+        }
+        assert_eq!(layer.active_partitions.as_ref().unwrap().stamp, Some(layer.stamp));
+        assert!(recounted(&mut layer));
+        assert_eq!(run(&mut layer), run(&mut expected));
+    }
+
+    #[test]
+    fn transformed_queries() {
+        use cgmath::{Basis2, Decomposed, Rad};
+
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_stored_bounds()
+            .with_refined_tests()
+            .build();
+        let objects: Vec<_> = (0..8u32)
+            .map(|id| {
+                let min = Point2::new(id as f32 * 2f32 + 0.25f32, 0.25f32);
+                (Bounds{min, max: min + Vector2::new(1.5f32, 1.5f32)}, id)
+            })
+            .collect();
+        layer.extend(system_bounds, objects.iter().cloned());
+
+        // an instance rotated a quarter turn and moved, so model +x is world +y
+        let transform = Decomposed{
+            scale: 1f32,
+            rot: Basis2::from_angle(Rad(std::f32::consts::FRAC_PI_2)),
+            disp: Vector2::new(100f32, 100f32)};
+        let world_bounds = Bounds{min: Point2::new(98.5f32, 104.5f32), max: Point2::new(99.5f32, 107.5f32)};
+        assert_eq!(layer.test_box_transformed(system_bounds, world_bounds, &transform, None), &vec![2, 3]);
+
+        let origin = Point2::new(99f32, 90f32);
+        let direction = Vector2::new(0f32, 1f32);
+        let hit = layer.pick_ray_transformed(system_bounds, origin, direction, &transform, 100f32, None,
+            |origin, direction, _, id| {
+                let (bounds, _) = objects[id as usize];
+                bounds.intersect_ray(*origin, *direction).map_or(f32::INFINITY, |(min, _)| min)
+            });
+        let (dist, id, point) = hit.unwrap();
+        assert_eq!(id, 0);
+        assert!((dist - 10.25f32).abs() < 1e-4);
+        assert!((point - Point2::new(99f32, 100.25f32)).magnitude() < 1e-4);
+    }
+
+    #[test]
+    fn test_sphere() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_stored_bounds()
+            .with_refined_tests()
+            .build();
+        let objects: Vec<_> = (0..256u32)
+            .map(|id| {
+                let min = Point2::new((id % 16) as f32 + 0.1f32, (id / 16) as f32 + 0.1f32);
+                (Bounds{min, max: min + Vector2::new(0.8f32, 0.8f32)}, id)
+            })
+            .collect();
+        layer.extend(system_bounds, objects.iter().cloned());
+
+        let center = Point2::new(8f32, 8f32);
+        let radius = 2.5f32;
+        let expected: Vec<u32> = objects.iter()
+            .filter(|(bounds, _)| {
+                let nearest = Point2::new(
+                    center.x.max(bounds.min.x).min(bounds.max.x),
+                    center.y.max(bounds.min.y).min(bounds.max.y));
+                (nearest - center).magnitude() <= radius
+            })
+            .map(|&(_, id)| id)
+            .collect();
+        assert_eq!(layer.test_sphere(system_bounds, center, radius, None), &expected);
+
+        // the corners of the enclosing box are excluded
+        let corner = 5 * 16 + 5;
+        assert!(!expected.contains(&corner));
+        let enclosing = Bounds{min: center - Vector2::new(radius, radius), max: center + Vector2::new(radius, radius)};
+        assert!(layer.test_box(system_bounds, enclosing, None).contains(&corner));
+    }
+
+    #[test]
+    fn exclusion_groups() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, (0..4u32).map(|id| {
+            let min = Point2::new(1f32 + id as f32 * 0.25f32, 1f32);
+            (Bounds{min, max: min + Vector2::new(2f32, 2f32)}, id)
+        }));
+
+        // a ragdoll of three parts, and an unrelated object
+        layer.set_group(0, Some(7));
+        layer.set_group(1, Some(7));
+        layer.set_group(2, Some(7));
+        assert_eq!(layer.group(1), Some(7));
+        assert_eq!(layer.group(3), None);
+
+        let mut pairs: Vec<_> = layer.scan().iter().map(|&(a, b)| (a.min(b), a.max(b))).collect();
+        pairs.sort_unstable();
+        assert_eq!(pairs, vec![(0, 3), (1, 3), (2, 3)]);
+        assert!(layer.filtered_pairs() >= 3);
+
+        let mut pairs: Vec<_> = layer.scan_committed(|_, _| true).iter().map(|&(a, b)| (a.min(b), a.max(b))).collect();
+        pairs.sort_unstable();
+        assert_eq!(pairs, vec![(0, 3), (1, 3), (2, 3)]);
+
+        layer.set_group(2, None);
+        assert_eq!(layer.scan().len(), 5);
+        layer.clear_groups();
+        assert_eq!(layer.scan().len(), 6);
+    }
+
+    #[test]
+    fn pick_ray_with_flags() {
+        const CLICKABLE: u32 = 1;
+        const HIDDEN: u32 = 2;
+
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        let objects: Vec<_> = (0..8u32)
+            .map(|id| {
+                let min = Point2::new(id as f32 * 2f32 + 0.25f32, 0.25f32);
+                (Bounds{min, max: min + Vector2::new(1.5f32, 1.5f32)}, id)
+            })
+            .collect();
+        layer.extend(system_bounds, objects.iter().cloned());
+        layer.set_flags(3, CLICKABLE | HIDDEN);
+        layer.set_flags(5, CLICKABLE);
+        layer.set_flags(6, CLICKABLE);
+        assert_eq!(layer.flags(5), CLICKABLE);
+        assert_eq!(layer.flags(0), 0);
+
+        let mut calls: Vec<u32> = Vec::new();
+        let hit = layer.pick_ray_with_flags(system_bounds, Point2::new(0f32, 1f32), Vector2::new(1f32, 0f32),
+            100f32, None, CLICKABLE, HIDDEN, |origin, direction, _, id| {
+                calls.push(id);
+                let (bounds, _) = objects[id as usize];
+                bounds.intersect_ray(*origin, *direction).map_or(f32::INFINITY, |(min, _)| min)
+            });
+        assert_eq!(hit.map(|(_, id, _)| id), Some(5));
+        assert!(calls.iter().all(|&id| id == 5 || id == 6));
+
+        layer.set_flags(3, CLICKABLE);
+        let hit = layer.pick_ray_with_flags(system_bounds, Point2::new(0f32, 1f32), Vector2::new(1f32, 0f32),
+            100f32, None, CLICKABLE, HIDDEN, |origin, direction, _, id| {
+                let (bounds, _) = objects[id as usize];
+                bounds.intersect_ray(*origin, *direction).map_or(f32::INFINITY, |(min, _)| min)
+            });
+        assert_eq!(hit.map(|(_, id, _)| id), Some(3));
+    }
+
+    #[test]
+    fn pick_ray_interpolated() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let before = [
+            (Bounds{min: Point2::new(2f32, 0f32), max: Point2::new(3f32, 1f32)}, 0),
+            (Bounds{min: Point2::new(10f32, 0f32), max: Point2::new(11f32, 1f32)}, 1)];
+        let after = [
+            (Bounds{min: Point2::new(6f32, 0f32), max: Point2::new(7f32, 1f32)}, 0),
+            (Bounds{min: Point2::new(10f32, 0f32), max: Point2::new(11f32, 1f32)}, 1)];
+        let mut previous: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        previous.extend(system_bounds, before.iter().cloned());
+        let mut current: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        current.extend(system_bounds, after.iter().cloned());
+
+        for &(alpha, expected) in &[(0f32, 2f32), (0.25f32, 3f32), (0.5f32, 4f32), (1f32, 6f32)] {
+            let mut calls: Vec<u32> = Vec::new();
+            let hit = Layer::pick_ray_interpolated(&mut previous, &mut current, alpha, system_bounds,
+                Point2::new(0f32, 0.5f32), Vector2::new(1f32, 0f32), 100f32, None,
+                |origin, direction, alpha, _, id| {
+                    calls.push(id);
+                    let (lhs, _) = before[id as usize];
+                    let (rhs, _) = after[id as usize];
+                    let bounds = Bounds{
+                        min: lhs.min + (rhs.min - lhs.min) * alpha,
+                        max: lhs.max + (rhs.max - lhs.max) * alpha};
+                    bounds.intersect_ray(*origin, *direction).map_or(f32::INFINITY, |(min, _)| min)
+                });
+            let (dist, id, point) = hit.unwrap();
+            assert_eq!(id, 0);
+            assert!((dist - expected).abs() < 1e-4);
+            assert!((point - Point2::new(expected, 0.5f32)).magnitude() < 1e-4);
+            calls.sort_unstable();
+            assert_eq!(calls, vec![0]);
+        }
+    }
+
+    #[test]
+    fn test_box_sorted_by_distance() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_stored_bounds()
+            .build();
+        layer.extend(system_bounds, (0..8u32).map(|id| {
+            let min = Point2::new(id as f32 * 2f32, 0f32);
+            (Bounds{min, max: min + Vector2::new(1f32, 1f32)}, id)
+        }));
+        layer.extend_unbounded(std::iter::once(100));
+
+        let test_bounds = Bounds{min: Point2::new(3.5f32, 0f32), max: Point2::new(12.5f32, 1f32)};
+        let results = layer.test_box_sorted_by_distance(system_bounds, test_bounds, Point2::new(8.5f32, 0.5f32), None);
+        let ids: Vec<u32> = results.iter().map(|&(id, _)| id).collect();
+        assert_eq!(ids, vec![4, 3, 5, 2, 6]);
+        assert!(results[0].1.abs() < 1e-3);
+        assert!((results[1].1 - 1.5f32).abs() < 1e-3);
+        assert!((results[2].1 - 1.5f32).abs() < 1e-3);
+        assert!((results[4].1 - 3.5f32).abs() < 1e-3);
+
+        // objects of varying sizes, many with multiple entries, and limited depths
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_stored_bounds()
+            .build();
+        let objects: Vec<_> = (0..64u32).map(|id| {
+            let min = Point2::new((id * 7 % 15) as f32 + 0.3f32, (id * 5 % 13) as f32 + 0.6f32);
+            let size = (id % 5) as f32 * 0.7f32 + 0.2f32;
+            (Bounds{min, max: min + Vector2::new(size, size * 0.5f32)}, id)
+        }).collect();
+        layer.extend(system_bounds, objects.iter().cloned());
+        let test_bounds = Bounds{min: Point2::new(2.5f32, 3f32), max: Point2::new(11f32, 12.5f32)};
+        let mut expected: Vec<u32> = objects.iter()
+            .filter(|&&(bounds, _)| bounds.overlaps(test_bounds))
+            .map(|&(_, id)| id)
+            .collect();
+        expected.sort_unstable();
+        for &max_depth in &[None, Some(3)] {
+            let results = layer.test_box_sorted_by_distance(system_bounds, test_bounds, Point2::new(6f32, 7f32), max_depth);
+            assert!(results.windows(2).all(|pair| (pair[0].1, pair[0].0) < (pair[1].1, pair[1].0)));
+            let mut ids: Vec<u32> = results.iter().map(|&(id, _)| id).collect();
+            ids.sort_unstable();
+            assert_eq!(ids, expected);
+        }
+    }
+
+    #[test]
+    fn composed_geometry() {
+        use crate::geom::{IntersectionTestGeometry, UnionTestGeometry};
+
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, (0..256u32).map(|id| {
+            let min = Point2::new((id % 16) as f32 + 0.25f32, (id / 16) as f32 + 0.25f32);
+            (Bounds{min, max: min + Vector2::new(0.5f32, 0.5f32)}, id)
+        }));
+
+        let lhs = SphereTestGeometry::with_system_bounds(system_bounds, Point2::new(4f32, 4f32), 2f32);
+        let rhs = SphereTestGeometry::with_system_bounds(system_bounds, Point2::new(12f32, 10f32), 3f32);
+        let mut expected = layer.test(&lhs, None).clone();
+        expected.extend(layer.test(&rhs, None).iter().cloned());
+        expected.sort_unstable();
+        expected.dedup();
+        let union = UnionTestGeometry::new(lhs, rhs.clone());
+        assert_eq!(layer.test(&union, None), &expected);
+
+        // the left half of the right sphere
+        let left = BoxTestGeometry::with_system_bounds(system_bounds, Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(11.9f32, 16f32)});
+        let sphere = layer.test(&rhs, None).clone();
+        let found = layer.test(&IntersectionTestGeometry::new(rhs, left), None).clone();
+        assert!(found.iter().all(|id| sphere.contains(id) && id % 16 <= 11));
+        assert!(found.contains(&(10 * 16 + 10)));
+        assert!(!found.contains(&(10 * 16 + 13)));
+    }
+
+    #[test]
+    fn test_circle() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, (0..256u32).map(|id| {
+            let min = Point2::new((id % 16) as f32 + 0.1f32, (id / 16) as f32 + 0.1f32);
+            (Bounds{min, max: min + Vector2::new(0.8f32, 0.8f32)}, id)
+        }));
+
+        let found = layer.test_circle(system_bounds, Point2::new(8f32, 8f32), 2.5f32, None).clone();
+        assert!(found.contains(&(8 * 16 + 5)));
+        assert!(!found.contains(&(5 * 16 + 5)));
+        let test_bounds = Bounds{min: Point2::new(5.5f32, 5.5f32), max: Point2::new(10.5f32, 10.5f32)};
+        assert!(layer.test_box(system_bounds, test_bounds, None).contains(&(5 * 16 + 5)));
+    }
+
+    #[test]
+    fn test_sdf() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_stored_bounds()
+            .with_refined_tests()
+            .build();
+        let objects: Vec<_> = (0..256u32)
+            .map(|id| {
+                let min = Point2::new((id % 16) as f32 + 0.25f32, (id / 16) as f32 + 0.25f32);
+                (Bounds{min, max: min + Vector2::new(0.5f32, 0.5f32)}, id)
+            })
+            .collect();
+        layer.extend(system_bounds, objects.iter().cloned());
+
+        // a ring of radius 4 and thickness 0.5
+        let sdf = |point: Point2<f32>| ((point - Point2::new(8.5f32, 8.5f32)).magnitude() - 4f32).abs() - 0.25f32;
+        let found = layer.test_sdf(system_bounds, sdf, None).clone();
+        for id in &[72, 132, 140, 200] {
+            assert!(found.contains(id));
+        }
+        assert!(!found.contains(&136));
+        let half_diagonal = Vector2::new(0.25f32, 0.25f32).magnitude();
+        for &id in &found {
+            let (bounds, _) = objects[id as usize];
+            assert!(sdf(bounds.center()) <= half_diagonal + 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_ellipsoid() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_stored_bounds()
+            .with_refined_tests()
+            .build();
+        let objects: Vec<_> = (0..256u32)
+            .map(|id| {
+                let min = Point2::new((id % 16) as f32 + 0.25f32, (id / 16) as f32 + 0.25f32);
+                (Bounds{min, max: min + Vector2::new(0.5f32, 0.5f32)}, id)
+            })
+            .collect();
+        layer.extend(system_bounds, objects.iter().cloned());
+
+        // wide and flat, reaching two objects either side of the center but none above or below
+        let center = Point2::new(8.5f32, 8.5f32);
+        let radii = Vector2::new(2.5f32, 0.5f32);
+        let mut found = layer.test_ellipsoid(system_bounds, center, radii, None).clone();
+        found.sort_unstable();
+        assert_eq!(found, (134..139).collect::<Vec<u32>>());
+
+        // the nearest object to a point between cells, in scaled distance
+        let test_geom = EllipsoidTestGeometry::with_system_bounds(
+            system_bounds,
+            Point2::new(5f32, 5.5f32),
+            Vector2::new(1f32, 4f32));
+        let hit = layer.pick(&test_geom, 1f32, None, |test_geom, _, id| {
+            let (bounds, _) = objects[id as usize];
+            test_geom.scaled_distance_to(bounds)
+        });
+        let (dist, id) = hit.unwrap();
+        assert!(id == 84 || id == 85);
+        assert!((dist - 0.25f32).abs() < 1e-4);
+    }
+
+    #[test]
+    fn one_way() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let objects = [
+            (Bounds{min: Point2::new(2f32, 8f32), max: Point2::new(10f32, 8.5f32)}, 0),
+            (Bounds{min: Point2::new(2f32, 12f32), max: Point2::new(10f32, 12.5f32)}, 1)];
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, objects.iter().cloned());
+
+        // a platform passable from below
+        layer.set_one_way(0, Some(Vector2::new(0f32, 1f32)));
+        assert!(layer.is_one_way(0));
+        let get_dist = |origin: &Point2<f32>, direction: &Vector2<f32>, _, id: u32| {
+            let (bounds, _) = objects[id as usize];
+            match bounds.intersect_ray(*origin, *direction) {
+                Some((min, max)) if max >= 0f32 => min.max(0f32),
+                _ => f32::INFINITY
+            }
+        };
+        let up = layer.pick_ray(system_bounds, Point2::new(5f32, 1f32), Vector2::new(0f32, 1f32), 100f32, None, get_dist);
+        assert_eq!(up.map(|(_, id, _)| id), Some(1));
+        let down = layer.pick_ray(system_bounds, Point2::new(5f32, 15f32), Vector2::new(0f32, -1f32), 100f32, None, get_dist);
+        assert_eq!(down.map(|(_, id, _)| id), Some(1));
+        let down = layer.pick_ray(system_bounds, Point2::new(5f32, 10f32), Vector2::new(0f32, -1f32), 100f32, None, get_dist);
+        assert_eq!(down.map(|(_, id, _)| id), Some(0));
+
+        let start_bounds = Bounds{min: Point2::new(4f32, 6f32), max: Point2::new(5f32, 7f32)};
+        let jumping = layer.test_swept_box(system_bounds, start_bounds, Vector2::new(0f32, 2f32), None);
+        assert!(!jumping.contains(&0));
+        let start_bounds = Bounds{min: Point2::new(4f32, 9f32), max: Point2::new(5f32, 10f32)};
+        let falling = layer.test_swept_box(system_bounds, start_bounds, Vector2::new(0f32, -2f32), None);
+        assert!(falling.contains(&0));
+
+        layer.set_one_way(0, None::<Vector2<f32>>);
+        let up = layer.pick_ray(system_bounds, Point2::new(5f32, 1f32), Vector2::new(0f32, 1f32), 100f32, None, get_dist);
+        assert_eq!(up.map(|(_, id, _)| id), Some(0));
+    }
+
+    #[test]
+    fn test_swept_box() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_stored_bounds()
+            .with_refined_tests()
+            .build();
+        let objects: Vec<_> = (0..256u32)
+            .map(|id| {
+                let min = Point2::new((id % 16) as f32 + 0.25f32, (id / 16) as f32 + 0.25f32);
+                (Bounds{min, max: min + Vector2::new(0.5f32, 0.5f32)}, id)
+            })
+            .collect();
+        layer.extend(system_bounds, objects.iter().cloned());
+
+        // a diagonal move, from the bottom left to the top right
+        let start_bounds = Bounds{min: Point2::new(0.4f32, 0.4f32), max: Point2::new(0.6f32, 0.6f32)};
+        let displacement = Vector2::new(15f32, 15f32);
+        let mut found = layer.test_swept_box(system_bounds, start_bounds, displacement, None).clone();
+        found.sort_unstable();
+        assert_eq!(found, (0..16).map(|i| i * 17).collect::<Vec<u32>>());
+
+        let at_rest = layer.test_swept_box(system_bounds, start_bounds, Vector2::new(0f32, 0f32), None);
+        assert_eq!(at_rest, &vec![0]);
+    }
+
+    #[test]
+    fn test_segment() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_stored_bounds()
+            .with_refined_tests()
+            .build();
+        layer.extend(system_bounds, (0..256u32).map(|id| {
+            let min = Point2::new((id % 16) as f32 + 0.25f32, (id / 16) as f32 + 0.25f32);
+            (Bounds{min, max: min + Vector2::new(0.5f32, 0.5f32)}, id)
+        }));
+
+        let (a, b) = (Point2::new(0.5f32, 4.5f32), Point2::new(5.5f32, 4.5f32));
+        let mut found = layer.test_segment(system_bounds, a, b, 0f32, None).clone();
+        found.sort_unstable();
+        assert_eq!(found, (64..70).collect::<Vec<u32>>());
+
+        let mut found = layer.test_segment(system_bounds, a, b, 0.8f32, None).clone();
+        found.sort_unstable();
+        let expected: Vec<u32> = (3..6).flat_map(|row| (0..7).map(move |col| row * 16 + col)).collect();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn test_many() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_inactive_excluded_from_queries()
+            .build();
+        layer.extend(system_bounds, (0..256u32).map(|id| {
+            let min = Point2::new((id % 16) as f32 + 0.25f32, (id / 16) as f32 + 0.25f32);
+            (Bounds{min, max: min + Vector2::new(0.5f32, 0.5f32)}, id)
+        }));
+        layer.extend_unbounded(std::iter::once(1000));
+        layer.set_active(17, false);
+
+        let test_geoms: Vec<_> = (0..64)
+            .map(|i| {
+                let min = Point2::new((i % 8) as f32 * 2f32 + 0.5f32, (i / 8) as f32 * 1.5f32);
+                BoxTestGeometry::with_system_bounds(system_bounds, Bounds{min, max: min + Vector2::new(1.5f32, 3f32)})
+            })
+            .collect();
+        let results = layer.test_many(&test_geoms, None);
+        assert_eq!(results.len(), test_geoms.len());
+        for (test_geom, results) in test_geoms.iter().zip(&results) {
+            assert_eq!(results, layer.test(test_geom, None));
+        }
+        assert!(!results[0].contains(&17));
+        assert!(results.iter().all(|results| results.contains(&1000)));
+    }
+
+    #[test]
+    fn test_union() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, (0..8u32).map(|id| {
+            let min = Point2::new(id as f32 * 2f32 + 0.25f32, 0.25f32);
+            (Bounds{min, max: min + Vector2::new(1.5f32, 1.5f32)}, id)
+        }));
+        layer.extend_unbounded(std::iter::once(100));
+
+        let test_bounds = [
+            Bounds{min: Point2::new(0f32, 0f32), max: Point2::new(5f32, 1f32)},
+            Bounds{min: Point2::new(3f32, 0f32), max: Point2::new(9f32, 1f32)},
+            Bounds{min: Point2::new(14f32, 0f32), max: Point2::new(15f32, 1f32)}];
+        let mut expected: Vec<u32> = test_bounds.iter()
+            .flat_map(|&test_bounds| layer.test_box(system_bounds, test_bounds, None).clone())
+            .collect();
+        expected.sort_unstable();
+        expected.dedup();
+
+        let test_geoms: Vec<_> = test_bounds.iter()
+            .map(|&test_bounds| BoxTestGeometry::with_system_bounds(system_bounds, test_bounds))
+            .collect();
+        assert_eq!(layer.test_union(&test_geoms, None), &expected);
+        assert_eq!(expected.iter().filter(|&&id| id == 100).count(), 1);
+    }
+
+    #[test]
+    fn rebuild_region() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_stored_bounds()
+            .build();
+        let object_bounds = |x: f32, y: f32| {
+            let min = Point2::new(x + 0.25f32, y + 0.25f32);
+            Bounds{min, max: min + Vector2::new(0.5f32, 0.5f32)}
+        };
+        layer.extend(system_bounds, (0..256u32).map(|id| (object_bounds((id % 16) as f32, (id / 16) as f32), id)));
+        // spans the edge of the region
+        layer.extend(system_bounds, std::iter::once((Bounds{
+            min: Point2::new(7.5f32, 4.25f32),
+            max: Point2::new(8.5f32, 4.75f32)}, 500)));
+
+        let region = Bounds{min: Point2::new(4f32, 4f32), max: Point2::new(8f32, 8f32)};
+        let removed = layer.rebuild_region(system_bounds, region, (0..4u32).map(|i| {
+            (object_bounds(4f32 + i as f32, 4f32 + i as f32), 1000 + i)
+        }));
+        assert!(removed >= 16);
+
+        let found = layer.test_box(system_bounds, region, None).clone();
+        for id in 0..256u32 {
+            let (x, y) = (id % 16, id / 16);
+            let inside = (4..8).contains(&x) && (4..8).contains(&y);
+            assert_eq!(layer.test_box(system_bounds, object_bounds(x as f32, y as f32), None).contains(&id), !inside);
+            assert_eq!(layer.local_bounds(id).is_some(), !inside);
+        }
+        assert!(found.contains(&1000) && found.contains(&1003));
+        assert!(layer.local_bounds(500).is_some());
+        assert!(layer.test_box(system_bounds, object_bounds(8f32, 4f32), None).contains(&500));
+        assert!(layer.validate().is_empty());
+
+        // a small object just outside of the region is indexed in deep cells, which must be kept
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_stored_bounds()
+            .build();
+        let tiny = Bounds{min: Point2::new(0.2499992f32, 1f32), max: Point2::new(0.2499994f32, 1.0000002f32)};
+        layer.extend(system_bounds, vec![(tiny, 0), (object_bounds(1f32, 1f32), 1)].into_iter());
+        let region = Bounds{min: Point2::new(0.25f32, 0.25f32), max: Point2::new(4f32, 4f32)};
+        assert_eq!(layer.rebuild_region(system_bounds, region, std::iter::empty()), 1);
+        assert!(layer.local_bounds(0).is_some());
+        assert_eq!(layer.test_box(system_bounds, tiny, None), &[0]);
+    }
+
+    #[test]
+    fn test_point() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_stored_bounds()
+            .build();
+        let objects: Vec<_> = (0..64u32)
+            .map(|id| {
+                let min = Point2::new((id % 8) as f32 * 2f32 + 0.25f32, (id / 8) as f32 * 2f32 + 0.25f32);
+                (Bounds{min, max: min + Vector2::new(1.5f32, 1.5f32)}, id)
+            })
+            .chain(std::iter::once((Bounds{min: Point2::new(0f32, 0f32), max: Point2::new(16f32, 16f32)}, 100)))
+            .collect();
+        layer.extend(system_bounds, objects.iter().cloned());
+
+        let point = Point2::new(5f32, 7f32);
+        let found = layer.test_point(system_bounds, point, None).clone();
+        let boxed = layer.test_box(system_bounds, Bounds::new(point, point), None).clone();
+        assert!(found.contains(&(3 * 8 + 2)));
+        assert!(found.contains(&100));
+        assert!(found.iter().all(|id| boxed.contains(id)));
+        assert!(layer.test_point(system_bounds, Point2::new(20f32, 7f32), None).is_empty());
+
+        let mut refined: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_stored_bounds()
+            .with_refined_tests()
+            .build();
+        refined.extend(system_bounds, objects.iter().cloned());
+        assert_eq!(refined.test_point(system_bounds, point, None), &vec![3 * 8 + 2, 100]);
+        assert_eq!(refined.test_point(system_bounds, Point2::new(6f32, 7f32), None), &vec![100]);
+    }
+
+    #[test]
+    fn test_half_space() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, (0..256u32).map(|id| {
+            let min = Point2::new((id % 16) as f32 + 0.25f32, (id / 16) as f32 + 0.25f32);
+            (Bounds{min, max: min + Vector2::new(0.5f32, 0.5f32)}, id)
+        }));
+
+        // the diagonal half-space x + y >= 16
+        let found = layer.test_half_space(system_bounds, Vector2::new(1f32, 1f32), -16f32, None).clone();
+        for id in 0..256u32 {
+            let (x, y) = (id % 16, id / 16);
+            if x + y >= 16 {
+                assert!(found.contains(&id));
+            } else if x + y < 14 {
+                assert!(!found.contains(&id));
+            }
+        }
+    }
+
+    #[test]
+    fn test_shell() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_stored_bounds()
+            .with_refined_tests()
+            .build();
+        layer.extend(system_bounds, (0..256u32).map(|id| {
+            let min = Point2::new((id % 16) as f32 + 0.25f32, (id / 16) as f32 + 0.25f32);
+            (Bounds{min, max: min + Vector2::new(0.5f32, 0.5f32)}, id)
+        }));
+
+        let center = Point2::new(8f32, 8f32);
+        let found = layer.test_shell(system_bounds, center, 3f32, 5f32, None).clone();
+        for id in 0..256u32 {
+            let min = Point2::new((id % 16) as f32 + 0.25f32, (id / 16) as f32 + 0.25f32);
+            let bounds = Bounds{min, max: min + Vector2::new(0.5f32, 0.5f32)};
+            let (near, far) = (bounds.distance_to(center), bounds.farthest_distance_to(center));
+            if near > 5.1f32 || far < 2.9f32 {
+                assert!(!found.contains(&id));
+            } else if near < 4.9f32 && far > 3.1f32 {
+                assert!(found.contains(&id));
+            }
+        }
+
+        assert!(layer.test_shell(system_bounds, center, 5f32, 3f32, None).is_empty());
+    }
+
+    #[test]
+    fn test_polygon() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, (0..256u32).map(|id| {
+            let min = Point2::new((id % 16) as f32 + 0.25f32, (id / 16) as f32 + 0.25f32);
+            (Bounds{min, max: min + Vector2::new(0.5f32, 0.5f32)}, id)
+        }));
+
+        // a diamond centered on (8, 8)
+        let vertices = [
+            Point2::new(8f32, 2f32),
+            Point2::new(14f32, 8f32),
+            Point2::new(8f32, 14f32),
+            Point2::new(2f32, 8f32)];
+        let found = layer.test_polygon(system_bounds, &vertices, None).clone();
+        for id in 0..256u32 {
+            let (x, y) = ((id % 16) as i32, (id / 16) as i32);
+            let dist = (x - 8).abs() + (y - 8).abs();
+            if dist < 5 {
+                assert!(found.contains(&id));
+            } else if dist > 8 || !(1..15).contains(&x) || !(1..15).contains(&y) {
+                assert!(!found.contains(&id));
+            }
+        }
+    }
+
+    #[test]
+    fn target_entries() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_target_entries(10)
+            .build();
+        // each straddles a cell corner at its own depth, producing four entries
+        let objects: Vec<_> = (0..10u32)
+            .map(|id| {
+                let center = Point2::new(id as f32 + 1f32, 8f32);
+                let half_size = Vector2::new(0.2f32, 0.2f32);
+                (Bounds{min: center - half_size, max: center + half_size}, id)
+            })
+            .collect();
+        layer.extend(system_bounds, objects.iter().cloned());
+        layer.commit();
+
+        let entries = |id: u32| layer.iter().filter(|&&(_, id_)| id_ == id).count();
+        assert_eq!((0..3).map(entries).collect::<Vec<_>>(), vec![4, 4, 4]);
+        assert!((3..10).all(|id| entries(id) == 1));
+        assert_eq!(layer.iter().count(), 19);
+
+        // coarse objects are still found
+        let test_bounds = Bounds{min: Point2::new(8.9f32, 8.1f32), max: Point2::new(9f32, 8.15f32)};
+        assert!(layer.test_box(system_bounds, test_bounds, None).contains(&8));
+
+        // the budget also applies to objects re-indexed or added by `update_all`
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_stored_bounds()
+            .with_target_entries(6)
+            .build();
+        layer.extend(system_bounds, vec![objects[0], objects[2]].into_iter());
+        layer.commit();
+        assert_eq!(layer.iter().count(), 8);
+
+        let updated = vec![(2, objects[4].0), (6, objects[6].0)];
+        assert_eq!(layer.update_all(system_bounds, updated.into_iter()), 2);
+        let entries = |id: u32| layer.iter().filter(|&&(_, id_)| id_ == id).count();
+        assert_eq!((0..8).map(entries).collect::<Vec<_>>(), vec![4, 0, 4, 0, 0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn test_cone() {
+        use cgmath::Deg;
+
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_stored_bounds()
+            .with_refined_tests()
+            .build();
+        let center = |id: u32| Point2::new((id % 16) as f32 + 0.5f32, (id / 16) as f32 + 0.5f32);
+        layer.extend(system_bounds, (0..256u32).map(|id| {
+            let half_size = Vector2::new(0.1f32, 0.1f32);
+            (Bounds{min: center(id) - half_size, max: center(id) + half_size}, id)
+        }));
+
+        // a 60 degree cone, facing +x from the middle of the left edge
+        let apex = Point2::new(0.5f32, 8.5f32);
+        let found = layer.test_cone(system_bounds, apex, Vector2::new(2f32, 0f32), Deg(30f32).into(), 10f32, None).clone();
+        for id in 0..256u32 {
+            let offset = center(id) - apex;
+            let angle = offset.y.abs().atan2(offset.x);
+            if offset.magnitude() < 9.5f32 && angle < 25f32.to_radians() {
+                assert!(found.contains(&id), "{} should be inside", id);
+            } else if offset.magnitude() > 10.5f32 || angle > 40f32.to_radians() {
+                assert!(!found.contains(&id), "{} should be outside", id);
+            }
+        }
+    }
+
+    #[test]
+    fn test_oriented_box() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        let center = |id: u32| Point2::new((id % 16) as f32 + 0.5f32, (id / 16) as f32 + 0.5f32);
+        layer.extend(system_bounds, (0..256u32).map(|id| {
+            let half_size = Vector2::new(0.1f32, 0.1f32);
+            (Bounds{min: center(id) - half_size, max: center(id) + half_size}, id)
+        }));
+
+        // a long, thin box along the diagonal
+        let diagonal = Vector2::new(1f32, 1f32).normalize();
+        let across = Vector2::new(-1f32, 1f32).normalize();
+        let planes = FrustumTestGeometry::oriented_box_planes(
+            Point2::new(8f32, 8f32),
+            &[(diagonal, 6f32), (across, 0.5f32)]);
+        assert_eq!(planes.len(), 4);
+        let found = layer.test_frustum(system_bounds, &planes, None).clone();
+        for id in 0..256u32 {
+            let offset = center(id) - Point2::new(8f32, 8f32);
+            let (along, off_axis) = (offset.dot(diagonal).abs(), offset.dot(across).abs());
+            if along < 5.5f32 && off_axis < 0.1f32 {
+                assert!(found.contains(&id), "{} should be inside", id);
+            } else if along > 7f32 || off_axis > 2f32 {
+                assert!(!found.contains(&id), "{} should be outside", id);
+            }
+        }
+    }
+
+    #[test]
+    fn scan_within() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_stored_bounds()
+            .build();
+        layer.extend(system_bounds, vec![
+            (Bounds{min: Point2::new(1f32, 1f32), max: Point2::new(2f32, 2f32)}, 0),
+            (Bounds{min: Point2::new(1.5f32, 1.5f32), max: Point2::new(2.5f32, 2.5f32)}, 1),
+            (Bounds{min: Point2::new(3f32, 1f32), max: Point2::new(4f32, 2f32)}, 2),
+            (Bounds{min: Point2::new(5f32, 5f32), max: Point2::new(6f32, 6f32)}, 3),
+            (Bounds{min: Point2::new(12f32, 12f32), max: Point2::new(13f32, 13f32)}, 4)].into_iter());
+        layer.extend_unbounded(std::iter::once(100));
+
+        let pairs = layer.scan_within(system_bounds, 1.5f32);
+        let ids: Vec<(u32, u32)> = pairs.iter().map(|&(a, b, _)| (a, b)).collect();
+        assert_eq!(ids, vec![(0, 1), (0, 2), (1, 2)]);
+        let distances: Vec<f32> = pairs.iter().map(|&(_, _, distance)| distance).collect();
+        for (actual, expected) in distances.into_iter().zip(vec![0f32, 1f32, 0.5f32]) {
+            assert!((actual - expected).abs() < 1e-3, "{} != {}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn near_pairs_with() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut characters: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_stored_bounds()
+            .build();
+        characters.extend(system_bounds, vec![
+            (Bounds{min: Point2::new(1f32, 1f32), max: Point2::new(2f32, 2f32)}, 0),
+            (Bounds{min: Point2::new(8f32, 8f32), max: Point2::new(9f32, 9f32)}, 1),
+            (Bounds{min: Point2::new(14f32, 1f32), max: Point2::new(15f32, 2f32)}, 2)].into_iter());
+        let mut triggers: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_stored_bounds()
+            .build();
+        triggers.extend(system_bounds, vec![
+            (Bounds{min: Point2::new(3f32, 1f32), max: Point2::new(5f32, 3f32)}, 0),
+            (Bounds{min: Point2::new(8.5f32, 8.5f32), max: Point2::new(12f32, 12f32)}, 1)].into_iter());
+
+        let pairs = characters.near_pairs_with(&mut triggers, system_bounds, 1.5f32);
+        let ids: Vec<(u32, u32)> = pairs.iter().map(|&(a, b, _)| (a, b)).collect();
+        assert_eq!(ids, vec![(0, 0), (1, 1)]);
+        assert!((pairs[0].2 - 1f32).abs() < 1e-3);
+        assert!(pairs[1].2.abs() < 1e-3);
+
+        characters.set_active(1, false);
+        triggers.set_active(1, false);
+        let pairs = characters.near_pairs_with(&mut triggers, system_bounds, 1.5f32);
+        assert_eq!(pairs.len(), 1);
+    }
+
+    #[test]
+    fn merge_mapped() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut prefab: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_stored_bounds()
+            .build();
+        prefab.extend(system_bounds, vec![
+            (Bounds{min: Point2::new(1f32, 1f32), max: Point2::new(2f32, 2f32)}, 0),
+            (Bounds{min: Point2::new(8f32, 8f32), max: Point2::new(9f32, 9f32)}, 1)].into_iter());
+        prefab.extend_unbounded(std::iter::once(2));
+
+        let mut world: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_stored_bounds()
+            .build();
+        world.extend(system_bounds, std::iter::once(
+            (Bounds{min: Point2::new(1f32, 1f32), max: Point2::new(2f32, 2f32)}, 0)));
+        world.merge_mapped(&prefab, |id| id + 100);
+        world.commit();
+
+        let test_bounds = Bounds{min: Point2::new(0.5f32, 0.5f32), max: Point2::new(1.5f32, 1.5f32)};
+        let mut found = world.test_box(system_bounds, test_bounds, None).clone();
+        found.sort_unstable();
+        assert_eq!(found, vec![0, 100, 102]);
+        assert!(world.stored_bounds(101).is_some());
+        assert!(world.stored_bounds(1).is_none());
+    }
+}
@@ -45,8 +45,34 @@
 //! let potential_collisions = layer.scan();
 //! # }
 //! ```
+//! 
+//! # Math types
+//! 
+//! Points and vectors are [cgmath](https://docs.rs/cgmath) types.  The version used by this crate is
+//! re-exported as `broadphase::cgmath`, so dependents don't need to depend on (and match) it directly.
+//! Alternatively, bounds may be converted from plain arrays with `Bounds::to_points` (and back with
+//! `Bounds::to_arrays`), and ray origins and directions with `.into()`:
+//! 
+//! ```rust
+//! extern crate zvxryb_broadphase as broadphase;
+//! 
+//! use broadphase::{Bounds, Layer, LayerBuilder, Index64_3D};
+//! 
+//! let system_bounds = Bounds{min: [0f32; 3], max: [64f32; 3]}.to_points();
+//! let object_bounds = Bounds{min: [1f32; 3], max: [2f32; 3]}.to_points();
+//! 
+//! let mut layer: Layer<Index64_3D, u32> = LayerBuilder::new().build();
+//! layer.extend(system_bounds, std::iter::once((object_bounds, 0)));
+//! let hits = layer.test_ray(system_bounds, [0f32; 3].into(), [1f32; 3].into(), 0f32, 100f32, None);
+//! assert_eq!(hits, &vec![0]);
+//! ```
+//! 
+//! With the `mint` feature, bounds also convert to and from `Bounds<mint::Point3<f32>>` (and 2D) with
+//! `.into()`, as do points and vectors, so any math library supporting [mint](https://docs.rs/mint)
+//! can be used at the API boundary without depending on cgmath at all.
 
-extern crate cgmath;
+/// The cgmath version used by this crate's public API
+pub extern crate cgmath;
 extern crate num_traits;
 extern crate rustc_hash;
 
@@ -54,6 +80,21 @@ extern crate rustc_hash;
 #[macro_use]
 extern crate serde;
 
+#[cfg(any(test, feature="replay", feature="stream", feature="bake"))]
+extern crate bincode;
+
+#[cfg(feature="mmap")]
+extern crate memmap;
+
+#[cfg(feature="async")]
+extern crate blocking;
+
+#[cfg(feature="mint")]
+extern crate mint;
+
+#[cfg(feature="bevy_reflect")]
+extern crate bevy_reflect;
+
 #[cfg(feature="parallel")]
 extern crate rayon;
 
@@ -72,11 +113,68 @@ extern crate log;
 #[macro_use]
 extern crate smallvec;
 
+/// Assertions which are only checked with the `strict-checks` feature, as they may be very expensive
+macro_rules! strict_assert {
+    ($($arg:tt)*) => {
+        if cfg!(feature="strict-checks") {
+            assert!($($arg)*);
+        }
+    };
+}
+
 mod traits;
 mod geom;
 mod index;
 mod layer;
+mod bounds_table;
+mod analysis;
+mod bit_grid;
+mod frozen;
+mod backend;
+mod spatial_hash;
+mod sweep_and_prune;
+mod recenter;
+mod dyn_layer;
+mod compressed;
+mod contacts;
+mod frustum_cache;
+mod instanced;
+mod observer;
+mod sync_layer;
+
+#[cfg(feature="export")]
+mod export;
+
+#[cfg(feature="replay")]
+pub mod replay;
+
+#[cfg(feature="bake")]
+pub mod bake;
+
+#[cfg(feature="stream")]
+mod stream;
+
+#[cfg(feature="mmap")]
+mod mapped;
+
+#[cfg(feature="async")]
+mod nonblocking;
 
-pub use crate::geom::{Bounds, TestGeometry, RayTestGeometry, SystemBounds};
-pub use crate::index::{SpatialIndex, Index32_2D, Index64_2D, Index64_3D};
-pub use crate::layer::{Layer, LayerBuilder};
\ No newline at end of file
+pub use crate::analysis::{LayerAnalysis, MemoryReport};
+pub use crate::backend::Broadphase;
+pub use crate::bit_grid::BitGrid;
+pub use crate::contacts::{ContactEvent, ContactKind, ContactTracker};
+pub use crate::compressed::{CompressedPairs, CompressedPairsIter, PackedID};
+pub use crate::dyn_layer::DynLayer;
+pub use crate::frozen::FrozenLayer;
+pub use crate::frustum_cache::FrustumCache;
+pub use crate::geom::{Bounds, BoundedTestGeometry, CacheKey, CastTestGeometry, ConeTestGeometry, Containment, ConvexTestGeometry, EllipsoidTestGeometry, FrustumTestGeometry, IntersectionTestGeometry, PlaneTestGeometry, PointTestGeometry, PolygonTestGeometry, TestGeometry, RayError, RayTestGeometry, SdfTestGeometry, ShellTestGeometry, SphereCastTestGeometry, SphereTestGeometry, SweptBoxTestGeometry, SystemBounds, UnionTestGeometry};
+pub use crate::instanced::InstancedLayer;
+pub use crate::index::{SpatialIndex, Index32_2D, Index64_2D, Index64_3D, Index64_3DDepthHigh};
+pub use crate::layer::{Layer, LayerBuilder, OutOfBoundsPolicy, PairArrays, QueryResults, Violation};
+pub use crate::observer::TraversalObserver;
+pub use crate::recenter::RecenteringBounds;
+pub use crate::spatial_hash::SpatialHash;
+pub use crate::sweep_and_prune::SweepAndPrune;
+pub use crate::sync_layer::SyncLayer;
+pub use crate::traits::ObjectID;
\ No newline at end of file
@@ -0,0 +1,202 @@
+// mlodato, 2020
+
+use crate::frozen::FrozenLayer;
+use crate::geom::{Bounds, IndexGenerator};
+use crate::index::SpatialIndex;
+use crate::layer::LayerBuilder;
+use crate::traits::ObjectID;
+
+use memmap::Mmap;
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::marker::PhantomData;
+use std::mem::{align_of, size_of, size_of_val};
+use std::sync::Arc;
+
+/// Identifies files written by `FrozenLayer::write_mapped`, in native byte order
+const MAGIC: u32 = 0x4250_4d4d;
+const VERSION: u32 = 1;
+
+/// Entries start at this offset, which is sufficiently aligned for any entry type
+const HEADER_SIZE: usize = 64;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Whether `(Index, ID)` entries may be written as raw bytes, i.e. they contain no padding
+fn entries_are_packed<Index, ID>() -> bool {
+    size_of::<(Index, ID)>() == size_of::<Index>() + size_of::<ID>()
+}
+
+/// Sorted entries backed by a memory-mapped file, shared between clones
+#[derive(Clone)]
+pub(crate) struct MappedEntries<Index, ID> {
+    map: Arc<Mmap>,
+    len: usize,
+    _entries: PhantomData<(Index, ID)>,
+}
+
+impl<Index, ID> MappedEntries<Index, ID> {
+    pub(crate) fn entries(&self) -> &[(Index, ID)] {
+        let entries = self.map[HEADER_SIZE..].as_ptr() as *const (Index, ID);
+        // size, alignment, and sorting are checked by `FrozenLayer::map_file`
+        unsafe { std::slice::from_raw_parts(entries, self.len) }
+    }
+}
+
+impl<Index, ID> FrozenLayer<Index, ID>
+where
+    Index: SpatialIndex,
+    ID: ObjectID,
+    Bounds<Index::Point>: IndexGenerator<Index>
+{
+    /// [`map_file`]: #method.map_file
+    /// Write all entries in the raw, platform-specific format used by [`map_file`]
+    /// 
+    /// Fails with `ErrorKind::InvalidInput` if `(Index, ID)` contains padding bytes.  Unbounded objects
+    /// and stored bounds are not written.
+    pub fn write_mapped<W>(&self, mut writer: W) -> io::Result<()>
+    where
+        W: Write
+    {
+        if !entries_are_packed::<Index, ID>() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "entries contain padding bytes"));
+        }
+
+        let entries = self.entries();
+        let mut header = [0u8; HEADER_SIZE];
+        let fields = [
+            MAGIC,
+            VERSION,
+            size_of::<(Index, ID)>() as u32,
+            align_of::<(Index, ID)>() as u32,
+            size_of::<ID>() as u32,
+            self.layer().min_depth()];
+        for (field, bytes) in fields.iter().zip(header.chunks_mut(4)) {
+            bytes.copy_from_slice(&field.to_ne_bytes());
+        }
+        header[24..32].copy_from_slice(&(entries.len() as u64).to_ne_bytes());
+        writer.write_all(&header)?;
+
+        // entries contain no padding, so all of their bytes are initialized
+        let bytes = unsafe { std::slice::from_raw_parts(entries.as_ptr() as *const u8, size_of_val(entries)) };
+        writer.write_all(bytes)
+    }
+
+    /// [`write_mapped`]: #method.write_mapped
+    /// Create a `FrozenLayer` whose entries are backed by a memory-mapped file written by [`write_mapped`]
+    /// 
+    /// The mapping is read-only and shared with any other process mapping the same file, so the entries
+    /// occupy physical memory only once.  The entry size, alignment, byte order, and sort order are
+    /// checked, failing with `ErrorKind::InvalidData`.  See `Layer::freeze_with_ranges` for
+    /// `range_depth`; precomputed ranges are not shared.
+    /// 
+    /// # Safety
+    /// 
+    /// The file must have been written by [`write_mapped`] for the same `Index` and `ID` types, on a
+    /// platform with the same data layout, and must not be modified while it is mapped.  `ID` must not
+    /// contain references or pointers.
+    pub unsafe fn map_file(file: &File, range_depth: Option<u32>) -> io::Result<Self> {
+        let map = Mmap::map(file)?;
+        if map.len() < HEADER_SIZE {
+            return Err(invalid_data("truncated header"));
+        }
+
+        let mut fields = [0u32; 6];
+        for (field, bytes) in fields.iter_mut().zip(map.chunks(4)) {
+            let mut field_bytes = [0u8; 4];
+            field_bytes.copy_from_slice(bytes);
+            *field = u32::from_ne_bytes(field_bytes);
+        }
+        let [magic, version, entry_size, entry_align, id_size, min_depth] = fields;
+        let mut len_bytes = [0u8; 8];
+        len_bytes.copy_from_slice(&map[24..32]);
+        let len = u64::from_ne_bytes(len_bytes) as usize;
+
+        if magic == MAGIC.swap_bytes() {
+            return Err(invalid_data("written with a different byte order"));
+        }
+        if magic != MAGIC || version != VERSION {
+            return Err(invalid_data("not a mapped layer, or an unsupported version"));
+        }
+        if entry_size as usize != size_of::<(Index, ID)>()
+            || entry_align as usize != align_of::<(Index, ID)>()
+            || id_size as usize != size_of::<ID>()
+        {
+            return Err(invalid_data("entry layout does not match"));
+        }
+        if len.checked_mul(size_of::<(Index, ID)>()).and_then(|size| size.checked_add(HEADER_SIZE)) != Some(map.len()) {
+            return Err(invalid_data("entry count does not match file size"));
+        }
+        if map[HEADER_SIZE..].as_ptr().align_offset(align_of::<(Index, ID)>()) != 0 {
+            return Err(invalid_data("entries are misaligned"));
+        }
+
+        let mapped = MappedEntries{
+            map: Arc::new(map),
+            len,
+            _entries: PhantomData};
+        if mapped.entries().windows(2).any(|pair| pair[0] > pair[1]) {
+            return Err(invalid_data("entries are not sorted"));
+        }
+
+        let layer = LayerBuilder::new()
+            .with_min_depth(min_depth)
+            .build();
+        Ok(Self::with_mapped(layer, range_depth, mapped))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index64_3D;
+    use crate::layer::Layer;
+
+    use cgmath::Point3;
+
+    #[test]
+    fn map_file() {
+        let system_bounds = Bounds{
+            min: Point3::new(0f32, 0f32, 0f32),
+            max: Point3::new(64f32, 64f32, 64f32)};
+        let mut layer: Layer<Index64_3D, u64> = LayerBuilder::new().build();
+        layer.extend(system_bounds, (0..64u64).map(|id| {
+            let x = (id % 16) as f32 * 3.5f32 + 0.5f32;
+            let y = (id / 16) as f32 * 7.5f32 + 0.5f32;
+            (Bounds{min: Point3::new(x, y, x), max: Point3::new(x + 5f32, y + 1f32, x + 1f32)}, id)
+        }));
+        let mut frozen = layer.freeze();
+
+        let path = std::env::temp_dir().join(format!("broadphase-mapped-{}.bin", std::process::id()));
+        frozen.write_mapped(File::create(&path).unwrap()).unwrap();
+        let mut mapped: FrozenLayer<Index64_3D, u64> = unsafe {
+            FrozenLayer::map_file(&File::open(&path).unwrap(), Some(4)).unwrap()
+        };
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(mapped.is_mapped());
+        assert!(mapped == frozen);
+        assert_eq!(mapped.scan(), frozen.scan());
+        let test_bounds = Bounds{
+            min: Point3::new(0f32, 0f32, 0f32),
+            max: Point3::new(16f32, 16f32, 16f32)};
+        assert_eq!(
+            mapped.test_box(system_bounds, test_bounds, None),
+            frozen.test_box(system_bounds, test_bounds, None));
+
+        // entries are copied when the mapping is modified
+        mapped.merge(&Layer::default());
+        assert!(!mapped.is_mapped());
+        assert!(mapped == frozen);
+
+        // mismatched entry layout
+        let path = std::env::temp_dir().join(format!("broadphase-mapped-{}-u32.bin", std::process::id()));
+        frozen.write_mapped(File::create(&path).unwrap()).unwrap();
+        let result = unsafe { FrozenLayer::<Index64_3D, u32>::map_file(&File::open(&path).unwrap(), None) };
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(result.err().map(|err| err.kind()), Some(io::ErrorKind::InvalidData));
+    }
+}
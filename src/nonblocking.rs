@@ -0,0 +1,76 @@
+// mlodato, 2020
+
+use crate::geom::{Bounds, IndexGenerator};
+use crate::index::SpatialIndex;
+use crate::layer::Layer;
+use crate::traits::ObjectID;
+
+use blocking::unblock;
+
+impl<Index, ID> Layer<Index, ID>
+where
+    Index: SpatialIndex + Send + 'static,
+    ID: ObjectID + Send + 'static,
+    Bounds<Index::Point>: IndexGenerator<Index>
+{
+    /// Run `f` on this `Layer` in a thread pool for blocking operations, returning it when done
+    async fn unblock_with<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut Self) + Send + 'static
+    {
+        unblock(move || {
+                f(&mut self);
+                self
+            }).await
+    }
+
+    /// [`sort`]: #method.sort
+    /// Equivalent to [`sort`], run in a thread pool for blocking operations
+    /// 
+    /// This is intended for use from async code, e.g. game servers, where a large sort would otherwise
+    /// block the executor.  The work runs in the thread pool of the `blocking` crate, independent of
+    /// any particular async runtime.  The `Layer` is moved into the returned future and handed back on
+    /// completion, so dropping the future drops the `Layer` along with it.
+    pub async fn sort_async(self) -> Self {
+        self.unblock_with(|layer| layer.sort()).await
+    }
+
+    /// [`scan`]: #method.scan
+    /// [`sort_async`]: #method.sort_async
+    /// [`scan_results`]: #method.scan_results
+    /// Equivalent to [`scan`], run in a thread pool for blocking operations, see [`sort_async`]
+    /// 
+    /// The pairs are available from [`scan_results`] on the returned `Layer`.
+    pub async fn scan_async(self) -> Self {
+        self.unblock_with(|layer| { layer.scan(); }).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geom::Bounds;
+    use crate::index::Index64_2D;
+    use crate::layer::{Layer, LayerBuilder};
+
+    use cgmath::Point2;
+    use futures_lite::future::block_on;
+
+    #[test]
+    fn scan_async() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, (0..64u32).map(|id| {
+            let x = (id % 8) as f32 * 1.5f32 + 0.5f32;
+            let y = (id / 8) as f32 * 1.5f32 + 0.5f32;
+            (Bounds{min: Point2::new(x, y), max: Point2::new(x + 2f32, y + 2f32)}, id)
+        }));
+        let mut expected = layer.clone();
+
+        let layer = block_on(layer.sort_async());
+        assert!(layer.is_committed());
+        let layer = block_on(layer.scan_async());
+        assert_eq!(layer.scan_results(), expected.scan());
+    }
+}
@@ -0,0 +1,76 @@
+// mlodato, 2020
+
+use crate::index::SpatialIndex;
+
+/// [`Layer::test_observed`]: struct.Layer.html#method.test_observed
+/// [`Layer::scan_observed`]: struct.Layer.html#method.scan_observed
+/// Callbacks invoked while traversing a `Layer`, see [`Layer::test_observed`] and [`Layer::scan_observed`]
+/// 
+/// All methods do nothing by default.  Traversal is generic over the observer, so unobserved queries
+/// (which use `()`) pay nothing for these hooks.  This is intended for visual debuggers and coverage
+/// analysis.
+pub trait TraversalObserver<Index>
+where
+    Index: SpatialIndex
+{
+    /// Called for each cell visited which contains entries
+    /// 
+    /// During tests, `entry_count` includes entries in any sub-cells; during scans, cells are visited
+    /// in sorted order and `entry_count` is the number of entries with exactly this index.
+    fn on_cell_visited(&mut self, _index: Index, _entry_count: usize) {}
+
+    /// Called when test geometry is subdivided at `depth`, before its sub-cells are visited
+    fn on_subdivide(&mut self, _depth: u32) {}
+}
+
+impl<Index> TraversalObserver<Index> for ()
+where
+    Index: SpatialIndex
+{}
+
+/// Reports the fraction of some number of entries processed, at most about once per percent
+pub(crate) struct Progress<F>
+where
+    F: FnMut(f32)
+{
+    callback: F,
+    total: usize,
+    done: usize,
+    next: usize,
+}
+
+impl<F> Progress<F>
+where
+    F: FnMut(f32)
+{
+    pub(crate) fn new(mut callback: F, total: usize) -> Self {
+        callback(0f32);
+        Self{callback, total, done: 0, next: Self::step(total)}
+    }
+
+    fn step(total: usize) -> usize {
+        (total / 100).max(1)
+    }
+
+    pub(crate) fn advance(&mut self, entries: usize) {
+        self.done += entries;
+        if self.done >= self.next && self.done < self.total {
+            (self.callback)(self.done as f32 / self.total as f32);
+            self.next = self.done + Self::step(self.total);
+        }
+    }
+
+    pub(crate) fn finish(mut self) {
+        (self.callback)(1f32);
+    }
+}
+
+impl<Index, F> TraversalObserver<Index> for Progress<F>
+where
+    Index: SpatialIndex,
+    F: FnMut(f32)
+{
+    fn on_cell_visited(&mut self, _index: Index, entry_count: usize) {
+        self.advance(entry_count);
+    }
+}
@@ -0,0 +1,133 @@
+// mlodato, 2020
+
+use crate::geom::{Bounds, IndexGenerator, VecDim};
+use crate::index::SpatialIndex;
+use crate::layer::Layer;
+use crate::traits::ObjectID;
+
+use cgmath::prelude::*;
+
+/// [`Layer`]: struct.Layer.html
+/// [`Layer::translate`]: struct.Layer.html#method.translate
+/// System bounds which follow a focus point (e.g. a camera or player) in steps of whole cells
+/// 
+/// For very large worlds, this keeps index precision high near the focus point.  When the focus moves
+/// far enough from the center, the bounds are moved by a whole number of cells at `depth`, and existing
+/// [`Layer`]s may be re-mapped using [`Layer::translate`] (or `recenter`) rather than being rebuilt.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RecenteringBounds<Point> {
+    bounds: Bounds<Point>,
+    depth: u32,
+}
+
+impl<Point> RecenteringBounds<Point>
+where
+    Point: EuclideanSpace<Scalar = f32> + VecDim,
+    Point::Diff: ElementWise + Array<Element = f32>
+{
+    /// Create recentering bounds, moving in steps of whole cells at `depth`
+    /// 
+    /// `depth` must be non-zero.  Shallower depths move less often, in larger steps.
+    pub fn new(bounds: Bounds<Point>, depth: u32) -> Self {
+        assert!(depth > 0 && depth < 32, "depth out of range");
+        Self{bounds, depth}
+    }
+
+    /// The current system bounds
+    pub fn bounds(&self) -> Bounds<Point> {
+        self.bounds
+    }
+
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// The size of a single step, in global coordinates
+    pub fn step_size(&self) -> Point::Diff {
+        // see `SystemBounds::to_global`; local coordinates span 0x0000_0000 to 0xffff_ff00
+        const RANGE: f32 = 0xffff_ff00u32 as f32;
+        let scale = (1u64 << (32 - self.depth)) as f32 / RANGE;
+        self.bounds.sizef() * scale
+    }
+
+    /// Move the bounds so that `focus` is near the center
+    /// 
+    /// Returns the number of cells moved along each axis, suitable for [`Layer::translate`], or `None`
+    /// if the bounds did not move
+    pub fn follow(&mut self, focus: Point) -> Option<[i32; 3]> {
+        let step = self.step_size();
+        let offset = focus - self.bounds.center();
+
+        let mut cells = [0i32; 3];
+        let mut moved = Point::Diff::zero();
+        for axis in 0..Point::DIM {
+            cells[axis] = (offset[axis] / step[axis]).round() as i32;
+            moved[axis] = cells[axis] as f32 * step[axis];
+        }
+
+        if cells.iter().all(|&cells| cells == 0) {
+            return None;
+        }
+
+        self.bounds = Bounds::new(self.bounds.min + moved, self.bounds.max + moved);
+        Some(cells)
+    }
+
+    /// Move the bounds so that `focus` is near the center, re-mapping a `Layer` accordingly
+    /// 
+    /// Returns `true` if the bounds moved
+    pub fn recenter<Index, ID>(&mut self, focus: Point, layer: &mut Layer<Index, ID>) -> bool
+    where
+        Index: SpatialIndex,
+        ID: ObjectID,
+        Bounds<Index::Point>: IndexGenerator<Index> + From<Index>
+    {
+        match self.follow(focus) {
+            Some(cells) => {
+                layer.translate(self.depth, cells);
+                true
+            },
+            None => false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index64_2D;
+    use crate::layer::LayerBuilder;
+
+    use cgmath::{Point2, Vector2};
+
+    #[test]
+    fn recenter_matches_rebuild() {
+        let mut bounds = RecenteringBounds::new(Bounds{
+            min: Point2::new(-64f32, -64f32),
+            max: Point2::new(64f32, 64f32)}, 4);
+        let objects: Vec<_> = (0..16u32).map(|id| {
+            let min = Point2::new(id as f32 * 2f32 - 20f32, id as f32 * 2f32 - 10f32);
+            (Bounds{min, max: min + Vector2::new(2.5f32, 2.5f32)}, id)
+        }).collect();
+
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(bounds.bounds(), objects.iter().cloned());
+        let mut expected = layer.scan().clone();
+        assert!(!expected.is_empty());
+
+        assert!(!bounds.recenter(Point2::new(1f32, 1f32), &mut layer));
+        assert!(bounds.recenter(Point2::new(20f32, -9f32), &mut layer));
+        assert_eq!(bounds.follow(Point2::new(20f32, -9f32)), None);
+
+        let mut rebuilt: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        rebuilt.extend(bounds.bounds(), objects.iter().cloned());
+
+        let mut pairs = layer.scan().clone();
+        let mut rebuilt_pairs = rebuilt.scan().clone();
+        pairs.sort();
+        rebuilt_pairs.sort();
+        expected.sort();
+        assert_eq!(pairs, expected);
+        assert_eq!(rebuilt_pairs, expected);
+    }
+}
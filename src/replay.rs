@@ -0,0 +1,508 @@
+// mlodato, 2020
+
+//! Recording of `Layer` operations for offline replay and debugging
+//! 
+//! [`Recorder`]: struct.Recorder.html
+//! 
+//! A [`Recorder`] wraps a `Layer`, forwarding each operation while logging its parameters and results to
+//! a compact binary stream.  A frame in which, for example, a collision was missed in production may then
+//! be replayed offline against the exact same layer state.
+//! 
+//! Only operations with serializable parameters are recorded: `clear`, `extend`, `scan`, `test_box`,
+//! `test_ray`, and `pick_ray`.  For `pick_ray`, the distances returned by the user-provided `get_dist`
+//! callback are recorded, so that the pick may be replayed without it.
+//! 
+//! [`run`]: fn.run.html
+//! 
+//! Recordings are replayed with [`run`], which re-executes each operation and compares its results to
+//! those recorded.
+
+use crate::geom::{Bounds, BoxTestGeometry, IndexGenerator, RayTestGeometry, SystemBounds, TestGeometry, VecDim};
+use crate::index::SpatialIndex;
+use crate::layer::Layer;
+use crate::traits::ObjectID;
+
+use cgmath::prelude::*;
+use rustc_hash::FxHashMap;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use std::fmt::Debug;
+use std::io::{Read, Write};
+
+const MAGIC: [u8; 4] = *b"BPRC";
+const VERSION: u32 = 1;
+
+/// The header at the start of each recording
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Header {
+    pub magic: [u8; 4],
+    pub version: u32,
+    /// The dimension of recorded points
+    pub dim: u32,
+}
+
+impl Header {
+    fn new(dim: usize) -> Self {
+        Self{
+            magic: MAGIC,
+            version: VERSION,
+            dim: dim as u32}
+    }
+}
+
+/// Recorded bounds, independent of the point type
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RecordedBounds {
+    pub min: Vec<f32>,
+    pub max: Vec<f32>,
+}
+
+impl RecordedBounds {
+    fn new<Point_>(bounds: Bounds<Point_>) -> Self
+    where
+        Point_: EuclideanSpace<Scalar = f32>
+    {
+        Self{
+            min: to_vec(bounds.min),
+            max: to_vec(bounds.max)}
+    }
+
+    fn to_bounds<Point_>(&self) -> Bounds<Point_>
+    where
+        Point_: EuclideanSpace<Scalar = f32>
+    {
+        Bounds::new(from_slice(&self.min), from_slice(&self.max))
+    }
+}
+
+fn to_vec<Arr>(arr: Arr) -> Vec<f32>
+where
+    Arr: Array<Element = f32>
+{
+    (0..Arr::len()).map(|i| arr[i]).collect()
+}
+
+fn from_slice<Point_>(slice: &[f32]) -> Point_
+where
+    Point_: EuclideanSpace<Scalar = f32>
+{
+    let mut point = Point_::origin();
+    for (i, &value) in slice.iter().enumerate().take(Point_::len()) {
+        point[i] = value;
+    }
+    point
+}
+
+/// A single recorded operation, including its results
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum Event<ID> {
+    Clear,
+    Extend{
+        system_bounds: RecordedBounds,
+        objects: Vec<(RecordedBounds, ID)>},
+    Scan{
+        results: Vec<(ID, ID)>},
+    TestBox{
+        system_bounds: RecordedBounds,
+        test_bounds: RecordedBounds,
+        max_depth: Option<u32>,
+        results: Vec<ID>},
+    TestRay{
+        system_bounds: RecordedBounds,
+        origin: Vec<f32>,
+        direction: Vec<f32>,
+        range_min: f32,
+        range_max: f32,
+        max_depth: Option<u32>,
+        results: Vec<ID>},
+    PickRay{
+        system_bounds: RecordedBounds,
+        origin: Vec<f32>,
+        direction: Vec<f32>,
+        max_dist: f32,
+        max_depth: Option<u32>,
+        distances: Vec<(ID, f32)>,
+        result: Option<(f32, ID)>},
+}
+
+/// Forwards operations to a `Layer`, recording each one to a writer
+/// 
+/// Each method returns an error if recording fails; the operation itself is always applied.
+pub struct Recorder<'a, Index, ID, W>
+where
+    Index: SpatialIndex,
+    ID: ObjectID,
+    Bounds<Index::Point>: IndexGenerator<Index>
+{
+    layer: &'a mut Layer<Index, ID>,
+    writer: W,
+}
+
+impl<'a, Index, ID, W> Recorder<'a, Index, ID, W>
+where
+    Index: SpatialIndex,
+    ID: ObjectID + Serialize,
+    Bounds<Index::Point>: IndexGenerator<Index>,
+    W: Write
+{
+    /// Start recording operations on `layer`, writing a header to `writer`
+    pub fn new(layer: &'a mut Layer<Index, ID>, mut writer: W) -> bincode::Result<Self> {
+        bincode::serialize_into(&mut writer, &Header::new(Index::Point::len()))?;
+        Ok(Self{layer, writer})
+    }
+
+    /// The underlying `Layer`
+    /// 
+    /// Operations applied directly to the `Layer` are not recorded
+    pub fn layer(&mut self) -> &mut Layer<Index, ID> {
+        self.layer
+    }
+
+    /// Stop recording, returning the writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn record(&mut self, event: &Event<ID>) -> bincode::Result<()> {
+        bincode::serialize_into(&mut self.writer, event)
+    }
+
+    /// Record `Layer::clear`
+    pub fn clear(&mut self) -> bincode::Result<()> {
+        self.layer.clear();
+        self.record(&Event::Clear)
+    }
+
+    /// Record `Layer::extend`
+    pub fn extend<Iter, Point_>(&mut self, system_bounds: Bounds<Point_>, objects: Iter) -> bincode::Result<()>
+    where
+        Iter: std::iter::Iterator<Item = (Bounds<Point_>, ID)>,
+        Point_: EuclideanSpace<Scalar = f32>,
+        Point_::Diff: ElementWise,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
+    {
+        let objects: Vec<_> = objects.collect();
+        self.layer.extend(system_bounds, objects.iter().cloned());
+        self.record(&Event::Extend{
+            system_bounds: RecordedBounds::new(system_bounds),
+            objects: objects.into_iter()
+                .map(|(bounds, id)| (RecordedBounds::new(bounds), id))
+                .collect()})
+    }
+
+    /// Record `Layer::scan`
+    pub fn scan(&mut self) -> bincode::Result<&Vec<(ID, ID)>> {
+        let results = self.layer.scan().clone();
+        self.record(&Event::Scan{results})?;
+        Ok(self.layer.scan())
+    }
+
+    /// Record `Layer::test_box`
+    pub fn test_box<Point_>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        test_bounds: Bounds<Point_>,
+        max_depth: Option<u32>) -> bincode::Result<Vec<ID>>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + Debug,
+        Point_::Diff: ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        BoxTestGeometry<Point_>: TestGeometry,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
+    {
+        let results = self.layer.test_box(system_bounds, test_bounds, max_depth).clone();
+        self.record(&Event::TestBox{
+            system_bounds: RecordedBounds::new(system_bounds),
+            test_bounds: RecordedBounds::new(test_bounds),
+            max_depth,
+            results: results.clone()})?;
+        Ok(results)
+    }
+
+    /// Record `Layer::test_ray`
+    pub fn test_ray<Point_>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        origin   : Point_,
+        direction: Point_::Diff,
+        range_min: f32,
+        range_max: f32,
+        max_depth: Option<u32>) -> bincode::Result<Vec<ID>>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+        Point_::Diff: ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        RayTestGeometry<Point_>: TestGeometry,
+        Bounds<Point_>: SystemBounds<Point_, Index::Point>
+    {
+        let results = self.layer.test_ray(system_bounds, origin, direction, range_min, range_max, max_depth).clone();
+        self.record(&Event::TestRay{
+            system_bounds: RecordedBounds::new(system_bounds),
+            origin: to_vec(origin),
+            direction: to_vec(Point_::from_vec(direction)),
+            range_min,
+            range_max,
+            max_depth,
+            results: results.clone()})?;
+        Ok(results)
+    }
+
+    /// Record `Layer::pick_ray`, including each distance returned by `get_dist`
+    #[allow(clippy::type_complexity)]
+    pub fn pick_ray<Point_, GetDist>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        origin   : Point_,
+        direction: Point_::Diff,
+        max_dist: f32,
+        max_depth: Option<u32>,
+        mut get_dist: GetDist) -> bincode::Result<Option<(f32, ID, Point_)>>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+        Point_::Diff: VectorSpace<Scalar = f32> + ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        RayTestGeometry<Point_>: TestGeometry,
+        GetDist: FnMut(&Point_, &Point_::Diff, f32, ID) -> f32
+    {
+        let mut distances: Vec<(ID, f32)> = Vec::new();
+        let result = self.layer.pick_ray(system_bounds, origin, direction, max_dist, max_depth,
+            |origin, direction, max_dist, id| {
+                let dist = get_dist(origin, direction, max_dist, id);
+                distances.push((id, dist));
+                dist
+            });
+        self.record(&Event::PickRay{
+            system_bounds: RecordedBounds::new(system_bounds),
+            origin: to_vec(origin),
+            direction: to_vec(Point_::from_vec(direction)),
+            max_dist,
+            max_depth,
+            distances,
+            result: result.map(|(dist, id, _)| (dist, id))})?;
+        Ok(result)
+    }
+}
+
+/// A replayed operation whose results differ from those recorded
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mismatch<ID> {
+    /// The position of the operation within the recording
+    pub event: usize,
+    /// The recorded operation and results
+    pub expected: Event<ID>,
+    /// The replayed operation and results
+    pub actual: Event<ID>,
+}
+
+/// The outcome of [`run`]
+/// 
+/// [`run`]: fn.run.html
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report<ID> {
+    /// The number of operations replayed
+    pub events: usize,
+    pub mismatches: Vec<Mismatch<ID>>,
+}
+
+impl<ID> Report<ID> {
+    /// Returns `true` if all replayed results matched those recorded
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// [`Recorder`]: struct.Recorder.html
+/// Re-execute a recording made by [`Recorder`] against `layer`, comparing results to those recorded
+/// 
+/// `Point_` is the (global) point type used when recording, and must be specified explicitly.  To reproduce
+/// a recording exactly, `layer` should be configured identically to the recorded `Layer` (e.g. by using the
+/// same `LayerBuilder` settings) and should contain the same initial data.
+pub fn run<Point_, R, Index, ID>(mut reader: R, layer: &mut Layer<Index, ID>) -> bincode::Result<Report<ID>>
+where
+    R: Read,
+    Index: SpatialIndex,
+    ID: ObjectID + DeserializeOwned,
+    Bounds<Index::Point>: IndexGenerator<Index>,
+    Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+    Point_::Diff: VectorSpace<Scalar = f32> + ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+    Bounds<Point_>: SystemBounds<Point_, Index::Point>,
+    BoxTestGeometry<Point_>: TestGeometry,
+    RayTestGeometry<Point_>: TestGeometry
+{
+    let header: Header = bincode::deserialize_from(&mut reader)?;
+    if header.magic != MAGIC || header.version != VERSION {
+        return Err(Box::new(bincode::ErrorKind::Custom("unrecognized recording format".to_string())));
+    }
+    if header.dim as usize != Point_::len() {
+        return Err(Box::new(bincode::ErrorKind::Custom(format!(
+            "recording dimension ({}) does not match point dimension ({})", header.dim, Point_::len()))));
+    }
+
+    let mut report = Report{
+        events: 0,
+        mismatches: Vec::new()};
+    loop {
+        let expected: Event<ID> = match bincode::deserialize_from(&mut reader) {
+            Ok(event) => event,
+            Err(err) => match *err {
+                bincode::ErrorKind::Io(ref err) if err.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                _ => return Err(err)
+            }
+        };
+
+        let actual = replay_event::<Point_, Index, ID>(&expected, layer);
+        if actual != expected {
+            report.mismatches.push(Mismatch{
+                event: report.events,
+                expected,
+                actual});
+        }
+        report.events += 1;
+    }
+
+    Ok(report)
+}
+
+/// Apply a single recorded operation, returning the operation with its replayed results
+fn replay_event<Point_, Index, ID>(event: &Event<ID>, layer: &mut Layer<Index, ID>) -> Event<ID>
+where
+    Index: SpatialIndex,
+    ID: ObjectID,
+    Bounds<Index::Point>: IndexGenerator<Index>,
+    Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+    Point_::Diff: VectorSpace<Scalar = f32> + ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+    Bounds<Point_>: SystemBounds<Point_, Index::Point>,
+    BoxTestGeometry<Point_>: TestGeometry,
+    RayTestGeometry<Point_>: TestGeometry
+{
+    match event.clone() {
+        Event::Clear => {
+            layer.clear();
+            Event::Clear
+        },
+        Event::Extend{system_bounds, objects} => {
+            layer.extend(
+                system_bounds.to_bounds::<Point_>(),
+                objects.iter().map(|(bounds, id)| (bounds.to_bounds(), *id)));
+            Event::Extend{system_bounds, objects}
+        },
+        Event::Scan{..} => Event::Scan{
+            results: layer.scan().clone()},
+        Event::TestBox{system_bounds, test_bounds, max_depth, ..} => {
+            let results = layer.test_box(
+                system_bounds.to_bounds::<Point_>(),
+                test_bounds.to_bounds(),
+                max_depth).clone();
+            Event::TestBox{system_bounds, test_bounds, max_depth, results}
+        },
+        Event::TestRay{system_bounds, origin, direction, range_min, range_max, max_depth, ..} => {
+            let results = layer.test_ray(
+                system_bounds.to_bounds::<Point_>(),
+                from_slice(&origin),
+                from_slice::<Point_>(&direction).to_vec(),
+                range_min,
+                range_max,
+                max_depth).clone();
+            Event::TestRay{system_bounds, origin, direction, range_min, range_max, max_depth, results}
+        },
+        Event::PickRay{system_bounds, origin, direction, max_dist, max_depth, distances, ..} => {
+            let table: FxHashMap<ID, f32> = distances.iter().cloned().collect();
+            let mut replayed: Vec<(ID, f32)> = Vec::new();
+            let result = layer.pick_ray(
+                system_bounds.to_bounds::<Point_>(),
+                from_slice(&origin),
+                from_slice::<Point_>(&direction).to_vec(),
+                max_dist,
+                max_depth,
+                |_, _, _, id| {
+                    let dist = table.get(&id).cloned().unwrap_or(f32::INFINITY);
+                    replayed.push((id, dist));
+                    dist
+                });
+            Event::PickRay{
+                system_bounds,
+                origin,
+                direction,
+                max_dist,
+                max_depth,
+                distances: replayed,
+                result: result.map(|(dist, id, _)| (dist, id))}
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index64_2D;
+    use crate::layer::LayerBuilder;
+
+    use cgmath::{Point2, Vector2};
+
+    #[test]
+    fn record_events() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let object = Bounds{
+            min: Point2::new(1f32, 1f32),
+            max: Point2::new(2f32, 2f32)};
+
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        let mut recorder = Recorder::new(&mut layer, Vec::new()).unwrap();
+        recorder.clear().unwrap();
+        recorder.extend(system_bounds, vec![(object, 0), (object, 1)].into_iter()).unwrap();
+        assert_eq!(recorder.scan().unwrap(), &vec![(1, 0)]);
+        let pick = recorder.pick_ray(system_bounds, Point2::new(0f32, 1.5f32), Vector2::new(1f32, 0f32), 16f32, None,
+            |_, _, _, id| 1f32 + id as f32).unwrap();
+        assert_eq!(pick.map(|(dist, id, _)| (dist, id)), Some((1f32, 0)));
+        let data = recorder.into_inner();
+
+        let mut reader = data.as_slice();
+        let header: Header = bincode::deserialize_from(&mut reader).unwrap();
+        assert_eq!(header, Header::new(2));
+        let events: Vec<Event<u32>> = (0..4)
+            .map(|_| bincode::deserialize_from(&mut reader).unwrap())
+            .collect();
+        assert!(reader.is_empty());
+        assert_eq!(events[0], Event::Clear);
+        assert_eq!(events[2], Event::Scan{results: vec![(1, 0)]});
+        match &events[3] {
+            Event::PickRay{distances, result, ..} => {
+                assert_eq!(distances.len(), 2);
+                assert_eq!(result, &Some((1f32, 0)));
+            },
+            event => panic!("unexpected event: {:?}", event)
+        }
+    }
+
+    #[test]
+    fn replay_recording() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let object = |x: f32, id: u32| (Bounds{
+            min: Point2::new(x, 1f32),
+            max: Point2::new(x + 1f32, 2f32)}, id);
+
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        let mut recorder = Recorder::new(&mut layer, Vec::new()).unwrap();
+        recorder.extend(system_bounds, vec![object(1f32, 0), object(1.5f32, 1), object(8f32, 2)].into_iter()).unwrap();
+        recorder.scan().unwrap();
+        recorder.test_box(system_bounds, object(7f32, 0).0, None).unwrap();
+        recorder.test_ray(system_bounds, Point2::new(0f32, 1.5f32), Vector2::new(1f32, 0f32), 0f32, 16f32, None).unwrap();
+        recorder.pick_ray(system_bounds, Point2::new(0f32, 1.5f32), Vector2::new(1f32, 0f32), 16f32, None,
+            |_, _, _, id| 10f32 - id as f32).unwrap();
+        let data = recorder.into_inner();
+
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        let report = run::<Point2<f32>, _, _, _>(data.as_slice(), &mut layer).unwrap();
+        assert_eq!(report.events, 5);
+        assert!(report.is_ok(), "{:?}", report);
+
+        // replaying against a layer with different initial contents should detect mismatches
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, std::iter::once(object(8.5f32, 3)));
+        let report = run::<Point2<f32>, _, _, _>(data.as_slice(), &mut layer).unwrap();
+        assert!(!report.is_ok());
+        assert_eq!(report.mismatches[0].event, 1);
+    }
+}
@@ -0,0 +1,312 @@
+// mlodato, 2020
+
+use crate::backend::Broadphase;
+use crate::geom::{Bounds, VecDim};
+use crate::traits::ObjectID;
+
+use cgmath::prelude::*;
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use std::fmt::Debug;
+
+type CellKey = [i32; 3];
+
+/// [`Broadphase`]: trait.Broadphase.html
+/// [`Layer`]: struct.Layer.html
+/// A uniform-grid spatial hash implementing [`Broadphase`]
+/// 
+/// Each object is inserted into every grid cell its bounds overlap.  For objects of uniform size and
+/// distribution (particles, projectiles, etc.) with a `cell_size` similar to the object size, this
+/// may outperform the hierarchical index used by [`Layer`].  Performance degrades rapidly for objects
+/// much larger than `cell_size`.
+/// 
+/// Rays are only traversed within `system_bounds`
+pub struct SpatialHash<ID>
+where
+    ID: ObjectID
+{
+    cell_size: f32,
+    cells: FxHashMap<CellKey, Vec<ID>>,
+
+    // temporary data used within a method:
+    collisions: Vec<(ID, ID)>,
+    test_results: Vec<ID>,
+    processed: FxHashSet<ID>,
+}
+
+impl<ID> SpatialHash<ID>
+where
+    ID: ObjectID
+{
+    /// Create an empty `SpatialHash` with a given (global) cell size
+    pub fn new(cell_size: f32) -> Self {
+        assert!(cell_size > 0f32, "cell_size must be positive");
+        Self{
+            cell_size,
+            cells: FxHashMap::default(),
+            collisions: Vec::new(),
+            test_results: Vec::new(),
+            processed: FxHashSet::default(),
+        }
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    fn cell_at<Point_>(cell_size: f32, system_bounds: &Bounds<Point_>, point: Point_) -> CellKey
+    where
+        Point_: EuclideanSpace<Scalar = f32> + VecDim
+    {
+        let mut key: CellKey = [0; 3];
+        for (axis, key) in key.iter_mut().enumerate().take(Point_::DIM) {
+            *key = ((point[axis] - system_bounds.min[axis]) / cell_size).floor() as i32;
+        }
+        key
+    }
+
+    /// Walk the cells intersected by a ray, in order, until `visit` returns `false`
+    /// 
+    /// `visit` is called with each cell and the ray distance at which that cell is entered
+    #[allow(clippy::needless_range_loop)]
+    fn traverse_ray<Point_, Visit>(
+        cell_size: f32,
+        system_bounds: &Bounds<Point_>,
+        origin: Point_,
+        direction: Point_::Diff,
+        mut range_min: f32,
+        mut range_max: f32,
+        mut visit: Visit)
+    where
+        Point_: EuclideanSpace<Scalar = f32> + VecDim,
+        Point_::Diff: std::ops::Index<usize, Output = f32>,
+        Visit: FnMut(CellKey, f32) -> bool
+    {
+        let dim = Point_::DIM;
+
+        for axis in 0..dim {
+            let d0 = (system_bounds.min[axis] - origin[axis]) / direction[axis];
+            let d1 = (system_bounds.max[axis] - origin[axis]) / direction[axis];
+            if direction[axis] != 0f32 {
+                range_min = range_min.max(d0.min(d1));
+                range_max = range_max.min(d0.max(d1));
+            } else if origin[axis] < system_bounds.min[axis] || origin[axis] > system_bounds.max[axis] {
+                return;
+            }
+        }
+
+        if range_min > range_max {
+            return;
+        }
+
+        let start = origin + direction * range_min;
+        let mut cell = Self::cell_at(cell_size, system_bounds, start);
+        let mut step:  [i32; 3] = [0; 3];
+        let mut next:  [f32; 3] = [f32::INFINITY; 3];
+        let mut delta: [f32; 3] = [f32::INFINITY; 3];
+        for axis in 0..dim {
+            if direction[axis] > 0f32 {
+                step[axis] = 1;
+                let boundary = system_bounds.min[axis] + (cell[axis] + 1) as f32 * cell_size;
+                next[axis] = (boundary - origin[axis]) / direction[axis];
+                delta[axis] = cell_size / direction[axis];
+            } else if direction[axis] < 0f32 {
+                step[axis] = -1;
+                let boundary = system_bounds.min[axis] + cell[axis] as f32 * cell_size;
+                next[axis] = (boundary - origin[axis]) / direction[axis];
+                delta[axis] = -cell_size / direction[axis];
+            }
+        }
+
+        let mut entry = range_min;
+        loop {
+            if !visit(cell, entry) {
+                return;
+            }
+
+            let axis = (0..dim)
+                .min_by(|&lhs, &rhs| next[lhs].partial_cmp(&next[rhs]).unwrap())
+                .unwrap();
+            if !next[axis].is_finite() || next[axis] > range_max {
+                return;
+            }
+            entry = next[axis];
+            cell[axis] += step[axis];
+            next[axis] += delta[axis];
+        }
+    }
+}
+
+impl<ID, Point_> Broadphase<Point_> for SpatialHash<ID>
+where
+    ID: ObjectID,
+    Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+    Point_::Diff: VectorSpace<Scalar = f32> + std::ops::Index<usize, Output = f32> + Debug,
+{
+    type ID = ID;
+
+    fn clear(&mut self) {
+        self.cells.clear();
+    }
+
+    fn extend<Iter>(&mut self, system_bounds: Bounds<Point_>, objects: Iter)
+    where
+        Iter: Iterator<Item = (Bounds<Point_>, ID)>
+    {
+        for (bounds, id) in objects {
+            let min = Self::cell_at(self.cell_size, &system_bounds, bounds.min);
+            let max = Self::cell_at(self.cell_size, &system_bounds, bounds.max);
+            for z in min[2]..=max[2] {
+                for y in min[1]..=max[1] {
+                    for x in min[0]..=max[0] {
+                        self.cells.entry([x, y, z])
+                            .or_default()
+                            .push(id);
+                    }
+                }
+            }
+        }
+    }
+
+    fn scan(&mut self) -> &Vec<(ID, ID)> {
+        self.collisions.clear();
+
+        for ids in self.cells.values() {
+            for (i, &lhs) in ids.iter().enumerate() {
+                for &rhs in &ids[i+1..] {
+                    if lhs != rhs {
+                        self.collisions.push((lhs.min(rhs), lhs.max(rhs)));
+                    }
+                }
+            }
+        }
+
+        self.collisions.sort_unstable();
+        self.collisions.dedup();
+
+        &self.collisions
+    }
+
+    fn test_ray(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        origin   : Point_,
+        direction: Point_::Diff,
+        range_min: f32,
+        range_max: f32) -> &Vec<ID>
+    {
+        self.test_results.clear();
+
+        let cells = &self.cells;
+        let results = &mut self.test_results;
+        Self::traverse_ray(self.cell_size, &system_bounds, origin, direction, range_min, range_max, |cell, _| {
+            if let Some(ids) = cells.get(&cell) {
+                results.extend(ids.iter());
+            }
+            true
+        });
+
+        results.sort();
+        results.dedup();
+
+        results
+    }
+
+    fn pick_ray<GetDist>(
+        &mut self,
+        system_bounds: Bounds<Point_>,
+        origin   : Point_,
+        direction: Point_::Diff,
+        max_dist: f32,
+        mut get_dist: GetDist) -> Option<(f32, ID, Point_)>
+    where
+        GetDist: FnMut(&Point_, &Point_::Diff, f32, ID) -> f32
+    {
+        self.processed.clear();
+
+        let cells = &self.cells;
+        let processed = &mut self.processed;
+        let mut nearest = max_dist;
+        let mut result: Option<ID> = None;
+        Self::traverse_ray(self.cell_size, &system_bounds, origin, direction, 0f32, max_dist, |cell, entry| {
+            if entry > nearest {
+                return false;
+            }
+            if let Some(ids) = cells.get(&cell) {
+                for &id in ids {
+                    if !processed.insert(id) {
+                        continue;
+                    }
+                    let dist = get_dist(&origin, &direction, nearest, id);
+                    if dist.is_finite() && (dist < nearest || (dist == nearest && matches!(result, Some(result) if id < result))) {
+                        nearest = dist;
+                        result = Some(id);
+                    }
+                }
+            }
+            true
+        });
+
+        result.map(|id| (nearest, id, origin + direction * nearest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index64_3D;
+    use crate::layer::{Layer, LayerBuilder};
+
+    use cgmath::{Point3, Vector3};
+    use rand::prelude::*;
+
+    #[test]
+    fn pick_ties() {
+        let system_bounds = Bounds{
+            min: Point3::new(0f32, 0f32, 0f32),
+            max: Point3::new(64f32, 64f32, 64f32)};
+        let bounds = Bounds{
+            min: Point3::new(4.5f32, 0.5f32, 0.5f32),
+            max: Point3::new(5.5f32, 1.5f32, 1.5f32)};
+
+        let mut hash = SpatialHash::new(2f32);
+        Broadphase::extend(&mut hash, system_bounds, [9u32, 3, 6].iter().map(|&id| (bounds, id)));
+        let origin = Point3::new(0f32, 1f32, 1f32);
+        let direction = Vector3::new(1f32, 0f32, 0f32);
+        let picked = hash.pick_ray(system_bounds, origin, direction, 64f32, |origin, direction, _, _| {
+            bounds.intersect_ray(*origin, *direction).unwrap().0
+        });
+        assert_eq!(picked.map(|(_, id, _)| id), Some(3));
+    }
+
+    #[test]
+    fn scan_superset_of_overlaps() {
+        let system_bounds = Bounds{
+            min: Point3::new(0f32, 0f32, 0f32),
+            max: Point3::new(64f32, 64f32, 64f32)};
+
+        let mut prng = rand_chacha::ChaChaRng::seed_from_u64(0);
+        let objects: Vec<_> = (0..500u32).map(|id| {
+            let min = Point3::new(
+                prng.gen_range(0f32, 62f32),
+                prng.gen_range(0f32, 62f32),
+                prng.gen_range(0f32, 62f32));
+            let size = prng.gen_range(0.1f32, 2f32);
+            (Bounds{min, max: min + Vector3::new(size, size, size)}, id)
+        }).collect();
+
+        let mut hash = SpatialHash::new(2f32);
+        Broadphase::extend(&mut hash, system_bounds, objects.iter().cloned());
+        let mut layer: Layer<Index64_3D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, objects.iter().cloned());
+
+        let pairs: FxHashSet<_> = Broadphase::<Point3<f32>>::scan(&mut hash).iter().cloned().collect();
+        for &(lhs, rhs) in layer.scan() {
+            let (lhs_bounds, _) = objects[lhs as usize];
+            let (rhs_bounds, _) = objects[rhs as usize];
+            if lhs_bounds.overlaps(rhs_bounds) {
+                assert!(pairs.contains(&(lhs.min(rhs), lhs.max(rhs))));
+            }
+        }
+    }
+}
@@ -0,0 +1,148 @@
+// mlodato, 2020
+
+use crate::geom::{Bounds, IndexGenerator};
+use crate::index::SpatialIndex;
+use crate::layer::Layer;
+use crate::traits::ObjectID;
+
+use serde::de::DeserializeOwned;
+
+use std::io::Read;
+
+/// Merge the sorted entries of `run` into the sorted entries of `tree`, leaving `run` empty
+/// 
+/// The merge is done in-place, from the back, so no memory is needed beyond `tree`'s final size.
+fn merge_run<T>(tree: &mut Vec<T>, run: &mut Vec<T>)
+where
+    T: Copy + Ord
+{
+    match (tree.last(), run.first()) {
+        (Some(last), Some(first)) if last > first => {},
+        // already in order, which is typical for data which was sorted when it was serialized
+        _ => {
+            tree.append(run);
+            return;
+        }
+    }
+
+    let mut i = tree.len();
+    let mut j = run.len();
+    tree.extend_from_slice(run);
+    let mut k = tree.len();
+    while j > 0 {
+        k -= 1;
+        if i > 0 && tree[i-1] > run[j-1] {
+            i -= 1;
+            tree[k] = tree[i];
+        } else {
+            j -= 1;
+            tree[k] = run[j];
+        }
+    }
+    run.clear();
+}
+
+impl<Index, ID> Layer<Index, ID>
+where
+    Index: SpatialIndex + DeserializeOwned,
+    ID: ObjectID + DeserializeOwned,
+    Bounds<Index::Point>: IndexGenerator<Index>
+{
+    /// Replace the contents of this `Layer` with a bincode-serialized `Layer` read from `reader`
+    /// 
+    /// Entries are decoded at most `chunk_size` at a time; each chunk is sorted and merged into the
+    /// committed entries, so loading a large layer needs no intermediate buffer beyond a single chunk
+    /// (in contrast to `bincode::deserialize_from`, followed by `commit` for data which was not sorted).
+    /// Data which was sorted when serialized is appended directly; otherwise, merging may be slow.
+    /// 
+    /// Configuration (e.g. from `LayerBuilder`) is retained, and the result is committed.  Staged
+    /// changes are discarded, and the `Layer` is left empty if an error occurs.  `reader` should
+    /// generally be buffered, e.g. with `std::io::BufReader`.
+    pub fn read_stream<R>(&mut self, mut reader: R, chunk_size: usize) -> bincode::Result<()>
+    where
+        R: Read
+    {
+        assert!(chunk_size > 0, "chunk_size must be non-zero");
+
+        let result = (|| {
+            // fields are read in the order they are serialized: `min_depth`, then `tree`
+            let min_depth: u32 = bincode::deserialize_from(&mut reader)?;
+            let len: u64 = bincode::deserialize_from(&mut reader)?;
+            let tree = self.reset_committed(min_depth);
+            tree.reserve(len as usize);
+
+            let mut chunk: Vec<(Index, ID)> = Vec::with_capacity(chunk_size.min(len as usize));
+            let mut remaining = len;
+            while remaining > 0 {
+                let n = remaining.min(chunk_size as u64);
+                for _ in 0..n {
+                    chunk.push(bincode::deserialize_from(&mut reader)?);
+                }
+                remaining -= n;
+
+                chunk.sort_unstable();
+                merge_run(tree, &mut chunk);
+            }
+
+            // entries are always sorted now, regardless of the serialized flag
+            let _sorted: bool = bincode::deserialize_from(&mut reader)?;
+            Ok(())
+        })();
+
+        if result.is_err() {
+            self.reset_committed(0);
+        }
+        strict_assert!(self.validate().is_empty(), "invalid layer: {:?}", self.validate());
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index64_2D;
+    use crate::layer::LayerBuilder;
+
+    use cgmath::Point2;
+
+    #[test]
+    fn merge_runs() {
+        let mut tree = vec![1, 4, 6];
+        merge_run(&mut tree, &mut vec![7, 8]);
+        merge_run(&mut tree, &mut vec![0, 2, 5, 9]);
+        assert_eq!(tree, vec![0, 1, 2, 4, 5, 6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn read_stream() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let mut layer: Layer<Index64_2D, u32> = LayerBuilder::new()
+            .with_min_depth(1)
+            .build();
+        layer.extend(system_bounds, (0..32u32).map(|id| {
+            let x = (id % 8) as f32 * 1.5f32 + 0.5f32;
+            let y = (id / 8) as f32 * 3.5f32 + 0.5f32;
+            (Bounds{min: Point2::new(x, y), max: Point2::new(x + 1f32, y + 1f32)}, id)
+        }));
+        layer.commit();
+
+        let sorted = bincode::serialize(&layer).unwrap();
+        let mut entries: Vec<_> = layer.iter().cloned().collect();
+        entries.reverse();
+        let unsorted = bincode::serialize(&(1u32, (entries, false))).unwrap();
+
+        for bytes in &[sorted, unsorted] {
+            let mut result: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+            result.read_stream(bytes.as_slice(), 5).unwrap();
+            assert!(result.is_committed());
+            assert!(result == layer);
+        }
+
+        let mut result: Layer<Index64_2D, u32> = LayerBuilder::new().build();
+        let bytes = bincode::serialize(&layer).unwrap();
+        assert!(result.read_stream(&bytes[..bytes.len() / 2], 5).is_err());
+        assert_eq!(result.iter().len(), 0);
+    }
+}
@@ -0,0 +1,216 @@
+// mlodato, 2020
+
+use crate::backend::Broadphase;
+use crate::geom::Bounds;
+use crate::traits::ObjectID;
+
+use cgmath::prelude::*;
+use rustc_hash::FxHashMap;
+use smallvec::SmallVec;
+
+use std::fmt::Debug;
+
+/// [`Broadphase`]: trait.Broadphase.html
+/// [`update`]: trait.Broadphase.html#method.update
+/// A single-axis sweep-and-prune implementing [`Broadphase`]
+/// 
+/// Objects are kept sorted by their minimum bound along `axis`.  This order is retained between calls
+/// to [`update`] and restored with an insertion sort, which is nearly linear when objects move only
+/// slightly between frames.  This is best suited to scenes with high temporal coherence and few
+/// insertions or removals.
+/// 
+/// Pairs reported by `scan` are exact bounding box overlaps
+pub struct SweepAndPrune<Point_, ID>
+where
+    Point_: EuclideanSpace<Scalar = f32>,
+    ID: ObjectID
+{
+    axis: usize,
+    objects: Vec<(Bounds<Point_>, ID)>,
+
+    // temporary data used within a method:
+    collisions: Vec<(ID, ID)>,
+    test_results: Vec<ID>,
+    hits: Vec<(f32, ID)>,
+}
+
+impl<Point_, ID> SweepAndPrune<Point_, ID>
+where
+    Point_: EuclideanSpace<Scalar = f32>,
+    ID: ObjectID
+{
+    /// Create an empty `SweepAndPrune`, sorting along the given axis
+    pub fn new(axis: usize) -> Self {
+        assert!(axis < Point_::len(), "axis out of range");
+        Self{
+            axis,
+            objects: Vec::new(),
+            collisions: Vec::new(),
+            test_results: Vec::new(),
+            hits: Vec::new(),
+        }
+    }
+
+    /// Iterate over all objects, in sorted order
+    pub fn iter(&self) -> std::slice::Iter<'_, (Bounds<Point_>, ID)> {
+        self.objects.iter()
+    }
+
+    fn sort(&mut self) {
+        let axis = self.axis;
+        for i in 1..self.objects.len() {
+            let mut j = i;
+            while j > 0 && self.objects[j - 1].0.min[axis] > self.objects[j].0.min[axis] {
+                self.objects.swap(j - 1, j);
+                j -= 1;
+            }
+        }
+    }
+}
+
+impl<Point_, ID> Broadphase<Point_> for SweepAndPrune<Point_, ID>
+where
+    Point_: EuclideanSpace<Scalar = f32> + Debug,
+    Point_::Diff: VectorSpace<Scalar = f32> + std::ops::Index<usize, Output = f32> + Debug,
+    ID: ObjectID
+{
+    type ID = ID;
+
+    fn clear(&mut self) {
+        self.objects.clear();
+    }
+
+    fn extend<Iter>(&mut self, _system_bounds: Bounds<Point_>, objects: Iter)
+    where
+        Iter: Iterator<Item = (Bounds<Point_>, ID)>
+    {
+        self.objects.extend(objects);
+        self.sort();
+    }
+
+    /// Replace all objects, retaining the previous order of objects which are still present
+    fn update<Iter>(&mut self, _system_bounds: Bounds<Point_>, objects: Iter)
+    where
+        Iter: Iterator<Item = (Bounds<Point_>, ID)>
+    {
+        let mut updated: FxHashMap<ID, SmallVec<[Bounds<Point_>; 1]>> = FxHashMap::default();
+        let mut added: Vec<(Bounds<Point_>, ID)> = Vec::new();
+        for (bounds, id) in objects {
+            updated.entry(id).or_default().push(bounds);
+        }
+
+        self.objects.retain(|(_, id)| updated.contains_key(id));
+        for (bounds, id) in self.objects.iter_mut() {
+            if let Some(new_bounds) = updated.get_mut(id).and_then(|new_bounds| new_bounds.pop()) {
+                *bounds = new_bounds;
+            }
+        }
+        for (id, new_bounds) in updated {
+            added.extend(new_bounds.into_iter().map(|bounds| (bounds, id)));
+        }
+        self.objects.extend(added);
+
+        self.sort();
+    }
+
+    fn scan(&mut self) -> &Vec<(ID, ID)> {
+        self.collisions.clear();
+
+        let axis = self.axis;
+        for (i, &(lhs_bounds, lhs)) in self.objects.iter().enumerate() {
+            for &(rhs_bounds, rhs) in &self.objects[i+1..] {
+                if rhs_bounds.min[axis] > lhs_bounds.max[axis] {
+                    break;
+                }
+                if lhs != rhs && lhs_bounds.overlaps(rhs_bounds) {
+                    self.collisions.push((lhs.min(rhs), lhs.max(rhs)));
+                }
+            }
+        }
+
+        self.collisions.sort_unstable();
+        self.collisions.dedup();
+
+        &self.collisions
+    }
+
+    fn test_ray(
+        &mut self,
+        _system_bounds: Bounds<Point_>,
+        origin   : Point_,
+        direction: Point_::Diff,
+        range_min: f32,
+        range_max: f32) -> &Vec<ID>
+    {
+        self.test_results.clear();
+        self.test_results.extend(self.objects.iter()
+            .filter(|(bounds, _)| match bounds.intersect_ray(origin, direction) {
+                Some((t0, t1)) => t0 <= range_max && t1 >= range_min,
+                None => false
+            })
+            .map(|&(_, id)| id));
+        self.test_results.sort();
+        self.test_results.dedup();
+        &self.test_results
+    }
+
+    fn pick_ray<GetDist>(
+        &mut self,
+        _system_bounds: Bounds<Point_>,
+        origin   : Point_,
+        direction: Point_::Diff,
+        max_dist: f32,
+        mut get_dist: GetDist) -> Option<(f32, ID, Point_)>
+    where
+        GetDist: FnMut(&Point_, &Point_::Diff, f32, ID) -> f32
+    {
+        self.hits.clear();
+        self.hits.extend(self.objects.iter()
+            .filter_map(|&(bounds, id)| match bounds.intersect_ray(origin, direction) {
+                Some((t0, t1)) if t0 <= max_dist && t1 >= 0f32 => Some((t0.max(0f32), id)),
+                _ => None
+            }));
+        self.hits.sort_unstable_by(|(lhs, _), (rhs, _)| lhs.partial_cmp(rhs).unwrap());
+
+        let mut nearest = max_dist;
+        let mut result: Option<ID> = None;
+        for &(entry, id) in &self.hits {
+            if entry > nearest {
+                break;
+            }
+            let dist = get_dist(&origin, &direction, nearest, id);
+            if dist.is_finite() && (dist < nearest || (dist == nearest && matches!(result, Some(result) if id < result))) {
+                nearest = dist;
+                result = Some(id);
+            }
+        }
+
+        result.map(|id| (nearest, id, origin + direction * nearest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cgmath::{Point2, Vector2};
+
+    #[test]
+    fn update_retains_order() {
+        let system_bounds = Bounds{
+            min: Point2::new(0f32, 0f32),
+            max: Point2::new(16f32, 16f32)};
+        let object = |x: f32, id: u32| (Bounds{
+            min: Point2::new(x, 0f32),
+            max: Point2::new(x, 0f32) + Vector2::new(1.5f32, 1f32)}, id);
+
+        let mut sap = SweepAndPrune::new(0);
+        sap.update(system_bounds, vec![object(0f32, 0), object(1f32, 1), object(4f32, 2)].into_iter());
+        assert_eq!(Broadphase::<Point2<f32>>::scan(&mut sap), &vec![(0, 1)]);
+
+        sap.update(system_bounds, vec![object(5f32, 0), object(1f32, 1), object(4f32, 2), object(9f32, 3)].into_iter());
+        let order: Vec<u32> = sap.iter().map(|&(_, id)| id).collect();
+        assert_eq!(order, vec![1, 2, 0, 3]);
+        assert_eq!(Broadphase::<Point2<f32>>::scan(&mut sap), &vec![(0, 2)]);
+    }
+}
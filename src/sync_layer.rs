@@ -0,0 +1,267 @@
+// mlodato, 2020
+
+use crate::geom::{
+    Bounds,
+    BoxTestGeometry,
+    IndexGenerator,
+    RayTestGeometry,
+    TestGeometry,
+    VecDim,
+};
+use crate::index::SpatialIndex;
+use crate::layer::Layer;
+use crate::traits::ObjectID;
+
+use cgmath::prelude::*;
+
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex, RwLock};
+
+/// [`Layer`]: struct.Layer.html
+/// [`rebuild`]: #method.rebuild
+/// A [`Layer`] which may be queried from many threads while it is being rebuilt
+/// 
+/// Queries run against an immutable, sorted snapshot and take `&self`, so they may be called
+/// concurrently.  [`rebuild`] prepares the next snapshot separately (reusing the storage of a
+/// retired snapshot when no other thread still holds it) and then publishes it atomically; queries
+/// never observe a partially-built layer, and are only blocked for the duration of the swap.
+/// 
+/// Since a snapshot is shared, query results are returned by value rather than from the layer's
+/// internal buffers, and persistent per-layer scan state (see `LayerBuilder::with_hot_cell_sap`)
+/// is not used.
+pub struct SyncLayer<Index, ID>
+where
+    Index: SpatialIndex,
+    ID: ObjectID,
+    Bounds<Index::Point>: IndexGenerator<Index>
+{
+    build: Mutex<Option<Layer<Index, ID>>>,
+    query: RwLock<Arc<Layer<Index, ID>>>,
+}
+
+impl<Index, ID> SyncLayer<Index, ID>
+where
+    Index: SpatialIndex,
+    ID: ObjectID,
+    Bounds<Index::Point>: IndexGenerator<Index>
+{
+    /// Create a `SyncLayer`, using the contents of `layer` as the initial snapshot
+    pub fn new(mut layer: Layer<Index, ID>) -> Self {
+        layer.commit();
+        Self{
+            build: Mutex::new(None),
+            query: RwLock::new(Arc::new(layer))}
+    }
+
+    /// The current snapshot
+    /// 
+    /// The snapshot remains valid (and unchanged) after later rebuilds, for as long as it is held
+    pub fn snapshot(&self) -> Arc<Layer<Index, ID>> {
+        self.query.read().unwrap().clone()
+    }
+
+    /// Replace the current snapshot with `layer`
+    pub fn publish(&self, mut layer: Layer<Index, ID>) {
+        layer.commit();
+        let _build = self.build.lock().unwrap();
+        *self.query.write().unwrap() = Arc::new(layer);
+    }
+
+    /// Build and publish a new snapshot
+    /// 
+    /// `f` is called with an empty `Layer` (with the same configuration as the current snapshot),
+    /// which it should fill, e.g. with `Layer::extend`.  The result is sorted before being
+    /// published.  Concurrent calls to `rebuild` are serialized.
+    pub fn rebuild<F>(&self, f: F)
+    where
+        F: FnOnce(&mut Layer<Index, ID>)
+    {
+        let mut build = self.build.lock().unwrap();
+
+        let mut layer = match build.take() {
+            Some(layer) => layer,
+            None => self.snapshot().as_ref().clone()
+        };
+        layer.clear();
+        f(&mut layer);
+        layer.commit();
+
+        let retired = std::mem::replace(&mut *self.query.write().unwrap(), Arc::new(layer));
+
+        // reuse the retired snapshot's storage if no queries are still using it
+        *build = Arc::try_unwrap(retired).ok();
+    }
+
+    /// [`Layer::test`]: struct.Layer.html#method.test
+    /// Equivalent to [`Layer::test`], run against the current snapshot
+    pub fn test<TestGeom>(
+        &self,
+        test_geom: &TestGeom,
+        max_depth: Option<u32>) -> Vec<ID>
+    where
+        TestGeom: TestGeometry
+    {
+        self.snapshot().test_committed(test_geom, max_depth)
+    }
+
+    /// [`Layer::test_box`]: struct.Layer.html#method.test_box
+    /// Equivalent to [`Layer::test_box`], run against the current snapshot
+    pub fn test_box<Point_>(
+        &self,
+        system_bounds: Bounds<Point_>,
+        test_bounds: Bounds<Point_>,
+        max_depth: Option<u32>) -> Vec<ID>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + Debug,
+        Point_::Diff: ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        BoxTestGeometry<Point_>: TestGeometry
+    {
+        let test_geom = BoxTestGeometry::with_system_bounds(
+            system_bounds,
+            test_bounds);
+
+        self.test(&test_geom, max_depth)
+    }
+
+    /// [`Layer::test_ray`]: struct.Layer.html#method.test_ray
+    /// Equivalent to [`Layer::test_ray`], run against the current snapshot
+    pub fn test_ray<Point_>(
+        &self,
+        system_bounds: Bounds<Point_>,
+        origin   : Point_,
+        direction: Point_::Diff,
+        range_min: f32,
+        range_max: f32,
+        max_depth: Option<u32>) -> Vec<ID>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+        Point_::Diff: ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        RayTestGeometry<Point_>: TestGeometry
+    {
+        let test_geom = RayTestGeometry::with_system_bounds(
+            system_bounds,
+            origin,
+            direction,
+            range_min,
+            range_max);
+
+        self.test(&test_geom, max_depth)
+    }
+
+    /// [`Layer::pick`]: struct.Layer.html#method.pick
+    /// Equivalent to [`Layer::pick`], run against the current snapshot
+    pub fn pick<TestGeom, GetDist>(
+        &self,
+        test_geom: &TestGeom,
+        max_dist: f32,
+        max_depth: Option<u32>,
+        get_dist: GetDist) -> Option<(f32, ID)>
+    where
+        TestGeom: TestGeometry,
+        GetDist: FnMut(&TestGeom, f32, ID) -> f32
+    {
+        self.snapshot().pick_committed(test_geom, max_dist, max_depth, get_dist)
+    }
+
+    /// [`Layer::pick_ray`]: struct.Layer.html#method.pick_ray
+    /// Equivalent to [`Layer::pick_ray`], run against the current snapshot
+    pub fn pick_ray<Point_, GetDist>(
+        &self,
+        system_bounds: Bounds<Point_>,
+        origin   : Point_,
+        direction: Point_::Diff,
+        max_dist: f32,
+        max_depth: Option<u32>,
+        mut get_dist: GetDist) -> Option<(f32, ID, Point_)>
+    where
+        Point_: EuclideanSpace<Scalar = f32> + VecDim + Debug,
+        Point_::Diff: VectorSpace<Scalar = f32> + ElementWise + std::ops::Index<usize, Output = f32> + Debug,
+        RayTestGeometry<Point_>: TestGeometry,
+        GetDist: FnMut(&Point_, &Point_::Diff, f32, ID) -> f32
+    {
+        let test_geom = RayTestGeometry::with_system_bounds(
+            system_bounds,
+            origin,
+            direction,
+            0f32,
+            max_dist);
+
+        self.pick(&test_geom, max_dist, max_depth, |_, max_dist, id| {
+                get_dist(&origin, &direction, max_dist, id)
+            })
+            .map(|(dist, id)| {
+                let point = origin + direction * dist;
+                (dist, id, point)
+            })
+    }
+
+    /// [`Layer::scan`]: struct.Layer.html#method.scan
+    /// Equivalent to [`Layer::scan`], run against the current snapshot
+    pub fn scan(&self) -> Vec<(ID, ID)> {
+        self.scan_filtered(|_, _| true)
+    }
+
+    /// [`Layer::scan_filtered`]: struct.Layer.html#method.scan_filtered
+    /// Equivalent to [`Layer::scan_filtered`], run against the current snapshot
+    pub fn scan_filtered<F>(&self, filter: F) -> Vec<(ID, ID)>
+    where
+        F: FnMut(ID, ID) -> bool
+    {
+        self.snapshot().scan_committed(filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index64_3D;
+    use crate::layer::LayerBuilder;
+
+    use cgmath::Point3;
+
+    #[test]
+    fn concurrent_queries() {
+        let system_bounds = Bounds{
+            min: Point3::new(0f32, 0f32, 0f32),
+            max: Point3::new(64f32, 64f32, 64f32)};
+        let object_bounds = |x: f32| Bounds{
+            min: Point3::new(x, x, x),
+            max: Point3::new(x + 1.5f32, x + 1.5f32, x + 1.5f32)};
+        let test_bounds = Bounds{
+            min: Point3::new(0f32, 0f32, 0f32),
+            max: Point3::new(8f32, 8f32, 8f32)};
+
+        let mut layer: Layer<Index64_3D, u32> = LayerBuilder::new().build();
+        layer.extend(system_bounds, (0..32).map(|i| (object_bounds(i as f32), i)));
+        let expected_test = layer.test_box(system_bounds, test_bounds, None).clone();
+        let expected_scan = layer.scan().clone();
+
+        let sync_layer = SyncLayer::new(layer);
+        assert_eq!(sync_layer.test_box(system_bounds, test_bounds, None), expected_test);
+        assert_eq!(sync_layer.scan(), expected_scan);
+
+        let sync_layer = Arc::new(sync_layer);
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let sync_layer = sync_layer.clone();
+                let expected_test = expected_test.clone();
+                std::thread::spawn(move || for _ in 0..64 {
+                    // either the old or the new snapshot, never a mix of the two
+                    let results = sync_layer.test_box(system_bounds, test_bounds, None);
+                    assert!(results == expected_test || results.iter().all(|&id| id >= 100));
+                })
+            })
+            .collect();
+        for _ in 0..16 {
+            sync_layer.rebuild(|layer| {
+                layer.extend(system_bounds, (0..32).map(|i| (object_bounds(i as f32), i + 100)));
+            });
+        }
+        for reader in readers {
+            reader.join().unwrap();
+        }
+
+        let results = sync_layer.test_box(system_bounds, test_bounds, None);
+        assert_eq!(results, expected_test.iter().map(|&id| id + 100).collect::<Vec<_>>());
+    }
+}
@@ -3,14 +3,84 @@
 use std::fmt::Debug;
 use std::hash::Hash;
 
+/// Object identifiers stored alongside each index
+/// 
+/// This is implemented for any type with the required bounds, including all primitive integers (e.g.
+/// `u32`, `u64`, `u128`) and 16-byte UUID types such as `uuid::Uuid`.
+/// 
+/// Each entry in a `Layer` is an `(Index, ID)` pair, so the size of `ID` directly affects memory
+/// bandwidth during sorting and scanning.  Pairing an ID with an index of the same size avoids padding:
+/// 
+/// | Index         | ID                 | Entry size |
+/// |---------------|--------------------|------------|
+/// | `Index32_2D`  | `u32`              | 8 bytes    |
+/// | `Index64_3D`  | `u64`              | 16 bytes   |
+/// | `Index64_3D`  | `[u8; 16]` / UUID  | 24 bytes   |
+/// | `Index64_3D`  | `u128`             | 32 bytes (`u128` is 16-byte aligned, leaving 8 bytes of padding) |
+/// 
+/// Where 128-bit IDs are needed, prefer a byte-array representation (such as `uuid::Uuid`) or
+/// `[u64; 2]` over `u128` to avoid the padding.
 #[cfg(not(feature="parallel"))]
 pub trait ObjectID: Copy + Clone + Hash + Ord + Debug {}
 
 #[cfg(not(feature="parallel"))]
 impl<T: Copy + Clone + Hash + Ord + Debug> ObjectID for T {}
 
+/// Object identifiers stored alongside each index
+/// 
+/// This is implemented for any type with the required bounds, including all primitive integers (e.g.
+/// `u32`, `u64`, `u128`) and 16-byte UUID types such as `uuid::Uuid`.
+/// 
+/// Each entry in a `Layer` is an `(Index, ID)` pair, so the size of `ID` directly affects memory
+/// bandwidth during sorting and scanning.  Pairing an ID with an index of the same size avoids padding:
+/// 
+/// | Index         | ID                 | Entry size |
+/// |---------------|--------------------|------------|
+/// | `Index32_2D`  | `u32`              | 8 bytes    |
+/// | `Index64_3D`  | `u64`              | 16 bytes   |
+/// | `Index64_3D`  | `[u8; 16]` / UUID  | 24 bytes   |
+/// | `Index64_3D`  | `u128`             | 32 bytes (`u128` is 16-byte aligned, leaving 8 bytes of padding) |
+/// 
+/// Where 128-bit IDs are needed, prefer a byte-array representation (such as `uuid::Uuid`) or
+/// `[u64; 2]` over `u128` to avoid the padding.
 #[cfg(feature="parallel")]
 pub trait ObjectID: Copy + Clone + Hash + Ord + Send + Sync + Debug {}
 
 #[cfg(feature="parallel")]
-impl<T: Copy + Clone + Hash + Ord + Send + Sync + Debug> ObjectID for T {}
\ No newline at end of file
+impl<T: Copy + Clone + Hash + Ord + Send + Sync + Debug> ObjectID for T {}
+#[cfg(test)]
+mod tests {
+    use crate::index::{Index32_2D, Index64_3D};
+    use std::mem::size_of;
+
+    #[test]
+    fn entry_sizes() {
+        assert_eq!(size_of::<(Index32_2D, u32)>(), 8);
+        assert_eq!(size_of::<(Index64_3D, u64)>(), 16);
+        assert_eq!(size_of::<(Index64_3D, [u8; 16])>(), 24);
+        assert_eq!(size_of::<(Index64_3D, [u64; 2])>(), 24);
+    }
+
+    #[test]
+    fn wide_ids() {
+        use crate::geom::Bounds;
+        use crate::layer::{Layer, LayerBuilder};
+        use cgmath::Point3;
+
+        let system_bounds = Bounds{
+            min: Point3::new(0f32, 0f32, 0f32),
+            max: Point3::new(8f32, 8f32, 8f32)};
+        let object_bounds = Bounds{
+            min: Point3::new(1f32, 1f32, 1f32),
+            max: Point3::new(2f32, 2f32, 2f32)};
+        let ids = [u128::MAX, 1u128 << 100];
+
+        let mut layer: Layer<Index64_3D, u128> = LayerBuilder::new().build();
+        layer.extend(system_bounds, ids.iter().map(|&id| (object_bounds, id)));
+        assert_eq!(layer.scan().len(), 1);
+
+        let mut layer: Layer<Index64_3D, [u64; 2]> = LayerBuilder::new().build();
+        layer.extend(system_bounds, ids.iter().map(|&id| (object_bounds, [(id >> 64) as u64, id as u64])));
+        assert_eq!(layer.scan().len(), 1);
+    }
+}
@@ -25,12 +25,20 @@ fn load_scene<P: AsRef<Path>>(rel_path: P) -> Scene {
 #[test]
 fn extend() {
     let mut input = load_scene("inputs/boxes-seed_0-d_1_1000-s_1_10-n_010000.br_scene");
-    let validation = load_scene("validation/0_layer_unsorted.br_scene");
+    let unsorted = load_scene("validation/0_layer_unsorted.br_scene");
+    let validation = load_scene("validation/1_layer_sorted.br_scene");
 
     input.layer.extend(
         input.system_bounds,
         input.object_bounds.iter().cloned());
 
+    // entries are staged, in the order `extend` previously committed them
+    if !input.layer.iter_staged().eq(unsorted.layer.iter()) {
+        panic!("Layer::extend() staged unexpected results");
+    }
+
+    input.layer.commit();
+
     let actual   = input     .layer;
     let expected = validation.layer;
 